@@ -1,29 +1,87 @@
 //! I2C bus mode implementation
 
 use crate::{traits::{BusMode, Scanner}, Error};
-use embedded_hal::i2c::I2c;
+use embedded_hal::i2c::{Error as _, ErrorKind, I2c, NoAcknowledgeSource, Operation};
+use esp32_bus_pirate_protocol::I2C_MAX_SEGMENTS;
 use heapless::Vec;
 
+pub use esp32_bus_pirate_protocol::{
+    I2cLineState, I2cRecoveryReport, I2cScanOptions, I2cScanProbe, I2cSpeed, I2cSweepEntry, I2cSweepReport,
+};
+
+/// Frees a stuck bus and re-initialises the peripheral, see [`recover_bus`]
+pub type Recovery<I> = fn(&mut I) -> Result<I2cRecoveryReport, Error>;
+
+/// Sets the bus's SCL frequency, in Hz
+pub type SetFrequency<I> = fn(&mut I, u32) -> Result<(), Error>;
+
 /// I2C bus mode
 pub struct I2cMode<I> {
     i2c: I,
     config: Option<I2cConfig>,
+    recovery: Option<Recovery<I>>,
+    set_frequency: Option<SetFrequency<I>>,
+    /// The bus can address a device without clocking a byte
+    quick_probe: bool,
+}
+
+/// What an I2C error says beyond its [`ErrorKind`]
+///
+/// `ErrorKind` has no timeout, so buses that can time out report it here;
+/// [`I2cMode`] then returns [`Error::Timeout`] instead of a communication
+/// failure.
+pub trait I2cFault: embedded_hal::i2c::Error {
+    /// Whether the transfer was abandoned because the bus stopped moving
+    fn is_timeout(&self) -> bool {
+        false
+    }
 }
 
+impl I2cFault for ErrorKind {}
+
+impl I2cFault for core::convert::Infallible {}
+
 /// I2C configuration
 #[derive(Debug, Clone, Copy)]
 pub struct I2cConfig {
     pub frequency: u32,
 }
 
-impl<I: I2c> I2cMode<I> {
+impl<I: I2c<Error: I2cFault>> I2cMode<I> {
     /// Create a new I2C mode instance
     pub fn new(i2c: I) -> Self {
-        Self { i2c, config: None }
+        Self { i2c, config: None, recovery: None, set_frequency: None, quick_probe: true }
+    }
+
+    /// Refuse [`I2cScanProbe::Quick`] scans with [`Error::Unsupported`], for
+    /// a bus that cannot end a read without clocking a byte
+    pub fn without_quick_probe(mut self) -> Self {
+        self.quick_probe = false;
+        self
+    }
+
+    /// Let the mode free a stuck bus with `recovery`, and do so before
+    /// every scan
+    pub fn with_recovery(mut self, recovery: Recovery<I>) -> Self {
+        self.recovery = Some(recovery);
+        self
+    }
+
+    /// Let the mode change the bus frequency with `set_frequency`: on
+    /// [`init`](BusMode::init) and for [`sweep`](Self::sweep)
+    pub fn with_frequency_control(mut self, set_frequency: SetFrequency<I>) -> Self {
+        self.set_frequency = Some(set_frequency);
+        self
+    }
+
+    /// The configuration [`init`](BusMode::init) applied, while the mode
+    /// is up
+    pub fn config(&self) -> Option<I2cConfig> {
+        self.config
     }
 }
 
-impl<I: I2c> BusMode for I2cMode<I> {
+impl<I: I2c<Error: I2cFault>> BusMode for I2cMode<I> {
     type Config = I2cConfig;
     
     fn name(&self) -> &'static str {
@@ -31,6 +89,9 @@ impl<I: I2c> BusMode for I2cMode<I> {
     }
     
     fn init(&mut self, config: Self::Config) -> Result<(), Error> {
+        if let Some(set_frequency) = self.set_frequency {
+            set_frequency(&mut self.i2c, config.frequency)?;
+        }
         self.config = Some(config);
         Ok(())
     }
@@ -41,31 +102,25 @@ impl<I: I2c> BusMode for I2cMode<I> {
     }
 }
 
-impl<I: I2c> Scanner for I2cMode<I> {
+impl<I: I2c<Error: I2cFault>> Scanner for I2cMode<I> {
     type DeviceId = u8;
     
+    /// Probe 0x08-0x77 with empty writes, see [`scan_with`](I2cMode::scan_with)
     fn scan(&mut self) -> Result<Vec<u8, 128>, Error> {
-        let mut devices = Vec::new();
-        
-        // Scan all valid 7-bit addresses (0x08-0x77)
-        for addr in 0x08..=0x77 {
-            // Try to write zero bytes to detect device presence
-            if self.i2c.write(addr, &[]).is_ok() {
-                devices.push(addr).ok();
-            }
-        }
-        
-        Ok(devices)
+        self.scan_with(&I2cScanOptions::default())
     }
 }
 
-impl<I: I2c> I2cMode<I> {
+impl<I: I2c<Error: I2cFault>> I2cMode<I> {
     /// Read a register from an I2C device
+    ///
+    /// Failures are classified as by [`transaction`](Self::transaction), with
+    /// the register write as segment 0 and the read as segment 1.
     pub fn read_register(&mut self, addr: u8, reg: u8) -> Result<u8, Error> {
         let mut buf = [0u8; 1];
         self.i2c
             .write_read(addr, &[reg], &mut buf)
-            .map_err(|_| Error::Communication)?;
+            .map_err(|e| classify(e, Some(0)))?;
         Ok(buf[0])
     }
     
@@ -73,16 +128,331 @@ impl<I: I2c> I2cMode<I> {
     pub fn write_register(&mut self, addr: u8, reg: u8, value: u8) -> Result<(), Error> {
         self.i2c
             .write(addr, &[reg, value])
-            .map_err(|_| Error::Communication)
+            .map_err(|e| classify(e, Some(0)))
     }
     
     /// Read multiple bytes from an I2C device
     pub fn read(&mut self, addr: u8, buffer: &mut [u8]) -> Result<(), Error> {
-        self.i2c.read(addr, buffer).map_err(|_| Error::Communication)
+        self.i2c.read(addr, buffer).map_err(|e| classify(e, None))
     }
     
     /// Write multiple bytes to an I2C device
     pub fn write(&mut self, addr: u8, data: &[u8]) -> Result<(), Error> {
-        self.i2c.write(addr, data).map_err(|_| Error::Communication)
+        self.i2c.write(addr, data).map_err(|e| classify(e, Some(0)))
+    }
+    
+    /// Run `operations` on one device with repeated starts in between
+    ///
+    /// A NACK is reported as [`Error::Nack`] with the segment it ended, when
+    /// that is known. The bus only says whether the address or a data byte
+    /// was refused, not where: an address NACK is put on the first segment (a
+    /// device that acknowledged its address once does so again after a
+    /// repeated start), and a data NACK on the only write segment. With
+    /// several writes, or when the bus cannot tell address from data, the
+    /// segment is `None`. Lost arbitration, bus faults and timeouts have their
+    /// own [`Error`] variants.
+    pub fn transaction(&mut self, addr: u8, operations: &mut [Operation<'_>]) -> Result<(), Error> {
+        self.i2c
+            .transaction(addr, operations)
+            .map_err(|e| transaction_error(e, operations))
+    }
+    
+    /// Run `operations` on the device at 10-bit address `addr`, see
+    /// [`ten_bit_transaction`]
+    ///
+    /// NACKs are reported as by [`transaction`](Self::transaction), except
+    /// that a data NACK has no segment: it may have refused `A7..A0` rather
+    /// than the data after it.
+    pub fn transaction_ten_bit(&mut self, addr: u16, operations: &mut [Operation<'_>]) -> Result<(), Error> {
+        ten_bit_transaction(&mut self.i2c, addr, operations).map_err(|e| match e {
+            TenBitError::Invalid => Error::InvalidConfig,
+            TenBitError::Bus(e) => classify(e, None),
+        })
+    }
+    
+    /// Probe the addresses `options` select and return the ones that
+    /// acknowledge, see [`scan_bus`]
+    pub fn scan_with(&mut self, options: &I2cScanOptions) -> Result<Vec<u8, 128>, Error> {
+        self.check_probe(options)?;
+        self.recover_before_scan()?;
+        scan_bus(&mut self.i2c, options)
+    }
+    
+    /// Scan at every [`I2cSpeed`], then go back to the configured frequency
+    ///
+    /// A device missing at the higher speeds usually means slow edges: long
+    /// wires or weak pull-ups. Returns `None` when the mode was set up
+    /// without a way to change the frequency.
+    pub fn sweep(&mut self, options: &I2cScanOptions) -> Result<Option<I2cSweepReport>, Error> {
+        let Some(set_frequency) = self.set_frequency else {
+            return Ok(None);
+        };
+        self.check_probe(options)?;
+        self.recover_before_scan()?;
+        let mut report = I2cSweepReport { devices: Vec::new() };
+        let swept = I2cSpeed::ALL.into_iter().try_for_each(|speed| {
+            set_frequency(&mut self.i2c, speed.hz())?;
+            for addr in scan_bus(&mut self.i2c, options)? {
+                // There is room for every address at every speed
+                match report.devices.iter_mut().find(|entry| entry.addr == addr) {
+                    Some(entry) => entry.speeds.push(speed).ok(),
+                    None => report.devices.push(I2cSweepEntry { addr, speeds: [speed].into_iter().collect() }).ok(),
+                };
+            }
+            Ok(())
+        });
+        // Back to the configured speed even after a failed scan
+        if let Some(config) = self.config {
+            set_frequency(&mut self.i2c, config.frequency)?;
+        }
+        swept?;
+        report.devices.sort_unstable_by_key(|entry| entry.addr);
+        Ok(Some(report))
+    }
+    
+    /// Probe every 10-bit address and return the ones that acknowledge
+    ///
+    /// A probe writes just the two address bytes.
+    pub fn scan_ten_bit(&mut self) -> Result<Vec<u16, 128>, Error> {
+        self.recover_before_scan()?;
+        let mut devices = Vec::new();
+        for addr in 0..=TEN_BIT_ADDRESS_MAX {
+            match self.i2c.write(ten_bit_prefix(addr), &[addr as u8]) {
+                Ok(()) => {
+                    devices.push(addr).ok();
+                }
+                Err(e) if matches!(e.kind(), ErrorKind::NoAcknowledge(_)) => {}
+                Err(e) => return Err(classify(e, None)),
+            }
+        }
+        Ok(devices)
+    }
+    
+    /// Free the bus if a device is holding it, see [`recover_bus`]
+    ///
+    /// Returns `None` when the bus was set up without a way to drive its
+    /// lines directly.
+    pub fn recover(&mut self) -> Result<Option<I2cRecoveryReport>, Error> {
+        let Some(recovery) = self.recovery else {
+            return Ok(None);
+        };
+        let report = recovery(&mut self.i2c)?;
+        if let Some(config) = self.config {
+            self.init(config)?;
+        }
+        Ok(Some(report))
+    }
+    
+    /// Fail before touching the bus if `options` asks for a probe it
+    /// cannot send
+    fn check_probe(&self, options: &I2cScanOptions) -> Result<(), Error> {
+        if options.probe == I2cScanProbe::Quick && !self.quick_probe {
+            return Err(Error::Unsupported);
+        }
+        Ok(())
+    }
+    
+    /// A bus that cannot be freed would fail every probe; say so once instead
+    fn recover_before_scan(&mut self) -> Result<(), Error> {
+        match self.recover()? {
+            Some(report) if !report.recovered => Err(Error::BusFault),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// One of the two I2C lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum I2cLine {
+    Scl,
+    Sda,
+}
+
+/// The I2C lines driven as open-drain GPIOs, for [`recover_bus`]
+///
+/// While the lines are in use they are disconnected from the I2C
+/// peripheral; handing them back is up to the implementation.
+pub trait I2cLines {
+    /// Release `line` to its pull-up (`true`) or pull it low
+    fn set(&mut self, line: I2cLine, high: bool);
+
+    /// Level on `line`
+    fn get(&mut self, line: I2cLine) -> bool;
+
+    /// Turn the pins' internal pull-ups on or off
+    fn set_internal_pull_ups(&mut self, enabled: bool);
+
+    /// Wait `us` microseconds
+    fn delay_us(&mut self, us: u32);
+}
+
+/// Half an SCL period while recovering, for 100 kHz
+const RECOVERY_HALF_PERIOD_US: u32 = 5;
+
+/// Time for a released line to rise, even through a weak internal pull-up
+const LINE_SETTLE_US: u32 = 50;
+
+/// Clocks that finish any byte a device could be sending, plus its ACK
+const RECOVERY_CLOCKS: u8 = 9;
+
+/// Free a bus a device is holding low and report on its lines
+///
+/// A device that lost track of a read in progress keeps SDA low for the
+/// next 0 bit it wants to send. Clocking SCL, at most nine times, lets it
+/// finish the byte and release SDA; a STOP then resets every device on the
+/// bus. Nothing can be done about SCL held low, so the report only says so.
+///
+/// Before that the lines are sampled released, first without the internal
+/// pull-ups, to tell a missing pull-up from a device holding a line.
+pub fn recover_bus(lines: &mut (impl I2cLines + ?Sized)) -> I2cRecoveryReport {
+    lines.set(I2cLine::Scl, true);
+    lines.set(I2cLine::Sda, true);
+    lines.set_internal_pull_ups(false);
+    lines.delay_us(LINE_SETTLE_US);
+    let pulled_up = (lines.get(I2cLine::Scl), lines.get(I2cLine::Sda));
+    lines.set_internal_pull_ups(true);
+    lines.delay_us(LINE_SETTLE_US);
+
+    let mut report = I2cRecoveryReport {
+        scl: I2cLineState { pull_up: pulled_up.0, held_low: !lines.get(I2cLine::Scl) },
+        sda: I2cLineState { pull_up: pulled_up.1, held_low: !lines.get(I2cLine::Sda) },
+        clocks: 0,
+        recovered: false,
+    };
+    if report.sda.held_low && !report.scl.held_low {
+        while report.clocks < RECOVERY_CLOCKS && !lines.get(I2cLine::Sda) {
+            clock_line(lines, I2cLine::Scl, false);
+            clock_line(lines, I2cLine::Scl, true);
+            report.clocks += 1;
+        }
+        // STOP: SDA rises while SCL is high
+        clock_line(lines, I2cLine::Scl, false);
+        clock_line(lines, I2cLine::Sda, false);
+        clock_line(lines, I2cLine::Scl, true);
+        clock_line(lines, I2cLine::Sda, true);
+    }
+    report.recovered = lines.get(I2cLine::Scl) && lines.get(I2cLine::Sda);
+    report
+}
+
+/// Set `line` and hold it for half an SCL period
+fn clock_line(lines: &mut (impl I2cLines + ?Sized), line: I2cLine, high: bool) {
+    lines.set(line, high);
+    lines.delay_us(RECOVERY_HALF_PERIOD_US);
+}
+
+/// Probe the addresses `options` select on `i2c` and return the ones that
+/// acknowledge
+///
+/// The one scan behind [`I2cMode`] and the HAL's I2C bus. An address NACK
+/// means no device; any other failure, such as a bus held low, ends the
+/// scan with its error rather than making the bus look empty.
+pub fn scan_bus<I: I2c<Error: I2cFault> + ?Sized>(i2c: &mut I, options: &I2cScanOptions) -> Result<Vec<u8, 128>, Error> {
+    let (first, last) = options.range();
+    let mut devices = Vec::new();
+    for addr in first..=last {
+        let probed = match options.probe {
+            I2cScanProbe::Write => i2c.write(addr, &[]),
+            I2cScanProbe::Read => i2c.read(addr, &mut [0]),
+            I2cScanProbe::Quick => i2c.read(addr, &mut []),
+        };
+        match probed {
+            Ok(()) => {
+                devices.push(addr).ok();
+            }
+            Err(e) if matches!(e.kind(), ErrorKind::NoAcknowledge(_)) => {}
+            Err(e) => return Err(classify(e, None)),
+        }
+    }
+    Ok(devices)
+}
+
+/// Highest 10-bit I2C address
+pub const TEN_BIT_ADDRESS_MAX: u16 = 0x3FF;
+
+/// Why [`ten_bit_transaction`] failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TenBitError<E> {
+    /// The address is above [`TEN_BIT_ADDRESS_MAX`], or there are too many
+    /// segments
+    Invalid,
+    /// The bus failed
+    Bus(E),
+}
+
+/// Run `operations` on the device at 10-bit address `addr`, over a bus that
+/// only knows 7-bit addresses
+///
+/// The address bytes are sent in software: `11110 A9 A8` goes out as the
+/// 7-bit address and `A7..A0` as the first byte of a write. Every write
+/// needs both bytes, while a read after a repeated start only needs the
+/// first, since the device is still addressed. The one expansion behind
+/// [`I2cMode::transaction_ten_bit`] and the HAL's 10-bit I2C bus.
+pub fn ten_bit_transaction<I: I2c + ?Sized>(
+    i2c: &mut I,
+    addr: u16,
+    operations: &mut [Operation<'_>],
+) -> Result<(), TenBitError<I::Error>> {
+    if addr > TEN_BIT_ADDRESS_MAX {
+        return Err(TenBitError::Invalid);
+    }
+    let low = [addr as u8];
+    let mut expanded: Vec<Operation<'_>, { 2 * I2C_MAX_SEGMENTS }> = Vec::new();
+    let mut after_read = false;
+    for operation in operations.iter_mut() {
+        let (operation, read) = match operation {
+            Operation::Write(data) => (Operation::Write(data), false),
+            Operation::Read(buf) => (Operation::Read(buf), true),
+        };
+        if expanded.is_empty() || (!read && after_read) {
+            expanded.push(Operation::Write(&low)).map_err(|_| TenBitError::Invalid)?;
+        }
+        expanded.push(operation).map_err(|_| TenBitError::Invalid)?;
+        after_read = read;
+    }
+    i2c.transaction(ten_bit_prefix(addr), &mut expanded).map_err(TenBitError::Bus)
+}
+
+/// 7-bit address that opens a transfer to 10-bit address `addr`: `11110 A9 A8`
+fn ten_bit_prefix(addr: u16) -> u8 {
+    0x78 | (addr >> 8) as u8
+}
+
+/// Classify a failed transaction, attributing a data NACK to its write
+/// segment when `operations` has only one
+fn transaction_error(err: impl I2cFault, operations: &[Operation<'_>]) -> Error {
+    let mut writes = operations
+        .iter()
+        .enumerate()
+        .filter(|(_, op)| matches!(op, Operation::Write(_)))
+        .map(|(i, _)| i);
+    let only_write = match (writes.next(), writes.next()) {
+        (Some(i), None) => Some(i),
+        _ => None,
+    };
+    classify(err, only_write)
+}
+
+/// Turn a bus error into an [`Error`]
+///
+/// `data_segment` is the segment a data NACK is put on, if it is known; an
+/// address NACK is always put on segment 0, and one the bus could not place
+/// on none.
+fn classify(err: impl I2cFault, data_segment: Option<usize>) -> Error {
+    if err.is_timeout() {
+        return Error::Timeout;
+    }
+    match err.kind() {
+        ErrorKind::NoAcknowledge(source) => {
+            let segment = match source {
+                NoAcknowledgeSource::Address => Some(0),
+                NoAcknowledgeSource::Data => data_segment,
+                NoAcknowledgeSource::Unknown => None,
+            };
+            Error::Nack { segment, source }
+        }
+        ErrorKind::ArbitrationLoss => Error::ArbitrationLost,
+        ErrorKind::Bus => Error::BusFault,
+        _ => Error::Communication,
     }
 }
@@ -0,0 +1,195 @@
+//! 24Cxx I2C EEPROMs
+//!
+//! The 24C01 to 24C1024 all work the same way. A write sends the memory
+//! address (one byte up to the 24C16, two above), then up to a page of data.
+//! Bytes past the end of the page wrap to its start, so [`Eeprom::write`]
+//! splits data on page boundaries. After the STOP, the part is busy
+//! programming for a few milliseconds and does not acknowledge its address;
+//! each page is followed by polling until it does.
+//!
+//! Memory address bits that do not fit in the address bytes select a block
+//! through the low bits of the device address: a 24C16 answers at
+//! 0x50-0x57, one 256-byte block each. Reads are split on block boundaries,
+//! where the parts differ in what happens next.
+
+use crate::i2c::{I2cFault, I2cMode};
+use crate::Error;
+use embedded_hal::i2c::{I2c, NoAcknowledgeSource, Operation};
+use esp32_bus_pirate_protocol::bulk::crc32_digest;
+use heapless::Vec;
+
+pub use esp32_bus_pirate_protocol::{EepromPart, I2cEeprom};
+
+/// Address polls before a write cycle is considered hung; parts finish in
+/// 5-10 ms, a few hundred polls at 1 MHz
+const WRITE_CYCLE_POLLS: u32 = 10_000;
+
+/// Chunk size for reads that are only compared or checksummed
+const SCRATCH_SIZE: usize = 64;
+
+/// A 24Cxx EEPROM on an [`I2cMode`] bus
+pub struct Eeprom<'a, I> {
+    i2c: &'a mut I2cMode<I>,
+    device: I2cEeprom,
+}
+
+impl<'a, I: I2c<Error: I2cFault>> Eeprom<'a, I> {
+    /// Talk to `device` over `i2c`
+    pub fn new(i2c: &'a mut I2cMode<I>, device: I2cEeprom) -> Self {
+        Self { i2c, device }
+    }
+
+    /// Size in bytes
+    pub fn capacity(&self) -> u32 {
+        self.device.part.capacity()
+    }
+
+    /// Addresses of the part's blocks that acknowledge, in order
+    ///
+    /// A missing part gives none; a smaller part than expected, or another
+    /// device sharing the address range, only some.
+    pub fn probe(&mut self) -> Result<Vec<u8, 8>, Error> {
+        let mut found = Vec::new();
+        for block in 0..self.device.part.blocks() {
+            let addr = self.base_address() | block;
+            match self.i2c.write(addr, &[]) {
+                Ok(()) => {
+                    found.push(addr).ok();
+                }
+                Err(Error::Nack { .. }) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(found)
+    }
+
+    /// Read from `address` on
+    pub fn read(&mut self, mut address: u32, mut buffer: &mut [u8]) -> Result<(), Error> {
+        self.check_range(address, buffer.len())?;
+        let block = self.block_size();
+        while !buffer.is_empty() {
+            let room = (block - address % block) as usize;
+            let (chunk, rest) = buffer.split_at_mut(room.min(buffer.len()));
+
+            let (addr, bytes) = self.memory_address(address);
+            let width = usize::from(self.device.part.address_bytes());
+            self.i2c
+                .transaction(addr, &mut [Operation::Write(&bytes[..width]), Operation::Read(chunk)])?;
+
+            address += chunk.len() as u32;
+            buffer = rest;
+        }
+        Ok(())
+    }
+
+    /// Write `data` from `address` on, a page at a time, waiting for each
+    /// write cycle to finish
+    pub fn write(&mut self, mut address: u32, mut data: &[u8]) -> Result<(), Error> {
+        self.check_range(address, data.len())?;
+        let page = self.device.part.page_size();
+        while !data.is_empty() {
+            let room = (page - address % page) as usize;
+            let (chunk, rest) = data.split_at(room.min(data.len()));
+
+            let (addr, bytes) = self.memory_address(address);
+            let width = usize::from(self.device.part.address_bytes());
+            // Adjacent writes go out as one, without a repeated start
+            self.i2c
+                .transaction(addr, &mut [Operation::Write(&bytes[..width]), Operation::Write(chunk)])?;
+            self.wait_write_cycle(addr)?;
+
+            address += chunk.len() as u32;
+            data = rest;
+        }
+        Ok(())
+    }
+
+    /// Fill the whole part with 0xFF
+    pub fn erase(&mut self) -> Result<(), Error> {
+        let blank = [0xFF; 256];
+        let page = self.device.part.page_size() as usize;
+        let mut address = 0;
+        while address < self.capacity() {
+            self.write(address, &blank[..page])?;
+            address += page as u32;
+        }
+        Ok(())
+    }
+
+    /// Compare the contents from `address` on with `data`
+    ///
+    /// Returns the address of the first byte that differs, if any.
+    pub fn verify(&mut self, address: u32, data: &[u8]) -> Result<Option<u32>, Error> {
+        self.check_range(address, data.len())?;
+        let mut scratch = [0u8; SCRATCH_SIZE];
+        for (i, expected) in data.chunks(SCRATCH_SIZE).enumerate() {
+            let start = address + (i * SCRATCH_SIZE) as u32;
+            let actual = &mut scratch[..expected.len()];
+            self.read(start, actual)?;
+            if let Some(offset) = actual.iter().zip(expected).position(|(a, b)| a != b) {
+                return Ok(Some(start + offset as u32));
+            }
+        }
+        Ok(None)
+    }
+
+    /// CRC-32 of `len` bytes from `address` on, as a bulk transfer of them
+    /// would carry
+    pub fn checksum(&mut self, address: u32, len: u32) -> Result<u32, Error> {
+        self.check_range(address, len as usize)?;
+        let mut digest = crc32_digest();
+        let mut scratch = [0u8; SCRATCH_SIZE];
+        let mut offset = 0;
+        while offset < len {
+            let chunk = &mut scratch[..(len - offset).min(SCRATCH_SIZE as u32) as usize];
+            self.read(address + offset, chunk)?;
+            digest.update(chunk);
+            offset += chunk.len() as u32;
+        }
+        Ok(digest.finalize())
+    }
+
+    /// Poll the part at `addr` until it acknowledges again after a write
+    fn wait_write_cycle(&mut self, addr: u8) -> Result<(), Error> {
+        for _ in 0..WRITE_CYCLE_POLLS {
+            match self.i2c.write(addr, &[]) {
+                Ok(()) => return Ok(()),
+                Err(Error::Nack {
+                    source: NoAcknowledgeSource::Address | NoAcknowledgeSource::Unknown,
+                    ..
+                }) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Device address of block 0, with the block select bits cleared
+    fn base_address(&self) -> u8 {
+        self.device.addr & !(self.device.part.blocks() - 1)
+    }
+
+    /// Bytes one device address covers
+    fn block_size(&self) -> u32 {
+        1 << (8 * u32::from(self.device.part.address_bytes()))
+    }
+
+    /// Device address and big-endian memory address bytes for `address`;
+    /// only the first `address_bytes` of them are sent
+    fn memory_address(&self, address: u32) -> (u8, [u8; 2]) {
+        let block = (address / self.block_size()) as u8;
+        let bytes = match self.device.part.address_bytes() {
+            1 => [address as u8, 0],
+            _ => [(address >> 8) as u8, address as u8],
+        };
+        (self.base_address() | block, bytes)
+    }
+
+    fn check_range(&self, address: u32, len: usize) -> Result<(), Error> {
+        let end = u64::from(address) + len as u64;
+        if end > u64::from(self.capacity()) {
+            return Err(Error::InvalidConfig);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+//! Annotated I2C scans: telling parts that share an address apart
+//!
+//! Many sensors carry a fixed ID in a register (WHO_AM_I, CHIP_ID). A BME280
+//! and a BMP280 both answer at 0x76, but register 0xD0 reads 0x60 on one and
+//! 0x58 on the other. [`I2cMode::identify`] reads the ID registers of the
+//! parts in [`ID_PROBES`] that may sit at an address; the first match names
+//! the part.
+//!
+//! Probing writes a register pointer and reads a byte, which a device that
+//! is not the expected part may act on, so it is optional: without it the
+//! report only lists the addresses and the host falls back to the parts
+//! [`candidates`](esp32_bus_pirate_protocol::i2c_devices::candidates) knows
+//! at each.
+
+use crate::{i2c::{I2cFault, I2cMode, I2cScanOptions}, Error};
+use embedded_hal::i2c::I2c;
+use heapless::Vec;
+
+pub use esp32_bus_pirate_protocol::{I2cChip, I2cScanEntry, I2cScanReport};
+
+/// A part's ID register and the value it reads
+#[derive(Debug, Clone, Copy)]
+pub struct IdProbe {
+    /// Addresses the part can be strapped to
+    pub addrs: &'static [u8],
+    /// ID register
+    pub reg: u8,
+    /// Value the register reads on this part
+    pub id: u8,
+    /// Part the value identifies
+    pub chip: I2cChip,
+}
+
+const fn probe(addrs: &'static [u8], reg: u8, id: u8, chip: I2cChip) -> IdProbe {
+    IdProbe { addrs, reg, id, chip }
+}
+
+/// Known ID registers
+///
+/// At an address several parts share, the likelier part comes first: it is
+/// tried first, and a register another part lacks may read anything there.
+pub static ID_PROBES: &[IdProbe] = &[
+    // Bosch environmental sensors
+    probe(&[0x76, 0x77], 0xD0, 0x60, I2cChip::Bme280),
+    probe(&[0x76, 0x77], 0xD0, 0x58, I2cChip::Bmp280),
+    probe(&[0x76, 0x77], 0xD0, 0x61, I2cChip::Bme680),
+    probe(&[0x77], 0xD0, 0x55, I2cChip::Bmp180),
+    probe(&[0x76, 0x77], 0x00, 0x50, I2cChip::Bmp388),
+    probe(&[0x76, 0x77], 0x00, 0x60, I2cChip::Bmp390),
+    // IMUs
+    probe(&[0x68, 0x69], 0x75, 0x68, I2cChip::Mpu6050),
+    probe(&[0x68, 0x69], 0x75, 0x70, I2cChip::Mpu6500),
+    probe(&[0x68, 0x69], 0x75, 0x71, I2cChip::Mpu9250),
+    probe(&[0x68, 0x69], 0x75, 0x19, I2cChip::Mpu6886),
+    probe(&[0x68, 0x69], 0x00, 0x24, I2cChip::Bmi270),
+    probe(&[0x68, 0x69], 0x00, 0xEA, I2cChip::Icm20948),
+    probe(&[0x6A, 0x6B], 0x0F, 0x69, I2cChip::Lsm6ds3),
+    probe(&[0x6A, 0x6B], 0x0F, 0x6A, I2cChip::Lsm6dsl),
+    probe(&[0x6A, 0x6B], 0x0F, 0x6C, I2cChip::Lsm6dsox),
+    probe(&[0x6A, 0x6B], 0x0F, 0xD7, I2cChip::L3gd20h),
+    probe(&[0x28, 0x29], 0x00, 0xA0, I2cChip::Bno055),
+    // Accelerometers and magnetometers
+    probe(&[0x18, 0x19], 0x0F, 0x33, I2cChip::Lis3dh),
+    probe(&[0x1C, 0x1E], 0x0F, 0x3D, I2cChip::Lis3mdl),
+    probe(&[0x1E], 0x4F, 0x40, I2cChip::Lis2mdl),
+    probe(&[0x1D, 0x53], 0x00, 0xE5, I2cChip::Adxl345),
+    probe(&[0x1C, 0x1D], 0x0D, 0x1A, I2cChip::Mma8451),
+    probe(&[0x0E], 0x07, 0xC4, I2cChip::Mag3110),
+    // Light, distance and colour; the TCS34725 and APDS-9960 registers are
+    // addressed with the command bit set
+    probe(&[0x29], 0x92, 0x44, I2cChip::Tcs34725),
+    probe(&[0x29], 0xC0, 0xEE, I2cChip::Vl53l0x),
+    probe(&[0x39], 0x92, 0xAB, I2cChip::Apds9960),
+    // Pressure, humidity and temperature
+    probe(&[0x5C, 0x5D], 0x0F, 0xB1, I2cChip::Lps22hb),
+    probe(&[0x5C, 0x5D], 0x0F, 0xBD, I2cChip::Lps25hb),
+    probe(&[0x5F], 0x0F, 0xBC, I2cChip::Hts221),
+    probe(&[0x60], 0x0C, 0xC4, I2cChip::Mpl3115a2),
+    probe(&[0x40], 0xFF, 0x10, I2cChip::Hdc1080),
+    // 16-bit ID registers, of which the first byte is read
+    probe(&[0x18, 0x19, 0x1A, 0x1B, 0x1C, 0x1D, 0x1E, 0x1F], 0x07, 0x04, I2cChip::Mcp9808),
+    probe(&[0x40, 0x41, 0x44, 0x45], 0xFF, 0x22, I2cChip::Ina260),
+    // Air quality
+    probe(&[0x5A, 0x5B], 0x20, 0x81, I2cChip::Ccs811),
+];
+
+/// ID registers read at one address, so parts sharing one read it once
+const READS: usize = 4;
+
+impl<I: I2c<Error: I2cFault>> I2cMode<I> {
+    /// Read the ID registers of the parts that may sit at `addr` and return
+    /// the first that matches
+    ///
+    /// A register the device does not acknowledge just matches nothing;
+    /// other bus failures end the probe with their error.
+    pub fn identify(&mut self, addr: u8) -> Result<Option<I2cChip>, Error> {
+        let mut reads: Vec<(u8, Option<u8>), READS> = Vec::new();
+        for probe in ID_PROBES.iter().filter(|probe| probe.addrs.contains(&addr)) {
+            let value = match reads.iter().find(|(reg, _)| *reg == probe.reg) {
+                Some(&(_, value)) => value,
+                None => {
+                    let value = match self.read_register(addr, probe.reg) {
+                        Ok(value) => Some(value),
+                        Err(Error::Nack { .. }) => None,
+                        Err(e) => return Err(e),
+                    };
+                    // Past the cache size a register is read again, which is only slower
+                    reads.push((probe.reg, value)).ok();
+                    value
+                }
+            };
+            if value == Some(probe.id) {
+                return Ok(Some(probe.chip));
+            }
+        }
+        Ok(None)
+    }
+
+    /// [`scan_with`](I2cMode::scan_with) `options`, and with `identify` read
+    /// each device's ID register
+    pub fn scan_report(&mut self, options: &I2cScanOptions, identify: bool) -> Result<I2cScanReport, Error> {
+        let mut report = I2cScanReport { devices: Vec::new() };
+        for addr in self.scan_with(options)? {
+            let chip = if identify { self.identify(addr)? } else { None };
+            // The scan found at most as many devices as the report holds
+            report.devices.push(I2cScanEntry { addr, chip }).ok();
+        }
+        Ok(report)
+    }
+}
@@ -0,0 +1,239 @@
+//! Passive I2C sniffer
+//!
+//! The sniffer never drives the bus. Something samples SCL and SDA every
+//! time either line changes (GPIO edge interrupts, RMT capture, a logic
+//! analyser dump) and hands the levels to an [`I2cDecoder`], which turns
+//! them into START, STOP, address and data records:
+//!
+//! - SDA falling while SCL is high is a START, or a repeated START inside a
+//!   transaction; SDA rising while SCL is high is a STOP
+//! - Otherwise SDA is sampled on every rising SCL edge: eight data bits,
+//!   most significant first, then the acknowledge bit (low for ACK)
+//! - The first byte after a START is the address; `11110 A9 A8` followed by
+//!   a write of `A7..A0` is a 10-bit address, and a repeated START with
+//!   `11110 A9 A8` and the read bit addresses the same device for reading
+//!
+//! The decoder is a plain state machine, so it runs anywhere; [`I2cSniffer`]
+//! pairs it with an [`EdgeSource`] to implement [`Sniffer`].
+
+use crate::{traits::Sniffer, Error};
+use esp32_bus_pirate_protocol::message::{Mode, SniffRecord, SnifferEvent};
+
+/// Levels of both lines right after one of them changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    /// Microseconds since capture started
+    pub timestamp_us: u32,
+    /// SCL level
+    pub scl: bool,
+    /// SDA level
+    pub sda: bool,
+}
+
+/// Captures [`Edge`]s from the bus lines
+pub trait EdgeSource {
+    /// Start capturing; timestamps count from here
+    fn start(&mut self) -> Result<(), Error>;
+
+    /// Stop capturing
+    fn stop(&mut self) -> Result<(), Error>;
+
+    /// Oldest edge not read yet (non-blocking)
+    fn next_edge(&mut self) -> Option<Edge>;
+}
+
+/// What the next byte on the bus is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    /// No transaction; bits are ignored until a START
+    Idle,
+    /// First byte after a START
+    Address,
+    /// Second byte of a 10-bit address, after `11110 A9 A8` and write
+    TenBitLow { high: u16 },
+    /// Data bytes
+    Data,
+}
+
+/// Turns SCL/SDA edges into [`SnifferEvent`]s
+#[derive(Debug, Clone)]
+pub struct I2cDecoder {
+    scl: bool,
+    sda: bool,
+    phase: Phase,
+    /// Bits of the current byte so far, or 8 while waiting for its ACK bit
+    bits: u8,
+    value: u8,
+    byte_start_us: u32,
+    /// Last 10-bit address written to, which a read after a repeated START
+    /// with the same prefix refers to
+    ten_bit: Option<u16>,
+}
+
+impl Default for I2cDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl I2cDecoder {
+    /// Decoder for an idle bus, both lines high
+    pub fn new() -> Self {
+        Self {
+            scl: true,
+            sda: true,
+            phase: Phase::Idle,
+            bits: 0,
+            value: 0,
+            byte_start_us: 0,
+            ten_bit: None,
+        }
+    }
+
+    /// Feed the next edge; returns the record it completed, if any
+    pub fn feed(&mut self, edge: Edge) -> Option<SnifferEvent> {
+        let (scl_was, sda_was) = (self.scl, self.sda);
+        self.scl = edge.scl;
+        self.sda = edge.sda;
+
+        let record = if scl_was && edge.scl && sda_was != edge.sda {
+            self.condition(edge.sda)
+        } else if !scl_was && edge.scl {
+            self.clock(edge.sda, edge.timestamp_us)
+        } else {
+            None
+        };
+        let timestamp_us = match record {
+            Some(SniffRecord::Address { .. } | SniffRecord::Data { .. }) => self.byte_start_us,
+            _ => edge.timestamp_us,
+        };
+        record.map(|record| SnifferEvent {
+            mode: Mode::I2c,
+            timestamp_us,
+            record,
+        })
+    }
+
+    /// SDA changed while SCL was high: START, repeated START or STOP
+    fn condition(&mut self, sda: bool) -> Option<SniffRecord> {
+        let in_transaction = self.phase != Phase::Idle;
+        self.bits = 0;
+        self.value = 0;
+        if sda {
+            self.phase = Phase::Idle;
+            self.ten_bit = None;
+            in_transaction.then_some(SniffRecord::Stop)
+        } else {
+            self.phase = Phase::Address;
+            Some(if in_transaction { SniffRecord::RepeatedStart } else { SniffRecord::Start })
+        }
+    }
+
+    /// Rising SCL: one data bit, or the ACK bit that completes a byte
+    fn clock(&mut self, sda: bool, timestamp_us: u32) -> Option<SniffRecord> {
+        if self.phase == Phase::Idle {
+            return None;
+        }
+        if self.bits < 8 {
+            if self.bits == 0 {
+                self.byte_start_us = timestamp_us;
+            }
+            self.value = (self.value << 1) | u8::from(sda);
+            self.bits += 1;
+            return None;
+        }
+
+        let (value, ack) = (self.value, !sda);
+        self.bits = 0;
+        self.value = 0;
+        match self.phase {
+            Phase::Address if value & 0xF8 == 0xF0 => {
+                let high = u16::from((value >> 1) & 0x03);
+                if value & 1 == 0 {
+                    self.phase = Phase::TenBitLow { high };
+                    return None;
+                }
+                self.phase = Phase::Data;
+                let addr = self.ten_bit.filter(|addr| addr >> 8 == high).unwrap_or(u16::from(value >> 1));
+                Some(SniffRecord::Address { addr, read: true, ack })
+            }
+            Phase::Address => {
+                self.phase = Phase::Data;
+                Some(SniffRecord::Address {
+                    addr: u16::from(value >> 1),
+                    read: value & 1 == 1,
+                    ack,
+                })
+            }
+            Phase::TenBitLow { high } => {
+                let addr = high << 8 | u16::from(value);
+                self.phase = Phase::Data;
+                self.ten_bit = Some(addr);
+                Some(SniffRecord::Address { addr, read: false, ack })
+            }
+            Phase::Data => Some(SniffRecord::Data { value, ack }),
+            Phase::Idle => None,
+        }
+    }
+}
+
+/// I2C sniffer: an [`I2cDecoder`] fed by an [`EdgeSource`]
+pub struct I2cSniffer<E> {
+    edges: E,
+    decoder: I2cDecoder,
+    running: bool,
+}
+
+impl<E: EdgeSource> I2cSniffer<E> {
+    /// Sniffer capturing from `edges`, stopped
+    pub fn new(edges: E) -> Self {
+        Self {
+            edges,
+            decoder: I2cDecoder::new(),
+            running: false,
+        }
+    }
+
+    /// Whether the sniffer is capturing
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Stop and return the edge source
+    pub fn release(mut self) -> E {
+        if self.running {
+            let _ = self.edges.stop();
+        }
+        self.edges
+    }
+}
+
+impl<E: EdgeSource> Sniffer for I2cSniffer<E> {
+    type Event = SnifferEvent;
+
+    fn start_sniff(&mut self) -> Result<(), Error> {
+        // Capture may begin in the middle of a transfer; nothing is
+        // reported until the next START
+        self.decoder = I2cDecoder::new();
+        self.edges.start()?;
+        self.running = true;
+        Ok(())
+    }
+
+    fn stop_sniff(&mut self) -> Result<(), Error> {
+        self.running = false;
+        self.edges.stop()
+    }
+
+    fn read_event(&mut self) -> Result<Option<SnifferEvent>, Error> {
+        if !self.running {
+            return Ok(None);
+        }
+        while let Some(edge) = self.edges.next_edge() {
+            if let Some(event) = self.decoder.feed(edge) {
+                return Ok(Some(event));
+            }
+        }
+        Ok(None)
+    }
+}
@@ -0,0 +1,323 @@
+//! I2C target (slave) emulation
+//!
+//! The Bus Pirate answers at an address of its own and behaves like a
+//! register-based sensor, so a host MCU's driver can be tested against it.
+//! The device is an [`I2cRegisterMap`]; [`RegisterTarget`] runs it:
+//!
+//! - A write's first byte selects a register. Unknown registers are not
+//!   acknowledged
+//! - Further bytes of the write fill the selected register, most
+//!   significant byte first; each byte takes effect as it arrives. Bytes
+//!   for a read-only register are not acknowledged and change nothing
+//! - A read returns the selected register's bytes, most significant first.
+//!   Unknown registers read as 0xFF
+//! - Once all bytes of a register are transferred, an auto-incrementing map
+//!   moves on to the next register address; otherwise the same register
+//!   starts over
+//! - The selected register persists across transactions, so a write of the
+//!   register byte alone followed by a read works with or without a
+//!   repeated start
+//!
+//! [`RegisterTarget`] only sees bytes and bus conditions, so it runs on the
+//! host as well. [`I2cTargetMode`] pairs it with a [`TargetBus`], the I2C
+//! peripheral in target role, and reports every access as an
+//! [`I2cTargetAccess`].
+
+use crate::{traits::BusMode, Error};
+use esp32_bus_pirate_protocol::message::Event;
+use heapless::Vec;
+
+pub use esp32_bus_pirate_protocol::message::{
+    I2cRegister, I2cRegisterMap, I2cTargetAccess, RegisterAccess, I2C_TARGET_LOG_SIZE,
+};
+
+/// What the controller did, as seen by the peripheral in target role
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetEvent {
+    /// Addressed for a write or, with `read`, a read; also after a repeated
+    /// start
+    Start { read: bool },
+    /// The controller wrote a byte; answer with [`TargetBus::ack`]
+    Written(u8),
+    /// The controller clocks out a byte; answer with [`TargetBus::send`]
+    ReadRequest,
+    /// STOP
+    Stop,
+}
+
+/// An I2C peripheral in target role
+///
+/// The peripheral stretches the clock after a [`Written`](TargetEvent::Written)
+/// byte and on a [`ReadRequest`](TargetEvent::ReadRequest) until it is
+/// answered, so a target polled from the main loop keeps up with any
+/// controller that honours clock stretching.
+pub trait TargetBus {
+    /// Start answering at the 7-bit address `addr`
+    fn listen(&mut self, addr: u8) -> Result<(), Error>;
+
+    /// Stop answering and release the bus
+    fn release(&mut self) -> Result<(), Error>;
+
+    /// Next step of a transaction (non-blocking)
+    fn next_event(&mut self) -> Option<TargetEvent>;
+
+    /// Acknowledge the last written byte, or refuse it with `false`
+    fn ack(&mut self, ack: bool);
+
+    /// Send `byte` for the last read request
+    fn send(&mut self, byte: u8);
+}
+
+impl<B: TargetBus + ?Sized> TargetBus for &mut B {
+    fn listen(&mut self, addr: u8) -> Result<(), Error> {
+        (**self).listen(addr)
+    }
+
+    fn release(&mut self) -> Result<(), Error> {
+        (**self).release()
+    }
+
+    fn next_event(&mut self) -> Option<TargetEvent> {
+        (**self).next_event()
+    }
+
+    fn ack(&mut self, ack: bool) {
+        (**self).ack(ack)
+    }
+
+    fn send(&mut self, byte: u8) {
+        (**self).send(byte)
+    }
+}
+
+/// A register map answering controller accesses
+#[derive(Debug, Clone)]
+pub struct RegisterTarget {
+    map: I2cRegisterMap,
+    /// Selected register address
+    pointer: u8,
+    /// Byte of the selected register the next data byte is
+    byte: u8,
+    /// Access since the last START, if a transaction is open
+    access: Option<I2cTargetAccess>,
+    /// The open transaction is a write that has not selected a register yet
+    awaiting_register: bool,
+}
+
+impl RegisterTarget {
+    /// Run `map`, with the first register in it selected
+    ///
+    /// Fails with `InvalidConfig` for an address outside 0x08-0x77, a
+    /// register width outside 1-4, a value wider than its register, or two
+    /// registers at one address.
+    pub fn new(map: I2cRegisterMap) -> Result<Self, Error> {
+        if !(0x08..=0x77).contains(&map.addr) {
+            return Err(Error::InvalidConfig);
+        }
+        for (i, register) in map.registers.iter().enumerate() {
+            let fits = register.width >= 4 || register.value >> (8 * u32::from(register.width)) == 0;
+            if !(1..=4).contains(&register.width) || !fits {
+                return Err(Error::InvalidConfig);
+            }
+            if map.registers[..i].iter().any(|other| other.address == register.address) {
+                return Err(Error::InvalidConfig);
+            }
+        }
+
+        Ok(Self {
+            pointer: map.registers.first().map_or(0, |register| register.address),
+            map,
+            byte: 0,
+            access: None,
+            awaiting_register: false,
+        })
+    }
+
+    /// The map with its current values
+    pub fn map(&self) -> &I2cRegisterMap {
+        &self.map
+    }
+
+    /// Current value of the register at `address`
+    pub fn value(&self, address: u8) -> Option<u32> {
+        self.register(address).map(|register| register.value)
+    }
+
+    /// Address the controller selected last
+    pub fn selected(&self) -> u8 {
+        self.pointer
+    }
+
+    /// The controller addressed the target; returns the access a repeated
+    /// start ended
+    pub fn start(&mut self, read: bool) -> Option<I2cTargetAccess> {
+        let ended = self.access.take();
+        self.byte = 0;
+        self.awaiting_register = !read;
+        self.access = Some(I2cTargetAccess {
+            read,
+            reg: self.pointer,
+            data: Vec::new(),
+            len: 0,
+            nacked: false,
+        });
+        ended
+    }
+
+    /// The controller wrote `value`; returns whether it is acknowledged
+    pub fn write(&mut self, value: u8) -> bool {
+        if core::mem::take(&mut self.awaiting_register) {
+            let known = self.register(value).is_some();
+            if known {
+                self.pointer = value;
+                self.byte = 0;
+            }
+            if let Some(access) = &mut self.access {
+                access.reg = value;
+                access.nacked |= !known;
+            }
+            return known;
+        }
+
+        let (pointer, byte) = (self.pointer, self.byte);
+        let ack = match self.register_mut(pointer) {
+            Some(register) if register.access == RegisterAccess::ReadWrite => {
+                let shift = 8 * u32::from(register.width - 1 - byte);
+                register.value = register.value & !(0xFF << shift) | u32::from(value) << shift;
+                true
+            }
+            _ => false,
+        };
+        self.log(value, ack);
+        if ack {
+            self.advance();
+        }
+        ack
+    }
+
+    /// The controller reads a byte; returns it
+    pub fn read(&mut self) -> u8 {
+        let value = match self.register(self.pointer) {
+            Some(register) => (register.value >> (8 * u32::from(register.width - 1 - self.byte))) as u8,
+            None => 0xFF,
+        };
+        self.log(value, true);
+        self.advance();
+        value
+    }
+
+    /// STOP; returns the access it ended
+    pub fn stop(&mut self) -> Option<I2cTargetAccess> {
+        self.awaiting_register = false;
+        self.access.take()
+    }
+
+    /// Answer `bus` until an access ends or nothing is pending
+    pub fn service<B: TargetBus + ?Sized>(&mut self, bus: &mut B) -> Option<I2cTargetAccess> {
+        while let Some(event) = bus.next_event() {
+            let ended = match event {
+                TargetEvent::Start { read } => self.start(read),
+                TargetEvent::Written(value) => {
+                    let ack = self.write(value);
+                    bus.ack(ack);
+                    None
+                }
+                TargetEvent::ReadRequest => {
+                    let value = self.read();
+                    bus.send(value);
+                    None
+                }
+                TargetEvent::Stop => self.stop(),
+            };
+            if ended.is_some() {
+                return ended;
+            }
+        }
+        None
+    }
+
+    /// Move past the byte just transferred
+    fn advance(&mut self) {
+        let width = self.register(self.pointer).map_or(1, |register| register.width);
+        self.byte += 1;
+        if self.byte >= width {
+            self.byte = 0;
+            if self.map.auto_increment {
+                self.pointer = self.pointer.wrapping_add(1);
+            }
+        }
+    }
+
+    fn log(&mut self, value: u8, ack: bool) {
+        if let Some(access) = &mut self.access {
+            // Past the log size the bytes are only counted
+            access.data.push(value).ok();
+            access.len = access.len.saturating_add(1);
+            access.nacked |= !ack;
+        }
+    }
+
+    fn register(&self, address: u8) -> Option<&I2cRegister> {
+        self.map.registers.iter().find(|register| register.address == address)
+    }
+
+    fn register_mut(&mut self, address: u8) -> Option<&mut I2cRegister> {
+        self.map.registers.iter_mut().find(|register| register.address == address)
+    }
+}
+
+/// I2C target mode: a [`RegisterTarget`] on a [`TargetBus`]
+///
+/// [`init`](BusMode::init) loads the map and starts answering; call
+/// [`poll`](Self::poll) often to answer the controller.
+pub struct I2cTargetMode<B> {
+    bus: B,
+    target: Option<RegisterTarget>,
+}
+
+impl<B: TargetBus> I2cTargetMode<B> {
+    /// Target mode on `bus`, not answering yet
+    pub fn new(bus: B) -> Self {
+        Self { bus, target: None }
+    }
+
+    /// The running register map, if any
+    pub fn target(&self) -> Option<&RegisterTarget> {
+        self.target.as_ref()
+    }
+
+    /// Answer the controller; returns the next access that ended
+    pub fn poll(&mut self) -> Option<I2cTargetAccess> {
+        self.target.as_mut()?.service(&mut self.bus)
+    }
+
+    /// [`poll`](Self::poll), as a protocol event for subscribed hosts
+    pub fn poll_event(&mut self) -> Option<Event> {
+        self.poll().map(Event::I2cTarget)
+    }
+
+    /// Give the bus back
+    pub fn release(self) -> B {
+        self.bus
+    }
+}
+
+impl<B: TargetBus> BusMode for I2cTargetMode<B> {
+    type Config = I2cRegisterMap;
+
+    fn name(&self) -> &'static str {
+        "I2C target"
+    }
+
+    fn init(&mut self, config: Self::Config) -> Result<(), Error> {
+        let target = RegisterTarget::new(config)?;
+        self.bus.listen(target.map().addr)?;
+        self.target = Some(target);
+        Ok(())
+    }
+
+    fn deinit(&mut self) -> Result<(), Error> {
+        self.target = None;
+        self.bus.release()
+    }
+}
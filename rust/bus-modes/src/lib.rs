@@ -7,6 +7,10 @@
 
 pub mod traits;
 pub mod i2c;
+pub mod i2c_eeprom;
+pub mod i2c_identify;
+pub mod i2c_sniffer;
+pub mod i2c_target;
 pub mod spi;
 pub mod uart;
 // pub mod onewire;
@@ -16,6 +20,9 @@ pub mod uart;
 
 pub use traits::{BusMode, Scanner, Sniffer};
 
+use embedded_hal::i2c::NoAcknowledgeSource;
+use esp32_bus_pirate_protocol::ErrorCode;
+
 /// Common error type for bus operations
 #[derive(Debug)]
 pub enum Error {
@@ -29,4 +36,31 @@ pub enum Error {
     InvalidConfig,
     /// Bus already in use
     Busy,
+    /// Segment `segment` of a transaction was not acknowledged; `None` when
+    /// the bus could not tell which one
+    Nack {
+        segment: Option<usize>,
+        source: NoAcknowledgeSource,
+    },
+    /// Another controller won arbitration for the bus
+    ArbitrationLost,
+    /// The bus misbehaved: a misplaced START or STOP, or a line held low
+    BusFault,
+    /// The bus cannot do what was asked
+    Unsupported,
+}
+
+impl From<Error> for ErrorCode {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Nack { source: NoAcknowledgeSource::Address, .. } => ErrorCode::AddressNack,
+            Error::Nack { source: NoAcknowledgeSource::Data, .. } => ErrorCode::DataNack,
+            Error::ArbitrationLost => ErrorCode::ArbitrationLost,
+            Error::Communication | Error::NoDevice | Error::Nack { .. } | Error::BusFault => ErrorCode::BusError,
+            Error::Timeout => ErrorCode::Timeout,
+            Error::InvalidConfig => ErrorCode::InvalidParameter,
+            Error::Busy => ErrorCode::Busy,
+            Error::Unsupported => ErrorCode::Unsupported,
+        }
+    }
 }
@@ -1,12 +1,44 @@
 //! SPI bus mode implementation
 
 use crate::{traits::BusMode, Error};
-use embedded_hal::spi::SpiDevice;
+use embedded_hal::spi::{Operation, SpiDevice};
+
+/// SPI NOR flash commands shared by virtually every 25-series part
+pub mod flash {
+    /// Read the JEDEC manufacturer and device ID
+    pub const READ_ID: u8 = 0x9F;
+    /// Read data (24-bit address)
+    pub const READ: u8 = 0x03;
+    /// Set the write enable latch
+    pub const WRITE_ENABLE: u8 = 0x06;
+    /// Read status register 1
+    pub const READ_STATUS: u8 = 0x05;
+    /// Program up to one page (24-bit address)
+    pub const PAGE_PROGRAM: u8 = 0x02;
+    /// Erase one 4 KB sector (24-bit address)
+    pub const SECTOR_ERASE: u8 = 0x20;
+    
+    /// Status register: write or erase in progress
+    pub const STATUS_BUSY: u8 = 0x01;
+    /// Program page size in bytes
+    pub const PAGE_SIZE: u32 = 256;
+    /// Erase sector size in bytes
+    pub const SECTOR_SIZE: u32 = 4096;
+}
+
+/// Status polls before a flash write or erase is considered hung
+const FLASH_BUSY_POLLS: u32 = 1_000_000;
+
+/// Holds chip select asserted across transactions (`true`) or hands it
+/// back to the device (`false`)
+pub type ChipSelect<S> = fn(&mut S, bool) -> Result<(), Error>;
 
 /// SPI bus mode
 pub struct SpiMode<S> {
     spi: S,
     config: Option<SpiConfig>,
+    chip_select: Option<ChipSelect<S>>,
+    selected: bool,
 }
 
 /// SPI configuration
@@ -18,7 +50,27 @@ pub struct SpiConfig {
 impl<S: SpiDevice> SpiMode<S> {
     /// Create a new SPI mode instance
     pub fn new(spi: S) -> Self {
-        Self { spi, config: None }
+        Self { spi, config: None, chip_select: None, selected: false }
+    }
+    
+    /// Let the mode hold chip select low between transactions with
+    /// `chip_select`, see [`select`](Self::select)
+    pub fn with_chip_select(mut self, chip_select: ChipSelect<S>) -> Self {
+        self.chip_select = Some(chip_select);
+        self
+    }
+    
+    /// Hold chip select asserted until `select(false)`, so several transfers
+    /// reach the device as one command
+    ///
+    /// Without a [`ChipSelect`] routine the device pulses chip select for
+    /// every transfer and this fails with [`Error::InvalidConfig`]. The mode
+    /// lets go of chip select when it is deinitialised.
+    pub fn select(&mut self, asserted: bool) -> Result<(), Error> {
+        let chip_select = self.chip_select.ok_or(Error::InvalidConfig)?;
+        chip_select(&mut self.spi, asserted)?;
+        self.selected = asserted;
+        Ok(())
     }
     
     /// Transfer data (full duplex)
@@ -28,12 +80,90 @@ impl<S: SpiDevice> SpiMode<S> {
             .map_err(|_| Error::Communication)
     }
     
+    /// Run `operations` with chip select held for all of them
+    pub fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Error> {
+        self.spi
+            .transaction(operations)
+            .map_err(|_| Error::Communication)
+    }
+    
     /// Read Flash ID (common SPI Flash command)
     pub fn read_flash_id(&mut self) -> Result<[u8; 3], Error> {
-        let mut cmd = [0x9F, 0x00, 0x00, 0x00];
+        let mut cmd = [flash::READ_ID, 0x00, 0x00, 0x00];
         self.transfer(&mut cmd)?;
         Ok([cmd[1], cmd[2], cmd[3]])
     }
+    
+    /// Flash size in bytes, from the capacity byte of the JEDEC ID
+    pub fn flash_capacity(&mut self) -> Result<u32, Error> {
+        let [_, _, capacity] = self.read_flash_id()?;
+        match capacity {
+            // 2^10 (1 KB) to 2^24 (16 MB) is all a 24-bit address can reach
+            10..=24 => Ok(1 << capacity),
+            _ => Err(Error::NoDevice),
+        }
+    }
+    
+    /// Read flash contents starting at `address`
+    pub fn read_flash(&mut self, address: u32, buffer: &mut [u8]) -> Result<(), Error> {
+        let cmd = flash_command(flash::READ, address);
+        self.spi
+            .transaction(&mut [Operation::Write(&cmd), Operation::Read(buffer)])
+            .map_err(|_| Error::Communication)
+    }
+    
+    /// Erase the 4 KB sector containing `address`
+    pub fn erase_flash_sector(&mut self, address: u32) -> Result<(), Error> {
+        self.write_enable()?;
+        self.spi
+            .write(&flash_command(flash::SECTOR_ERASE, address))
+            .map_err(|_| Error::Communication)?;
+        self.wait_flash_ready()
+    }
+    
+    /// Program `data` at `address`, split on page boundaries
+    ///
+    /// The target range must have been erased.
+    pub fn program_flash(&mut self, mut address: u32, mut data: &[u8]) -> Result<(), Error> {
+        while !data.is_empty() {
+            let room = (flash::PAGE_SIZE - address % flash::PAGE_SIZE) as usize;
+            let (page, rest) = data.split_at(room.min(data.len()));
+            
+            self.write_enable()?;
+            let cmd = flash_command(flash::PAGE_PROGRAM, address);
+            self.spi
+                .transaction(&mut [Operation::Write(&cmd), Operation::Write(page)])
+                .map_err(|_| Error::Communication)?;
+            self.wait_flash_ready()?;
+            
+            address += page.len() as u32;
+            data = rest;
+        }
+        Ok(())
+    }
+    
+    fn write_enable(&mut self) -> Result<(), Error> {
+        self.spi
+            .write(&[flash::WRITE_ENABLE])
+            .map_err(|_| Error::Communication)
+    }
+    
+    fn wait_flash_ready(&mut self) -> Result<(), Error> {
+        for _ in 0..FLASH_BUSY_POLLS {
+            let mut status = [flash::READ_STATUS, 0];
+            self.transfer(&mut status)?;
+            if status[1] & flash::STATUS_BUSY == 0 {
+                return Ok(());
+            }
+        }
+        Err(Error::Timeout)
+    }
+}
+
+/// Command byte followed by a 24-bit big-endian address
+fn flash_command(cmd: u8, address: u32) -> [u8; 4] {
+    let [_, a2, a1, a0] = address.to_be_bytes();
+    [cmd, a2, a1, a0]
 }
 
 impl<S: SpiDevice> BusMode for SpiMode<S> {
@@ -50,6 +180,9 @@ impl<S: SpiDevice> BusMode for SpiMode<S> {
     
     fn deinit(&mut self) -> Result<(), Error> {
         self.config = None;
+        if self.selected {
+            self.select(false)?;
+        }
         Ok(())
     }
 }
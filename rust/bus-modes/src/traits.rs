@@ -1,6 +1,7 @@
 //! Common traits for bus modes
 
 use crate::Error;
+use esp32_bus_pirate_protocol::message::{Event, SnifferEvent};
 use heapless::Vec;
 
 /// Common interface for all bus modes
@@ -40,4 +41,12 @@ pub trait Sniffer {
     
     /// Read a captured event (non-blocking)
     fn read_event(&mut self) -> Result<Option<Self::Event>, Error>;
+    
+    /// Read a captured event as a protocol event for subscribed hosts
+    fn poll_event(&mut self) -> Result<Option<Event>, Error>
+    where
+        Self::Event: Into<SnifferEvent>,
+    {
+        Ok(self.read_event()?.map(|event| Event::Sniffer(event.into())))
+    }
 }
@@ -1,6 +1,12 @@
 //! UART bus mode implementation
 
 use crate::{traits::BusMode, Error};
+use embedded_io::{Read, ReadReady, Write};
+use esp32_bus_pirate_protocol::message::Event;
+use heapless::Vec;
+
+/// Largest number of bytes carried by one `UartRx` event
+pub const RX_EVENT_SIZE: usize = 256;
 
 /// UART bus mode
 pub struct UartMode<U> {
@@ -28,7 +34,11 @@ impl<U> BusMode for UartMode<U> {
         "UART"
     }
     
+    /// Apply `config`, keeping the previous one if it is invalid
     fn init(&mut self, config: Self::Config) -> Result<(), Error> {
+        if config.baudrate == 0 {
+            return Err(Error::InvalidConfig);
+        }
         self.config = Some(config);
         Ok(())
     }
@@ -39,5 +49,35 @@ impl<U> BusMode for UartMode<U> {
     }
 }
 
-// Note: Full UART implementation requires embedded-io traits
-// which we'll add when we implement the firmware
+impl<U: Write> UartMode<U> {
+    /// Write all bytes to the UART
+    pub fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.uart.write_all(data).map_err(|_| Error::Communication)
+    }
+}
+
+impl<U: Read + ReadReady> UartMode<U> {
+    /// Read whatever bytes are pending without blocking
+    ///
+    /// Returns 0 when nothing has been received.
+    pub fn read_available(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        if buffer.is_empty() || !self.uart.read_ready().map_err(|_| Error::Communication)? {
+            return Ok(0);
+        }
+        self.uart.read(buffer).map_err(|_| Error::Communication)
+    }
+    
+    /// Collect pending receive data as a `UartRx` event for subscribed hosts
+    pub fn poll_event(&mut self) -> Result<Option<Event>, Error> {
+        let mut buf = [0u8; RX_EVENT_SIZE];
+        let n = self.read_available(&mut buf)?;
+        if n == 0 {
+            return Ok(None);
+        }
+        
+        let mut data = Vec::new();
+        // `n` never exceeds the capacity
+        data.extend_from_slice(&buf[..n]).ok();
+        Ok(Some(Event::UartRx { data }))
+    }
+}
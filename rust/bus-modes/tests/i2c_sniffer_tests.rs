@@ -0,0 +1,276 @@
+//! I2C sniffer decoder tests, fed with synthetic SCL/SDA edge timelines
+
+use esp32_bus_pirate_bus_modes::i2c_sniffer::{Edge, EdgeSource, I2cDecoder, I2cSniffer};
+use esp32_bus_pirate_bus_modes::{Error, Sniffer};
+use esp32_bus_pirate_protocol::message::{Event, Mode, SniffRecord, SnifferEvent};
+use std::collections::VecDeque;
+
+/// Builds the edges a controller produces, 5 µs per line change; only
+/// actual level changes are recorded, as an edge capture would see them
+struct Timeline {
+    now: u32,
+    scl: bool,
+    sda: bool,
+    edges: Vec<Edge>,
+}
+
+impl Timeline {
+    fn new() -> Self {
+        Self { now: 0, scl: true, sda: true, edges: Vec::new() }
+    }
+
+    fn set(&mut self, scl: bool, sda: bool) -> &mut Self {
+        self.now += 5;
+        if (scl, sda) != (self.scl, self.sda) {
+            self.scl = scl;
+            self.sda = sda;
+            self.edges.push(Edge { timestamp_us: self.now, scl, sda });
+        }
+        self
+    }
+
+    /// START, or repeated START after a byte (SCL is low then)
+    fn start(&mut self) -> &mut Self {
+        let scl = self.scl;
+        self.set(scl, true).set(true, true).set(true, false).set(false, false)
+    }
+
+    fn stop(&mut self) -> &mut Self {
+        self.set(false, false).set(true, false).set(true, true)
+    }
+
+    fn bit(&mut self, level: bool) -> &mut Self {
+        self.set(false, level).set(true, level).set(false, level)
+    }
+
+    fn byte(&mut self, value: u8, ack: bool) -> &mut Self {
+        for i in (0..8).rev() {
+            self.bit(value >> i & 1 == 1);
+        }
+        self.bit(!ack)
+    }
+
+    fn decode(&self) -> Vec<SniffRecord> {
+        let mut decoder = I2cDecoder::new();
+        self.edges.iter().filter_map(|edge| decoder.feed(*edge)).map(|event| event.record).collect()
+    }
+}
+
+/// Edge source replaying a timeline
+#[derive(Default)]
+struct Replay {
+    edges: VecDeque<Edge>,
+    running: bool,
+}
+
+impl EdgeSource for Replay {
+    fn start(&mut self) -> Result<(), Error> {
+        self.running = true;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), Error> {
+        self.running = false;
+        Ok(())
+    }
+
+    fn next_edge(&mut self) -> Option<Edge> {
+        self.edges.pop_front()
+    }
+}
+
+fn address(addr: u16, read: bool, ack: bool) -> SniffRecord {
+    SniffRecord::Address { addr, read, ack }
+}
+
+fn data(value: u8, ack: bool) -> SniffRecord {
+    SniffRecord::Data { value, ack }
+}
+
+// ===== Decoding =====
+
+#[test]
+fn test_decode_write() {
+    let records = Timeline::new().start().byte(0x50 << 1, true).byte(0x00, true).byte(0xA5, true).stop().decode();
+
+    assert_eq!(
+        records,
+        vec![SniffRecord::Start, address(0x50, false, true), data(0x00, true), data(0xA5, true), SniffRecord::Stop]
+    );
+}
+
+#[test]
+fn test_decode_register_read() {
+    let records = Timeline::new()
+        .start()
+        .byte(0x68 << 1, true)
+        .byte(0x75, true)
+        .start()
+        .byte(0x68 << 1 | 1, true)
+        .byte(0x71, true)
+        .byte(0x00, false)
+        .stop()
+        .decode();
+
+    assert_eq!(
+        records,
+        vec![
+            SniffRecord::Start,
+            address(0x68, false, true),
+            data(0x75, true),
+            SniffRecord::RepeatedStart,
+            address(0x68, true, true),
+            data(0x71, true),
+            data(0x00, false),
+            SniffRecord::Stop,
+        ]
+    );
+}
+
+#[test]
+fn test_decode_address_nack() {
+    let records = Timeline::new().start().byte(0x42 << 1 | 1, false).stop().decode();
+
+    assert_eq!(records, vec![SniffRecord::Start, address(0x42, true, false), SniffRecord::Stop]);
+}
+
+#[test]
+fn test_decode_ten_bit_addresses() {
+    // Write to 0x2A5: 11110 10 0, then 0xA5
+    let records = Timeline::new().start().byte(0xF4, true).byte(0xA5, true).byte(0x01, true).stop().decode();
+    assert_eq!(records, vec![SniffRecord::Start, address(0x2A5, false, true), data(0x01, true), SniffRecord::Stop]);
+
+    // Read from 0x2A5: the write of the low byte, then the prefix with the
+    // read bit after a repeated START
+    let records = Timeline::new()
+        .start()
+        .byte(0xF4, true)
+        .byte(0xA5, true)
+        .start()
+        .byte(0xF5, true)
+        .byte(0x3C, false)
+        .stop()
+        .decode();
+    assert_eq!(
+        records,
+        vec![
+            SniffRecord::Start,
+            address(0x2A5, false, true),
+            SniffRecord::RepeatedStart,
+            address(0x2A5, true, true),
+            data(0x3C, false),
+            SniffRecord::Stop,
+        ]
+    );
+}
+
+#[test]
+fn test_ten_bit_read_without_preceding_write() {
+    // Nothing says which low byte was meant, so the prefix is reported as is
+    let records = Timeline::new().start().byte(0xF5, false).stop().decode();
+
+    assert_eq!(records, vec![SniffRecord::Start, address(0x7A, true, false), SniffRecord::Stop]);
+}
+
+#[test]
+fn test_capture_starting_mid_transfer() {
+    let mut timeline = Timeline::new();
+    timeline.start().byte(0x50 << 1, true).byte(0x10, true).stop().start().byte(0x51 << 1 | 1, true).stop();
+
+    // Drop the first START and the first few bits
+    let edges = &timeline.edges[10..];
+    let mut decoder = I2cDecoder::new();
+    let records: Vec<_> = edges.iter().filter_map(|edge| decoder.feed(*edge)).map(|event| event.record).collect();
+
+    assert_eq!(records, vec![SniffRecord::Start, address(0x51, true, true), SniffRecord::Stop]);
+}
+
+#[test]
+fn test_stop_outside_transaction_is_ignored() {
+    let records = Timeline::new().set(false, true).set(false, false).stop().decode();
+
+    assert!(records.is_empty());
+}
+
+#[test]
+fn test_simultaneous_edges_are_not_conditions() {
+    // SDA changing in the same sample as SCL is data setup, not START/STOP
+    let mut timeline = Timeline::new();
+    timeline.start().byte(0x50 << 1, true);
+    timeline.set(true, true).set(false, false).set(true, false).stop();
+
+    let records = timeline.decode();
+    assert_eq!(records, vec![SniffRecord::Start, address(0x50, false, true), SniffRecord::Stop]);
+}
+
+#[test]
+fn test_event_timestamps() {
+    let mut timeline = Timeline::new();
+    timeline.start().byte(0x50 << 1, true).stop();
+
+    let mut decoder = I2cDecoder::new();
+    let events: Vec<SnifferEvent> = timeline.edges.iter().filter_map(|edge| decoder.feed(*edge)).collect();
+
+    assert!(events.iter().all(|event| event.mode == Mode::I2c));
+    // START at the SDA fall, the address byte from its first rising SCL,
+    // STOP at the SDA rise
+    assert_eq!(events[0].timestamp_us, 15);
+    assert_eq!(events[1].timestamp_us, 30);
+    assert_eq!(events[2].timestamp_us, 170);
+}
+
+// ===== Sniffer =====
+
+#[test]
+fn test_sniffer_reads_events() {
+    let mut timeline = Timeline::new();
+    timeline.start().byte(0x50 << 1, true).byte(0x42, true).stop();
+    let mut sniffer = I2cSniffer::new(Replay { edges: timeline.edges.into(), running: false });
+
+    // Nothing is read before starting
+    assert_eq!(sniffer.read_event().unwrap(), None);
+
+    sniffer.start_sniff().unwrap();
+    assert!(sniffer.is_running());
+    let mut records = Vec::new();
+    while let Some(event) = sniffer.read_event().unwrap() {
+        records.push(event.record);
+    }
+    assert_eq!(records, vec![SniffRecord::Start, address(0x50, false, true), data(0x42, true), SniffRecord::Stop]);
+
+    sniffer.stop_sniff().unwrap();
+    assert!(!sniffer.release().running);
+}
+
+#[test]
+fn test_sniffer_poll_event() {
+    let mut timeline = Timeline::new();
+    timeline.start();
+    let mut sniffer = I2cSniffer::new(Replay { edges: timeline.edges.into(), running: false });
+    sniffer.start_sniff().unwrap();
+
+    let event = sniffer.poll_event().unwrap();
+    assert!(matches!(event, Some(Event::Sniffer(SnifferEvent { record: SniffRecord::Start, .. }))));
+    assert_eq!(sniffer.poll_event().unwrap(), None);
+}
+
+#[test]
+fn test_restart_resets_decoder() {
+    let mut timeline = Timeline::new();
+    timeline.start().bit(true).bit(false).stop();
+    let mut replay = Replay { edges: timeline.edges.into(), running: false };
+    replay.edges.extend(Timeline::new().start().byte(0x21 << 1, true).edges.iter().copied());
+    let mut sniffer = I2cSniffer::new(replay);
+
+    sniffer.start_sniff().unwrap();
+    assert_eq!(sniffer.read_event().unwrap().map(|event| event.record), Some(SniffRecord::Start));
+    sniffer.stop_sniff().unwrap();
+
+    // The rest of the interrupted transfer is not reported, not even its STOP
+    sniffer.start_sniff().unwrap();
+    let mut records = Vec::new();
+    while let Some(event) = sniffer.read_event().unwrap() {
+        records.push(event.record);
+    }
+    assert_eq!(records, vec![SniffRecord::Start, address(0x21, false, true)]);
+}
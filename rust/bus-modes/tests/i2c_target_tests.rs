@@ -0,0 +1,237 @@
+//! I2C target emulation tests: the register map engine answering scripted
+//! controller transactions
+
+use esp32_bus_pirate_bus_modes::i2c_target::{
+    I2cRegister, I2cRegisterMap, I2cTargetAccess, I2cTargetMode, RegisterAccess, RegisterTarget, TargetBus, TargetEvent,
+};
+use esp32_bus_pirate_bus_modes::{BusMode, Error};
+use esp32_bus_pirate_protocol::message::Event;
+use std::collections::VecDeque;
+
+fn register(address: u8, width: u8, access: RegisterAccess, value: u32) -> I2cRegister {
+    I2cRegister { address, width, access, value }
+}
+
+/// A temperature sensor: a read-only chip ID, a 16-bit reading and an
+/// 8-bit configuration register
+fn sensor(auto_increment: bool) -> I2cRegisterMap {
+    I2cRegisterMap {
+        addr: 0x48,
+        auto_increment,
+        registers: [
+            register(0x00, 1, RegisterAccess::ReadOnly, 0xA1),
+            register(0x01, 2, RegisterAccess::ReadOnly, 0x1234),
+            register(0x02, 1, RegisterAccess::ReadWrite, 0x00),
+        ]
+        .into_iter()
+        .collect(),
+    }
+}
+
+/// Controller side of the bus: queues transactions and records what the
+/// target answered
+#[derive(Default)]
+struct Controller {
+    listening: Option<u8>,
+    pending: VecDeque<TargetEvent>,
+    acks: Vec<bool>,
+    received: Vec<u8>,
+}
+
+impl Controller {
+    fn write(&mut self, data: &[u8]) -> &mut Self {
+        self.pending.push_back(TargetEvent::Start { read: false });
+        self.pending.extend(data.iter().map(|&b| TargetEvent::Written(b)));
+        self
+    }
+
+    fn read(&mut self, len: usize) -> &mut Self {
+        self.pending.push_back(TargetEvent::Start { read: true });
+        self.pending.extend((0..len).map(|_| TargetEvent::ReadRequest));
+        self
+    }
+
+    fn stop(&mut self) -> &mut Self {
+        self.pending.push_back(TargetEvent::Stop);
+        self
+    }
+}
+
+impl TargetBus for Controller {
+    fn listen(&mut self, addr: u8) -> Result<(), Error> {
+        self.listening = Some(addr);
+        Ok(())
+    }
+
+    fn release(&mut self) -> Result<(), Error> {
+        self.listening = None;
+        Ok(())
+    }
+
+    fn next_event(&mut self) -> Option<TargetEvent> {
+        self.pending.pop_front()
+    }
+
+    fn ack(&mut self, ack: bool) {
+        self.acks.push(ack);
+    }
+
+    fn send(&mut self, byte: u8) {
+        self.received.push(byte);
+    }
+}
+
+fn run(target: &mut RegisterTarget, bus: &mut Controller) -> Vec<I2cTargetAccess> {
+    std::iter::from_fn(|| target.service(bus)).collect()
+}
+
+fn access(read: bool, reg: u8, data: &[u8], nacked: bool) -> I2cTargetAccess {
+    I2cTargetAccess {
+        read,
+        reg,
+        data: data.iter().copied().collect(),
+        len: data.len() as u16,
+        nacked,
+    }
+}
+
+#[test]
+fn test_register_read() {
+    let mut target = RegisterTarget::new(sensor(true)).unwrap();
+    let mut bus = Controller::default();
+    bus.write(&[0x01]).read(2).stop();
+
+    let accesses = run(&mut target, &mut bus);
+    assert_eq!(bus.received, [0x12, 0x34]);
+    assert_eq!(bus.acks, [true]);
+    assert_eq!(accesses, [access(false, 0x01, &[], false), access(true, 0x01, &[0x12, 0x34], false)]);
+}
+
+#[test]
+fn test_pointer_survives_stop() {
+    let mut target = RegisterTarget::new(sensor(false)).unwrap();
+    let mut bus = Controller::default();
+    bus.write(&[0x01]).stop().read(2).stop();
+
+    run(&mut target, &mut bus);
+    assert_eq!(bus.received, [0x12, 0x34]);
+    assert_eq!(target.selected(), 0x01);
+}
+
+#[test]
+fn test_auto_increment() {
+    let mut target = RegisterTarget::new(sensor(true)).unwrap();
+    let mut bus = Controller::default();
+    // Past the last register, unknown addresses read as 0xFF
+    bus.write(&[0x00]).read(5).stop();
+    run(&mut target, &mut bus);
+    assert_eq!(bus.received, [0xA1, 0x12, 0x34, 0x00, 0xFF]);
+}
+
+#[test]
+fn test_without_auto_increment_register_repeats() {
+    let mut target = RegisterTarget::new(sensor(false)).unwrap();
+    let mut bus = Controller::default();
+    bus.write(&[0x01]).read(5).stop();
+    run(&mut target, &mut bus);
+    assert_eq!(bus.received, [0x12, 0x34, 0x12, 0x34, 0x12]);
+}
+
+#[test]
+fn test_register_write() {
+    let mut target = RegisterTarget::new(sensor(true)).unwrap();
+    let mut bus = Controller::default();
+    bus.write(&[0x02, 0x5A]).stop();
+
+    let accesses = run(&mut target, &mut bus);
+    assert_eq!(bus.acks, [true, true]);
+    assert_eq!(target.value(0x02), Some(0x5A));
+    assert_eq!(accesses, [access(false, 0x02, &[0x5A], false)]);
+}
+
+#[test]
+fn test_multi_byte_write_is_big_endian() {
+    let mut map = sensor(false);
+    map.registers.push(register(0x10, 3, RegisterAccess::ReadWrite, 0)).unwrap();
+    let mut target = RegisterTarget::new(map).unwrap();
+    let mut bus = Controller::default();
+    bus.write(&[0x10, 0xAB, 0xCD]).stop();
+
+    run(&mut target, &mut bus);
+    // Bytes take effect as they arrive; the last one was never sent
+    assert_eq!(target.value(0x10), Some(0xABCD00));
+}
+
+#[test]
+fn test_read_only_and_unknown_registers_are_refused() {
+    let mut target = RegisterTarget::new(sensor(true)).unwrap();
+    let mut bus = Controller::default();
+    bus.write(&[0x00, 0x55]).stop().write(&[0x7F]).stop();
+
+    let accesses = run(&mut target, &mut bus);
+    assert_eq!(bus.acks, [true, false, false]);
+    assert_eq!(target.value(0x00), Some(0xA1));
+    assert_eq!(target.selected(), 0x00);
+    assert_eq!(accesses, [access(false, 0x00, &[0x55], true), access(false, 0x7F, &[], true)]);
+}
+
+#[test]
+fn test_repeated_start_ends_an_access() {
+    let mut target = RegisterTarget::new(sensor(true)).unwrap();
+    let mut bus = Controller::default();
+    bus.write(&[0x00]).read(1);
+
+    // The read is still open until the STOP
+    assert_eq!(run(&mut target, &mut bus), [access(false, 0x00, &[], false)]);
+    bus.stop();
+    assert_eq!(run(&mut target, &mut bus), [access(true, 0x00, &[0xA1], false)]);
+}
+
+#[test]
+fn test_long_access_is_truncated() {
+    let mut target = RegisterTarget::new(sensor(false)).unwrap();
+    let mut bus = Controller::default();
+    bus.write(&[0x01]).stop().read(100).stop();
+
+    let accesses = run(&mut target, &mut bus);
+    assert_eq!(accesses[1].len, 100);
+    assert_eq!(accesses[1].data.len(), 32);
+}
+
+#[test]
+fn test_invalid_maps_are_refused() {
+    let mut map = sensor(true);
+    map.addr = 0x03;
+    assert!(matches!(RegisterTarget::new(map), Err(Error::InvalidConfig)));
+
+    let mut map = sensor(true);
+    map.registers.push(register(0x01, 1, RegisterAccess::ReadWrite, 0)).unwrap();
+    assert!(matches!(RegisterTarget::new(map), Err(Error::InvalidConfig)));
+
+    let mut map = sensor(true);
+    map.registers[0].value = 0x100;
+    assert!(matches!(RegisterTarget::new(map), Err(Error::InvalidConfig)));
+
+    let mut map = sensor(true);
+    map.registers[0].width = 5;
+    assert!(matches!(RegisterTarget::new(map), Err(Error::InvalidConfig)));
+}
+
+#[test]
+fn test_target_mode() {
+    let mut bus = Controller::default();
+    bus.write(&[0x02, 0x01]).stop();
+    let mut mode = I2cTargetMode::new(bus);
+
+    // Nothing is answered before a map is loaded
+    assert_eq!(mode.poll(), None);
+
+    mode.init(sensor(true)).unwrap();
+    let event = mode.poll_event().unwrap();
+    assert_eq!(event, Event::I2cTarget(access(false, 0x02, &[0x01], false)));
+    assert_eq!(mode.target().and_then(|target| target.value(0x02)), Some(0x01));
+
+    mode.deinit().unwrap();
+    assert!(mode.target().is_none());
+    assert_eq!(mode.release().listening, None);
+}
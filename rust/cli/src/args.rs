@@ -0,0 +1,550 @@
+//! Command-line definitions and argument parsers
+
+use crate::output::DataFormat;
+use clap::{Args, Parser, Subcommand};
+use esp32_bus_pirate_client::DEFAULT_RETRIES;
+use esp32_bus_pirate_protocol::{EepromPart, I2cOp, I2cScanProbe, Mode, Topic, I2C_WRITE_SIZE};
+use std::path::PathBuf;
+
+/// Control an ESP32 Bus Pirate from the command line
+#[derive(Debug, Parser)]
+#[command(name = "bpctl", version)]
+pub struct Cli {
+    /// Serial device, TCP `host:port`, or Unix socket (`unix:PATH`)
+    #[arg(short, long, global = true, default_value = "/dev/ttyACM0")]
+    pub port: String,
+
+    /// Baud rate for serial devices
+    #[arg(short, long, global = true, default_value_t = 115_200)]
+    pub baud: u32,
+
+    /// Print results as JSON, one object per line
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    /// How binary data is shown when not printing JSON
+    #[arg(short, long, global = true, value_enum, default_value_t = DataFormat::Hexdump)]
+    pub format: DataFormat,
+
+    /// Reply timeout in milliseconds
+    #[arg(long, global = true, default_value_t = 2000)]
+    pub timeout: u64,
+
+    /// Times a command is re-sent after a timeout
+    #[arg(long, global = true, default_value_t = DEFAULT_RETRIES)]
+    pub retries: u32,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Show device information from the protocol handshake
+    Info,
+    /// Get or set the bus mode
+    #[command(subcommand)]
+    Mode(ModeCommand),
+    /// I2C operations
+    #[command(subcommand)]
+    I2c(I2cCommand),
+    /// SPI operations
+    #[command(subcommand)]
+    Spi(SpiCommand),
+    /// UART operations
+    #[command(subcommand)]
+    Uart(UartCommand),
+    /// Device configuration
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Files on the device
+    #[command(subcommand)]
+    File(FileCommand),
+    /// SPI flash dumps and writes (bulk transfer)
+    #[command(subcommand)]
+    Flash(FlashCommand),
+    /// 24Cxx I2C EEPROMs
+    Eeprom(EepromArgs),
+    /// Subscribe to events and print them as they arrive
+    Events(EventsArgs),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ModeCommand {
+    /// Show the current mode
+    Get,
+    /// Switch to a mode (hiz, i2c, spi, uart, ...)
+    Set {
+        #[arg(value_parser = parse_mode)]
+        mode: Mode,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum I2cCommand {
+    /// Scan the bus for devices
+    Scan {
+        /// Scan 10-bit addresses instead of 7-bit ones
+        #[arg(long)]
+        ten_bit: bool,
+        /// List the parts known to use each address found
+        #[arg(long, conflicts_with = "ten_bit")]
+        annotate: bool,
+        /// Read ID registers to name the part at each address (implies
+        /// --annotate)
+        #[arg(long, conflicts_with = "ten_bit")]
+        identify: bool,
+        /// How to probe each address: write (zero-byte write), read
+        /// (one-byte read) or quick (SMBus quick read)
+        #[arg(long, default_value = "write", value_parser = parse_scan_probe, conflicts_with = "ten_bit")]
+        probe: I2cScanProbe,
+        /// Also probe the reserved addresses 0x00-0x07 and 0x78-0x7F
+        #[arg(long, conflicts_with = "ten_bit")]
+        reserved: bool,
+        /// Scan at 100 kHz, 400 kHz and 1 MHz and list the speeds each device
+        /// answers at
+        #[arg(long, conflicts_with_all = ["ten_bit", "annotate", "identify"])]
+        sweep: bool,
+    },
+    /// Write bytes to a device
+    Write {
+        /// 7-bit address (hex)
+        #[arg(value_parser = parse_hex_u8)]
+        addr: u8,
+        /// Bytes to write (hex)
+        #[arg(required = true, value_parser = parse_hex_bytes)]
+        bytes: Vec<HexBytes>,
+    },
+    /// Read bytes from a device
+    Read {
+        /// 7-bit address (hex)
+        #[arg(value_parser = parse_hex_u8)]
+        addr: u8,
+        /// Number of bytes
+        #[arg(value_parser = parse_int::<u8>)]
+        len: u8,
+    },
+    /// Read one register
+    RegRead {
+        /// 7-bit address (hex)
+        #[arg(value_parser = parse_hex_u8)]
+        addr: u8,
+        /// Register (hex)
+        #[arg(value_parser = parse_hex_u8)]
+        reg: u8,
+    },
+    /// Write one register
+    RegWrite {
+        /// 7-bit address (hex)
+        #[arg(value_parser = parse_hex_u8)]
+        addr: u8,
+        /// Register (hex)
+        #[arg(value_parser = parse_hex_u8)]
+        reg: u8,
+        /// Value (hex)
+        #[arg(value_parser = parse_hex_u8)]
+        value: u8,
+    },
+    /// Run one transaction with a repeated start between segments
+    Txn {
+        /// 7-bit address, or 10-bit with `--ten-bit` (hex)
+        #[arg(value_parser = parse_hex_u16)]
+        addr: u16,
+        /// Address a device with a 10-bit address
+        #[arg(long)]
+        ten_bit: bool,
+        /// Segments in order: `w:HEX` writes bytes, `r:N` reads N bytes
+        #[arg(required = true, value_parser = parse_i2c_op)]
+        ops: Vec<I2cOp>,
+    },
+    /// Free a bus a device is holding low and show the state of its lines
+    Recover,
+    /// Emulate a register-based device for another controller
+    #[command(subcommand)]
+    Target(I2cTargetCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum I2cTargetCommand {
+    /// Start answering with the register map in a JSON file
+    ///
+    /// `{"addr": 72, "auto_increment": true, "registers": [{"address": 0,
+    /// "width": 1, "access": "ReadOnly", "value": 161}]}`
+    Start { map: PathBuf },
+    /// Stop answering
+    Stop,
+    /// Show the current register values
+    Registers,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SpiCommand {
+    /// Full-duplex transfer; prints the bytes clocked in
+    Xfer {
+        /// Bytes to send (hex)
+        #[arg(required = true, value_parser = parse_hex_bytes)]
+        bytes: Vec<HexBytes>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum UartCommand {
+    /// Send bytes
+    Write {
+        /// Bytes to send (hex)
+        #[arg(value_parser = parse_hex_bytes, required_unless_present = "text")]
+        bytes: Vec<HexBytes>,
+        /// Send this text instead of hex bytes
+        #[arg(long, conflicts_with = "bytes")]
+        text: Option<String>,
+    },
+    /// Read up to LEN received bytes
+    Read {
+        #[arg(value_parser = parse_int::<u16>)]
+        len: u16,
+    },
+    /// Change the baud rate
+    Config {
+        #[arg(value_parser = parse_int::<u32>)]
+        baudrate: u32,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Read a configuration value
+    Get { key: String },
+    /// Set a configuration value
+    Set { key: String, value: String },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum FileCommand {
+    /// List a directory
+    Ls {
+        #[arg(default_value = "/")]
+        path: String,
+    },
+    /// Read a small file in one message
+    Read {
+        path: String,
+        /// Save to a local file instead of printing
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Write a small local file in one message
+    Write { path: String, input: PathBuf },
+    /// Download a file of any size (bulk transfer)
+    Get {
+        path: String,
+        /// Save to a local file instead of printing
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Upload a local file of any size (bulk transfer)
+    Put { path: String, input: PathBuf },
+    /// Run a script stored on the device and print what it emits
+    Run { path: String },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum FlashCommand {
+    /// Read LEN bytes starting at ADDRESS
+    Read {
+        #[arg(value_parser = parse_int::<u32>)]
+        address: u32,
+        #[arg(value_parser = parse_int::<u32>)]
+        len: u32,
+        /// Save to a local file instead of printing
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Write a local file starting at ADDRESS, a multiple of 4096
+    ///
+    /// Every 4 KB sector the file reaches is erased first, including the
+    /// rest of the last one.
+    Write {
+        #[arg(value_parser = parse_int::<u32>)]
+        address: u32,
+        input: PathBuf,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct EepromArgs {
+    /// Part number, such as `24c256` or `256`
+    #[arg(long, value_parser = parse_eeprom_part)]
+    pub part: EepromPart,
+    /// Device address (hex), with the block select bits clear
+    #[arg(long, value_parser = parse_hex_u8, default_value = "50")]
+    pub addr: u8,
+    #[command(subcommand)]
+    pub command: EepromCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum EepromCommand {
+    /// Check that every block of the part acknowledges
+    Probe,
+    /// Read the contents (bulk transfer)
+    Dump {
+        /// First byte to read
+        #[arg(long, value_parser = parse_int::<u32>, default_value = "0")]
+        address: u32,
+        /// Bytes to read; the rest of the part by default
+        #[arg(long, value_parser = parse_int::<u32>)]
+        len: Option<u32>,
+        /// Save to a local file instead of printing
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Write a local file starting at ADDRESS, then verify it
+    Write {
+        #[arg(value_parser = parse_int::<u32>)]
+        address: u32,
+        input: PathBuf,
+        /// Skip the verify pass
+        #[arg(long)]
+        no_verify: bool,
+    },
+    /// Fill the part with 0xFF
+    Erase,
+    /// Check that the part holds a local file from ADDRESS on
+    Verify {
+        #[arg(value_parser = parse_int::<u32>)]
+        address: u32,
+        input: PathBuf,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct EventsArgs {
+    /// Topics to subscribe to (uart-rx, sniffer, gpio, touch, log, i2c-target)
+    #[arg(required = true, value_parser = parse_topic)]
+    pub topics: Vec<Topic>,
+    /// Exit after this many events
+    #[arg(short = 'n', long)]
+    pub count: Option<usize>,
+    /// Exit after this many seconds
+    #[arg(short, long)]
+    pub duration: Option<u64>,
+}
+
+/// One hex token from the command line, which may hold several bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HexBytes(pub Vec<u8>);
+
+/// Concatenate hex tokens into one buffer
+pub fn concat(tokens: &[HexBytes]) -> Vec<u8> {
+    tokens.iter().flat_map(|t| t.0.iter().copied()).collect()
+}
+
+/// Parse hex bytes such as `9f`, `0x9f` or `9f0000`
+pub fn parse_hex_bytes(s: &str) -> Result<HexBytes, String> {
+    let digits = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")).unwrap_or(s);
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return Err(format!("`{s}` is not a whole number of hex bytes"));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| format!("`{s}` is not hex")))
+        .collect::<Result<_, _>>()
+        .map(HexBytes)
+}
+
+/// Parse an I2C transaction segment such as `w:00a0` or `r:16`
+pub fn parse_i2c_op(s: &str) -> Result<I2cOp, String> {
+    if let Some(hex) = s.strip_prefix("w:") {
+        let bytes = parse_hex_bytes(hex)?;
+        bytes.0[..]
+            .try_into()
+            .map(I2cOp::Write)
+            .map_err(|_| format!("`{s}` writes more than {I2C_WRITE_SIZE} bytes"))
+    } else if let Some(len) = s.strip_prefix("r:") {
+        parse_int(len).map(I2cOp::Read)
+    } else {
+        Err(format!("`{s}` is neither `w:HEX` nor `r:N`"))
+    }
+}
+
+/// Parse one hex byte such as `50` or `0x50`
+pub fn parse_hex_u8(s: &str) -> Result<u8, String> {
+    let digits = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")).unwrap_or(s);
+    u8::from_str_radix(digits, 16).map_err(|_| format!("`{s}` is not a hex byte"))
+}
+
+/// Parse a hex number of up to 16 bits such as `2a5` or `0x2a5`
+pub fn parse_hex_u16(s: &str) -> Result<u16, String> {
+    let digits = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")).unwrap_or(s);
+    u16::from_str_radix(digits, 16).map_err(|_| format!("`{s}` is not a hex number"))
+}
+
+/// Parse a decimal number, or hex with a `0x` prefix
+pub fn parse_int<T: TryFrom<u64>>(s: &str) -> Result<T, String> {
+    let value = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(digits) => u64::from_str_radix(digits, 16),
+        None => s.parse(),
+    }
+    .map_err(|_| format!("`{s}` is not a number"))?;
+    T::try_from(value).map_err(|_| format!("`{s}` is out of range"))
+}
+
+/// Every bus mode, for name lookup
+const MODES: [Mode; 20] = [
+    Mode::HiZ,
+    Mode::I2c,
+    Mode::Spi,
+    Mode::Uart,
+    Mode::OneWire,
+    Mode::TwoWire,
+    Mode::ThreeWire,
+    Mode::Dio,
+    Mode::Infrared,
+    Mode::Usb,
+    Mode::Bluetooth,
+    Mode::Wifi,
+    Mode::Ethernet,
+    Mode::Jtag,
+    Mode::Led,
+    Mode::I2s,
+    Mode::Can,
+    Mode::SubGhz,
+    Mode::Rfid,
+    Mode::Rf24,
+];
+
+/// Lowercase name with separators removed, so `uart-rx` matches `UartRx`
+fn normalize(s: &str) -> String {
+    s.chars()
+        .filter(|c| !matches!(c, '-' | '_'))
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Parse a mode name, case-insensitively
+pub fn parse_mode(s: &str) -> Result<Mode, String> {
+    MODES
+        .into_iter()
+        .find(|mode| normalize(&format!("{mode:?}")) == normalize(s))
+        .ok_or_else(|| format!("unknown mode `{s}`"))
+}
+
+/// Parse an event topic name, case-insensitively
+pub fn parse_topic(s: &str) -> Result<Topic, String> {
+    Topic::ALL
+        .into_iter()
+        .find(|topic| normalize(&format!("{topic:?}")) == normalize(s))
+        .ok_or_else(|| format!("unknown topic `{s}`"))
+}
+
+/// Parse an I2C scan probe: `write`, `read` or `quick`
+pub fn parse_scan_probe(s: &str) -> Result<I2cScanProbe, String> {
+    match normalize(s).as_str() {
+        "write" => Ok(I2cScanProbe::Write),
+        "read" => Ok(I2cScanProbe::Read),
+        "quick" => Ok(I2cScanProbe::Quick),
+        _ => Err(format!("unknown probe `{s}`, expected write, read or quick")),
+    }
+}
+
+/// Parse a 24Cxx part number such as `24c256`, `24LC256` or `256`
+pub fn parse_eeprom_part(s: &str) -> Result<EepromPart, String> {
+    let name = normalize(s);
+    let name = name.strip_prefix("24").unwrap_or(&name);
+    let kbits = name.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+    EepromPart::ALL
+        .into_iter()
+        .find(|part| kbits.parse() == Ok(part.capacity() / 128))
+        .ok_or_else(|| format!("unknown EEPROM `{s}`"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_parse_hex_bytes() {
+        assert_eq!(parse_hex_bytes("9f"), Ok(HexBytes(vec![0x9f])));
+        assert_eq!(parse_hex_bytes("0x9f0001"), Ok(HexBytes(vec![0x9f, 0x00, 0x01])));
+        assert!(parse_hex_bytes("9").is_err());
+        assert!(parse_hex_bytes("zz").is_err());
+    }
+
+    #[test]
+    fn test_parse_int() {
+        assert_eq!(parse_int::<u32>("4096"), Ok(4096));
+        assert_eq!(parse_int::<u32>("0x1000"), Ok(4096));
+        assert!(parse_int::<u8>("256").is_err());
+    }
+
+    #[test]
+    fn test_parse_i2c_op() {
+        assert_eq!(parse_i2c_op("w:0x00a0"), Ok(I2cOp::Write([0x00, 0xa0][..].try_into().unwrap())));
+        assert_eq!(parse_i2c_op("r:16"), Ok(I2cOp::Read(16)));
+        assert!(parse_i2c_op("r:0x10000").is_err());
+        assert!(parse_i2c_op(&format!("w:{}", "00".repeat(I2C_WRITE_SIZE + 1))).is_err());
+        assert!(parse_i2c_op("x:00").is_err());
+    }
+
+    #[test]
+    fn test_parse_names() {
+        assert_eq!(parse_mode("i2c"), Ok(Mode::I2c));
+        assert_eq!(parse_mode("SUB-GHZ"), Ok(Mode::SubGhz));
+        assert!(parse_mode("nope").is_err());
+        assert_eq!(parse_topic("uart-rx"), Ok(Topic::UartRx));
+    }
+
+    #[test]
+    fn test_parse_eeprom_part() {
+        assert_eq!(parse_eeprom_part("24c256"), Ok(EepromPart::C256));
+        assert_eq!(parse_eeprom_part("24LC01"), Ok(EepromPart::C01));
+        assert_eq!(parse_eeprom_part("1024"), Ok(EepromPart::C1024));
+        assert!(parse_eeprom_part("24c03").is_err());
+    }
+
+    #[test]
+    fn test_eeprom_arguments() {
+        let cli = Cli::try_parse_from(["bpctl", "eeprom", "--part", "24c16", "--addr", "0x54", "write", "0x100", "x.bin"])
+            .unwrap();
+        match cli.command {
+            Command::Eeprom(EepromArgs {
+                part: EepromPart::C16,
+                addr: 0x54,
+                command: EepromCommand::Write { address: 0x100, no_verify: false, .. },
+            }) => {}
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_scan_arguments() {
+        let cli = Cli::try_parse_from(["bpctl", "i2c", "scan", "--identify"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::I2c(I2cCommand::Scan { identify: true, ten_bit: false, probe: I2cScanProbe::Write, .. })
+        ));
+        let cli = Cli::try_parse_from(["bpctl", "i2c", "scan", "--sweep", "--probe", "read", "--reserved"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::I2c(I2cCommand::Scan { sweep: true, reserved: true, probe: I2cScanProbe::Read, .. })
+        ));
+        assert!(Cli::try_parse_from(["bpctl", "i2c", "scan", "--ten-bit", "--annotate"]).is_err());
+        assert!(Cli::try_parse_from(["bpctl", "i2c", "scan", "--sweep", "--identify"]).is_err());
+        assert!(parse_scan_probe("peek").is_err());
+    }
+
+    #[test]
+    fn test_spi_xfer_arguments() {
+        let cli = Cli::try_parse_from(["bpctl", "--port", "localhost:5555", "spi", "xfer", "9f", "00", "0000"]).unwrap();
+        match cli.command {
+            Command::Spi(SpiCommand::Xfer { bytes }) => assert_eq!(concat(&bytes), vec![0x9f, 0, 0, 0]),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,538 @@
+//! `bpctl` - command-line control for the ESP32 Bus Pirate
+//!
+//! Every subcommand maps onto one protocol message (or a bulk transfer), so
+//! the tool can drive the device from shell scripts and CI:
+//!
+//! ```text
+//! bpctl --port /dev/ttyACM0 i2c scan
+//! bpctl --port 192.168.4.1:5555 spi xfer 9f 00 00 00
+//! bpctl --port unix:/tmp/bus-pirate.sock --json uart read 64
+//! ```
+
+mod args;
+mod output;
+mod port;
+
+use args::{
+    concat, Cli, Command, ConfigCommand, EepromArgs, EepromCommand, EventsArgs, FileCommand, FlashCommand,
+    I2cCommand, I2cTargetCommand, ModeCommand, SpiCommand, UartCommand,
+};
+use clap::Parser;
+use esp32_bus_pirate_client::{error::describe, Client, Error as ClientError};
+use esp32_bus_pirate_protocol::message::{
+    BulkTarget, DeviceInfo, ErrorCode, Event, Features, I2cAddress, I2cChip, I2cEeprom, I2cLineState,
+    I2cRecoveryReport, I2cRegisterMap, I2cScanOptions, I2cScanReport, I2cSpeed, I2cSweepReport, I2cTargetAccess,
+    NackSource, SniffRecord,
+};
+use output::{hex_spaced, Printer};
+use port::{Port, Target};
+use serde_json::json;
+use std::{
+    error::Error,
+    fs,
+    path::Path,
+    process::ExitCode,
+    time::{Duration, Instant},
+};
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let printer = Printer::new(cli.json, cli.format);
+
+    match run(&cli, &printer) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            let code = e
+                .downcast_ref::<ClientError>()
+                .and_then(ClientError::code)
+                .map(|code| json!(format!("{code:?}")));
+            printer.error(&e.to_string(), code);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: &Cli, out: &Printer) -> Result<()> {
+    let port = Port::open(&Target::parse(&cli.port), cli.baud)
+        .map_err(|e| format!("cannot open {}: {e}", cli.port))?;
+    let mut client = Client::new(port)
+        .with_timeout(reply_timeout(cli))
+        .with_retries(cli.retries);
+    client.handshake()?;
+
+    match &cli.command {
+        Command::Info => info(&client, out),
+        Command::Mode(cmd) => match cmd {
+            ModeCommand::Get => {
+                let mode = client.get_mode()?;
+                out.value(json!({ "mode": mode }), || format!("{mode:?}"));
+            }
+            ModeCommand::Set { mode } => {
+                client.set_mode(*mode)?;
+                out.ok();
+            }
+        },
+        Command::I2c(cmd) => match cmd {
+            I2cCommand::Scan { sweep: true, probe, reserved, .. } => {
+                let report = client.i2c_scan_sweep(I2cScanOptions { probe: *probe, reserved: *reserved })?;
+                let devices: Vec<_> = report
+                    .devices
+                    .iter()
+                    .map(|entry| {
+                        json!({
+                            "addr": entry.addr,
+                            "speeds": entry.speeds.iter().map(|speed| speed.hz()).collect::<Vec<_>>(),
+                        })
+                    })
+                    .collect();
+                out.value(json!({ "devices": devices }), || describe_sweep(&report));
+            }
+            I2cCommand::Scan { annotate, identify, probe, reserved, .. } if *annotate || *identify => {
+                let options = I2cScanOptions { probe: *probe, reserved: *reserved };
+                let report = client.i2c_scan_report(options, *identify)?;
+                let devices: Vec<_> = report
+                    .devices
+                    .iter()
+                    .map(|entry| {
+                        json!({
+                            "addr": entry.addr,
+                            "chip": entry.chip.map(I2cChip::name),
+                            "candidates": entry
+                                .candidates()
+                                .iter()
+                                .map(|device| json!({ "part": device.part, "kind": device.kind }))
+                                .collect::<Vec<_>>(),
+                        })
+                    })
+                    .collect();
+                out.value(json!({ "devices": devices }), || describe_scan(&report));
+            }
+            I2cCommand::Scan { ten_bit, probe, reserved, .. } => {
+                let options = I2cScanOptions { probe: *probe, reserved: *reserved };
+                let (devices, width) = if *ten_bit {
+                    (client.i2c_scan_ten_bit()?, 3)
+                } else if options == I2cScanOptions::default() {
+                    // The plain scan works with v1 firmware too
+                    (client.i2c_scan()?.into_iter().map(u16::from).collect(), 2)
+                } else {
+                    let report = client.i2c_scan_report(options, false)?;
+                    (report.devices.iter().map(|entry| u16::from(entry.addr)).collect(), 2)
+                };
+                out.value(json!({ "devices": devices }), || {
+                    if devices.is_empty() {
+                        "no devices found".to_owned()
+                    } else {
+                        devices.iter().map(|a| format!("0x{a:0width$x}")).collect::<Vec<_>>().join("\n")
+                    }
+                });
+            }
+            I2cCommand::Write { addr, bytes } => {
+                client.i2c_write(*addr, &concat(bytes))?;
+                out.ok();
+            }
+            I2cCommand::Read { addr, len } => out.data(&client.i2c_read(*addr, *len)?),
+            I2cCommand::RegRead { addr, reg } => {
+                let value = client.i2c_read_register(*addr, *reg)?;
+                out.value(json!({ "value": value }), || format!("0x{value:02x}"));
+            }
+            I2cCommand::RegWrite { addr, reg, value } => {
+                client.i2c_write_register(*addr, *reg, *value)?;
+                out.ok();
+            }
+            I2cCommand::Txn { addr, ten_bit, ops } => {
+                let addr = if *ten_bit {
+                    I2cAddress::TenBit(*addr)
+                } else {
+                    I2cAddress::SevenBit(u8::try_from(*addr).map_err(|_| "7-bit address out of range")?)
+                };
+                let report = client.i2c_transaction(addr, ops)?;
+                if let Some(nack) = report.nack {
+                    let refused = match nack.source {
+                        NackSource::Address => "address",
+                        NackSource::Data => "data",
+                        NackSource::Unknown => "transfer",
+                    };
+                    return Err(match nack.segment {
+                        Some(segment) => format!("{refused} not acknowledged in segment {segment}"),
+                        None => format!("{refused} not acknowledged"),
+                    }
+                    .into());
+                }
+                if !report.data.is_empty() {
+                    out.data(&report.data);
+                }
+                out.ok();
+            }
+            I2cCommand::Recover => {
+                let report = client.i2c_recover()?;
+                out.value(serde_json::to_value(report).unwrap_or_default(), || describe_recovery(&report));
+                if !report.recovered {
+                    return Err("the bus is still stuck".into());
+                }
+            }
+            I2cCommand::Target(I2cTargetCommand::Start { map }) => {
+                let map: I2cRegisterMap = serde_json::from_slice(&fs::read(map)?)
+                    .map_err(|e| format!("invalid register map {}: {e}", map.display()))?;
+                client.i2c_target_start(map)?;
+                out.ok();
+            }
+            I2cCommand::Target(I2cTargetCommand::Stop) => {
+                client.i2c_target_stop()?;
+                out.ok();
+            }
+            I2cCommand::Target(I2cTargetCommand::Registers) => {
+                let map = client.i2c_target_registers()?;
+                out.value(serde_json::to_value(&map).unwrap_or_default(), || describe_registers(&map));
+            }
+        },
+        Command::Spi(SpiCommand::Xfer { bytes }) => out.data(&client.spi_transfer(&concat(bytes))?),
+        Command::Uart(cmd) => match cmd {
+            UartCommand::Write { bytes, text } => {
+                let data = match text {
+                    Some(text) => text.as_bytes().to_vec(),
+                    None => concat(bytes),
+                };
+                client.uart_write(&data)?;
+                out.ok();
+            }
+            UartCommand::Read { len } => out.data(&client.uart_read(*len)?),
+            UartCommand::Config { baudrate } => {
+                client.uart_config(*baudrate)?;
+                out.ok();
+            }
+        },
+        Command::Config(cmd) => match cmd {
+            ConfigCommand::Get { key } => {
+                let value = client.get_config(key)?;
+                out.value(json!({ "key": key, "value": value }), || value.clone());
+            }
+            ConfigCommand::Set { key, value } => {
+                client.set_config(key, value)?;
+                out.ok();
+            }
+        },
+        Command::File(cmd) => match cmd {
+            FileCommand::Ls { path } => {
+                let files = client.file_list(path)?;
+                out.value(json!({ "files": files }), || files.join("\n"));
+            }
+            FileCommand::Read { path, output } => save_or_print(out, output.as_deref(), &client.file_read(path)?)?,
+            FileCommand::Write { path, input } => {
+                client.file_write(path, &fs::read(input)?)?;
+                out.ok();
+            }
+            FileCommand::Get { path, output } => {
+                let data = client.bulk_read(file_target(path)?, 0, u32::MAX)?;
+                save_or_print(out, output.as_deref(), &data)?;
+            }
+            FileCommand::Put { path, input } => {
+                client.bulk_write(file_target(path)?, &fs::read(input)?)?;
+                out.ok();
+            }
+            FileCommand::Run { path } => {
+                let report = client.run_script(path)?;
+                if !report.output.is_empty() {
+                    out.data(&report.output);
+                }
+                if let Some(error) = report.error {
+                    return Err(format!("script stopped at line {}: {}", error.line, describe(error.code)).into());
+                }
+                out.ok();
+            }
+        },
+        Command::Flash(cmd) => match cmd {
+            FlashCommand::Read { address, len, output } => {
+                let data = client.bulk_read(BulkTarget::SpiFlash { address: *address }, 0, *len)?;
+                save_or_print(out, output.as_deref(), &data)?;
+            }
+            FlashCommand::Write { address, input } => {
+                client.bulk_write(BulkTarget::SpiFlash { address: *address }, &fs::read(input)?)?;
+                out.ok();
+            }
+        },
+        Command::Eeprom(args) => eeprom(&mut client, out, args)?,
+        Command::Events(args) => events(&mut client, out, args)?,
+    }
+    Ok(())
+}
+
+/// Allowance per page when erasing; the write cycle is 5-10 ms
+const ERASE_PAGE_TIME: Duration = Duration::from_millis(10);
+
+/// Reply timeout for `command`: the configured one, or longer for commands
+/// the device takes a while to answer
+fn reply_timeout(cli: &Cli) -> Duration {
+    let timeout = Duration::from_millis(cli.timeout);
+    match &cli.command {
+        Command::Eeprom(EepromArgs { part, command: EepromCommand::Erase, .. }) => {
+            let pages = part.capacity() / part.page_size();
+            timeout.max(ERASE_PAGE_TIME * pages)
+        }
+        _ => timeout,
+    }
+}
+
+fn eeprom(client: &mut Client<Port>, out: &Printer, args: &EepromArgs) -> Result<()> {
+    let eeprom = I2cEeprom { addr: args.addr, part: args.part };
+    match &args.command {
+        EepromCommand::Probe => {
+            let found = client.i2c_eeprom_probe(eeprom)?;
+            let part = args.part;
+            out.value(
+                json!({
+                    "addresses": found,
+                    "capacity": part.capacity(),
+                    "page_size": part.page_size(),
+                    "address_bytes": part.address_bytes(),
+                }),
+                || {
+                    let addrs: Vec<String> = found.iter().map(|a| format!("0x{a:02x}")).collect();
+                    format!(
+                        "part:      {part:?}\ncapacity:  {} bytes\npage size: {} bytes\nanswers:   {}",
+                        part.capacity(),
+                        part.page_size(),
+                        addrs.join(" "),
+                    )
+                },
+            );
+            if found.is_empty() {
+                return Err(format!("no EEPROM at 0x{:02x}", args.addr).into());
+            }
+            if found.len() < usize::from(part.blocks()) {
+                return Err(format!("only {} of {} blocks answer", found.len(), part.blocks()).into());
+            }
+        }
+        EepromCommand::Dump { address, len, output } => {
+            let target = BulkTarget::I2cEeprom { eeprom, address: *address };
+            let data = client.bulk_read(target, 0, len.unwrap_or(u32::MAX))?;
+            save_or_print(out, output.as_deref(), &data)?;
+        }
+        EepromCommand::Write { address, input, no_verify } => {
+            let data = fs::read(input)?;
+            client.bulk_write(BulkTarget::I2cEeprom { eeprom, address: *address }, &data)?;
+            if !no_verify {
+                verify(client, eeprom, *address, &data)?;
+            }
+            out.ok();
+        }
+        EepromCommand::Erase => {
+            client.i2c_eeprom_erase(eeprom)?;
+            out.ok();
+        }
+        EepromCommand::Verify { address, input } => {
+            verify(client, eeprom, *address, &fs::read(input)?)?;
+            out.ok();
+        }
+    }
+    Ok(())
+}
+
+fn verify(client: &mut Client<Port>, eeprom: I2cEeprom, address: u32, data: &[u8]) -> Result<()> {
+    match client.i2c_eeprom_verify(eeprom, address, data) {
+        Err(e) if e.code() == Some(ErrorCode::ChecksumMismatch) => {
+            Err(format!("EEPROM contents differ from the file at 0x{address:x}+{}", data.len()).into())
+        }
+        result => Ok(result?),
+    }
+}
+
+fn file_target(path: &str) -> Result<BulkTarget> {
+    let path = path.try_into().map_err(|_| "path is too long")?;
+    Ok(BulkTarget::File(path))
+}
+
+fn save_or_print(out: &Printer, output: Option<&Path>, data: &[u8]) -> Result<()> {
+    match output {
+        Some(path) => {
+            fs::write(path, data)?;
+            out.value(json!({ "len": data.len(), "output": path }), || {
+                format!("{} bytes written to {}", data.len(), path.display())
+            });
+        }
+        None => out.data(data),
+    }
+    Ok(())
+}
+
+/// Feature flags and their display names
+const FEATURES: [(Features, &str); 7] = [
+    (Features::SEQUENCE_IDS, "sequence-ids"),
+    (Features::FILESYSTEM, "filesystem"),
+    (Features::WIFI, "wifi"),
+    (Features::DISPLAY, "display"),
+    (Features::BULK_TRANSFER, "bulk-transfer"),
+    (Features::EVENTS, "events"),
+    (Features::SCRIPTS, "scripts"),
+];
+
+fn info(client: &Client<Port>, out: &Printer) {
+    let Some(info) = client.device_info() else {
+        out.value(json!({ "version": client.version() }), || {
+            format!("protocol:  v{} (device did not answer Hello)", client.version())
+        });
+        return;
+    };
+
+    let features: Vec<&str> = FEATURES
+        .iter()
+        .filter(|(flag, _)| info.features.contains(*flag))
+        .map(|(_, name)| *name)
+        .collect();
+
+    let mut value = serde_json::to_value(info).unwrap_or_default();
+    value["feature_names"] = json!(features);
+    out.value(value, || describe_info(info, &features));
+}
+
+fn describe_info(info: &DeviceInfo, features: &[&str]) -> String {
+    let versions: Vec<String> = info.versions.iter().map(|v| format!("v{v}")).collect();
+    let modes: Vec<String> = info.modes.iter().map(|m| format!("{m:?}")).collect();
+    format!(
+        "board:     {}\nfirmware:  {}\nprotocol:  v{} (supports {})\nmax frame: {}\nbuffers:   rx {}, tx {}\nmodes:     {}\nfeatures:  {}",
+        info.board,
+        info.firmware_version,
+        info.version,
+        versions.join(", "),
+        info.max_frame,
+        info.rx_buffer,
+        info.tx_buffer,
+        modes.join(", "),
+        features.join(", "),
+    )
+}
+
+fn events(client: &mut Client<Port>, out: &Printer, args: &EventsArgs) -> Result<()> {
+    for topic in &args.topics {
+        client.subscribe(*topic)?;
+    }
+
+    let deadline = args.duration.map(|secs| Instant::now() + Duration::from_secs(secs));
+    let mut seen = 0;
+    while args.count.is_none_or(|count| seen < count) && deadline.is_none_or(|d| Instant::now() < d) {
+        if let Some(event) = client.poll_event(Duration::from_millis(100))? {
+            let value = serde_json::to_value(&event).unwrap_or_default();
+            out.value(value, || describe_event(&event));
+            seen += 1;
+        }
+    }
+
+    for topic in &args.topics {
+        client.unsubscribe(*topic)?;
+    }
+    Ok(())
+}
+
+fn describe_recovery(report: &I2cRecoveryReport) -> String {
+    let line = |state: &I2cLineState| {
+        if state.held_low {
+            "held low"
+        } else if state.pull_up {
+            "high, pulled up"
+        } else {
+            "high, no external pull-up"
+        }
+    };
+    format!(
+        "SCL:       {}\nSDA:       {}\nclocks:    {}\nrecovered: {}",
+        line(&report.scl),
+        line(&report.sda),
+        report.clocks,
+        if report.recovered { "yes" } else { "no" },
+    )
+}
+
+fn describe_scan(report: &I2cScanReport) -> String {
+    if report.devices.is_empty() {
+        return "no devices found".to_owned();
+    }
+    let lines = report.devices.iter().map(|entry| {
+        let parts = match entry.chip {
+            Some(chip) => chip.name().to_owned(),
+            None if entry.candidates().is_empty() => "unknown".to_owned(),
+            None => entry.candidates().iter().map(|device| device.part).collect::<Vec<_>>().join(", "),
+        };
+        format!("0x{:02x}  {parts}", entry.addr)
+    });
+    lines.collect::<Vec<_>>().join("\n")
+}
+
+fn describe_sweep(report: &I2cSweepReport) -> String {
+    if report.devices.is_empty() {
+        return "no devices found".to_owned();
+    }
+    let lines = report.devices.iter().map(|entry| {
+        let speeds: Vec<_> = entry
+            .speeds
+            .iter()
+            .map(|speed| match speed {
+                I2cSpeed::Standard => "100k",
+                I2cSpeed::Fast => "400k",
+                I2cSpeed::FastPlus => "1M",
+            })
+            .collect();
+        format!("0x{:02x}  {}", entry.addr, speeds.join(" "))
+    });
+    lines.collect::<Vec<_>>().join("\n")
+}
+
+fn describe_registers(map: &I2cRegisterMap) -> String {
+    let mut lines = vec![format!("target at 0x{:02x}", map.addr)];
+    for register in &map.registers {
+        let digits = usize::from(register.width) * 2;
+        lines.push(format!("reg 0x{:02x}  0x{:0digits$x}  {:?}", register.address, register.value, register.access));
+    }
+    lines.join("\n")
+}
+
+fn describe_access(access: &I2cTargetAccess) -> String {
+    let mut text = format!("{} reg 0x{:02x}", if access.read { "R" } else { "W" }, access.reg);
+    if !access.data.is_empty() {
+        text.push_str(&format!(": {}", hex_spaced(&access.data)));
+    }
+    let more = usize::from(access.len).saturating_sub(access.data.len());
+    if more > 0 {
+        text.push_str(&format!(" (+{more} bytes)"));
+    }
+    if access.nacked {
+        text.push_str(" NACK");
+    }
+    text
+}
+
+fn describe_event(event: &Event) -> String {
+    match event {
+        Event::UartRx { data } => format!("uart-rx  {}", hex_spaced(data)),
+        Event::Sniffer(sniffed) => {
+            let record = match sniffed.record {
+                SniffRecord::Start => "START".to_owned(),
+                SniffRecord::RepeatedStart => "RESTART".to_owned(),
+                SniffRecord::Stop => "STOP".to_owned(),
+                SniffRecord::Address { addr, read, ack } => {
+                    format!("ADDR 0x{addr:02x} {} {}", if read { "R" } else { "W" }, ack_str(ack))
+                }
+                SniffRecord::Data { value, ack } => format!("DATA 0x{value:02x} {}", ack_str(ack)),
+                SniffRecord::Exchange { mosi, miso } => format!("MOSI 0x{mosi:02x} MISO 0x{miso:02x}"),
+            };
+            format!("sniffer  {:>10}us {:?} {record}", sniffed.timestamp_us, sniffed.mode)
+        }
+        Event::GpioEdge { pin, rising, timestamp_us } => {
+            format!("gpio     {timestamp_us:>10}us pin {pin} {}", if *rising { "rising" } else { "falling" })
+        }
+        Event::Touch { x, y, pressed } => {
+            format!("touch    ({x}, {y}) {}", if *pressed { "down" } else { "up" })
+        }
+        Event::Log { level, text } => format!("log      {level:?}: {text}"),
+        Event::I2cTarget(access) => format!("i2c-tgt  {}", describe_access(access)),
+        Event::Overflow { dropped } => format!("overflow {dropped} events dropped"),
+    }
+}
+
+fn ack_str(ack: bool) -> &'static str {
+    if ack {
+        "ACK"
+    } else {
+        "NACK"
+    }
+}
@@ -0,0 +1,150 @@
+//! Human-readable and JSON output
+
+use clap::ValueEnum;
+use serde_json::{json, Value};
+use std::fmt::Write as _;
+
+/// How binary data is shown in human-readable mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DataFormat {
+    /// Offset, hex bytes and ASCII column, like `hexdump -C`
+    Hexdump,
+    /// Space-separated hex bytes
+    Hex,
+    /// Text, with non-printable bytes escaped as `\xNN`
+    Ascii,
+}
+
+/// Prints command results in the selected output mode
+pub struct Printer {
+    json: bool,
+    format: DataFormat,
+}
+
+impl Printer {
+    pub fn new(json: bool, format: DataFormat) -> Self {
+        Self { json, format }
+    }
+
+    /// Report a command that returns nothing
+    pub fn ok(&self) {
+        if self.json {
+            println!("{}", json!({ "ok": true }));
+        }
+    }
+
+    /// Print binary data
+    pub fn data(&self, data: &[u8]) {
+        if self.json {
+            println!("{}", json!({ "len": data.len(), "hex": hex(data) }));
+            return;
+        }
+        match self.format {
+            DataFormat::Hexdump => print!("{}", hexdump(data)),
+            DataFormat::Hex => println!("{}", hex_spaced(data)),
+            DataFormat::Ascii => println!("{}", ascii(data)),
+        }
+    }
+
+    /// Print a structured value, with `human` as the non-JSON rendering
+    pub fn value(&self, value: Value, human: impl FnOnce() -> String) {
+        if self.json {
+            println!("{value}");
+        } else {
+            println!("{}", human());
+        }
+    }
+
+    /// Print a failure to stderr (or stdout as JSON)
+    pub fn error(&self, message: &str, code: Option<Value>) {
+        if self.json {
+            println!("{}", json!({ "error": message, "code": code }));
+        } else {
+            eprintln!("bpctl: {message}");
+        }
+    }
+}
+
+/// Compact lowercase hex, e.g. `9f0000`
+pub fn hex(data: &[u8]) -> String {
+    data.iter().fold(String::with_capacity(data.len() * 2), |mut s, b| {
+        let _ = write!(s, "{b:02x}");
+        s
+    })
+}
+
+/// Space-separated hex, e.g. `9f 00 00`
+pub fn hex_spaced(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{b:02x}")).collect::<Vec<_>>().join(" ")
+}
+
+/// Text with non-printable bytes escaped
+pub fn ascii(data: &[u8]) -> String {
+    let mut s = String::with_capacity(data.len());
+    for &b in data {
+        match b {
+            b'\n' => s.push('\n'),
+            0x20..=0x7E => s.push(b as char),
+            _ => {
+                let _ = write!(s, "\\x{b:02x}");
+            }
+        }
+    }
+    s
+}
+
+/// Canonical hex+ASCII dump, 16 bytes per line
+pub fn hexdump(data: &[u8]) -> String {
+    let mut out = String::new();
+    for (i, line) in data.chunks(16).enumerate() {
+        let _ = write!(out, "{:08x}  ", i * 16);
+        for col in 0..16 {
+            match line.get(col) {
+                Some(b) => {
+                    let _ = write!(out, "{b:02x} ");
+                }
+                None => out.push_str("   "),
+            }
+            if col == 7 {
+                out.push(' ');
+            }
+        }
+        out.push_str(" |");
+        out.extend(line.iter().map(|&b| if (0x20..=0x7E).contains(&b) { b as char } else { '.' }));
+        out.push_str("|\n");
+    }
+    let _ = writeln!(out, "{:08x}", data.len());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(hex(&[0x9f, 0x00, 0xab]), "9f00ab");
+        assert_eq!(hex_spaced(&[0x9f, 0x00, 0xab]), "9f 00 ab");
+        assert_eq!(hex(&[]), "");
+    }
+
+    #[test]
+    fn test_ascii_escapes_binary() {
+        assert_eq!(ascii(b"ok\r\n\x00"), "ok\\x0d\n\\x00");
+    }
+
+    #[test]
+    fn test_hexdump_layout() {
+        let dump = hexdump(b"0123456789ABCDEF\x01");
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(
+            lines[0],
+            "00000000  30 31 32 33 34 35 36 37  38 39 41 42 43 44 45 46  |0123456789ABCDEF|"
+        );
+        assert_eq!(
+            lines[1],
+            "00000010  01                                                |.|"
+        );
+        assert_eq!(lines[2], "00000011");
+    }
+}
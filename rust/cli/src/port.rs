@@ -0,0 +1,161 @@
+//! Connecting to the device given a `--port` specification
+
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+#[cfg(unix)]
+use std::os::unix::{fs::FileTypeExt, net::UnixStream};
+
+/// Read timeout applied to every stream; the client enforces the real
+/// reply timeout on top of it
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Kind of connection described by a `--port` value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// Serial device such as `/dev/ttyACM0` or `COM3`
+    Serial(String),
+    /// TCP `host:port`
+    Tcp(String),
+    /// Unix domain socket, e.g. of a simulator
+    Unix(String),
+}
+
+impl Target {
+    /// Classify a `--port` value
+    ///
+    /// `tcp:` and `unix:` prefixes force the kind. Otherwise an existing
+    /// socket file is a Unix socket, `host:port` is TCP and anything else
+    /// is a serial device.
+    pub fn parse(spec: &str) -> Target {
+        if let Some(addr) = spec.strip_prefix("tcp:") {
+            return Target::Tcp(addr.to_owned());
+        }
+        if let Some(path) = spec.strip_prefix("unix:") {
+            return Target::Unix(path.to_owned());
+        }
+        if is_socket(spec) {
+            return Target::Unix(spec.to_owned());
+        }
+        match spec.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && !host.contains('/') && port.parse::<u16>().is_ok() => {
+                Target::Tcp(spec.to_owned())
+            }
+            _ => Target::Serial(spec.to_owned()),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_socket(path: &str) -> bool {
+    std::fs::metadata(path)
+        .map(|meta| meta.file_type().is_socket())
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_socket(_path: &str) -> bool {
+    false
+}
+
+/// An open connection to the device
+pub enum Port {
+    Serial(Box<dyn serialport::SerialPort>),
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Port {
+    /// Open `target`, using `baudrate` for serial devices
+    pub fn open(target: &Target, baudrate: u32) -> io::Result<Port> {
+        match target {
+            Target::Serial(path) => {
+                let port = serialport::new(path, baudrate)
+                    .timeout(POLL_INTERVAL)
+                    .open()
+                    .map_err(io::Error::from)?;
+                Ok(Port::Serial(port))
+            }
+            Target::Tcp(addr) => {
+                let stream = TcpStream::connect(addr)?;
+                stream.set_read_timeout(Some(POLL_INTERVAL))?;
+                stream.set_nodelay(true)?;
+                Ok(Port::Tcp(stream))
+            }
+            #[cfg(unix)]
+            Target::Unix(path) => {
+                let stream = UnixStream::connect(path)?;
+                stream.set_read_timeout(Some(POLL_INTERVAL))?;
+                Ok(Port::Unix(stream))
+            }
+            #[cfg(not(unix))]
+            Target::Unix(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Unix sockets are not supported on this platform",
+            )),
+        }
+    }
+}
+
+impl Read for Port {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Port::Serial(port) => port.read(buf),
+            Port::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Port::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Port {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Port::Serial(port) => port.write(buf),
+            Port::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Port::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Port::Serial(port) => port.flush(),
+            Port::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            Port::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serial_paths() {
+        assert_eq!(Target::parse("/dev/ttyACM0"), Target::Serial("/dev/ttyACM0".into()));
+        assert_eq!(Target::parse("COM3"), Target::Serial("COM3".into()));
+    }
+
+    #[test]
+    fn test_tcp_addresses() {
+        assert_eq!(Target::parse("192.168.4.1:5555"), Target::Tcp("192.168.4.1:5555".into()));
+        assert_eq!(Target::parse("localhost:5555"), Target::Tcp("localhost:5555".into()));
+        assert_eq!(Target::parse("tcp:[::1]:5555"), Target::Tcp("[::1]:5555".into()));
+    }
+
+    #[test]
+    fn test_unix_sockets() {
+        assert_eq!(Target::parse("unix:/tmp/sim.sock"), Target::Unix("/tmp/sim.sock".into()));
+    }
+
+    #[test]
+    fn test_bad_port_number_is_not_tcp() {
+        assert_eq!(Target::parse("host:notaport"), Target::Serial("host:notaport".into()));
+    }
+}
@@ -0,0 +1,620 @@
+//! Blocking protocol client
+
+use crate::error::{Error, Result};
+use esp32_bus_pirate_protocol::{
+    bulk::crc32,
+    codec::{Frame, MessageCodec},
+    message::{
+        BulkTarget, DeviceInfo, ErrorCode, Event, I2cAddress, I2cEeprom, I2cOp, I2cRecoveryReport, I2cRegisterMap,
+        I2cScanOptions, I2cScanReport, I2cSweepReport, I2cTransactionReport, Message, Mode, Response, ScriptReport,
+        Topic, I2C_MAX_SEGMENTS,
+    },
+    version::{PROTOCOL_VERSION_V1, SUPPORTED_VERSIONS},
+    BulkError, BulkReceiver, BulkSender, FrameDecoder, MAX_MESSAGE_SIZE, PROTOCOL_VERSION, UNSEQUENCED,
+};
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Default time to wait for each reply
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Default number of times a command is re-sent after a timeout
+pub const DEFAULT_RETRIES: u32 = 2;
+
+/// Blocking client for any byte stream connected to the device
+///
+/// The stream should be configured with a short read timeout (serial ports
+/// and `TcpStream` support this) or be non-blocking, so that the client can
+/// enforce its own reply timeout. Reads that fail with `TimedOut` or
+/// `WouldBlock` are treated as "no data yet".
+///
+/// Every command is sent with a fresh sequence ID and only a reply echoing
+/// that ID is accepted; late replies to earlier attempts are discarded.
+/// Events that arrive while waiting are queued for
+/// [`poll_event`](Self::poll_event).
+///
+/// A command whose reply was lost is re-sent, so a retried write may execute
+/// twice. Use [`with_retries(0)`](Self::with_retries) where that matters.
+pub struct Client<T> {
+    io: T,
+    decoder: FrameDecoder,
+    frames: VecDeque<Frame>,
+    events: VecDeque<Event>,
+    version: u8,
+    next_seq: u16,
+    next_transfer: u16,
+    timeout: Duration,
+    retries: u32,
+    device_info: Option<DeviceInfo>,
+}
+
+impl<T: Read + Write> Client<T> {
+    /// Wrap a stream without performing the `Hello` handshake
+    ///
+    /// The client speaks the current protocol version until
+    /// [`handshake`](Self::handshake) says otherwise.
+    pub fn new(io: T) -> Self {
+        Self {
+            io,
+            decoder: FrameDecoder::new(),
+            frames: VecDeque::new(),
+            events: VecDeque::new(),
+            version: PROTOCOL_VERSION,
+            next_seq: 0,
+            next_transfer: 0,
+            timeout: DEFAULT_TIMEOUT,
+            retries: DEFAULT_RETRIES,
+            device_info: None,
+        }
+    }
+
+    /// Wrap a stream and negotiate the protocol version
+    pub fn connect(io: T) -> Result<Self> {
+        let mut client = Self::new(io);
+        client.handshake()?;
+        Ok(client)
+    }
+
+    /// Set how long to wait for each reply
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set how many times a command is re-sent after a timeout
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Protocol version used for outgoing frames
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Capabilities reported by the device, if the handshake succeeded
+    pub fn device_info(&self) -> Option<&DeviceInfo> {
+        self.device_info.as_ref()
+    }
+
+    /// Borrow the underlying stream
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.io
+    }
+
+    /// Unwrap the underlying stream
+    pub fn into_inner(self) -> T {
+        self.io
+    }
+
+    // ===== Session =====
+
+    /// Negotiate the protocol version with a `Hello` handshake
+    ///
+    /// Devices that do not understand `Hello` (v1 firmware) either reject it
+    /// or never answer; the client then falls back to v1 and returns `None`.
+    pub fn handshake(&mut self) -> Result<Option<&DeviceInfo>> {
+        let hello = Message::Hello {
+            versions: heapless::Vec::from_slice(SUPPORTED_VERSIONS).map_err(|_| Error::TooLong { max: 8 })?,
+            max_frame: MAX_MESSAGE_SIZE as u16,
+        };
+
+        self.version = PROTOCOL_VERSION;
+        match self.request(&hello) {
+            Ok(Response::DeviceInfo(info)) => {
+                self.version = info.version;
+                self.device_info = Some(info);
+                Ok(self.device_info.as_ref())
+            }
+            Ok(other) => Err(unexpected(Message::Response(other))),
+            Err(Error::Timeout)
+            | Err(Error::Device(ErrorCode::InvalidCommand))
+            | Err(Error::Device(ErrorCode::ProtocolError)) => {
+                self.version = PROTOCOL_VERSION_V1;
+                self.device_info = None;
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    // ===== Raw Access =====
+
+    /// Send `msg` and return the device's reply
+    ///
+    /// `Error` replies are returned as [`Error::Device`]; any other reply,
+    /// including bulk transfer messages, is returned as is.
+    pub fn call(&mut self, msg: &Message) -> Result<Message> {
+        for _ in 0..=self.retries {
+            let seq = self.next_seq();
+            let frame = MessageCodec::encode_versioned(msg, self.version, seq)?;
+            self.io.write_all(&frame)?;
+            self.io.flush()?;
+
+            match self.wait_reply(seq) {
+                Err(Error::Timeout) => continue,
+                Ok(Message::Error(code)) => return Err(Error::Device(code)),
+                result => return result,
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Send `msg` and return the device's `Response`
+    pub fn request(&mut self, msg: &Message) -> Result<Response> {
+        match self.call(msg)? {
+            Message::Response(response) => Ok(response),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Send `msg` and expect `Response::Success`
+    pub fn command(&mut self, msg: &Message) -> Result<()> {
+        match self.request(msg)? {
+            Response::Success => Ok(()),
+            other => Err(unexpected(Message::Response(other))),
+        }
+    }
+
+    // ===== Mode Management =====
+
+    /// Switch the device to `mode`
+    pub fn set_mode(&mut self, mode: Mode) -> Result<()> {
+        self.command(&Message::SetMode { mode })
+    }
+
+    /// Query the current mode
+    pub fn get_mode(&mut self) -> Result<Mode> {
+        match self.request(&Message::GetMode)? {
+            Response::CurrentMode(mode) => Ok(mode),
+            other => Err(unexpected(Message::Response(other))),
+        }
+    }
+
+    // ===== I2C =====
+
+    /// Scan the I2C bus and return the responding addresses
+    pub fn i2c_scan(&mut self) -> Result<Vec<u8>> {
+        match self.request(&Message::I2cScan)? {
+            Response::I2cDevices(addrs) => Ok(addrs.to_vec()),
+            other => Err(unexpected(Message::Response(other))),
+        }
+    }
+
+    /// Scan the I2C bus for devices with 10-bit addresses
+    pub fn i2c_scan_ten_bit(&mut self) -> Result<Vec<u16>> {
+        match self.request(&Message::I2cScanTenBit)? {
+            Response::I2cTenBitDevices(addrs) => Ok(addrs.to_vec()),
+            other => Err(unexpected(Message::Response(other))),
+        }
+    }
+
+    /// Scan the I2C bus as `options` say; with `identify` the device reads
+    /// the ID register of each part it finds
+    ///
+    /// [`I2cScanEntry::candidates`](esp32_bus_pirate_protocol::I2cScanEntry::candidates) lists the parts that may be at an address
+    /// the device could not identify.
+    pub fn i2c_scan_report(&mut self, options: I2cScanOptions, identify: bool) -> Result<I2cScanReport> {
+        match self.request(&Message::I2cScanAnnotated { options, identify })? {
+            Response::I2cScanReport(report) => Ok(report),
+            other => Err(unexpected(Message::Response(other))),
+        }
+    }
+
+    /// Scan the I2C bus at 100 kHz, 400 kHz and 1 MHz, and report the speeds
+    /// each device answered at
+    pub fn i2c_scan_sweep(&mut self, options: I2cScanOptions) -> Result<I2cSweepReport> {
+        match self.request(&Message::I2cScanSweep { options })? {
+            Response::I2cSweepReport(report) => Ok(report),
+            other => Err(unexpected(Message::Response(other))),
+        }
+    }
+
+    /// Free an I2C bus a device is holding low, and report on its lines
+    pub fn i2c_recover(&mut self) -> Result<I2cRecoveryReport> {
+        match self.request(&Message::I2cRecover)? {
+            Response::I2cRecovery(report) => Ok(report),
+            other => Err(unexpected(Message::Response(other))),
+        }
+    }
+
+    /// Write `data` to the device at `addr`
+    pub fn i2c_write(&mut self, addr: u8, data: &[u8]) -> Result<()> {
+        self.command(&Message::I2cWrite {
+            addr,
+            data: bounded(data)?,
+        })
+    }
+
+    /// Read `len` bytes from the device at `addr`
+    pub fn i2c_read(&mut self, addr: u8, len: u8) -> Result<Vec<u8>> {
+        self.data(&Message::I2cRead { addr, len })
+    }
+
+    /// Read register `reg` of the device at `addr`
+    pub fn i2c_read_register(&mut self, addr: u8, reg: u8) -> Result<u8> {
+        let data = self.data(&Message::I2cReadRegister { addr, reg })?;
+        data.first()
+            .copied()
+            .ok_or_else(|| unexpected(Message::Response(Response::Data(heapless::Vec::new()))))
+    }
+
+    /// Write `value` to register `reg` of the device at `addr`
+    pub fn i2c_write_register(&mut self, addr: u8, reg: u8, value: u8) -> Result<()> {
+        self.command(&Message::I2cWriteRegister { addr, reg, value })
+    }
+
+    /// Run `ops` on the device at `addr` as one transaction, with a repeated
+    /// start between segments
+    ///
+    /// `addr` is a 7-bit address or an [`I2cAddress`]. A NACK does not fail
+    /// the call: the report names the segment it ended, if that is known.
+    pub fn i2c_transaction(&mut self, addr: impl Into<I2cAddress>, ops: &[I2cOp]) -> Result<I2cTransactionReport> {
+        let ops = heapless::Vec::from_slice(ops).map_err(|_| Error::TooLong { max: I2C_MAX_SEGMENTS })?;
+        let addr = addr.into();
+        match self.request(&Message::I2cTransaction { addr, ops })? {
+            Response::I2cTransaction(report) => Ok(report),
+            other => Err(unexpected(Message::Response(other))),
+        }
+    }
+
+    /// Addresses of `eeprom`'s blocks that acknowledge; none if it is missing
+    ///
+    /// Its contents are read and written with [`bulk_read`](Self::bulk_read)
+    /// and [`bulk_write`](Self::bulk_write) to [`BulkTarget::I2cEeprom`].
+    pub fn i2c_eeprom_probe(&mut self, eeprom: I2cEeprom) -> Result<Vec<u8>> {
+        match self.request(&Message::I2cEepromProbe { eeprom })? {
+            Response::I2cDevices(addrs) => Ok(addrs.to_vec()),
+            other => Err(unexpected(Message::Response(other))),
+        }
+    }
+
+    /// Fill `eeprom` with 0xFF
+    ///
+    /// The device replies when every page is written, which takes a few
+    /// seconds on the larger parts; the reply timeout has to allow for it.
+    pub fn i2c_eeprom_erase(&mut self, eeprom: I2cEeprom) -> Result<()> {
+        self.command(&Message::I2cEepromErase { eeprom })
+    }
+
+    /// Check that `eeprom` holds `data` from `address` on
+    ///
+    /// Only a CRC-32 of `data` is sent; contents that differ fail with
+    /// `ChecksumMismatch`.
+    pub fn i2c_eeprom_verify(&mut self, eeprom: I2cEeprom, address: u32, data: &[u8]) -> Result<()> {
+        let len = u32::try_from(data.len()).map_err(|_| Error::TooLong {
+            max: u32::MAX as usize,
+        })?;
+        self.command(&Message::I2cEepromVerify {
+            eeprom,
+            address,
+            len,
+            crc32: crc32(data),
+        })
+    }
+
+    /// Emulate the device described by `map` on the Bus Pirate's I2C
+    /// target peripheral (I2C mode only)
+    ///
+    /// Subscribe to `Topic::I2cTarget` to see each controller access.
+    pub fn i2c_target_start(&mut self, map: I2cRegisterMap) -> Result<()> {
+        self.command(&Message::I2cTargetStart { map })
+    }
+
+    /// Stop emulating
+    pub fn i2c_target_stop(&mut self) -> Result<()> {
+        self.command(&Message::I2cTargetStop)
+    }
+
+    /// Register values of the emulated device, including what the
+    /// controller wrote
+    pub fn i2c_target_registers(&mut self) -> Result<I2cRegisterMap> {
+        match self.request(&Message::I2cTargetRegisters)? {
+            Response::I2cRegisterMap(map) => Ok(map),
+            other => Err(unexpected(Message::Response(other))),
+        }
+    }
+
+    // ===== SPI =====
+
+    /// Full-duplex transfer; returns the bytes clocked in
+    pub fn spi_transfer(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        self.data(&Message::SpiTransfer {
+            data: bounded(data)?,
+        })
+    }
+
+    // ===== UART =====
+
+    /// Send `data` on the UART
+    pub fn uart_write(&mut self, data: &[u8]) -> Result<()> {
+        self.command(&Message::UartWrite {
+            data: bounded(data)?,
+        })
+    }
+
+    /// Read up to `len` received bytes
+    pub fn uart_read(&mut self, len: u16) -> Result<Vec<u8>> {
+        self.data(&Message::UartRead { len })
+    }
+
+    /// Change the UART baud rate
+    pub fn uart_config(&mut self, baudrate: u32) -> Result<()> {
+        self.command(&Message::UartConfig { baudrate })
+    }
+
+    // ===== Configuration =====
+
+    /// Set configuration `key` to `value`
+    pub fn set_config(&mut self, key: &str, value: &str) -> Result<()> {
+        self.command(&Message::SetConfig {
+            key: bounded_str(key)?,
+            value: bounded_str(value)?,
+        })
+    }
+
+    /// Read configuration `key`
+    pub fn get_config(&mut self, key: &str) -> Result<String> {
+        match self.request(&Message::GetConfig {
+            key: bounded_str(key)?,
+        })? {
+            Response::ConfigValue(value) => Ok(value.as_str().to_owned()),
+            other => Err(unexpected(Message::Response(other))),
+        }
+    }
+
+    // ===== Files =====
+
+    /// List the files in `path`
+    pub fn file_list(&mut self, path: &str) -> Result<Vec<String>> {
+        match self.request(&Message::FileList {
+            path: bounded_str(path)?,
+        })? {
+            Response::FileList(names) => Ok(names.iter().map(|name| name.as_str().to_owned()).collect()),
+            other => Err(unexpected(Message::Response(other))),
+        }
+    }
+
+    /// Read a file that fits in a single reply
+    ///
+    /// Use [`bulk_read`](Self::bulk_read) for larger files.
+    pub fn file_read(&mut self, path: &str) -> Result<Vec<u8>> {
+        self.data(&Message::FileRead {
+            path: bounded_str(path)?,
+        })
+    }
+
+    /// Write a file that fits in a single message
+    ///
+    /// Use [`bulk_write`](Self::bulk_write) for larger files.
+    pub fn file_write(&mut self, path: &str, data: &[u8]) -> Result<()> {
+        self.command(&Message::FileWrite {
+            path: bounded_str(path)?,
+            data: bounded(data)?,
+        })
+    }
+
+    /// Run the script stored at `path` and return what it did
+    ///
+    /// The device replies when the script has finished, so the timeout
+    /// must cover its delays. The command is not re-sent after a timeout,
+    /// which would run the script a second time.
+    pub fn run_script(&mut self, path: &str) -> Result<ScriptReport> {
+        let msg = Message::RunScript {
+            path: bounded_str(path)?,
+        };
+        let retries = std::mem::replace(&mut self.retries, 0);
+        let reply = self.request(&msg);
+        self.retries = retries;
+        match reply? {
+            Response::Script(report) => Ok(report),
+            other => Err(unexpected(Message::Response(other))),
+        }
+    }
+
+    // ===== Bulk Transfers =====
+
+    /// Download `len` bytes from `target` starting at `offset`
+    ///
+    /// The device announces the transfer with `BulkBegin`; each `BulkAck`
+    /// the host sends is answered with the chunk at the acked offset, and
+    /// the final ack with `BulkEnd`. A lost chunk is recovered by re-sending
+    /// the same ack.
+    pub fn bulk_read(&mut self, target: BulkTarget, offset: u32, len: u32) -> Result<Vec<u8>> {
+        let id = self.next_transfer_id();
+        let total_len = match self.call(&Message::BulkRequest { id, target, offset, len })? {
+            Message::BulkBegin { id: begin_id, total_len, .. } if begin_id == id => total_len,
+            other => return Err(unexpected(other)),
+        };
+
+        let mut receiver = BulkReceiver::new(id, total_len);
+        let mut data = Vec::with_capacity(total_len as usize);
+        let mut next = receiver.ack();
+        loop {
+            match self.call(&next)? {
+                Message::BulkChunk { id, offset, data: chunk } => {
+                    next = receiver.on_chunk(id, offset, &chunk, |_, bytes| {
+                        data.extend_from_slice(bytes);
+                        Ok(())
+                    })?;
+                }
+                Message::BulkEnd { id, crc32 } => {
+                    receiver.on_end(id, crc32)?;
+                    return Ok(data);
+                }
+                other => return Err(unexpected(other)),
+            }
+        }
+    }
+
+    /// Upload `data` to `target`
+    pub fn bulk_write(&mut self, target: BulkTarget, data: &[u8]) -> Result<()> {
+        let total_len = u32::try_from(data.len()).map_err(|_| Error::TooLong {
+            max: u32::MAX as usize,
+        })?;
+        let mut sender = BulkSender::new(self.next_transfer_id(), target, total_len);
+
+        let reply = self.call(&sender.begin())?;
+        self.on_bulk_ack(&mut sender, reply)?;
+
+        while let Some(chunk) = sender.next_chunk(|offset, buf| {
+            let start = offset as usize;
+            buf.copy_from_slice(&data[start..start + buf.len()]);
+            Ok(())
+        })? {
+            let reply = self.call(&chunk)?;
+            self.on_bulk_ack(&mut sender, reply)?;
+        }
+
+        // `next_chunk` only returns `None` once every byte has been sent
+        let end = sender.end().ok_or(Error::Transfer(BulkError::Incomplete))?;
+        let reply = self.call(&end)?;
+        self.on_bulk_ack(&mut sender, reply)?;
+
+        if sender.is_complete() {
+            Ok(())
+        } else {
+            Err(Error::Transfer(BulkError::Incomplete))
+        }
+    }
+
+    // ===== Events =====
+
+    /// Start receiving events on `topic`
+    pub fn subscribe(&mut self, topic: Topic) -> Result<()> {
+        self.command(&Message::Subscribe { topic })
+    }
+
+    /// Stop receiving events on `topic`
+    pub fn unsubscribe(&mut self, topic: Topic) -> Result<()> {
+        self.command(&Message::Unsubscribe { topic })
+    }
+
+    /// Wait up to `timeout` for the next event
+    pub fn poll_event(&mut self, timeout: Duration) -> Result<Option<Event>> {
+        if let Some(event) = self.events.pop_front() {
+            return Ok(Some(event));
+        }
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            match self.read_frame(deadline) {
+                Ok(Frame { message: Message::Event(event), .. }) => return Ok(Some(event)),
+                // Anything else is a late reply nobody is waiting for
+                Ok(_) => {}
+                Err(Error::Timeout) => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    // ===== Internals =====
+
+    fn data(&mut self, msg: &Message) -> Result<Vec<u8>> {
+        match self.request(msg)? {
+            Response::Data(data) => Ok(data.to_vec()),
+            other => Err(unexpected(Message::Response(other))),
+        }
+    }
+
+    fn on_bulk_ack(&mut self, sender: &mut BulkSender, reply: Message) -> Result<()> {
+        match reply {
+            Message::BulkAck { id, offset } => Ok(sender.on_ack(id, offset)?),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    fn next_seq(&mut self) -> u16 {
+        // Zero is reserved for uncorrelated frames
+        self.next_seq = self.next_seq.wrapping_add(1).max(1);
+        self.next_seq
+    }
+
+    fn next_transfer_id(&mut self) -> u16 {
+        self.next_transfer = self.next_transfer.wrapping_add(1);
+        self.next_transfer
+    }
+
+    /// Wait for the reply to the command sent with `seq`
+    fn wait_reply(&mut self, seq: u16) -> Result<Message> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let frame = self.read_frame(deadline)?;
+            match frame.message {
+                Message::Event(event) => self.events.push_back(event),
+                // v1 frames carry no sequence ID, so any reply is ours
+                message if frame.seq == seq || frame.seq == UNSEQUENCED && self.version == PROTOCOL_VERSION_V1 => {
+                    return Ok(message)
+                }
+                // Late reply to an earlier attempt
+                _ => {}
+            }
+        }
+    }
+
+    /// Read until a complete frame is available or `deadline` passes
+    fn read_frame(&mut self, deadline: Instant) -> Result<Frame> {
+        let mut buf = [0u8; 256];
+        loop {
+            if let Some(frame) = self.frames.pop_front() {
+                return Ok(frame);
+            }
+            if Instant::now() >= deadline {
+                return Err(Error::Timeout);
+            }
+
+            match self.io.read(&mut buf) {
+                Ok(0) => return Err(Error::Io(io::ErrorKind::UnexpectedEof.into())),
+                Ok(n) => {
+                    let frames = &mut self.frames;
+                    // Corrupted frames are counted in the decoder stats and
+                    // recovered by the retry logic
+                    self.decoder.decode_frames(&buf[..n], |item| {
+                        if let Ok(frame) = item {
+                            frames.push_back(frame);
+                        }
+                    });
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(Duration::from_millis(1)),
+                Err(e) if matches!(e.kind(), io::ErrorKind::TimedOut | io::ErrorKind::Interrupted) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+fn unexpected(msg: Message) -> Error {
+    Error::UnexpectedReply(Box::new(msg))
+}
+
+fn bounded<const N: usize>(data: &[u8]) -> Result<heapless::Vec<u8, N>> {
+    heapless::Vec::from_slice(data).map_err(|_| Error::TooLong { max: N })
+}
+
+fn bounded_str<const N: usize>(s: &str) -> Result<heapless::String<N>> {
+    heapless::String::try_from(s).map_err(|_| Error::TooLong { max: N })
+}
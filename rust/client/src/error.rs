@@ -0,0 +1,102 @@
+//! Client error type
+
+use esp32_bus_pirate_protocol::{message::Message, BulkError, ErrorCode};
+use std::{fmt, io};
+
+/// Result type for client operations
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// Errors returned by [`Client`](crate::Client) operations
+#[derive(Debug)]
+pub enum Error {
+    /// The underlying stream failed or was closed
+    Io(io::Error),
+    /// No reply arrived within the timeout, after all retries
+    Timeout,
+    /// The device rejected the command
+    Device(ErrorCode),
+    /// A frame could not be encoded for the negotiated protocol version
+    Protocol(esp32_bus_pirate_protocol::Error),
+    /// A bulk transfer failed on the host side
+    Transfer(BulkError),
+    /// The device replied with a message that does not answer the command
+    UnexpectedReply(Box<Message>),
+    /// An argument does not fit in a protocol message
+    TooLong {
+        /// Largest accepted length
+        max: usize,
+    },
+}
+
+impl Error {
+    /// Device error code, if the device rejected the command
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            Error::Device(code) => Some(*code),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {e}"),
+            Error::Timeout => f.write_str("timed out waiting for the device"),
+            Error::Device(code) => write!(f, "device error: {}", describe(*code)),
+            Error::Protocol(e) => write!(f, "protocol error: {e:?}"),
+            Error::Transfer(e) => write!(f, "bulk transfer failed: {e:?}"),
+            Error::UnexpectedReply(msg) => write!(f, "unexpected reply: {msg:?}"),
+            Error::TooLong { max } => write!(f, "argument longer than {max} bytes"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<esp32_bus_pirate_protocol::Error> for Error {
+    fn from(e: esp32_bus_pirate_protocol::Error) -> Self {
+        Error::Protocol(e)
+    }
+}
+
+impl From<BulkError> for Error {
+    fn from(e: BulkError) -> Self {
+        Error::Transfer(e)
+    }
+}
+
+/// Human-readable description of a device error code
+pub fn describe(code: ErrorCode) -> &'static str {
+    match code {
+        ErrorCode::InvalidCommand => "invalid command",
+        ErrorCode::ProtocolError => "protocol error",
+        ErrorCode::BusError => "bus error",
+        ErrorCode::FileNotFound => "file not found",
+        ErrorCode::PermissionDenied => "permission denied",
+        ErrorCode::Timeout => "operation timed out on the device",
+        ErrorCode::NotConfigured => "bus mode not configured",
+        ErrorCode::InvalidParameter => "invalid parameter",
+        ErrorCode::ChecksumMismatch => "transfer checksum mismatch",
+        ErrorCode::UnknownTransfer => "unknown transfer",
+        ErrorCode::Busy => "bus is in use by another client",
+        ErrorCode::AssertionFailed => "script assertion failed",
+        ErrorCode::AddressNack => "address not acknowledged",
+        ErrorCode::DataNack => "data not acknowledged",
+        ErrorCode::ArbitrationLost => "bus arbitration lost",
+        ErrorCode::Unsupported => "not supported by this board",
+    }
+}
@@ -0,0 +1,34 @@
+//! Host-side client for the ESP32 Bus Pirate binary protocol
+//!
+//! The client reuses the protocol crate's [`Message`] types and
+//! [`MessageCodec`](esp32_bus_pirate_protocol::MessageCodec), so it always
+//! matches the firmware it is built with. It works over any `Read + Write`
+//! byte stream: a serial port, a TCP socket or a pipe to a simulator.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use esp32_bus_pirate_client::Client;
+//! use esp32_bus_pirate_protocol::Mode;
+//! use std::{net::TcpStream, time::Duration};
+//!
+//! let stream = TcpStream::connect("192.168.4.1:5555")?;
+//! stream.set_read_timeout(Some(Duration::from_millis(50)))?;
+//!
+//! let mut client = Client::connect(stream)?;
+//! client.set_mode(Mode::I2c)?;
+//! for addr in client.i2c_scan()? {
+//!     println!("found device at 0x{addr:02X}");
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+pub mod client;
+pub mod error;
+
+pub use client::{Client, DEFAULT_RETRIES, DEFAULT_TIMEOUT};
+pub use error::{Error, Result};
+pub use esp32_bus_pirate_protocol::{
+    BulkTarget, DeviceInfo, EepromPart, ErrorCode, Event, I2cAddress, I2cChip, I2cEeprom, I2cRegister, I2cRegisterMap, I2cScanEntry, I2cScanOptions, I2cScanProbe, I2cScanReport, I2cSpeed, I2cSweepEntry, I2cSweepReport, I2cTargetAccess, I2cNack, I2cOp, I2cTransactionReport, Message, Mode, NackSource,
+    Response, ScriptError, ScriptReport, Topic,
+};
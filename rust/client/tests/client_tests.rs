@@ -0,0 +1,424 @@
+//! Client tests against an in-memory mock device
+
+use esp32_bus_pirate_client::{Client, Error};
+use esp32_bus_pirate_protocol::{
+    bulk::crc32,
+    codec::{Frame, MessageCodec},
+    message::*,
+    BulkReceiver, BulkSender, FrameDecoder, PROTOCOL_VERSION_V1, PROTOCOL_VERSION_V2,
+};
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    time::Duration,
+};
+
+/// Stream that feeds every written frame to `handler` and returns the
+/// bytes it produces on subsequent reads
+struct MockDevice<F> {
+    decoder: FrameDecoder,
+    rx: VecDeque<u8>,
+    received: Vec<Frame>,
+    handler: F,
+    closed: bool,
+}
+
+impl<F: FnMut(&Frame) -> Vec<u8>> MockDevice<F> {
+    fn new(handler: F) -> Self {
+        Self {
+            decoder: FrameDecoder::new(),
+            rx: VecDeque::new(),
+            received: Vec::new(),
+            handler,
+            closed: false,
+        }
+    }
+}
+
+impl<F: FnMut(&Frame) -> Vec<u8>> Write for MockDevice<F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut frames = Vec::new();
+        self.decoder.decode_frames(buf, |item| frames.push(item.unwrap()));
+        for frame in frames {
+            let reply = (self.handler)(&frame);
+            self.rx.extend(reply);
+            self.received.push(frame);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<F> Read for MockDevice<F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.rx.is_empty() {
+            return if self.closed {
+                Ok(0)
+            } else {
+                Err(io::ErrorKind::WouldBlock.into())
+            };
+        }
+        let n = buf.len().min(self.rx.len());
+        for (dst, src) in buf.iter_mut().zip(self.rx.drain(..n)) {
+            *dst = src;
+        }
+        Ok(n)
+    }
+}
+
+fn reply(request: &Frame, msg: Message) -> Vec<u8> {
+    MessageCodec::encode_reply(request, &msg).unwrap().to_vec()
+}
+
+fn respond(request: &Frame, response: Response) -> Vec<u8> {
+    reply(request, Message::Response(response))
+}
+
+fn client<F: FnMut(&Frame) -> Vec<u8>>(handler: F) -> Client<MockDevice<F>> {
+    Client::new(MockDevice::new(handler)).with_timeout(Duration::from_millis(20))
+}
+
+fn device_info(version: u8) -> DeviceInfo {
+    DeviceInfo {
+        version,
+        versions: heapless::Vec::from_slice(&[PROTOCOL_VERSION_V1, PROTOCOL_VERSION_V2]).unwrap(),
+        max_frame: 1024,
+        firmware_version: heapless::String::try_from("0.1.0").unwrap(),
+        board: heapless::String::try_from("mock").unwrap(),
+        modes: heapless::Vec::from_slice(&[Mode::HiZ, Mode::I2c]).unwrap(),
+        rx_buffer: 1024,
+        tx_buffer: 1024,
+        features: Features::SEQUENCE_IDS | Features::EVENTS,
+    }
+}
+
+// ===== Typed Commands =====
+
+#[test]
+fn test_i2c_scan() {
+    let mut client = client(|frame| match frame.message {
+        Message::I2cScan => respond(frame, Response::I2cDevices(heapless::Vec::from_slice(&[0x50, 0x68]).unwrap())),
+        _ => Vec::new(),
+    });
+
+    assert_eq!(client.i2c_scan().unwrap(), vec![0x50, 0x68]);
+}
+
+#[test]
+fn test_spi_transfer() {
+    let mut client = client(|frame| match &frame.message {
+        Message::SpiTransfer { data } => {
+            let echoed: heapless::Vec<u8, 512> = data.iter().map(|b| !b).collect();
+            respond(frame, Response::Data(echoed))
+        }
+        _ => Vec::new(),
+    });
+
+    assert_eq!(client.spi_transfer(&[0x9F, 0x00]).unwrap(), vec![0x60, 0xFF]);
+}
+
+#[test]
+fn test_set_and_get_mode() {
+    let mut mode = Mode::HiZ;
+    let mut client = client(move |frame| match frame.message {
+        Message::SetMode { mode: new } => {
+            mode = new;
+            respond(frame, Response::Success)
+        }
+        Message::GetMode => respond(frame, Response::CurrentMode(mode)),
+        _ => Vec::new(),
+    });
+
+    client.set_mode(Mode::Spi).unwrap();
+    assert_eq!(client.get_mode().unwrap(), Mode::Spi);
+}
+
+#[test]
+fn test_register_and_file_helpers() {
+    let mut client = client(|frame| match &frame.message {
+        Message::I2cReadRegister { reg, .. } => {
+            respond(frame, Response::Data(heapless::Vec::from_slice(&[*reg + 1]).unwrap()))
+        }
+        Message::FileList { .. } => {
+            let mut names = heapless::Vec::new();
+            names.push(heapless::String::try_from("boot.txt").unwrap()).unwrap();
+            respond(frame, Response::FileList(names))
+        }
+        Message::GetConfig { .. } => {
+            respond(frame, Response::ConfigValue(heapless::String::try_from("400000").unwrap()))
+        }
+        _ => Vec::new(),
+    });
+
+    assert_eq!(client.i2c_read_register(0x50, 0x10).unwrap(), 0x11);
+    assert_eq!(client.file_list("/").unwrap(), vec!["boot.txt".to_owned()]);
+    assert_eq!(client.get_config("i2c_frequency").unwrap(), "400000");
+}
+
+// ===== Errors =====
+
+#[test]
+fn test_device_error_is_typed() {
+    let mut client = client(|frame| reply(frame, Message::Error(ErrorCode::BusError)));
+
+    let err = client.i2c_write(0x50, &[0x00]).unwrap_err();
+    assert!(matches!(err, Error::Device(ErrorCode::BusError)));
+    assert_eq!(err.code(), Some(ErrorCode::BusError));
+}
+
+#[test]
+fn test_unexpected_reply() {
+    let mut client = client(|frame| respond(frame, Response::Success));
+
+    assert!(matches!(client.i2c_scan(), Err(Error::UnexpectedReply(_))));
+}
+
+#[test]
+fn test_argument_too_long_is_not_sent() {
+    let mut client = client(|_| Vec::new());
+
+    let err = client.spi_transfer(&[0; 300]).unwrap_err();
+    assert!(matches!(err, Error::TooLong { max: 256 }));
+    assert!(client.get_mut().received.is_empty());
+}
+
+#[test]
+fn test_closed_stream() {
+    let mut client = client(|_| Vec::new());
+    client.get_mut().closed = true;
+
+    let err = client.get_mode().unwrap_err();
+    assert!(matches!(err, Error::Io(e) if e.kind() == io::ErrorKind::UnexpectedEof));
+}
+
+// ===== Timeouts and Retries =====
+
+#[test]
+fn test_retry_after_lost_reply() {
+    let mut attempts = 0;
+    let mut client = client(move |frame| {
+        attempts += 1;
+        if attempts == 1 {
+            Vec::new()
+        } else {
+            respond(frame, Response::CurrentMode(Mode::Uart))
+        }
+    });
+
+    assert_eq!(client.get_mode().unwrap(), Mode::Uart);
+
+    // Each attempt uses a fresh sequence ID
+    let seqs: Vec<u16> = client.get_mut().received.iter().map(|f| f.seq).collect();
+    assert_eq!(seqs.len(), 2);
+    assert_ne!(seqs[0], seqs[1]);
+}
+
+#[test]
+fn test_timeout_after_all_retries() {
+    let mut client = client(|_| Vec::new()).with_retries(3);
+
+    assert!(matches!(client.get_mode(), Err(Error::Timeout)));
+    assert_eq!(client.get_mut().received.len(), 4);
+}
+
+#[test]
+fn test_stale_reply_is_ignored() {
+    let mut client = client(|frame| {
+        // A late reply to some earlier command arrives first
+        let stale = Frame {
+            seq: frame.seq.wrapping_sub(1),
+            ..frame.clone()
+        };
+        let mut out = respond(&stale, Response::CurrentMode(Mode::Jtag));
+        out.extend(respond(frame, Response::CurrentMode(Mode::I2c)));
+        out
+    });
+
+    assert_eq!(client.get_mode().unwrap(), Mode::I2c);
+}
+
+#[test]
+fn test_corrupted_reply_is_retried() {
+    let mut attempts = 0;
+    let mut client = client(move |frame| {
+        attempts += 1;
+        let mut out = respond(frame, Response::CurrentMode(Mode::Spi));
+        if attempts == 1 {
+            out[6] ^= 0xFF;
+        }
+        out
+    });
+
+    assert_eq!(client.get_mode().unwrap(), Mode::Spi);
+}
+
+// ===== Handshake =====
+
+#[test]
+fn test_handshake_negotiates_v2() {
+    let mut client = client(|frame| match &frame.message {
+        Message::Hello { versions, .. } => {
+            assert!(versions.contains(&PROTOCOL_VERSION_V2));
+            respond(frame, Response::DeviceInfo(device_info(PROTOCOL_VERSION_V2)))
+        }
+        _ => Vec::new(),
+    });
+
+    let info = client.handshake().unwrap().unwrap().clone();
+    assert_eq!(info.board.as_str(), "mock");
+    assert_eq!(client.version(), PROTOCOL_VERSION_V2);
+    assert!(client.device_info().unwrap().features.contains(Features::EVENTS));
+}
+
+#[test]
+fn test_handshake_falls_back_to_v1() {
+    // A v1 device drops v2 frames and only answers v1 ones
+    let mut client = client(|frame| {
+        if frame.version == PROTOCOL_VERSION_V1 {
+            respond(frame, Response::CurrentMode(Mode::HiZ))
+        } else {
+            Vec::new()
+        }
+    })
+    .with_retries(0);
+
+    assert!(client.handshake().unwrap().is_none());
+    assert_eq!(client.version(), PROTOCOL_VERSION_V1);
+
+    assert_eq!(client.get_mode().unwrap(), Mode::HiZ);
+    assert_eq!(client.get_mut().received.last().unwrap().version, PROTOCOL_VERSION_V1);
+}
+
+#[test]
+fn test_handshake_rejected_falls_back_to_v1() {
+    let mut client = client(|frame| reply(frame, Message::Error(ErrorCode::InvalidCommand)));
+
+    assert!(client.handshake().unwrap().is_none());
+    assert_eq!(client.version(), PROTOCOL_VERSION_V1);
+}
+
+// ===== Events =====
+
+#[test]
+fn test_events_arriving_with_replies_are_queued() {
+    let mut client = client(|frame| {
+        let event = Message::Event(Event::UartRx {
+            data: heapless::Vec::from_slice(b"boot").unwrap(),
+        });
+        let mut out = MessageCodec::encode(&event).unwrap().to_vec();
+        out.extend(respond(frame, Response::Success));
+        out
+    });
+
+    client.subscribe(Topic::UartRx).unwrap();
+
+    let event = client.poll_event(Duration::from_millis(10)).unwrap();
+    assert!(matches!(event, Some(Event::UartRx { data }) if data.as_slice() == b"boot"));
+    assert_eq!(client.poll_event(Duration::from_millis(10)).unwrap(), None);
+}
+
+// ===== Bulk Transfers =====
+
+#[test]
+fn test_bulk_read() {
+    let payload: Vec<u8> = (0..5000u32).map(|i| (i * 7) as u8).collect();
+    let source = payload.clone();
+    let mut sender: Option<BulkSender> = None;
+    let mut dropped_one = false;
+
+    let mut client = client(move |frame| match &frame.message {
+        Message::BulkRequest { id, target, len, .. } => {
+            let s = BulkSender::new(*id, target.clone(), *len);
+            let out = reply(frame, s.begin());
+            sender = Some(s);
+            out
+        }
+        Message::BulkAck { id, offset } => {
+            let s = sender.as_mut().unwrap();
+            s.on_ack(*id, *offset).unwrap();
+            let chunk = s
+                .next_chunk(|off, buf| {
+                    buf.copy_from_slice(&source[off as usize..off as usize + buf.len()]);
+                    Ok(())
+                })
+                .unwrap();
+            match chunk {
+                // Lose one chunk on the way back to exercise the retry
+                Some(_) if *offset > 0 && !dropped_one => {
+                    dropped_one = true;
+                    Vec::new()
+                }
+                Some(chunk) => reply(frame, chunk),
+                None => reply(frame, s.end().unwrap()),
+            }
+        }
+        _ => Vec::new(),
+    });
+
+    let data = client
+        .bulk_read(BulkTarget::SpiFlash { address: 0 }, 0, payload.len() as u32)
+        .unwrap();
+    assert_eq!(data, payload);
+}
+
+#[test]
+fn test_bulk_write() {
+    let payload: Vec<u8> = (0..3000u32).map(|i| (i ^ 0x5A) as u8).collect();
+    let mut receiver: Option<BulkReceiver> = None;
+    let mut stored = Vec::new();
+
+    let mut client = client(move |frame| match &frame.message {
+        Message::BulkBegin { id, total_len, .. } => {
+            let r = BulkReceiver::new(*id, *total_len);
+            let out = reply(frame, r.ack());
+            receiver = Some(r);
+            out
+        }
+        Message::BulkChunk { id, offset, data } => {
+            let r = receiver.as_mut().unwrap();
+            let ack = r
+                .on_chunk(*id, *offset, data, |_, bytes| {
+                    stored.extend_from_slice(bytes);
+                    Ok(())
+                })
+                .unwrap();
+            reply(frame, ack)
+        }
+        Message::BulkEnd { id, crc32: crc } => {
+            assert_eq!(*crc, crc32(&stored));
+            match receiver.as_mut().unwrap().on_end(*id, *crc) {
+                Ok(ack) => reply(frame, ack),
+                Err(e) => reply(frame, Message::Error(e.into())),
+            }
+        }
+        _ => Vec::new(),
+    });
+
+    client
+        .bulk_write(BulkTarget::File(heapless::String::try_from("/dump.bin").unwrap()), &payload)
+        .unwrap();
+}
+
+#[test]
+fn test_bulk_write_checksum_error() {
+    let mut client = client(|frame| match &frame.message {
+        Message::BulkBegin { id, .. } => reply(frame, Message::BulkAck { id: *id, offset: 0 }),
+        Message::BulkChunk { id, offset, data } => reply(
+            frame,
+            Message::BulkAck {
+                id: *id,
+                offset: offset + data.len() as u32,
+            },
+        ),
+        Message::BulkEnd { .. } => reply(frame, Message::Error(ErrorCode::ChecksumMismatch)),
+        _ => Vec::new(),
+    });
+
+    let err = client
+        .bulk_write(BulkTarget::SpiFlash { address: 0 }, &[1, 2, 3])
+        .unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::ChecksumMismatch));
+}
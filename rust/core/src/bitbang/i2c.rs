@@ -0,0 +1,219 @@
+//! Binary I2C sub-mode (`I2C1`)
+//!
+//! | Byte          | Command                                  | Reply                     |
+//! |---------------|------------------------------------------|---------------------------|
+//! | `0x02`        | Start                                    | `0x01`                    |
+//! | `0x03`        | Stop                                     | `0x01`, or `0x00` if a transfer since the start failed |
+//! | `0x04`        | Read a byte                              | The byte                  |
+//! | `0x06`/`0x07` | ACK / NACK the byte read                 | `0x01`                    |
+//! | `0x08`        | Write then read                          | `0x01`, bytes read        |
+//! | `0x1n`        | Write `n + 1` bytes                      | `0x01`, ACK (`0x00`) or NACK (`0x01`) per byte |
+//! | `0x60`–`0x63` | Speed: 5, 50, 100, 400 kHz               | `0x01`                    |
+//!
+//! Write then read takes a big-endian write count and read count, then the
+//! address byte and the bytes to write, and runs as one transaction.
+//!
+//! The [`I2c`] trait has no bare start and stop conditions, so the
+//! byte-level commands are mapped onto transactions the way a device sees
+//! them:
+//!
+//! - The first byte written after a start is the address byte. A new
+//!   device is probed with an empty write, which gives its ACK. A repeated
+//!   start to the same device continues with it.
+//! - Bytes written to a device are held and sent as one write at the stop,
+//!   or before the first read after a repeated start. Their ACKs only say
+//!   the bytes were taken: the device's answer comes with the stop, which
+//!   replies `0x00` if the write, or any read since the start, failed.
+//! - Each read is a transaction of its own, which devices that step their
+//!   address pointer (EEPROMs, sensors) cannot tell from a longer read.
+//!   ACK and NACK after a read have nothing left to do.
+
+use super::{bulk_len, counts, FAIL, HEADER_LEN, OK};
+use embedded_hal::i2c::{I2c, Operation};
+use embedded_io::Write;
+use esp32_bus_pirate_bus_modes::{
+    i2c::{I2cConfig, I2cFault, I2cMode},
+    BusMode,
+};
+use heapless::Vec;
+
+/// Reply to `0x01`
+pub const VERSION: &[u8] = b"I2C1";
+
+/// Bytes held for one write
+pub const WRITE_SIZE: usize = 256;
+
+/// Clock selected by `0x60`–`0x63`
+const SPEEDS: [u32; 4] = [5_000, 50_000, 100_000, 400_000];
+
+const ACK: u8 = 0x00;
+const NACK: u8 = 0x01;
+
+/// What the bus would be carrying between commands
+pub(super) struct I2cState {
+    /// Device addressed since the last start, and whether for reading
+    target: Option<(u8, bool)>,
+    /// The next byte written is an address byte
+    after_start: bool,
+    /// Bytes written to the target and not yet sent
+    data: Vec<u8, WRITE_SIZE>,
+    /// A transfer since the start was not acknowledged or failed
+    failed: bool,
+}
+
+impl I2cState {
+    pub(super) const fn new() -> Self {
+        Self {
+            target: None,
+            after_start: false,
+            data: Vec::new(),
+            failed: false,
+        }
+    }
+
+    /// Send held bytes to the target
+    fn flush<I: I2c<Error: I2cFault>>(&mut self, i2c: &mut I2cMode<I>) {
+        if let Some((addr, _)) = self.target {
+            if !self.data.is_empty() {
+                // The bytes were acknowledged to the host; the stop reports
+                // what the device said
+                self.failed |= i2c.write(addr, &self.data).is_err();
+                self.data.clear();
+            }
+        }
+    }
+
+    fn write<I: I2c<Error: I2cFault>>(&mut self, i2c: &mut I2cMode<I>, byte: u8) -> u8 {
+        if core::mem::take(&mut self.after_start) {
+            let (addr, read) = (byte >> 1, byte & 1 == 1);
+            if self.target.is_some_and(|(target, _)| target == addr) {
+                self.target = Some((addr, read));
+                return ACK;
+            }
+            self.flush(i2c);
+            self.target = None;
+            if i2c.write(addr, &[]).is_err() {
+                return NACK;
+            }
+            self.target = Some((addr, read));
+            return ACK;
+        }
+
+        match self.target {
+            Some((_, false)) if self.data.push(byte).is_ok() => ACK,
+            _ => NACK,
+        }
+    }
+
+    fn read<I: I2c<Error: I2cFault>>(&mut self, i2c: &mut I2cMode<I>) -> u8 {
+        let Some((addr, true)) = self.target else {
+            // Nothing is driving the bus, so it reads high
+            return 0xFF;
+        };
+        let mut byte = [0xFF];
+        let done = if self.data.is_empty() {
+            i2c.read(addr, &mut byte)
+        } else {
+            let done = i2c.transaction(addr, &mut [Operation::Write(&self.data), Operation::Read(&mut byte)]);
+            self.data.clear();
+            done
+        };
+        if done.is_ok() {
+            byte[0]
+        } else {
+            self.failed = true;
+            0xFF
+        }
+    }
+
+    /// Send held bytes and end the transfer; whether everything since the
+    /// start went through
+    fn stop<I: I2c<Error: I2cFault>>(&mut self, i2c: &mut I2cMode<I>) -> bool {
+        self.flush(i2c);
+        self.target = None;
+        self.after_start = false;
+        !core::mem::take(&mut self.failed)
+    }
+}
+
+pub(super) fn is_write_then_read(command: u8) -> bool {
+    command == 0x08
+}
+
+/// Bytes `command` takes before it can run
+pub(super) fn arg_len(command: u8) -> usize {
+    match command {
+        0x08 => HEADER_LEN,
+        0x10..=0x1F => bulk_len(command),
+        _ => 0,
+    }
+}
+
+/// Run a command without arguments
+pub(super) fn command<I: I2c<Error: I2cFault>, W: Write>(
+    state: &mut I2cState,
+    i2c: &mut I2cMode<I>,
+    command: u8,
+    out: &mut W,
+) -> Result<(), W::Error> {
+    let reply = match command {
+        0x02 => {
+            state.after_start = true;
+            OK
+        }
+        0x03 => {
+            if state.stop(i2c) {
+                OK
+            } else {
+                FAIL
+            }
+        }
+        0x04 => state.read(i2c),
+        0x06 | 0x07 => OK,
+        0x60..=0x63 => {
+            let frequency = SPEEDS[usize::from(command & 0x03)];
+            if i2c.init(I2cConfig { frequency }).is_ok() {
+                OK
+            } else {
+                FAIL
+            }
+        }
+        _ => FAIL,
+    };
+    out.write_all(&[reply])
+}
+
+/// Run `command` with its arguments in `args`, reading into `spare`
+pub(super) fn run<I: I2c<Error: I2cFault>, W: Write>(
+    state: &mut I2cState,
+    i2c: &mut I2cMode<I>,
+    command: u8,
+    args: &mut [u8],
+    spare: &mut [u8],
+    out: &mut W,
+) -> Result<(), W::Error> {
+    if is_write_then_read(command) {
+        let (_, len) = counts(args);
+        let addr = args[HEADER_LEN] >> 1;
+        let write = &args[HEADER_LEN + 1..];
+        let read = &mut spare[..len];
+        let done = match (write.is_empty(), read.is_empty()) {
+            (_, true) => i2c.write(addr, write),
+            (true, false) => i2c.read(addr, read),
+            (false, false) => i2c.transaction(addr, &mut [Operation::Write(write), Operation::Read(read)]),
+        };
+        if done.is_err() {
+            return out.write_all(&[FAIL]);
+        }
+        out.write_all(&[OK])?;
+        return out.write_all(&spare[..len]);
+    }
+
+    // Bulk write
+    out.write_all(&[OK])?;
+    for &byte in args.iter() {
+        let ack = state.write(i2c, byte);
+        out.write_all(&[ack])?;
+    }
+    Ok(())
+}
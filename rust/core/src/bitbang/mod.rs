@@ -0,0 +1,362 @@
+//! Binary "bitbang" mode of the original Bus Pirate (BBIO)
+//!
+//! Tools written for the Bus Pirate v3, such as flashrom, talk to it in a
+//! raw binary protocol rather than the framed one. They send `0x00` until
+//! it answers `BBIO1` (the [`ConsolePort`](crate::transport::ConsolePort)
+//! switches to bitbang mode after 20 of them), then pick a sub-mode:
+//!
+//! | Byte   | In `BBIO1`                    | Reply          |
+//! |--------|-------------------------------|----------------|
+//! | `0x00` | Reset to `BBIO1`              | `BBIO1`        |
+//! | `0x01` | SPI ([`spi`])                 | `SPI1`         |
+//! | `0x02` | I2C ([`i2c`])                 | `I2C1`         |
+//! | `0x03` | UART ([`uart`])               | `ART1`         |
+//! | `0x0F` | Leave bitbang mode            | `0x01`         |
+//!
+//! In every sub-mode `0x00` goes back to `BBIO1`, `0x01` repeats the
+//! sub-mode's version string, and `0x4x` (power, pull-ups, AUX and CS pin
+//! setup) is acknowledged but has no effect, as the board cannot switch
+//! them. Commands that take data, like the write-then-read commands, wait
+//! for all of it before running; replies are `0x01` for success and `0x00`
+//! for failure or anything not supported, such as the pin-level bitbang
+//! commands, self tests and the sniffers.
+//!
+//! The sub-modes drive the same [`Dispatcher`] modes as the framed
+//! protocol, and entering one takes the bus lease like `SetMode` does.
+//!
+//! ```rust,ignore
+//! if usb.console_mut().mode() == ConsoleMode::Bitbang {
+//!     if bitbang.input(&input[..n], &mut ctx, usb.console_mut())? == Control::Exit {
+//!         usb.console_mut().leave_bitbang();
+//!     }
+//! }
+//! ```
+
+pub mod i2c;
+pub mod spi;
+pub mod uart;
+
+use crate::dispatcher::Dispatcher;
+use crate::shell::ShellContext;
+use crate::platform::Platform;
+use crate::storage::Storage;
+use embedded_hal::{i2c::I2c, spi::SpiDevice};
+use embedded_io::{Read, ReadReady, Write};
+use esp32_bus_pirate_bus_modes::i2c::I2cFault;
+use esp32_bus_pirate_protocol::{Message, Mode, Response};
+
+/// Largest write-then-read transfer, header and both directions included
+pub const BUFFER_SIZE: usize = 4096;
+
+/// Reply to `0x00` in every mode
+pub const BBIO_VERSION: &[u8] = b"BBIO1";
+
+/// Command succeeded
+const OK: u8 = 0x01;
+
+/// Command failed or is not supported
+const FAIL: u8 = 0x00;
+
+/// Write and read counts before a write-then-read command's data
+const HEADER_LEN: usize = 4;
+
+/// Sub-mode the host has selected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitbangMode {
+    /// Top level, waiting for a sub-mode
+    Bbio,
+    /// Binary SPI
+    Spi,
+    /// Binary I2C
+    I2c,
+    /// Binary UART
+    Uart,
+    /// Transparent UART bridge, left only by disconnecting
+    UartBridge,
+}
+
+/// Whether the console should stay in bitbang mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    /// Keep passing bytes to [`Bitbang::input`]
+    Continue,
+    /// The host left bitbang mode
+    Exit,
+}
+
+/// Bitbang protocol state machine
+pub struct Bitbang {
+    mode: BitbangMode,
+    /// Command waiting for argument bytes, and the length it needs
+    pending: Option<(u8, usize)>,
+    args: [u8; BUFFER_SIZE],
+    len: usize,
+    i2c: i2c::I2cState,
+    /// Forward UART receive data to the host
+    uart_echo: bool,
+}
+
+impl Default for Bitbang {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bitbang {
+    /// Start at the top level, as just entered
+    pub const fn new() -> Self {
+        Self {
+            mode: BitbangMode::Bbio,
+            pending: None,
+            args: [0; BUFFER_SIZE],
+            len: 0,
+            i2c: i2c::I2cState::new(),
+            uart_echo: false,
+        }
+    }
+
+    /// Sub-mode the host has selected
+    pub fn mode(&self) -> BitbangMode {
+        self.mode
+    }
+
+    /// Forget everything, e.g. after the host has disconnected
+    pub fn reset(&mut self) {
+        self.mode = BitbangMode::Bbio;
+        self.pending = None;
+        self.len = 0;
+        self.i2c = i2c::I2cState::new();
+        self.uart_echo = false;
+    }
+
+    /// Run the commands in `input`, writing replies to `out`
+    ///
+    /// Bytes after a command that leaves bitbang mode are dropped.
+    pub fn input<I, S, U, F, P, D, W, const N: usize>(
+        &mut self,
+        input: &[u8],
+        ctx: &mut ShellContext<'_, I, S, U, F, P, D, N>,
+        out: &mut W,
+    ) -> Result<Control, W::Error>
+    where
+        I: I2c<Error: I2cFault>,
+        S: SpiDevice,
+        U: Read + ReadReady + Write,
+        F: Storage,
+        P: Platform,
+        W: Write,
+    {
+        for (i, &byte) in input.iter().enumerate() {
+            if self.mode == BitbangMode::UartBridge {
+                // A bridge has no commands left to look for
+                let _ = ctx.dispatcher.uart_mode().write(&input[i..]);
+                break;
+            }
+            match self.pending {
+                Some((command, need)) => {
+                    self.args[self.len] = byte;
+                    self.len += 1;
+                    if self.len == need {
+                        self.pending = None;
+                        self.run(command, ctx.dispatcher, out)?;
+                    }
+                }
+                None => {
+                    if self.command(byte, ctx, out)? == Control::Exit {
+                        return Ok(Control::Exit);
+                    }
+                }
+            }
+        }
+        Ok(Control::Continue)
+    }
+
+    /// Forward UART receive data while the host has asked for it
+    pub fn poll<I, S, U, F, P, W>(&mut self, dispatcher: &mut Dispatcher<I, S, U, F, P>, out: &mut W) -> Result<(), W::Error>
+    where
+        I: I2c<Error: I2cFault>,
+        S: SpiDevice,
+        U: Read + ReadReady + Write,
+        F: Storage,
+        P: Platform,
+        W: Write,
+    {
+        let echo = match self.mode {
+            BitbangMode::Uart => self.uart_echo,
+            BitbangMode::UartBridge => true,
+            _ => false,
+        };
+        if echo {
+            uart::forward(dispatcher.uart_mode(), out)?;
+        }
+        Ok(())
+    }
+
+    fn command<I, S, U, F, P, D, W, const N: usize>(
+        &mut self,
+        byte: u8,
+        ctx: &mut ShellContext<'_, I, S, U, F, P, D, N>,
+        out: &mut W,
+    ) -> Result<Control, W::Error>
+    where
+        I: I2c<Error: I2cFault>,
+        S: SpiDevice,
+        U: Read + ReadReady + Write,
+        F: Storage,
+        P: Platform,
+        W: Write,
+    {
+        if self.mode == BitbangMode::Bbio {
+            return self.top_level(byte, ctx, out);
+        }
+
+        match byte {
+            0x00 => {
+                // Like the original, leaving a sub-mode puts the pins in HiZ
+                set_mode(ctx, Mode::HiZ);
+                self.reset();
+                out.write_all(BBIO_VERSION)?;
+            }
+            0x01 => out.write_all(self.version())?,
+            0x40..=0x4F => out.write_all(&[OK])?,
+            _ => {
+                let need = match self.mode {
+                    BitbangMode::Spi => spi::arg_len(byte),
+                    BitbangMode::I2c => i2c::arg_len(byte),
+                    _ => uart::arg_len(byte),
+                };
+                if need > 0 {
+                    self.pending = Some((byte, need));
+                    self.len = 0;
+                    return Ok(Control::Continue);
+                }
+                let dispatcher = &mut *ctx.dispatcher;
+                match self.mode {
+                    BitbangMode::Spi => spi::command(dispatcher.spi_mode(), byte, out)?,
+                    BitbangMode::I2c => i2c::command(&mut self.i2c, dispatcher.i2c_mode(), byte, out)?,
+                    _ => {
+                        if let Some(mode) = uart::command(dispatcher.uart_mode(), &mut self.uart_echo, byte, out)? {
+                            self.mode = mode;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(Control::Continue)
+    }
+
+    fn top_level<I, S, U, F, P, D, W, const N: usize>(
+        &mut self,
+        byte: u8,
+        ctx: &mut ShellContext<'_, I, S, U, F, P, D, N>,
+        out: &mut W,
+    ) -> Result<Control, W::Error>
+    where
+        I: I2c<Error: I2cFault>,
+        S: SpiDevice,
+        U: Read + ReadReady + Write,
+        F: Storage,
+        P: Platform,
+        W: Write,
+    {
+        let (mode, bus) = match byte {
+            0x00 => {
+                out.write_all(BBIO_VERSION)?;
+                return Ok(Control::Continue);
+            }
+            0x01 => (BitbangMode::Spi, Mode::Spi),
+            0x02 => (BitbangMode::I2c, Mode::I2c),
+            0x03 => (BitbangMode::Uart, Mode::Uart),
+            0x0F => {
+                set_mode(ctx, Mode::HiZ);
+                self.reset();
+                out.write_all(&[OK])?;
+                out.flush()?;
+                return Ok(Control::Exit);
+            }
+            _ => {
+                out.write_all(&[FAIL])?;
+                return Ok(Control::Continue);
+            }
+        };
+
+        // Entering fails while another client holds the bus
+        if set_mode(ctx, bus) {
+            self.mode = mode;
+            out.write_all(self.version())
+        } else {
+            out.write_all(&[FAIL])
+        }
+        .map(|_| Control::Continue)
+    }
+
+    /// Run `command` now that its arguments are in
+    fn run<I, S, U, F, P, W>(&mut self, command: u8, dispatcher: &mut Dispatcher<I, S, U, F, P>, out: &mut W) -> Result<(), W::Error>
+    where
+        I: I2c<Error: I2cFault>,
+        S: SpiDevice,
+        U: Read + ReadReady + Write,
+        F: Storage,
+        P: Platform,
+        W: Write,
+    {
+        let write_then_read = match self.mode {
+            BitbangMode::Spi => spi::is_write_then_read(command),
+            BitbangMode::I2c => i2c::is_write_then_read(command),
+            _ => false,
+        };
+        if write_then_read && self.len == HEADER_LEN {
+            let (write, read) = counts(&self.args);
+            if HEADER_LEN + write + read > BUFFER_SIZE || (self.mode == BitbangMode::I2c && write == 0) {
+                return out.write_all(&[FAIL]);
+            }
+            if write > 0 {
+                // Now wait for the data
+                self.pending = Some((command, HEADER_LEN + write));
+                return Ok(());
+            }
+        }
+
+        let (args, spare) = self.args.split_at_mut(self.len);
+        self.len = 0;
+        match self.mode {
+            BitbangMode::Spi => spi::run(dispatcher.spi_mode(), command, args, spare, out),
+            BitbangMode::I2c => i2c::run(&mut self.i2c, dispatcher.i2c_mode(), command, args, spare, out),
+            _ => uart::run(dispatcher.uart_mode(), command, args, out),
+        }
+    }
+
+    fn version(&self) -> &'static [u8] {
+        match self.mode {
+            BitbangMode::Bbio => BBIO_VERSION,
+            BitbangMode::Spi => spi::VERSION,
+            BitbangMode::I2c => i2c::VERSION,
+            BitbangMode::Uart | BitbangMode::UartBridge => uart::VERSION,
+        }
+    }
+}
+
+/// Switch the dispatcher to `mode` through the session manager, so the bus
+/// lease applies
+fn set_mode<I, S, U, F, P, D, const N: usize>(ctx: &mut ShellContext<'_, I, S, U, F, P, D, N>, mode: Mode) -> bool
+where
+    I: I2c<Error: I2cFault>,
+    S: SpiDevice,
+    U: Read + ReadReady + Write,
+    F: Storage,
+    P: Platform,
+{
+    ctx.sessions.handle(ctx.dispatcher, ctx.id, &Message::SetMode { mode }) == Message::Response(Response::Success)
+}
+
+/// Write and read counts from a write-then-read header
+fn counts(header: &[u8]) -> (usize, usize) {
+    let write = u16::from_be_bytes([header[0], header[1]]);
+    let read = u16::from_be_bytes([header[2], header[3]]);
+    (usize::from(write), usize::from(read))
+}
+
+/// Bytes following a bulk command `0x1n`: one to sixteen
+fn bulk_len(command: u8) -> usize {
+    usize::from(command & 0x0F) + 1
+}
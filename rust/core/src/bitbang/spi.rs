@@ -0,0 +1,93 @@
+//! Binary SPI sub-mode (`SPI1`)
+//!
+//! | Byte          | Command                                          | Reply                 |
+//! |---------------|--------------------------------------------------|-----------------------|
+//! | `0x02`/`0x03` | Chip select low / high                           | `0x01`                |
+//! | `0x04`/`0x05` | Write then read, with / without chip select      | `0x01`, bytes read    |
+//! | `0x1n`        | Transfer `n + 1` bytes                           | `0x01`, bytes read    |
+//! | `0x60`–`0x67` | Speed: 30 kHz, 125 kHz, 250 kHz, 1, 2, 2.6, 4, 8 MHz | `0x01`            |
+//! | `0x8x`        | Pin output, clock polarity and phase, sampling   | `0x01` for SPI mode 0 |
+//!
+//! Write then read takes a big-endian write count and read count, then the
+//! bytes to write; flashrom uses it for every flash command.
+//!
+//! `0x02` holds chip select low until `0x03`, so a command sent with one
+//! `0x1n` and its data read with the next reach the device together. This
+//! needs the dispatcher's SPI chip select routine
+//! ([`with_spi_chip_select`](crate::Dispatcher::with_spi_chip_select));
+//! without one both fail, and every transfer gets its own chip select pulse.
+//! Write then read runs as one transaction either way.
+
+use super::{bulk_len, counts, FAIL, HEADER_LEN, OK};
+use embedded_hal::spi::{Operation, SpiDevice};
+use embedded_io::Write;
+use esp32_bus_pirate_bus_modes::{
+    spi::{SpiConfig, SpiMode},
+    BusMode,
+};
+
+/// Reply to `0x01`
+pub const VERSION: &[u8] = b"SPI1";
+
+/// Clock selected by `0x60`–`0x67`
+const SPEEDS: [u32; 8] = [30_000, 125_000, 250_000, 1_000_000, 2_000_000, 2_600_000, 4_000_000, 8_000_000];
+
+/// Clock idles low and data is sampled on the leading edge, in the `0x8x`
+/// bits the original uses (CKP = 0, CKE = 1)
+const MODE_0: u8 = 0b0010;
+
+pub(super) fn is_write_then_read(command: u8) -> bool {
+    matches!(command, 0x04 | 0x05)
+}
+
+/// Bytes `command` takes before it can run
+pub(super) fn arg_len(command: u8) -> usize {
+    match command {
+        0x04 | 0x05 => HEADER_LEN,
+        0x10..=0x1F => bulk_len(command),
+        _ => 0,
+    }
+}
+
+/// Run a command without arguments
+pub(super) fn command<S: SpiDevice, W: Write>(spi: &mut SpiMode<S>, command: u8, out: &mut W) -> Result<(), W::Error> {
+    let ok = match command {
+        0x02 | 0x03 => spi.select(command == 0x02).is_ok(),
+        0x60..=0x67 => {
+            let frequency = SPEEDS[usize::from(command & 0x07)];
+            spi.init(SpiConfig { frequency }).is_ok()
+        }
+        // Only the mode the SPI device is set up for
+        0x80..=0x8F => command & 0b0110 == MODE_0,
+        _ => false,
+    };
+    out.write_all(&[if ok { OK } else { FAIL }])
+}
+
+/// Run `command` with its arguments in `args`, reading into `spare`
+pub(super) fn run<S: SpiDevice, W: Write>(
+    spi: &mut SpiMode<S>,
+    command: u8,
+    args: &mut [u8],
+    spare: &mut [u8],
+    out: &mut W,
+) -> Result<(), W::Error> {
+    if is_write_then_read(command) {
+        let (_, len) = counts(args);
+        let mut operations = [Operation::Write(&args[HEADER_LEN..]), Operation::Read(&mut spare[..len])];
+        let done = spi.transaction(&mut operations).is_ok();
+        return reply(done, &spare[..len], out);
+    }
+
+    // Bulk transfer
+    let done = spi.transfer(args).is_ok();
+    reply(done, args, out)
+}
+
+fn reply<W: Write>(done: bool, read: &[u8], out: &mut W) -> Result<(), W::Error> {
+    if !done {
+        return out.write_all(&[FAIL]);
+    }
+    out.write_all(&[OK])?;
+    out.write_all(read)
+}
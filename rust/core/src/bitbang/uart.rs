@@ -0,0 +1,107 @@
+//! Binary UART sub-mode (`ART1`)
+//!
+//! | Byte          | Command                                          | Reply                |
+//! |---------------|--------------------------------------------------|----------------------|
+//! | `0x02`/`0x03` | Start / stop forwarding received bytes           | `0x01`               |
+//! | `0x07`        | Baud rate from a big-endian BRG value            | `0x01`               |
+//! | `0x0F`        | Transparent bridge until the host disconnects    | None                 |
+//! | `0x1n`        | Write `n + 1` bytes                              | `0x01` per byte, and one first |
+//! | `0x60`–`0x6A` | Baud: 300, 1200, 2400, 4800, 9600, 19200, 31250, 38400, 57600, -, 115200 | `0x01` |
+//! | `0x80`–`0x9F` | Pin output, data bits and parity, stop bits, idle level | `0x01` for 8N1 |
+//!
+//! Received bytes are forwarded by [`Bitbang::poll`](super::Bitbang::poll).
+
+use super::{bulk_len, BitbangMode, FAIL, OK};
+use embedded_io::{Read, ReadReady, Write};
+use esp32_bus_pirate_bus_modes::{
+    uart::{UartConfig, UartMode},
+    BusMode,
+};
+
+/// Reply to `0x01`
+pub const VERSION: &[u8] = b"ART1";
+
+/// Baud rates selected by `0x60`–`0x6A`; `0x69` has none
+const SPEEDS: [u32; 11] = [300, 1200, 2400, 4800, 9600, 19_200, 31_250, 38_400, 57_600, 0, 115_200];
+
+/// The original's baud rate generator clock: baud = this / (BRG + 1)
+const BRG_CLOCK: u32 = 4_000_000;
+
+/// 8 data bits, no parity, 1 stop bit, idle high, in the `0x80`–`0x9F` bits
+const FORMAT_8N1: u8 = 0b0000;
+
+/// Bytes `command` takes before it can run
+pub(super) fn arg_len(command: u8) -> usize {
+    match command {
+        0x07 => 2,
+        0x10..=0x1F => bulk_len(command),
+        _ => 0,
+    }
+}
+
+/// Run a command without arguments, returning the bridge mode when the
+/// host asks for it
+pub(super) fn command<U, W>(
+    uart: &mut UartMode<U>,
+    echo: &mut bool,
+    command: u8,
+    out: &mut W,
+) -> Result<Option<BitbangMode>, W::Error>
+where
+    U: Read + ReadReady + Write,
+    W: Write,
+{
+    let ok = match command {
+        0x02 | 0x03 => {
+            *echo = command == 0x02;
+            true
+        }
+        0x0F => return Ok(Some(BitbangMode::UartBridge)),
+        // `init` refuses the missing rate
+        0x60..=0x6A => set_baud(uart, SPEEDS[usize::from(command & 0x0F)]),
+        0x80..=0x9F => command & 0b1111 == FORMAT_8N1,
+        _ => false,
+    };
+    out.write_all(&[if ok { OK } else { FAIL }])?;
+    Ok(None)
+}
+
+/// Run `command` with its arguments in `args`
+pub(super) fn run<U, W>(uart: &mut UartMode<U>, command: u8, args: &[u8], out: &mut W) -> Result<(), W::Error>
+where
+    U: Read + ReadReady + Write,
+    W: Write,
+{
+    if command == 0x07 {
+        let brg = u32::from(u16::from_be_bytes([args[0], args[1]]));
+        let ok = set_baud(uart, BRG_CLOCK / (brg + 1));
+        return out.write_all(&[if ok { OK } else { FAIL }]);
+    }
+
+    // Bulk write
+    if uart.write(args).is_err() {
+        return out.write_all(&[FAIL]);
+    }
+    out.write_all(&[OK])?;
+    for _ in args {
+        out.write_all(&[OK])?;
+    }
+    Ok(())
+}
+
+/// Send bytes received on the UART to the host
+pub(super) fn forward<U, W>(uart: &mut UartMode<U>, out: &mut W) -> Result<(), W::Error>
+where
+    U: Read + ReadReady,
+    W: Write,
+{
+    let mut buf = [0u8; 64];
+    while let Ok(n @ 1..) = uart.read_available(&mut buf) {
+        out.write_all(&buf[..n])?;
+    }
+    Ok(())
+}
+
+fn set_baud<U>(uart: &mut UartMode<U>, baudrate: u32) -> bool {
+    uart.init(UartConfig { baudrate }).is_ok()
+}
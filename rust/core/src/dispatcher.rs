@@ -0,0 +1,858 @@
+//! Command dispatcher: executes protocol messages on the bus modes
+//!
+//! The dispatcher owns the bus modes and the active [`Mode`]. Transports
+//! feed it every decoded frame and send back the encoded reply, then drain
+//! any events that became due:
+//!
+//! ```rust,ignore
+//! while let Some(Ok(frame)) = decoder.poll_frame() {
+//!     let reply = dispatcher.handle_frame(&frame)?;
+//!     transport.send(&reply)?;
+//! }
+//! while let Some(event) = dispatcher.poll_event() {
+//!     let frame = dispatcher.encode_event(event)?;
+//!     transport.send(&frame)?;
+//! }
+//! ```
+//!
+//! Commands for a bus other than the active one fail with
+//! `ErrorCode::NotConfigured`. Failures of the bus itself are reported
+//! through `bus_modes::Error`'s conversion to [`ErrorCode`]: `AddressNack`
+//! or `DataNack` for an I2C device that did not acknowledge,
+//! `ArbitrationLost`, `BusError` for any other misbehaving device, `Timeout`,
+//! and `InvalidParameter` for a configuration the bus rejects. v1 hosts get
+//! `BusError` in place of the I2C-specific codes.
+//!
+//! 24Cxx EEPROMs are probed, erased and verified with the `I2cEeprom*`
+//! messages, and dumped and programmed with bulk transfers to
+//! `BulkTarget::I2cEeprom`; see [`Eeprom`] for page splitting and write
+//! cycles.
+//!
+//! `I2cScanAnnotated` scans with a choice of probe and address range, and
+//! can read the ID register of each device found, see
+//! [`i2c_identify`](esp32_bus_pirate_bus_modes::i2c_identify). Given a
+//! [`SetFrequency`] routine, `I2cScanSweep` scans at 100 kHz, 400 kHz and
+//! 1 MHz; without one it is `NotConfigured`.
+//!
+//! Given a [`Recovery`] routine, the dispatcher answers `I2cRecover` and
+//! frees a stuck I2C bus before every scan; a bus that stays stuck fails the
+//! scan with `BusError`. Without one, `I2cRecover` is `NotConfigured`.
+//!
+//! With an I2C target peripheral from the [`Platform`], `I2cTargetStart`
+//! emulates a register-based device (see [`RegisterTarget`]).
+//! [`poll_event`](Dispatcher::poll_event) answers the bus and publishes each
+//! access on `Topic::I2cTarget`, so it has to be called often while a
+//! target runs. The target may share the controller's peripheral and pins,
+//! so while it runs, commands that use the I2C controller fail with `Busy`;
+//! `I2cTargetStop` re-applies the controller's configuration. Leaving I2C
+//! mode stops the target.
+//!
+//! `RunScript` runs a stored [`script`](crate::script) to the end before
+//! replying, with GPIO and delays from the dispatcher's [`Platform`].
+
+use crate::platform::{NoPlatform, Platform};
+use crate::script::{self, SCRIPT_SIZE};
+use crate::storage::Storage;
+use embedded_hal::{
+    i2c::{I2c, NoAcknowledgeSource, Operation},
+    spi::SpiDevice,
+};
+use embedded_io::{Read, ReadReady, Write};
+use esp32_bus_pirate_bus_modes::{
+    i2c::{I2cConfig, I2cFault, I2cMode, Recovery, SetFrequency},
+    i2c_eeprom::Eeprom,
+    i2c_target::{I2cRegisterMap, RegisterTarget},
+    spi::{flash, ChipSelect, SpiConfig, SpiMode},
+    uart::{UartConfig, UartMode},
+    BusMode, Error, Scanner,
+};
+use esp32_bus_pirate_protocol::{
+    self as protocol,
+    version::{self, SUPPORTED_VERSIONS},
+    BulkError, BulkReceiver, BulkSender, BulkTarget, DeviceInfo, ErrorCode, Event, EventQueue,
+    Features, Frame, I2cAddress, I2cNack, I2cOp, I2cTransactionReport, Message, MessageCodec, Mode, NackSource,
+    Response, ScriptReport, Subscriptions, Topic, I2C_MAX_SEGMENTS, MAX_MESSAGE_SIZE, PROTOCOL_VERSION_V1,
+    UNSEQUENCED,
+};
+use heapless::{LinearMap, String, Vec};
+
+/// Bus modes the dispatcher can switch to
+pub const SUPPORTED_MODES: [Mode; 4] = [Mode::HiZ, Mode::I2c, Mode::Spi, Mode::Uart];
+
+/// I2C clock selected by `SetMode`
+pub const DEFAULT_I2C_FREQUENCY: u32 = 100_000;
+
+/// SPI clock selected by `SetMode`
+pub const DEFAULT_SPI_FREQUENCY: u32 = 1_000_000;
+
+/// UART baud rate selected by `SetMode`
+pub const DEFAULT_UART_BAUDRATE: u32 = 115_200;
+
+/// Events buffered between polls
+const EVENT_QUEUE_LEN: usize = 16;
+
+/// Configuration entries kept by `SetConfig`
+const CONFIG_ENTRIES: usize = 16;
+
+/// Largest file a single `FileRead` reply can carry
+const FILE_READ_MAX: usize = 512;
+
+/// Bulk transfer in progress
+enum Transfer {
+    /// Device to host; payload byte 0 is at `start` within `target`
+    Download {
+        sender: BulkSender,
+        target: BulkTarget,
+        start: u32,
+    },
+    /// Host to device
+    Upload {
+        receiver: BulkReceiver,
+        target: BulkTarget,
+    },
+}
+
+/// Executes protocol messages against the I2C, SPI and UART buses
+pub struct Dispatcher<I, S, U, F, P = NoPlatform> {
+    i2c: I2cMode<I>,
+    spi: SpiMode<S>,
+    uart: UartMode<U>,
+    storage: F,
+    platform: P,
+    mode: Mode,
+    board: &'static str,
+    version: u8,
+    config: LinearMap<String<32>, String<64>, CONFIG_ENTRIES>,
+    subscriptions: Subscriptions,
+    events: EventQueue<EVENT_QUEUE_LEN>,
+    transfer: Option<Transfer>,
+    target: Option<RegisterTarget>,
+}
+
+impl<I, S, U, F> Dispatcher<I, S, U, F>
+where
+    I: I2c<Error: I2cFault>,
+    S: SpiDevice,
+    U: Read + ReadReady + Write,
+    F: Storage,
+{
+    /// Create a dispatcher in HiZ mode, without GPIO or delays for scripts
+    pub fn new(i2c: I, spi: S, uart: U, storage: F) -> Self {
+        Self {
+            i2c: I2cMode::new(i2c),
+            spi: SpiMode::new(spi),
+            uart: UartMode::new(uart),
+            storage,
+            platform: NoPlatform,
+            mode: Mode::HiZ,
+            board: "ESP32 Bus Pirate",
+            version: PROTOCOL_VERSION_V1,
+            config: LinearMap::new(),
+            subscriptions: Subscriptions::new(),
+            events: EventQueue::new(),
+            transfer: None,
+            target: None,
+        }
+    }
+}
+
+impl<I, S, U, F, P> Dispatcher<I, S, U, F, P>
+where
+    I: I2c<Error: I2cFault>,
+    S: SpiDevice,
+    U: Read + ReadReady + Write,
+    F: Storage,
+    P: Platform,
+{
+    /// Give scripts the board's GPIO pins and delays
+    pub fn with_platform<Q: Platform>(self, platform: Q) -> Dispatcher<I, S, U, F, Q> {
+        Dispatcher {
+            i2c: self.i2c,
+            spi: self.spi,
+            uart: self.uart,
+            storage: self.storage,
+            platform,
+            mode: self.mode,
+            board: self.board,
+            version: self.version,
+            config: self.config,
+            subscriptions: self.subscriptions,
+            events: self.events,
+            transfer: self.transfer,
+            target: self.target,
+        }
+    }
+
+    /// Free stuck I2C buses with `recovery`, on `I2cRecover` and before
+    /// every scan
+    pub fn with_i2c_recovery(mut self, recovery: Recovery<I>) -> Self {
+        self.i2c = self.i2c.with_recovery(recovery);
+        self
+    }
+
+    /// Change the I2C frequency with `set_frequency`, when I2C mode starts
+    /// and for `I2cScanSweep`
+    pub fn with_i2c_frequency(mut self, set_frequency: SetFrequency<I>) -> Self {
+        self.i2c = self.i2c.with_frequency_control(set_frequency);
+        self
+    }
+
+    /// Answer quick-probe scans with `Unsupported`, for an I2C controller
+    /// that cannot address a device without clocking a byte
+    pub fn without_i2c_quick_probe(mut self) -> Self {
+        self.i2c = self.i2c.without_quick_probe();
+        self
+    }
+
+    /// Hold the SPI chip select across transfers with `chip_select`, for
+    /// bitbang tools that drive it themselves
+    pub fn with_spi_chip_select(mut self, chip_select: ChipSelect<S>) -> Self {
+        self.spi = self.spi.with_chip_select(chip_select);
+        self
+    }
+
+    /// Set the board name reported in `DeviceInfo` (up to 32 bytes)
+    pub fn with_board(mut self, board: &'static str) -> Self {
+        self.board = board;
+        self
+    }
+
+    /// Currently active bus mode
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub(crate) fn i2c_mode(&mut self) -> &mut I2cMode<I> {
+        &mut self.i2c
+    }
+
+    pub(crate) fn spi_mode(&mut self) -> &mut SpiMode<S> {
+        &mut self.spi
+    }
+
+    pub(crate) fn uart_mode(&mut self) -> &mut UartMode<U> {
+        &mut self.uart
+    }
+
+    pub(crate) fn platform_mut(&mut self) -> &mut P {
+        &mut self.platform
+    }
+
+    /// Protocol version negotiated for the current session
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Execute the message in `frame` and encode the reply
+    ///
+    /// The reply echoes the frame's version and sequence ID.
+    pub fn handle_frame(&mut self, frame: &Frame) -> Result<Vec<u8, MAX_MESSAGE_SIZE>, protocol::Error> {
+        let reply = if frame.message.min_version() > frame.version {
+            // e.g. `Hello` in a v1 frame, which v1 firmware would not know
+            Message::Error(ErrorCode::InvalidCommand)
+        } else {
+            self.handle(&frame.message)
+        };
+        encode_reply(frame, reply)
+    }
+
+    /// Execute `msg` and return the reply
+    pub fn handle(&mut self, msg: &Message) -> Message {
+        self.execute(msg).unwrap_or_else(Message::Error)
+    }
+
+    /// Queue an event raised outside the dispatcher (GPIO, touch, log)
+    ///
+    /// Returns `false` if no host is subscribed to it.
+    pub fn publish(&mut self, event: Event) -> bool {
+        self.events.publish(&self.subscriptions, event)
+    }
+
+    /// Next event to send to the host, collecting UART data if subscribed
+    /// and answering the I2C target's controller
+    pub fn poll_event(&mut self) -> Option<Event> {
+        if self.mode == Mode::Uart && self.subscriptions.is_subscribed(Topic::UartRx) {
+            if let Ok(Some(event)) = self.uart.poll_event() {
+                self.events.publish(&self.subscriptions, event);
+            }
+        }
+        if let (Some(target), Some(bus)) = (&mut self.target, self.platform.i2c_target()) {
+            // The controller waits on the target, so it is served even when
+            // nobody is subscribed
+            while let Some(access) = target.service(bus) {
+                self.events.publish(&self.subscriptions, Event::I2cTarget(access));
+            }
+        }
+        self.events.pop()
+    }
+
+    /// Replace the set of topics events are collected for
+    ///
+    /// A session layer serving several hosts sets the union of theirs.
+    pub fn set_subscriptions(&mut self, subscriptions: Subscriptions) {
+        self.subscriptions = subscriptions;
+    }
+
+    /// Negotiate a `Hello` without starting a new session
+    pub fn device_info(&self, versions: &[u8], max_frame: u16) -> Result<DeviceInfo, ErrorCode> {
+        let version = version::negotiate(versions).ok_or(ErrorCode::ProtocolError)?;
+
+        let mut features = Features::SEQUENCE_IDS | Features::BULK_TRANSFER | Features::EVENTS;
+        if self.storage.is_available() {
+            features = features | Features::FILESYSTEM | Features::SCRIPTS;
+        }
+
+        Ok(DeviceInfo {
+            version,
+            versions: Vec::from_slice(SUPPORTED_VERSIONS).unwrap_or_default(),
+            max_frame: version::negotiate_max_frame(max_frame),
+            firmware_version: String::try_from(env!("CARGO_PKG_VERSION")).unwrap_or_default(),
+            board: String::try_from(self.board).unwrap_or_default(),
+            modes: Vec::from_slice(&SUPPORTED_MODES).unwrap_or_default(),
+            rx_buffer: MAX_MESSAGE_SIZE as u16,
+            tx_buffer: MAX_MESSAGE_SIZE as u16,
+            features,
+        })
+    }
+
+    /// Encode an event for the current session
+    pub fn encode_event(&self, event: Event) -> Result<Vec<u8, MAX_MESSAGE_SIZE>, protocol::Error> {
+        MessageCodec::encode_versioned(&Message::Event(event), self.version, UNSEQUENCED)
+    }
+
+    /// Forget per-session state when the host disconnects
+    ///
+    /// The bus mode is device state and stays active.
+    pub fn end_session(&mut self) {
+        self.version = PROTOCOL_VERSION_V1;
+        self.subscriptions.clear();
+        self.events.clear();
+        self.transfer = None;
+    }
+
+    fn execute(&mut self, msg: &Message) -> Result<Message, ErrorCode> {
+        let response = match msg {
+            Message::Hello { versions, max_frame } => Response::DeviceInfo(self.hello(versions, *max_frame)?),
+
+            Message::SetMode { mode } => {
+                self.set_mode(*mode)?;
+                Response::Success
+            }
+            Message::GetMode => Response::CurrentMode(self.mode),
+
+            Message::I2cScan => {
+                self.require_i2c_controller()?;
+                Response::I2cDevices(self.i2c.scan()?)
+            }
+            Message::I2cWrite { addr, data } => {
+                self.require_i2c_controller()?;
+                self.i2c.write(*addr, data)?;
+                Response::Success
+            }
+            Message::I2cRead { addr, len } => {
+                self.require_i2c_controller()?;
+                let mut data = zeroed(usize::from(*len))?;
+                self.i2c.read(*addr, &mut data)?;
+                Response::Data(data)
+            }
+            Message::I2cReadRegister { addr, reg } => {
+                self.require_i2c_controller()?;
+                let value = self.i2c.read_register(*addr, *reg)?;
+                Response::Data(Vec::from_slice(&[value]).map_err(|_| ErrorCode::ProtocolError)?)
+            }
+            Message::I2cWriteRegister { addr, reg, value } => {
+                self.require_i2c_controller()?;
+                self.i2c.write_register(*addr, *reg, *value)?;
+                Response::Success
+            }
+            Message::I2cTransaction { addr, ops } => {
+                self.require_i2c_controller()?;
+                Response::I2cTransaction(self.i2c_transaction(*addr, ops)?)
+            }
+            Message::I2cScanTenBit => {
+                self.require_i2c_controller()?;
+                Response::I2cTenBitDevices(self.i2c.scan_ten_bit()?)
+            }
+            Message::I2cScanAnnotated { options, identify } => {
+                self.require_i2c_controller()?;
+                Response::I2cScanReport(self.i2c.scan_report(options, *identify)?)
+            }
+            Message::I2cScanSweep { options } => {
+                self.require_i2c_controller()?;
+                Response::I2cSweepReport(self.i2c.sweep(options)?.ok_or(ErrorCode::NotConfigured)?)
+            }
+            Message::I2cRecover => {
+                self.require_i2c_controller()?;
+                Response::I2cRecovery(self.i2c.recover()?.ok_or(ErrorCode::NotConfigured)?)
+            }
+            Message::I2cEepromProbe { eeprom } => {
+                self.require_i2c_controller()?;
+                let found = Eeprom::new(&mut self.i2c, *eeprom).probe()?;
+                Response::I2cDevices(Vec::from_slice(&found).map_err(|_| ErrorCode::ProtocolError)?)
+            }
+            Message::I2cEepromErase { eeprom } => {
+                self.require_i2c_controller()?;
+                Eeprom::new(&mut self.i2c, *eeprom).erase()?;
+                Response::Success
+            }
+            Message::I2cEepromVerify { eeprom, address, len, crc32 } => {
+                self.require_i2c_controller()?;
+                if Eeprom::new(&mut self.i2c, *eeprom).checksum(*address, *len)? != *crc32 {
+                    return Err(ErrorCode::ChecksumMismatch);
+                }
+                Response::Success
+            }
+            Message::I2cTargetStart { map } => {
+                self.require(Mode::I2c)?;
+                self.start_target(map.clone())?;
+                Response::Success
+            }
+            Message::I2cTargetStop => {
+                if self.stop_target()? {
+                    // The target may have reprogrammed the controller's peripheral
+                    if let Some(config) = self.i2c.config() {
+                        self.i2c.init(config)?;
+                    }
+                }
+                Response::Success
+            }
+            Message::I2cTargetRegisters => {
+                let target = self.target.as_ref().ok_or(ErrorCode::NotConfigured)?;
+                Response::I2cRegisterMap(target.map().clone())
+            }
+
+            Message::SpiTransfer { data } => {
+                self.require(Mode::Spi)?;
+                let mut data = Vec::from_slice(data).map_err(|_| ErrorCode::InvalidParameter)?;
+                self.spi.transfer(&mut data)?;
+                Response::Data(data)
+            }
+
+            Message::UartWrite { data } => {
+                self.require(Mode::Uart)?;
+                self.uart.write(data)?;
+                Response::Success
+            }
+            Message::UartRead { len } => {
+                self.require(Mode::Uart)?;
+                let mut data = zeroed(usize::from(*len).min(FILE_READ_MAX))?;
+                let n = self.uart.read_available(&mut data)?;
+                data.truncate(n);
+                Response::Data(data)
+            }
+            Message::UartConfig { baudrate } => {
+                self.require(Mode::Uart)?;
+                self.uart.init(UartConfig { baudrate: *baudrate })?;
+                Response::Success
+            }
+
+            Message::SetConfig { key, value } => {
+                self.config
+                    .insert(key.clone(), value.clone())
+                    .map_err(|_| ErrorCode::InvalidParameter)?;
+                Response::Success
+            }
+            Message::GetConfig { key } => {
+                Response::ConfigValue(self.config.get(key).cloned().ok_or(ErrorCode::InvalidParameter)?)
+            }
+
+            Message::FileList { path } => Response::FileList(self.storage.list(path)?),
+            Message::FileRead { path } => {
+                let size = self.storage.size(path)? as usize;
+                if size > FILE_READ_MAX {
+                    // Too large for one reply; the host should use a bulk transfer
+                    return Err(ErrorCode::InvalidParameter);
+                }
+                let mut data = zeroed(size)?;
+                let n = self.storage.read(path, 0, &mut data)?;
+                data.truncate(n);
+                Response::Data(data)
+            }
+            Message::FileWrite { path, data } => {
+                self.storage.write(path, 0, data)?;
+                Response::Success
+            }
+            Message::RunScript { path } => Response::Script(self.run_script(path)?),
+
+            Message::BulkRequest { id, target, offset, len } => return self.bulk_request(*id, target, *offset, *len),
+            Message::BulkBegin { id, target, total_len } => return self.bulk_begin(*id, target, *total_len),
+            Message::BulkChunk { id, offset, data } => return self.bulk_chunk(*id, *offset, data),
+            Message::BulkEnd { id, crc32 } => return self.bulk_end(*id, *crc32),
+            Message::BulkAck { id, offset } => return self.bulk_ack(*id, *offset),
+            Message::BulkResume { id } => return self.bulk_resume(*id),
+
+            Message::Subscribe { topic } => {
+                self.subscriptions.subscribe(*topic);
+                Response::Success
+            }
+            Message::Unsubscribe { topic } => {
+                self.subscriptions.unsubscribe(*topic);
+                Response::Success
+            }
+
+            // Device-to-host messages are not commands
+            Message::Response(_) | Message::Error(_) | Message::Event(_) => {
+                return Err(ErrorCode::InvalidCommand);
+            }
+        };
+        Ok(Message::Response(response))
+    }
+
+    fn hello(&mut self, versions: &[u8], max_frame: u16) -> Result<DeviceInfo, ErrorCode> {
+        let info = self.device_info(versions, max_frame)?;
+
+        // A Hello starts a new session
+        self.end_session();
+        self.version = info.version;
+        Ok(info)
+    }
+
+    /// Release the current bus and bring up `mode` with its default settings
+    ///
+    /// The device is left in HiZ if either step fails. A flash or EEPROM
+    /// transfer cannot outlive its bus mode and is abandoned.
+    pub(crate) fn set_mode(&mut self, mode: Mode) -> Result<(), ErrorCode> {
+        if !SUPPORTED_MODES.contains(&mode) {
+            return Err(ErrorCode::InvalidParameter);
+        }
+
+        if let Some(Transfer::Download { target, .. } | Transfer::Upload { target, .. }) = &self.transfer {
+            if !matches!(target, BulkTarget::File(_)) {
+                self.transfer = None;
+            }
+        }
+
+        let released = self.deinit(self.mode);
+        self.mode = Mode::HiZ;
+        released?;
+
+        let started = match mode {
+            Mode::I2c => self.i2c.init(I2cConfig {
+                frequency: DEFAULT_I2C_FREQUENCY,
+            }),
+            Mode::Spi => self.spi.init(SpiConfig {
+                frequency: DEFAULT_SPI_FREQUENCY,
+            }),
+            Mode::Uart => self.uart.init(UartConfig {
+                baudrate: DEFAULT_UART_BAUDRATE,
+            }),
+            _ => Ok(()),
+        };
+        if let Err(err) = started {
+            // Give back whatever the failed init claimed
+            let _ = self.deinit(mode);
+            return Err(err.into());
+        }
+        self.mode = mode;
+        Ok(())
+    }
+
+    fn deinit(&mut self, mode: Mode) -> Result<(), Error> {
+        match mode {
+            Mode::I2c => {
+                let stopped = self.stop_target().map(|_| ());
+                self.i2c.deinit().and(stopped)
+            }
+            Mode::Spi => self.spi.deinit(),
+            Mode::Uart => self.uart.deinit(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Emulate `map` on the platform's I2C target, replacing any map
+    /// already running
+    fn start_target(&mut self, map: I2cRegisterMap) -> Result<(), ErrorCode> {
+        let target = RegisterTarget::new(map)?;
+        let eeprom_transfer = match &self.transfer {
+            Some(Transfer::Download { target, .. } | Transfer::Upload { target, .. }) => {
+                matches!(target, BulkTarget::I2cEeprom { .. })
+            }
+            None => false,
+        };
+        if eeprom_transfer {
+            // The transfer still needs the controller
+            return Err(ErrorCode::Busy);
+        }
+        self.stop_target()?;
+        let bus = self.platform.i2c_target().ok_or(ErrorCode::NotConfigured)?;
+        bus.listen(target.map().addr)?;
+        self.target = Some(target);
+        Ok(())
+    }
+
+    /// Stop the running target, if any; returns whether one was running
+    fn stop_target(&mut self) -> Result<bool, Error> {
+        match (self.target.take(), self.platform.i2c_target()) {
+            (Some(_), Some(bus)) => bus.release().map(|()| true),
+            _ => Ok(false),
+        }
+    }
+
+    /// Require I2C mode with the controller free of a running target
+    pub(crate) fn require_i2c_controller(&self) -> Result<(), ErrorCode> {
+        self.require(Mode::I2c)?;
+        if self.target.is_some() {
+            return Err(ErrorCode::Busy);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn require(&self, mode: Mode) -> Result<(), ErrorCode> {
+        if self.mode == mode {
+            Ok(())
+        } else {
+            Err(ErrorCode::NotConfigured)
+        }
+    }
+
+    /// Run `ops` as one I2C transaction
+    ///
+    /// The read segments share one reply buffer, in order. A NACK is part of
+    /// the report rather than an error, so the host learns which segment it
+    /// ended, when the bus could tell.
+    fn i2c_transaction(&mut self, addr: I2cAddress, ops: &[I2cOp]) -> Result<I2cTransactionReport, ErrorCode> {
+        if ops.is_empty() || ops.contains(&I2cOp::Read(0)) {
+            return Err(ErrorCode::InvalidParameter);
+        }
+        let len = ops
+            .iter()
+            .map(|op| match op {
+                I2cOp::Read(len) => usize::from(*len),
+                I2cOp::Write(_) => 0,
+            })
+            .sum();
+        let mut data = zeroed(len)?;
+
+        // The operations borrow `data` until the transaction is done
+        let result = {
+            let mut operations: Vec<Operation<'_>, I2C_MAX_SEGMENTS> = Vec::new();
+            let mut rest = data.as_mut_slice();
+            for op in ops {
+                let operation = match op {
+                    I2cOp::Write(bytes) => Operation::Write(bytes),
+                    I2cOp::Read(len) => {
+                        let (buf, tail) = core::mem::take(&mut rest).split_at_mut(usize::from(*len));
+                        rest = tail;
+                        Operation::Read(buf)
+                    }
+                };
+                operations.push(operation).map_err(|_| ErrorCode::InvalidParameter)?;
+            }
+            match addr {
+                I2cAddress::SevenBit(addr) => self.i2c.transaction(addr, &mut operations),
+                I2cAddress::TenBit(addr) => self.i2c.transaction_ten_bit(addr, &mut operations),
+            }
+        };
+        match result {
+            Ok(()) => Ok(I2cTransactionReport { data, nack: None }),
+            Err(Error::Nack { segment, source }) => Ok(I2cTransactionReport {
+                data: Vec::new(),
+                nack: Some(I2cNack {
+                    segment: segment.map(|segment| segment as u8),
+                    source: nack_source(source),
+                }),
+            }),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Compile and run the script at `path`
+    ///
+    /// Only a script that cannot be read fails the command; compile and
+    /// run errors are part of the report.
+    fn run_script(&mut self, path: &str) -> Result<ScriptReport, ErrorCode> {
+        let size = self.storage.size(path)? as usize;
+        if size > SCRIPT_SIZE {
+            return Err(ErrorCode::InvalidParameter);
+        }
+        let mut source = [0u8; SCRIPT_SIZE];
+        let n = self.storage.read(path, 0, &mut source[..size])?;
+        let source = core::str::from_utf8(&source[..n]).map_err(|_| ErrorCode::InvalidParameter)?;
+
+        Ok(match script::compile(source) {
+            Ok(program) => script::run(&program, self),
+            Err(error) => ScriptReport {
+                output: Vec::new(),
+                error: Some(error),
+            },
+        })
+    }
+
+    // ===== Bulk Transfers =====
+
+    /// Bytes available in `target` from its start
+    fn target_size(&mut self, target: &BulkTarget) -> Result<u32, ErrorCode> {
+        match target {
+            BulkTarget::File(path) => self.storage.size(path),
+            BulkTarget::SpiFlash { address } => {
+                self.require(Mode::Spi)?;
+                let capacity = self.spi.flash_capacity()?;
+                capacity.checked_sub(*address).ok_or(ErrorCode::InvalidParameter)
+            }
+            BulkTarget::I2cEeprom { eeprom, address } => {
+                self.require_i2c_controller()?;
+                eeprom.part.capacity().checked_sub(*address).ok_or(ErrorCode::InvalidParameter)
+            }
+        }
+    }
+
+    fn bulk_request(&mut self, id: u16, target: &BulkTarget, offset: u32, len: u32) -> Result<Message, ErrorCode> {
+        let size = self.target_size(target)?;
+        let available = size.checked_sub(offset).ok_or(ErrorCode::InvalidParameter)?;
+
+        let sender = BulkSender::new(id, target.clone(), len.min(available));
+        let begin = sender.begin();
+        self.transfer = Some(Transfer::Download {
+            sender,
+            target: target.clone(),
+            start: offset,
+        });
+        Ok(begin)
+    }
+
+    fn bulk_ack(&mut self, id: u16, offset: u32) -> Result<Message, ErrorCode> {
+        let Some(Transfer::Download { sender, target, start }) = &mut self.transfer else {
+            return Err(ErrorCode::UnknownTransfer);
+        };
+
+        // The host pulls data: each ack is answered with the chunk it asks for
+        sender.on_ack(id, offset)?;
+        let chunk = sender.next_chunk(|offset, buf| {
+            read_target(&mut self.storage, &mut self.i2c, &mut self.spi, target, *start + offset, buf)
+        })?;
+        match chunk {
+            Some(chunk) => Ok(chunk),
+            // Kept until the next transfer so a lost `BulkEnd` can be re-sent
+            None => sender.end().ok_or(ErrorCode::ProtocolError),
+        }
+    }
+
+    fn bulk_begin(&mut self, id: u16, target: &BulkTarget, total_len: u32) -> Result<Message, ErrorCode> {
+        match target {
+            BulkTarget::File(path) => self.storage.write(path, 0, &[])?,
+            BulkTarget::SpiFlash { .. } | BulkTarget::I2cEeprom { .. } => {
+                if total_len > self.target_size(target)? {
+                    return Err(ErrorCode::InvalidParameter);
+                }
+            }
+        }
+        if let BulkTarget::SpiFlash { address } = target {
+            // Sectors are erased whole, which would lose the bytes of the
+            // first sector before an unaligned start
+            if address % flash::SECTOR_SIZE != 0 {
+                return Err(ErrorCode::InvalidParameter);
+            }
+        }
+
+        let receiver = BulkReceiver::new(id, total_len);
+        let ack = receiver.ack();
+        self.transfer = Some(Transfer::Upload {
+            receiver,
+            target: target.clone(),
+        });
+        Ok(ack)
+    }
+
+    fn bulk_chunk(&mut self, id: u16, offset: u32, data: &[u8]) -> Result<Message, ErrorCode> {
+        let Some(Transfer::Upload { receiver, target }) = &mut self.transfer else {
+            return Err(ErrorCode::UnknownTransfer);
+        };
+        Ok(receiver.on_chunk(id, offset, data, |offset, bytes| {
+            write_target(&mut self.storage, &mut self.i2c, &mut self.spi, target, offset, bytes)
+        })?)
+    }
+
+    fn bulk_end(&mut self, id: u16, crc32: u32) -> Result<Message, ErrorCode> {
+        let Some(Transfer::Upload { receiver, .. }) = &mut self.transfer else {
+            return Err(ErrorCode::UnknownTransfer);
+        };
+        Ok(receiver.on_end(id, crc32)?)
+    }
+
+    fn bulk_resume(&mut self, id: u16) -> Result<Message, ErrorCode> {
+        match &self.transfer {
+            Some(Transfer::Upload { receiver, .. }) if receiver.id() == id => Ok(receiver.ack()),
+            _ => Err(ErrorCode::UnknownTransfer),
+        }
+    }
+}
+
+/// Encode `reply` to `request`, in terms its version understands
+pub(crate) fn encode_reply(request: &Frame, reply: Message) -> Result<Vec<u8, MAX_MESSAGE_SIZE>, protocol::Error> {
+    let reply = match reply {
+        Message::Error(code) => Message::Error(code.downgrade(request.version)),
+        reply => reply,
+    };
+    MessageCodec::encode_reply(request, &reply)
+}
+
+/// A zero-filled reply buffer of `len` bytes
+fn zeroed(len: usize) -> Result<Vec<u8, 512>, ErrorCode> {
+    let mut data = Vec::new();
+    data.resize(len, 0).map_err(|_| ErrorCode::InvalidParameter)?;
+    Ok(data)
+}
+
+fn nack_source(source: NoAcknowledgeSource) -> NackSource {
+    match source {
+        NoAcknowledgeSource::Address => NackSource::Address,
+        NoAcknowledgeSource::Data => NackSource::Data,
+        NoAcknowledgeSource::Unknown => NackSource::Unknown,
+    }
+}
+
+/// Read payload bytes of a download, `offset` counting from the target's start
+fn read_target<I: I2c<Error: I2cFault>, S: SpiDevice, F: Storage>(
+    storage: &mut F,
+    i2c: &mut I2cMode<I>,
+    spi: &mut SpiMode<S>,
+    target: &BulkTarget,
+    offset: u32,
+    buf: &mut [u8],
+) -> Result<(), BulkError> {
+    match target {
+        BulkTarget::File(path) => match storage.read(path, offset, buf) {
+            Ok(n) if n == buf.len() => Ok(()),
+            _ => Err(BulkError::Io),
+        },
+        BulkTarget::SpiFlash { address } => spi.read_flash(address + offset, buf).map_err(io_error),
+        BulkTarget::I2cEeprom { eeprom, address } => {
+            Eeprom::new(i2c, *eeprom).read(address + offset, buf).map_err(io_error)
+        }
+    }
+}
+
+/// Store payload bytes of an upload, `offset` counting from the target's start
+///
+/// Flash sectors are erased as the upload enters them; uploads start on a
+/// sector boundary, so only the tail of the last sector is erased beyond the
+/// data. EEPROM needs no erase.
+fn write_target<I: I2c<Error: I2cFault>, S: SpiDevice, F: Storage>(
+    storage: &mut F,
+    i2c: &mut I2cMode<I>,
+    spi: &mut SpiMode<S>,
+    target: &BulkTarget,
+    offset: u32,
+    data: &[u8],
+) -> Result<(), BulkError> {
+    match target {
+        BulkTarget::File(path) => storage.write(path, offset, data).map_err(|_| BulkError::Io),
+        BulkTarget::SpiFlash { address } => {
+            let start = address + offset;
+            let end = start + data.len() as u32;
+            let mut sector = start.next_multiple_of(flash::SECTOR_SIZE);
+            while sector < end {
+                spi.erase_flash_sector(sector).map_err(io_error)?;
+                sector += flash::SECTOR_SIZE;
+            }
+            spi.program_flash(start, data).map_err(io_error)
+        }
+        BulkTarget::I2cEeprom { eeprom, address } => {
+            Eeprom::new(i2c, *eeprom).write(address + offset, data).map_err(io_error)
+        }
+    }
+}
+
+fn io_error(_: Error) -> BulkError {
+    BulkError::Io
+}
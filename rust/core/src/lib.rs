@@ -0,0 +1,24 @@
+#![no_std]
+
+//! Hardware-independent firmware logic for the ESP32 Bus Pirate
+//!
+//! Everything here is generic over the `embedded-hal` and `embedded-io`
+//! traits, so the same code runs on the ESP32-S3 and in the desktop
+//! simulator against virtual buses.
+
+pub mod bitbang;
+pub mod dispatcher;
+pub mod platform;
+pub mod script;
+pub mod session;
+pub mod shell;
+pub mod storage;
+pub mod transport;
+
+pub use bitbang::Bitbang;
+pub use dispatcher::Dispatcher;
+pub use platform::{NoPlatform, Platform};
+pub use session::{SessionId, SessionManager};
+pub use shell::{Shell, ShellContext};
+pub use storage::{NoStorage, Storage};
+pub use transport::{StreamTransport, Transport, TransportError};
@@ -0,0 +1,49 @@
+//! Board facilities outside the bus modes: pins and delays for scripts,
+//! and the I2C target peripheral
+
+use esp32_bus_pirate_bus_modes::i2c_target::TargetBus;
+use esp32_bus_pirate_protocol::ErrorCode;
+
+/// GPIO pins and delays used by [`script`](crate::script)s, and the I2C
+/// peripheral the dispatcher emulates devices with
+///
+/// Pin numbers are whatever the board maps them to; a pin the board does
+/// not expose should fail with `InvalidParameter`.
+pub trait Platform {
+    /// Drive `pin` high or low
+    fn set_pin(&mut self, pin: u8, high: bool) -> Result<(), ErrorCode>;
+
+    /// Level on `pin`
+    fn pin(&mut self, pin: u8) -> Result<bool, ErrorCode>;
+
+    /// Wait `us` microseconds
+    fn delay_us(&mut self, us: u32) -> Result<(), ErrorCode>;
+
+    /// I2C peripheral for target mode; `None` on boards without one
+    ///
+    /// It may be the I2C mode controller's own peripheral: the dispatcher
+    /// does not use the controller while a target runs, and re-applies its
+    /// configuration when the target stops.
+    fn i2c_target(&mut self) -> Option<&mut dyn TargetBus> {
+        None
+    }
+}
+
+/// Platform for boards that give scripts neither pins nor delays, nor an
+/// I2C target; every operation fails
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoPlatform;
+
+impl Platform for NoPlatform {
+    fn set_pin(&mut self, _pin: u8, _high: bool) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NotConfigured)
+    }
+
+    fn pin(&mut self, _pin: u8) -> Result<bool, ErrorCode> {
+        Err(ErrorCode::NotConfigured)
+    }
+
+    fn delay_us(&mut self, _us: u32) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NotConfigured)
+    }
+}
@@ -0,0 +1,559 @@
+//! Script source to [`Program`]
+
+use super::{BinaryOp, Op, Program, UnaryOp, MAX_BYTES, MAX_DEPTH, MAX_VARIABLES};
+use esp32_bus_pirate_protocol::{ErrorCode, Mode, ScriptError};
+use heapless::Vec;
+
+/// `break`s in one loop
+const MAX_BREAKS: usize = 8;
+
+/// Compile `source`, failing at the first line that is not valid
+pub fn compile(source: &str) -> Result<Program, ScriptError> {
+    let mut compiler = Compiler::default();
+    for (i, text) in source.lines().enumerate() {
+        compiler.line = u16::try_from(i + 1).unwrap_or(u16::MAX);
+        let mut lexer = Lexer::new(text);
+        if lexer.peek() == Token::End {
+            continue;
+        }
+        compiler.statement(&mut lexer).map_err(|Invalid| compiler.error())?;
+    }
+    if let Some(block) = compiler.blocks.last() {
+        // Report the block that was never closed
+        compiler.line = block.line;
+        return Err(compiler.error());
+    }
+    Ok(compiler.program)
+}
+
+/// The line does not compile
+struct Invalid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token<'a> {
+    Number(i32),
+    Name(&'a str),
+    Str(&'a str),
+    Symbol(&'static str),
+    /// Not a token at all
+    Unknown,
+    End,
+}
+
+const SYMBOLS: [&str; 23] = [
+    "->", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "*", "/", "%", "+", "-", "<", ">", "&", "^", "|", "!",
+    "~", "(", ")",
+];
+
+struct Lexer<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(line: &'a str) -> Self {
+        Self { rest: line }
+    }
+
+    fn peek(&self) -> Token<'a> {
+        self.scan().0
+    }
+
+    fn next(&mut self) -> Token<'a> {
+        let (token, rest) = self.scan();
+        self.rest = rest;
+        token
+    }
+
+    /// Take `symbol` if it comes next
+    fn eat(&mut self, symbol: &str) -> bool {
+        let found = matches!(self.peek(), Token::Symbol(s) if s == symbol);
+        if found {
+            self.next();
+        }
+        found
+    }
+
+    fn scan(&self) -> (Token<'a>, &'a str) {
+        let rest = self.rest.trim_start();
+        let Some(first) = rest.chars().next() else {
+            return (Token::End, rest);
+        };
+
+        if first == '#' {
+            return (Token::End, "");
+        }
+        if first == '"' {
+            return match rest[1..].find('"') {
+                Some(end) => (Token::Str(&rest[1..end + 1]), &rest[end + 2..]),
+                None => (Token::Unknown, ""),
+            };
+        }
+        if first.is_ascii_alphanumeric() || first == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let (word, after) = rest.split_at(end);
+            if !first.is_ascii_digit() {
+                return (Token::Name(word), after);
+            }
+            return match number(word) {
+                Some(value) => (Token::Number(value), after),
+                None => (Token::Unknown, ""),
+            };
+        }
+        match SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+            Some(symbol) => (Token::Symbol(symbol), &rest[symbol.len()..]),
+            None => (Token::Unknown, ""),
+        }
+    }
+}
+
+/// Decimal, `0x` or `0b` number; values up to `u32::MAX` wrap around
+fn number(word: &str) -> Option<i32> {
+    let (digits, radix) = if let Some(hex) = word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+        (hex, 16)
+    } else if let Some(bin) = word.strip_prefix("0b").or_else(|| word.strip_prefix("0B")) {
+        (bin, 2)
+    } else {
+        (word, 10)
+    };
+    u32::from_str_radix(digits, radix).ok().map(|value| value as i32)
+}
+
+/// A loop or `if` waiting for its `end`
+struct Block {
+    line: u16,
+    kind: BlockKind,
+}
+
+enum BlockKind {
+    /// The jump past the block when the condition is zero
+    If { skip: usize },
+    /// The jump past the `else` block at the end of the `if` block
+    Else { skip: usize },
+    Loop {
+        start: usize,
+        /// The conditional jump out, for counted and `while` loops
+        exit: Option<usize>,
+        breaks: Vec<usize, MAX_BREAKS>,
+    },
+}
+
+#[derive(Default)]
+struct Compiler<'a> {
+    program: Program,
+    /// Variable names by slot; loop counters have empty names
+    variables: Vec<&'a str, MAX_VARIABLES>,
+    blocks: Vec<Block, MAX_DEPTH>,
+    line: u16,
+}
+
+impl<'a> Compiler<'a> {
+    fn error(&self) -> ScriptError {
+        ScriptError {
+            line: self.line,
+            code: ErrorCode::InvalidCommand,
+        }
+    }
+
+    fn statement(&mut self, lexer: &mut Lexer<'a>) -> Result<(), Invalid> {
+        let Token::Name(keyword) = lexer.next() else {
+            return Err(Invalid);
+        };
+        match keyword {
+            "end" => self.end()?,
+            "else" => self.else_()?,
+            "break" => self.break_()?,
+            _ => {
+                self.emit(Op::Line(self.line))?;
+                self.command(keyword, lexer)?;
+            }
+        }
+        // Anything left over is a mistake
+        match lexer.next() {
+            Token::End => Ok(()),
+            _ => Err(Invalid),
+        }
+    }
+
+    fn command(&mut self, keyword: &str, lexer: &mut Lexer<'a>) -> Result<(), Invalid> {
+        match (keyword, lexer.peek()) {
+            ("mode", Token::Name(name)) => {
+                lexer.next();
+                let mode = match name {
+                    "hiz" => Mode::HiZ,
+                    "i2c" => Mode::I2c,
+                    "spi" => Mode::Spi,
+                    "uart" => Mode::Uart,
+                    _ => return Err(Invalid),
+                };
+                self.emit(Op::Mode(mode))
+            }
+            ("let", Token::Name(name)) => {
+                lexer.next();
+                if !lexer.eat_assign() {
+                    return Err(Invalid);
+                }
+                self.expr(lexer)?;
+                let slot = self.variable(name)?;
+                self.emit(Op::Store(slot))
+            }
+            ("i2c" | "spi" | "uart" | "pin", Token::Name(action)) => {
+                lexer.next();
+                self.bus(keyword, action, lexer)
+            }
+            ("delay", _) => {
+                self.expr(lexer)?;
+                let scale = match lexer.peek() {
+                    Token::Name("us") => 1,
+                    Token::Name("ms") | Token::End => 1000,
+                    _ => return Err(Invalid),
+                };
+                if lexer.peek() != Token::End {
+                    lexer.next();
+                }
+                self.emit(Op::Delay(scale))
+            }
+            ("loop", Token::End) => {
+                let start = self.here();
+                self.push_block(BlockKind::Loop {
+                    start,
+                    exit: None,
+                    breaks: Vec::new(),
+                })
+            }
+            ("loop", _) => {
+                // The count goes in a slot of its own, so loops can nest
+                let counter = self.counter()?;
+                self.expr(lexer)?;
+                self.emit(Op::Store(counter))?;
+                let start = self.here();
+                for op in [Op::Load(counter), Op::Push(0), Op::Binary(BinaryOp::Gt)] {
+                    self.emit(op)?;
+                }
+                let exit = self.here();
+                self.emit(Op::JumpIfZero(0))?;
+                for op in [Op::Load(counter), Op::Push(1), Op::Binary(BinaryOp::Sub), Op::Store(counter)] {
+                    self.emit(op)?;
+                }
+                self.push_block(BlockKind::Loop {
+                    start,
+                    exit: Some(exit),
+                    breaks: Vec::new(),
+                })
+            }
+            ("while", _) => {
+                // Back to this line's marker, so errors in the condition point here
+                let start = self.here() - 1;
+                self.expr(lexer)?;
+                let exit = self.here();
+                self.emit(Op::JumpIfZero(0))?;
+                self.push_block(BlockKind::Loop {
+                    start,
+                    exit: Some(exit),
+                    breaks: Vec::new(),
+                })
+            }
+            ("if", _) => {
+                self.expr(lexer)?;
+                let skip = self.here();
+                self.emit(Op::JumpIfZero(0))?;
+                self.push_block(BlockKind::If { skip })
+            }
+            ("assert", _) => {
+                self.expr(lexer)?;
+                self.emit(Op::Assert)
+            }
+            ("emit", _) => {
+                let count = self.bytes(lexer)?;
+                self.emit(Op::Emit(count))
+            }
+            _ => Err(Invalid),
+        }
+    }
+
+    fn bus(&mut self, bus: &str, action: &str, lexer: &mut Lexer<'a>) -> Result<(), Invalid> {
+        match (bus, action) {
+            ("i2c", "write") => {
+                self.expr(lexer)?;
+                let count = self.bytes(lexer)?;
+                self.emit(Op::I2cWrite(count))
+            }
+            ("i2c", "read") => {
+                self.expr(lexer)?;
+                self.read_into(lexer, Op::I2cRead)
+            }
+            ("i2c", "writeread") => {
+                self.expr(lexer)?;
+                let count = self.bytes(lexer)?;
+                self.read_into(lexer, |read| Op::I2cWriteRead(count, read))
+            }
+            ("spi", "transfer") => {
+                let count = self.bytes(lexer)?;
+                self.emit(Op::SpiTransfer(count))?;
+                let targets = self.targets(lexer, false)?;
+                if !targets.is_empty() && targets.len() != usize::from(count) {
+                    return Err(Invalid);
+                }
+                self.store(&targets, usize::from(count))
+            }
+            ("uart", "write") => {
+                let count = self.bytes(lexer)?;
+                self.emit(Op::UartWrite(count))
+            }
+            ("uart", "read") => self.read_into(lexer, Op::UartRead),
+            ("pin", "write") => {
+                self.expr(lexer)?;
+                self.expr(lexer)?;
+                self.emit(Op::PinWrite)
+            }
+            ("pin", "read") => {
+                self.expr(lexer)?;
+                self.emit(Op::PinRead)?;
+                let targets = self.targets(lexer, true)?;
+                if targets.len() != 1 {
+                    return Err(Invalid);
+                }
+                self.store(&targets, 1)
+            }
+            _ => Err(Invalid),
+        }
+    }
+
+    /// Emit a read of one byte per target after `->`, then store them
+    fn read_into(&mut self, lexer: &mut Lexer<'a>, op: impl FnOnce(u8) -> Op) -> Result<(), Invalid> {
+        let targets = self.targets(lexer, true)?;
+        let count = u8::try_from(targets.len()).map_err(|_| Invalid)?;
+        self.emit(op(count))?;
+        self.store(&targets, targets.len())
+    }
+
+    /// Variable slots after `->`, `None` for `_`
+    fn targets(&mut self, lexer: &mut Lexer<'a>, required: bool) -> Result<Vec<Option<u8>, MAX_BYTES>, Invalid> {
+        let mut targets = Vec::new();
+        if !lexer.eat("->") {
+            return if required { Err(Invalid) } else { Ok(targets) };
+        }
+        while let Token::Name(name) = lexer.peek() {
+            lexer.next();
+            let slot = if name == "_" { None } else { Some(self.variable(name)?) };
+            targets.push(slot).map_err(|_| Invalid)?;
+        }
+        if targets.is_empty() {
+            return Err(Invalid);
+        }
+        Ok(targets)
+    }
+
+    /// Take `count` values off the stack into `targets`, last first
+    fn store(&mut self, targets: &[Option<u8>], count: usize) -> Result<(), Invalid> {
+        for i in (0..count).rev() {
+            match targets.get(i).copied().flatten() {
+                Some(slot) => self.emit(Op::Store(slot))?,
+                None => self.emit(Op::Pop)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Push a list of expressions and strings up to `->` or the end of the
+    /// line, returning how many bytes it makes
+    fn bytes(&mut self, lexer: &mut Lexer<'a>) -> Result<u8, Invalid> {
+        let mut count = 0;
+        loop {
+            match lexer.peek() {
+                Token::End | Token::Symbol("->") => break,
+                Token::Str(text) => {
+                    lexer.next();
+                    for byte in text.bytes() {
+                        self.emit(Op::Push(i32::from(byte)))?;
+                    }
+                    count += text.len();
+                }
+                _ => {
+                    self.expr(lexer)?;
+                    count += 1;
+                }
+            }
+        }
+        if count == 0 || count > MAX_BYTES {
+            return Err(Invalid);
+        }
+        u8::try_from(count).map_err(|_| Invalid)
+    }
+
+    // ===== Expressions =====
+
+    fn expr(&mut self, lexer: &mut Lexer<'a>) -> Result<(), Invalid> {
+        self.binary(lexer, 0)
+    }
+
+    /// Operators binding at least as tightly as `level`, left to right
+    fn binary(&mut self, lexer: &mut Lexer<'a>, level: usize) -> Result<(), Invalid> {
+        if level == LEVELS.len() {
+            return self.unary(lexer);
+        }
+        self.binary(lexer, level + 1)?;
+        loop {
+            let op = match lexer.peek() {
+                Token::Symbol(symbol) => LEVELS[level].iter().find(|(s, _)| *s == symbol).map(|(_, op)| *op),
+                _ => None,
+            };
+            let Some(op) = op else {
+                return Ok(());
+            };
+            lexer.next();
+            self.binary(lexer, level + 1)?;
+            self.emit(Op::Binary(op))?;
+        }
+    }
+
+    fn unary(&mut self, lexer: &mut Lexer<'a>) -> Result<(), Invalid> {
+        let op = match lexer.next() {
+            Token::Number(value) => return self.emit(Op::Push(value)),
+            Token::Name(name) if name != "_" => {
+                let slot = self.variable(name)?;
+                return self.emit(Op::Load(slot));
+            }
+            Token::Symbol("(") => {
+                self.expr(lexer)?;
+                return if lexer.eat(")") { Ok(()) } else { Err(Invalid) };
+            }
+            Token::Symbol("-") => UnaryOp::Negate,
+            Token::Symbol("!") => UnaryOp::Not,
+            Token::Symbol("~") => UnaryOp::Invert,
+            _ => return Err(Invalid),
+        };
+        self.unary(lexer)?;
+        self.emit(Op::Unary(op))
+    }
+
+    // ===== Blocks =====
+
+    fn push_block(&mut self, kind: BlockKind) -> Result<(), Invalid> {
+        let line = self.line;
+        self.blocks.push(Block { line, kind }).map_err(|_| Invalid)
+    }
+
+    fn end(&mut self) -> Result<(), Invalid> {
+        let block = self.blocks.pop().ok_or(Invalid)?;
+        match block.kind {
+            BlockKind::If { skip } | BlockKind::Else { skip } => self.patch(skip),
+            BlockKind::Loop { start, exit, breaks } => {
+                self.emit(Op::Jump(target(start)?))?;
+                for jump in exit.into_iter().chain(breaks) {
+                    self.patch(jump)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn else_(&mut self) -> Result<(), Invalid> {
+        let Some(Block {
+            kind: BlockKind::If { skip },
+            ..
+        }) = self.blocks.last()
+        else {
+            return Err(Invalid);
+        };
+        let skip = *skip;
+        let jump = self.here();
+        self.emit(Op::Jump(0))?;
+        self.patch(skip)?;
+        if let Some(block) = self.blocks.last_mut() {
+            block.kind = BlockKind::Else { skip: jump };
+        }
+        Ok(())
+    }
+
+    fn break_(&mut self) -> Result<(), Invalid> {
+        let jump = self.here();
+        self.emit(Op::Jump(0))?;
+        let breaks = self
+            .blocks
+            .iter_mut()
+            .rev()
+            .find_map(|block| match &mut block.kind {
+                BlockKind::Loop { breaks, .. } => Some(breaks),
+                _ => None,
+            })
+            .ok_or(Invalid)?;
+        breaks.push(jump).map_err(|_| Invalid)
+    }
+
+    // ===== Output =====
+
+    fn emit(&mut self, op: Op) -> Result<(), Invalid> {
+        self.program.ops.push(op).map_err(|_| Invalid)
+    }
+
+    fn here(&self) -> usize {
+        self.program.ops.len()
+    }
+
+    /// Point the jump at `at` to the next instruction
+    fn patch(&mut self, at: usize) -> Result<(), Invalid> {
+        let to = target(self.here())?;
+        match &mut self.program.ops[at] {
+            Op::Jump(target) | Op::JumpIfZero(target) => *target = to,
+            _ => return Err(Invalid),
+        }
+        Ok(())
+    }
+
+    fn variable(&mut self, name: &'a str) -> Result<u8, Invalid> {
+        let slot = match self.variables.iter().position(|known| *known == name) {
+            Some(slot) => slot,
+            None => {
+                self.variables.push(name).map_err(|_| Invalid)?;
+                self.variables.len() - 1
+            }
+        };
+        u8::try_from(slot).map_err(|_| Invalid)
+    }
+
+    /// A fresh slot no name refers to
+    fn counter(&mut self) -> Result<u8, Invalid> {
+        self.variables.push("").map_err(|_| Invalid)?;
+        u8::try_from(self.variables.len() - 1).map_err(|_| Invalid)
+    }
+}
+
+impl Lexer<'_> {
+    /// Take a lone `=`, which is not an operator
+    fn eat_assign(&mut self) -> bool {
+        let rest = self.rest.trim_start();
+        match rest.strip_prefix('=') {
+            Some(after) if !after.starts_with('=') => {
+                self.rest = after;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Binary operators from the loosest binding to the tightest, as in Rust
+const LEVELS: [&[(&str, BinaryOp)]; 9] = [
+    &[("||", BinaryOp::LogicalOr)],
+    &[("&&", BinaryOp::LogicalAnd)],
+    &[
+        ("==", BinaryOp::Eq),
+        ("!=", BinaryOp::Ne),
+        ("<", BinaryOp::Lt),
+        ("<=", BinaryOp::Le),
+        (">", BinaryOp::Gt),
+        (">=", BinaryOp::Ge),
+    ],
+    &[("|", BinaryOp::Or)],
+    &[("^", BinaryOp::Xor)],
+    &[("&", BinaryOp::And)],
+    &[("<<", BinaryOp::Shl), (">>", BinaryOp::Shr)],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+    &[("*", BinaryOp::Mul), ("/", BinaryOp::Div), ("%", BinaryOp::Rem)],
+];
+
+fn target(index: usize) -> Result<u16, Invalid> {
+    u16::try_from(index).map_err(|_| Invalid)
+}
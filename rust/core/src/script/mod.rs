@@ -0,0 +1,169 @@
+//! Stored scripts: bus operations sequenced on the device
+//!
+//! A bring-up procedure such as "reset the sensor, wait, poll a status bit
+//! until it clears, check the chip ID" takes one host round trip per step
+//! over the protocol. As a script it is stored once with `FileWrite` (or a
+//! bulk transfer) and run with a single `RunScript`, which replies with a
+//! [`ScriptReport`](esp32_bus_pirate_protocol::ScriptReport) once the
+//! script has finished.
+//!
+//! ```text
+//! # Soft-reset a BME280 and wait for it to come back
+//! mode i2c
+//! i2c write 0x76 0xE0 0xB6
+//! loop 50
+//!     delay 2 ms
+//!     i2c writeread 0x76 0xF3 -> status
+//!     if status & 0x01 == 0
+//!         break
+//!     end
+//! end
+//! assert status & 0x01 == 0
+//! i2c writeread 0x76 0xD0 -> id
+//! assert id == 0x60
+//! emit id
+//! ```
+//!
+//! One statement per line; `#` starts a comment.
+//!
+//! | Statement                           | Action                                         |
+//! |-------------------------------------|------------------------------------------------|
+//! | `mode hiz\|i2c\|spi\|uart`          | Switch mode, as `SetMode` does                 |
+//! | `let v = expr`                      | Set a variable                                 |
+//! | `i2c write addr bytes`              | Write to a device                              |
+//! | `i2c read addr -> vars`             | Read one byte per variable                     |
+//! | `i2c writeread addr bytes -> vars`  | Write, then read without a stop in between     |
+//! | `spi transfer bytes [-> vars]`      | Full-duplex transfer, keeping the bytes read   |
+//! | `uart write bytes`                  | Send bytes                                     |
+//! | `uart read -> vars`                 | Take received bytes; `-1` where none was waiting |
+//! | `pin write pin expr`                | Drive a GPIO pin low (0) or high               |
+//! | `pin read pin -> v`                 | Read a GPIO pin as 0 or 1                      |
+//! | `delay expr [ms\|us]`               | Wait, in milliseconds unless `us` follows      |
+//! | `loop [count]` … `end`              | Repeat `count` times, or until `break`         |
+//! | `while expr` … `end`                | Repeat while `expr` is non-zero                |
+//! | `if expr` … `[else` …`] end`        | Run a block if `expr` is non-zero              |
+//! | `break`                             | Leave the innermost loop                       |
+//! | `assert expr`                       | Stop with `AssertionFailed` if `expr` is zero  |
+//! | `emit bytes`                        | Append bytes to the report's output            |
+//!
+//! `bytes` is a list of expressions, each sent as its low byte, and
+//! `"text"` strings. Items are separated by spaces, so `1 -1` is one item
+//! and a negative one needs parentheses: `1 (-1)`. Variables hold 32-bit signed integers, start at 0 and
+//! need no declaration; `_` in a variable list drops the byte. Expressions
+//! take decimal, `0x` and `0b` numbers, the operators `* / % + - << >> &
+//! ^ | == != < <= > >= && ||`, unary `- ! ~` and parentheses. Precedence is
+//! Rust's rather than C's, so `status & 0x01 == 0` tests the bit.
+//!
+//! Scripts are compiled to a compact stack [`Program`] before anything
+//! runs, so a typo fails the whole script rather than half of it. The
+//! script runs to the end within the `RunScript` command, and its
+//! operations need the bus lease like any other command; a runaway loop is
+//! stopped with `Timeout` after [`MAX_STEPS`] instructions.
+//!
+//! GPIO pins and delays come from the dispatcher's
+//! [`Platform`](crate::platform::Platform).
+
+mod compile;
+mod vm;
+
+pub use compile::compile;
+pub(crate) use vm::run;
+
+use esp32_bus_pirate_protocol::Mode;
+use heapless::Vec;
+
+/// Largest script source, in bytes
+pub const SCRIPT_SIZE: usize = 2048;
+
+/// Instructions a compiled script may have
+pub const MAX_OPS: usize = 512;
+
+/// Distinct variables in one script, loop counters included
+pub const MAX_VARIABLES: usize = 32;
+
+/// Bytes one statement may write or read
+pub const MAX_BYTES: usize = 64;
+
+/// Loops and `if`s open at once
+pub const MAX_DEPTH: usize = 8;
+
+/// Instructions a script may execute before it is stopped
+pub const MAX_STEPS: u32 = 1_000_000;
+
+/// A compiled script
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    ops: Vec<Op, MAX_OPS>,
+}
+
+impl Program {
+    /// Number of instructions
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Whether the script does nothing
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+/// Stack machine instruction
+///
+/// Bus operations take their address and bytes from the stack, first
+/// pushed first, and push the bytes they read in the same order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    /// Start of the statement on this line
+    Line(u16),
+    Push(i32),
+    Load(u8),
+    Store(u8),
+    Pop,
+    Unary(UnaryOp),
+    Binary(BinaryOp),
+    Jump(u16),
+    JumpIfZero(u16),
+    Mode(Mode),
+    I2cWrite(u8),
+    I2cRead(u8),
+    I2cWriteRead(u8, u8),
+    SpiTransfer(u8),
+    UartWrite(u8),
+    UartRead(u8),
+    PinWrite,
+    PinRead,
+    /// Wait for the value on the stack times this many microseconds
+    Delay(u32),
+    Assert,
+    Emit(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UnaryOp {
+    Negate,
+    Not,
+    Invert,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOp {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Xor,
+    Or,
+    LogicalAnd,
+    LogicalOr,
+}
@@ -0,0 +1,245 @@
+//! Running a [`Program`] against the dispatcher's buses
+
+use super::{BinaryOp, Op, Program, UnaryOp, MAX_BYTES, MAX_STEPS, MAX_VARIABLES};
+use crate::dispatcher::Dispatcher;
+use crate::platform::Platform;
+use crate::storage::Storage;
+use embedded_hal::{
+    i2c::{I2c, Operation},
+    spi::SpiDevice,
+};
+use embedded_io::{Read, ReadReady, Write};
+use esp32_bus_pirate_bus_modes::i2c::I2cFault;
+use esp32_bus_pirate_protocol::{ErrorCode, Mode, ScriptError, ScriptReport};
+use heapless::Vec;
+
+/// Values on the stack at once: a statement's bytes and some room for
+/// working them out
+const STACK_SIZE: usize = MAX_BYTES + 32;
+
+/// Run `program` to the end or to the first failure
+pub(crate) fn run<I, S, U, F, P>(program: &Program, dispatcher: &mut Dispatcher<I, S, U, F, P>) -> ScriptReport
+where
+    I: I2c<Error: I2cFault>,
+    S: SpiDevice,
+    U: Read + ReadReady + Write,
+    F: Storage,
+    P: Platform,
+{
+    let mut vm = Vm {
+        stack: Vec::new(),
+        variables: [0; MAX_VARIABLES],
+        output: Vec::new(),
+        line: 0,
+    };
+    let error = vm
+        .execute(program, dispatcher)
+        .err()
+        .map(|code| ScriptError { line: vm.line, code });
+    ScriptReport {
+        output: vm.output,
+        error,
+    }
+}
+
+struct Vm {
+    stack: Vec<i32, STACK_SIZE>,
+    variables: [i32; MAX_VARIABLES],
+    output: Vec<u8, 512>,
+    /// Line of the statement running
+    line: u16,
+}
+
+impl Vm {
+    fn execute<I, S, U, F, P>(&mut self, program: &Program, dispatcher: &mut Dispatcher<I, S, U, F, P>) -> Result<(), ErrorCode>
+    where
+        I: I2c<Error: I2cFault>,
+        S: SpiDevice,
+        U: Read + ReadReady + Write,
+        F: Storage,
+        P: Platform,
+    {
+        let mut pc = 0;
+        let mut steps = 0;
+        while let Some(&op) = program.ops.get(pc) {
+            steps += 1;
+            if steps > MAX_STEPS {
+                return Err(ErrorCode::Timeout);
+            }
+            pc += 1;
+
+            match op {
+                Op::Line(line) => self.line = line,
+                Op::Push(value) => self.push(value)?,
+                Op::Load(slot) => self.push(self.variables[usize::from(slot)])?,
+                Op::Store(slot) => self.variables[usize::from(slot)] = self.pop()?,
+                Op::Pop => {
+                    self.pop()?;
+                }
+                Op::Unary(op) => {
+                    let value = self.pop()?;
+                    self.push(unary(op, value))?;
+                }
+                Op::Binary(op) => {
+                    let right = self.pop()?;
+                    let left = self.pop()?;
+                    self.push(binary(op, left, right)?)?;
+                }
+                Op::Jump(to) => pc = usize::from(to),
+                Op::JumpIfZero(to) => {
+                    if self.pop()? == 0 {
+                        pc = usize::from(to);
+                    }
+                }
+                Op::Mode(mode) => dispatcher.set_mode(mode)?,
+
+                Op::I2cWrite(count) => {
+                    let data = self.bytes(count)?;
+                    let addr = self.address()?;
+                    dispatcher.require_i2c_controller()?;
+                    dispatcher.i2c_mode().write(addr, &data)?;
+                }
+                Op::I2cRead(count) => {
+                    let addr = self.address()?;
+                    dispatcher.require_i2c_controller()?;
+                    let mut data = zeroed(count);
+                    dispatcher.i2c_mode().read(addr, &mut data)?;
+                    self.push_bytes(&data)?;
+                }
+                Op::I2cWriteRead(count, len) => {
+                    let write = self.bytes(count)?;
+                    let addr = self.address()?;
+                    dispatcher.require_i2c_controller()?;
+                    let mut read = zeroed(len);
+                    dispatcher
+                        .i2c_mode()
+                        .transaction(addr, &mut [Operation::Write(&write), Operation::Read(&mut read)])?;
+                    self.push_bytes(&read)?;
+                }
+                Op::SpiTransfer(count) => {
+                    let mut data = self.bytes(count)?;
+                    dispatcher.require(Mode::Spi)?;
+                    dispatcher.spi_mode().transfer(&mut data)?;
+                    self.push_bytes(&data)?;
+                }
+                Op::UartWrite(count) => {
+                    let data = self.bytes(count)?;
+                    dispatcher.require(Mode::Uart)?;
+                    dispatcher.uart_mode().write(&data)?;
+                }
+                Op::UartRead(count) => {
+                    dispatcher.require(Mode::Uart)?;
+                    let mut data = zeroed(count);
+                    let n = dispatcher.uart_mode().read_available(&mut data)?;
+                    for (i, &byte) in data.iter().enumerate() {
+                        // Nothing received for the rest
+                        self.push(if i < n { i32::from(byte) } else { -1 })?;
+                    }
+                }
+                Op::PinWrite => {
+                    let level = self.pop()?;
+                    let pin = self.pin()?;
+                    dispatcher.platform_mut().set_pin(pin, level != 0)?;
+                }
+                Op::PinRead => {
+                    let pin = self.pin()?;
+                    let high = dispatcher.platform_mut().pin(pin)?;
+                    self.push(i32::from(high))?;
+                }
+                Op::Delay(scale) => {
+                    let time = u32::try_from(self.pop()?).map_err(|_| ErrorCode::InvalidParameter)?;
+                    dispatcher.platform_mut().delay_us(time.saturating_mul(scale))?;
+                }
+                Op::Assert => {
+                    if self.pop()? == 0 {
+                        return Err(ErrorCode::AssertionFailed);
+                    }
+                }
+                Op::Emit(count) => {
+                    let data = self.bytes(count)?;
+                    self.output
+                        .extend_from_slice(&data)
+                        .map_err(|_| ErrorCode::InvalidParameter)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, value: i32) -> Result<(), ErrorCode> {
+        // Only very deep parentheses get here
+        self.stack.push(value).map_err(|_| ErrorCode::InvalidParameter)
+    }
+
+    fn pop(&mut self) -> Result<i32, ErrorCode> {
+        self.stack.pop().ok_or(ErrorCode::ProtocolError)
+    }
+
+    fn push_bytes(&mut self, bytes: &[u8]) -> Result<(), ErrorCode> {
+        bytes.iter().try_for_each(|&byte| self.push(i32::from(byte)))
+    }
+
+    /// The top `count` values as bytes, the deepest first
+    fn bytes(&mut self, count: u8) -> Result<Vec<u8, MAX_BYTES>, ErrorCode> {
+        let start = self
+            .stack
+            .len()
+            .checked_sub(usize::from(count))
+            .ok_or(ErrorCode::ProtocolError)?;
+        let bytes = self.stack[start..].iter().map(|&value| value as u8).collect();
+        self.stack.truncate(start);
+        Ok(bytes)
+    }
+
+    /// A 7-bit I2C address
+    fn address(&mut self) -> Result<u8, ErrorCode> {
+        match u8::try_from(self.pop()?) {
+            Ok(addr) if addr < 0x80 => Ok(addr),
+            _ => Err(ErrorCode::InvalidParameter),
+        }
+    }
+
+    fn pin(&mut self) -> Result<u8, ErrorCode> {
+        u8::try_from(self.pop()?).map_err(|_| ErrorCode::InvalidParameter)
+    }
+}
+
+/// `len` zero bytes to read into
+fn zeroed(len: u8) -> Vec<u8, MAX_BYTES> {
+    let mut data = Vec::new();
+    // The compiler keeps `len` within `MAX_BYTES`
+    let _ = data.resize(usize::from(len), 0);
+    data
+}
+
+fn unary(op: UnaryOp, value: i32) -> i32 {
+    match op {
+        UnaryOp::Negate => value.wrapping_neg(),
+        UnaryOp::Not => i32::from(value == 0),
+        UnaryOp::Invert => !value,
+    }
+}
+
+fn binary(op: BinaryOp, left: i32, right: i32) -> Result<i32, ErrorCode> {
+    Ok(match op {
+        BinaryOp::Mul => left.wrapping_mul(right),
+        BinaryOp::Div | BinaryOp::Rem if right == 0 => return Err(ErrorCode::InvalidParameter),
+        BinaryOp::Div => left.wrapping_div(right),
+        BinaryOp::Rem => left.wrapping_rem(right),
+        BinaryOp::Add => left.wrapping_add(right),
+        BinaryOp::Sub => left.wrapping_sub(right),
+        BinaryOp::Shl => left.wrapping_shl(right as u32),
+        BinaryOp::Shr => left.wrapping_shr(right as u32),
+        BinaryOp::Lt => i32::from(left < right),
+        BinaryOp::Le => i32::from(left <= right),
+        BinaryOp::Gt => i32::from(left > right),
+        BinaryOp::Ge => i32::from(left >= right),
+        BinaryOp::Eq => i32::from(left == right),
+        BinaryOp::Ne => i32::from(left != right),
+        BinaryOp::And => left & right,
+        BinaryOp::Xor => left ^ right,
+        BinaryOp::Or => left | right,
+        BinaryOp::LogicalAnd => i32::from(left != 0 && right != 0),
+        BinaryOp::LogicalOr => i32::from(left != 0 || right != 0),
+    })
+}
@@ -0,0 +1,348 @@
+//! Client sessions across transports and the exclusive bus lease
+//!
+//! The device can be reached over several [`Transport`]s at once (USB, UART,
+//! TCP, WebSocket), some with more than one client. [`SessionManager`] sits
+//! between them and the [`Dispatcher`]: it keeps each client's protocol
+//! version and event subscriptions, and grants one client at a time an
+//! exclusive lease on the bus, so concurrent hosts cannot switch modes
+//! under each other.
+//!
+//! - The first client to send a command that touches the bus or changes
+//!   device state takes the lease. It keeps it until it disconnects or puts
+//!   the device back in HiZ.
+//! - Every other client gets a read-only monitor view: it can query the
+//!   device, mode and configuration, read files and subscribe to events,
+//!   e.g. to watch UART traffic. Anything else is refused with
+//!   `ErrorCode::Busy` (`PermissionDenied` for v1 hosts).
+//!
+//! Each session also remembers its client's recent sequence IDs, so a v2
+//! frame repeated by the host or a transport retry is not executed twice.
+//!
+//! ```rust,ignore
+//! const USB: u8 = 0;
+//! const TCP: u8 = 1;
+//!
+//! while let Some(bytes) = tcp.receive()? {
+//!     let frame = MessageCodec::decode_frame(bytes)?;
+//!     let id = SessionId::new(TCP, tcp.current_client().unwrap());
+//!     let reply = sessions.handle_frame(&mut dispatcher, id, &frame)?;
+//!     tcp.send(&reply)?;
+//! }
+//! sessions.prune(&mut dispatcher, TCP, &tcp);
+//!
+//! while let Some(event) = dispatcher.poll_event() {
+//!     for (id, frame) in sessions.event_frames(&event) {
+//!         match id.transport {
+//!             USB => usb.send(&frame)?,
+//!             _ => tcp.send_to(id.client, &frame)?,
+//!         }
+//!     }
+//! }
+//! ```
+
+use crate::dispatcher::{encode_reply, Dispatcher};
+use crate::platform::Platform;
+use crate::storage::Storage;
+use crate::transport::{ClientId, Transport};
+use embedded_hal::{i2c::I2c, spi::SpiDevice};
+use embedded_io::{Read, ReadReady, Write};
+use esp32_bus_pirate_bus_modes::{i2c::I2cFault, Error};
+use esp32_bus_pirate_protocol::{
+    self as protocol, ErrorCode, Event, Frame, Message, MessageCodec, Mode, Response, SequenceWindow, Subscriptions,
+    MAX_MESSAGE_SIZE, PROTOCOL_VERSION_V1, UNSEQUENCED,
+};
+use heapless::Vec;
+
+/// Sessions tracked unless configured otherwise
+pub const MAX_SESSIONS: usize = 8;
+
+/// A client on one of the device's transports
+///
+/// `transport` is whatever index the firmware gives each transport.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SessionId {
+    /// Transport the client is connected to
+    pub transport: u8,
+    /// Client within that transport
+    pub client: ClientId,
+}
+
+impl SessionId {
+    /// Session of `client` on `transport`
+    pub const fn new(transport: u8, client: ClientId) -> Self {
+        Self { transport, client }
+    }
+}
+
+/// Whether `msg` leaves the bus and device state alone, so a client
+/// without the lease may send it
+pub fn is_read_only(msg: &Message) -> bool {
+    matches!(
+        msg,
+        Message::Hello { .. }
+            | Message::GetMode
+            | Message::GetConfig { .. }
+            | Message::FileList { .. }
+            | Message::FileRead { .. }
+            | Message::Subscribe { .. }
+            | Message::Unsubscribe { .. }
+            // Not commands; the dispatcher refuses them
+            | Message::Response(_)
+            | Message::Error(_)
+            | Message::Event(_)
+    )
+}
+
+struct Session {
+    id: SessionId,
+    version: u8,
+    subscriptions: Subscriptions,
+    sequences: SequenceWindow,
+}
+
+/// Tracks up to `N` client sessions and which of them holds the bus
+pub struct SessionManager<const N: usize = MAX_SESSIONS> {
+    sessions: Vec<Session, N>,
+    holder: Option<SessionId>,
+}
+
+impl<const N: usize> Default for SessionManager<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> SessionManager<N> {
+    /// Create a manager with no sessions
+    pub const fn new() -> Self {
+        Self {
+            sessions: Vec::new(),
+            holder: None,
+        }
+    }
+
+    /// The session holding the bus lease
+    pub fn holder(&self) -> Option<SessionId> {
+        self.holder
+    }
+
+    /// Open sessions
+    pub fn sessions(&self) -> impl Iterator<Item = SessionId> + '_ {
+        self.sessions.iter().map(|session| session.id)
+    }
+
+    /// Protocol version `id` negotiated, if it has a session
+    pub fn version(&self, id: SessionId) -> Option<u8> {
+        self.sessions.iter().find(|session| session.id == id).map(|session| session.version)
+    }
+
+    /// Execute the message in `frame` from `id` and encode the reply
+    ///
+    /// A client's first frame opens its session. A frame that repeats one of
+    /// the client's recent sequence IDs is not executed and fails with
+    /// [`DuplicateSequence`](protocol::Error::DuplicateSequence); a `Hello`
+    /// clears them, since the client numbers its requests afresh.
+    pub fn handle_frame<I, S, U, F, P>(
+        &mut self,
+        dispatcher: &mut Dispatcher<I, S, U, F, P>,
+        id: SessionId,
+        frame: &Frame,
+    ) -> Result<Vec<u8, MAX_MESSAGE_SIZE>, protocol::Error>
+    where
+        I: I2c<Error: I2cFault>,
+        S: SpiDevice,
+        U: Read + ReadReady + Write,
+        F: Storage,
+        P: Platform,
+    {
+        // Without room for a session the frame is refused with `Busy` below
+        if let Ok(slot) = self.open(id) {
+            let sequences = &mut self.sessions[slot].sequences;
+            if matches!(frame.message, Message::Hello { .. }) {
+                sequences.clear();
+            }
+            sequences.check(frame.seq)?;
+        }
+
+        let reply = if frame.message.min_version() > frame.version {
+            Message::Error(ErrorCode::InvalidCommand)
+        } else {
+            self.handle(dispatcher, id, &frame.message)
+        };
+        encode_reply(frame, reply)
+    }
+
+    /// Execute `msg` from `id` and return the reply
+    pub fn handle<I, S, U, F, P>(&mut self, dispatcher: &mut Dispatcher<I, S, U, F, P>, id: SessionId, msg: &Message) -> Message
+    where
+        I: I2c<Error: I2cFault>,
+        S: SpiDevice,
+        U: Read + ReadReady + Write,
+        F: Storage,
+        P: Platform,
+    {
+        self.execute(dispatcher, id, msg).unwrap_or_else(Message::Error)
+    }
+
+    /// End the sessions of clients that have left `transport`
+    pub fn prune<I, S, U, F, P, T>(&mut self, dispatcher: &mut Dispatcher<I, S, U, F, P>, transport: u8, link: &T)
+    where
+        I: I2c<Error: I2cFault>,
+        S: SpiDevice,
+        U: Read + ReadReady + Write,
+        F: Storage,
+        P: Platform,
+        T: Transport,
+    {
+        loop {
+            let left = self
+                .sessions()
+                .find(|id| id.transport == transport && !link.is_client_connected(id.client));
+            let Some(id) = left else {
+                return;
+            };
+            self.end_session(dispatcher, id);
+        }
+    }
+
+    /// Forget `id`, releasing the bus if it held the lease
+    pub fn end_session<I, S, U, F, P>(&mut self, dispatcher: &mut Dispatcher<I, S, U, F, P>, id: SessionId)
+    where
+        I: I2c<Error: I2cFault>,
+        S: SpiDevice,
+        U: Read + ReadReady + Write,
+        F: Storage,
+        P: Platform,
+    {
+        self.sessions.retain(|session| session.id != id);
+        if self.holder == Some(id) {
+            self.release(dispatcher);
+        } else {
+            self.update_subscriptions(dispatcher);
+        }
+    }
+
+    /// Sessions subscribed to `event`, each with the frame to send it
+    pub fn event_frames<'a>(
+        &'a self,
+        event: &'a Event,
+    ) -> impl Iterator<Item = (SessionId, Vec<u8, MAX_MESSAGE_SIZE>)> + 'a {
+        self.sessions
+            .iter()
+            .filter(|session| session.subscriptions.wants(event))
+            .filter_map(|session| {
+                let msg = Message::Event(event.clone());
+                let frame = MessageCodec::encode_versioned(&msg, session.version, UNSEQUENCED).ok()?;
+                Some((session.id, frame))
+            })
+    }
+
+    fn execute<I, S, U, F, P>(
+        &mut self,
+        dispatcher: &mut Dispatcher<I, S, U, F, P>,
+        id: SessionId,
+        msg: &Message,
+    ) -> Result<Message, ErrorCode>
+    where
+        I: I2c<Error: I2cFault>,
+        S: SpiDevice,
+        U: Read + ReadReady + Write,
+        F: Storage,
+        P: Platform,
+    {
+        let slot = self.open(id)?;
+        match msg {
+            Message::Hello { versions, max_frame } => {
+                let info = dispatcher.device_info(versions, *max_frame)?;
+
+                // A Hello starts a new session for this client only; the
+                // holder keeps its lease but not its transfer in progress
+                let session = &mut self.sessions[slot];
+                session.version = info.version;
+                session.subscriptions.clear();
+                if self.holder == Some(id) {
+                    dispatcher.end_session();
+                }
+                self.update_subscriptions(dispatcher);
+                return Ok(Message::Response(Response::DeviceInfo(info)));
+            }
+            Message::Subscribe { .. } | Message::Unsubscribe { .. } => {
+                self.sessions[slot].subscriptions.handle(msg);
+                self.update_subscriptions(dispatcher);
+                return Ok(Message::Response(Response::Success));
+            }
+            _ => {}
+        }
+
+        if !is_read_only(msg) {
+            self.acquire(id)?;
+        }
+        let reply = dispatcher.handle(msg);
+        if matches!(msg, Message::SetMode { mode: Mode::HiZ }) && dispatcher.mode() == Mode::HiZ {
+            // The bus is idle again, so another client may take it
+            self.release(dispatcher);
+        }
+        Ok(reply)
+    }
+
+    /// Index of `id`'s session, opening one if needed
+    fn open(&mut self, id: SessionId) -> Result<usize, ErrorCode> {
+        if let Some(slot) = self.sessions.iter().position(|session| session.id == id) {
+            return Ok(slot);
+        }
+        self.sessions
+            .push(Session {
+                id,
+                version: PROTOCOL_VERSION_V1,
+                subscriptions: Subscriptions::new(),
+                sequences: SequenceWindow::new(),
+            })
+            .map_err(|_| ErrorCode::from(Error::Busy))?;
+        Ok(self.sessions.len() - 1)
+    }
+
+    /// Take the bus lease for `id`, opening its session if needed
+    ///
+    /// For clients that drive the bus without protocol messages, such as
+    /// the text shell. Fails with `Busy` while another session holds it.
+    pub fn acquire(&mut self, id: SessionId) -> Result<(), ErrorCode> {
+        self.open(id)?;
+        match self.holder {
+            Some(holder) if holder != id => Err(Error::Busy.into()),
+            _ => {
+                self.holder = Some(id);
+                Ok(())
+            }
+        }
+    }
+
+    /// Drop the lease along with the holder's transfer and queued events
+    fn release<I, S, U, F, P>(&mut self, dispatcher: &mut Dispatcher<I, S, U, F, P>)
+    where
+        I: I2c<Error: I2cFault>,
+        S: SpiDevice,
+        U: Read + ReadReady + Write,
+        F: Storage,
+        P: Platform,
+    {
+        self.holder = None;
+        dispatcher.end_session();
+        self.update_subscriptions(dispatcher);
+    }
+
+    /// Collect events for every topic some session wants
+    fn update_subscriptions<I, S, U, F, P>(&self, dispatcher: &mut Dispatcher<I, S, U, F, P>)
+    where
+        I: I2c<Error: I2cFault>,
+        S: SpiDevice,
+        U: Read + ReadReady + Write,
+        F: Storage,
+        P: Platform,
+    {
+        let all = self
+            .sessions
+            .iter()
+            .fold(Subscriptions::new(), |all, session| all.union(session.subscriptions));
+        dispatcher.set_subscriptions(all);
+    }
+}
@@ -0,0 +1,427 @@
+//! Running parsed syntax on the active bus
+//!
+//! The buses are driven through `embedded-hal`, which has no bare start or
+//! stop conditions, so everything between `[` and `]` is collected and run
+//! as one transaction:
+//!
+//! - **I2C**: the first byte after `[` is the address byte (address and R/W
+//!   bit). A repeated `[` to the same address continues the transaction
+//!   with a repeated start; a different address ends it and starts another.
+//!   Bytes need an open transaction, and delays cannot go inside one.
+//! - **SPI**: chip select is held from `[` to `]`. Bytes outside brackets
+//!   are sent in a transaction of their own.
+//! - **UART**: brackets are ignored; bytes are written as they come and `r`
+//!   takes whatever has been received.
+//!
+//! A transaction still open at the end of the line is closed.
+
+use super::syntax::{Op, Step};
+use super::{say, ShellError};
+use crate::dispatcher::Dispatcher;
+use crate::platform::Platform;
+use crate::storage::Storage;
+use core::fmt;
+use embedded_hal::{
+    delay::DelayNs,
+    i2c::{self, I2c},
+    spi::{self, SpiDevice},
+};
+use embedded_io::{Read, ReadReady, Write};
+use esp32_bus_pirate_bus_modes::{i2c::{I2cFault, I2cMode}, spi::SpiMode, uart::UartMode};
+use esp32_bus_pirate_protocol::{ErrorCode, Mode};
+use heapless::Vec;
+
+/// Bytes one transaction can write and read
+const TRANSACTION_SIZE: usize = 256;
+
+/// Address, write, read and delay runs in one transaction
+const TRANSACTION_PARTS: usize = 16;
+
+/// Byte clocked out while reading SPI
+const SPI_FILL: u8 = 0xFF;
+
+#[derive(Debug, Clone, Copy)]
+enum Part {
+    /// I2C address byte as typed
+    Address { byte: u8, restart: bool },
+    /// Bytes written
+    Write(usize),
+    /// Bytes read
+    Read(usize),
+    /// SPI delay in nanoseconds
+    Delay(u32),
+}
+
+/// A transaction being collected
+struct Transaction {
+    data: Vec<u8, TRANSACTION_SIZE>,
+    parts: Vec<Part, TRANSACTION_PARTS>,
+    /// Opened with `[` or `{` rather than implied by a lone byte
+    framed: bool,
+    /// Opened with `{`: show what SPI reads while writing
+    show_read: bool,
+}
+
+impl Transaction {
+    fn new(framed: bool, show_read: bool) -> Self {
+        Self {
+            data: Vec::new(),
+            parts: Vec::new(),
+            framed,
+            show_read,
+        }
+    }
+
+    fn part(&mut self, part: Part) -> Result<(), ShellError> {
+        self.parts.push(part).map_err(|_| ShellError::TooLong)
+    }
+
+    /// Add `count` copies of `byte` to a write or read run
+    fn bytes(&mut self, read: bool, byte: u8, count: u16) -> Result<(), ShellError> {
+        let count = usize::from(count);
+        if self.data.len() + count > TRANSACTION_SIZE {
+            return Err(ShellError::TooLong);
+        }
+        self.data.extend(core::iter::repeat_n(byte, count));
+        match (self.parts.last_mut(), read) {
+            (Some(Part::Write(len)), false) | (Some(Part::Read(len)), true) => *len += count,
+            (_, false) => self.part(Part::Write(count))?,
+            (_, true) => self.part(Part::Read(count))?,
+        }
+        Ok(())
+    }
+
+    fn address(&self) -> Option<u8> {
+        self.parts.iter().find_map(|part| match part {
+            Part::Address { byte, .. } => Some(byte >> 1),
+            _ => None,
+        })
+    }
+}
+
+/// Run `steps` on the active bus, reporting to `out`
+pub(super) fn run<I, S, U, F, P, D, W>(
+    dispatcher: &mut Dispatcher<I, S, U, F, P>,
+    steps: &[Step],
+    delay: &mut D,
+    out: &mut W,
+) -> Result<(), ShellError>
+where
+    I: I2c<Error: I2cFault>,
+    S: SpiDevice,
+    U: Read + ReadReady + Write,
+    F: Storage,
+    P: Platform,
+    D: DelayNs,
+    W: fmt::Write,
+{
+    match dispatcher.mode() {
+        Mode::I2c => {
+            dispatcher.require_i2c_controller()?;
+            run_i2c(dispatcher.i2c_mode(), steps, delay, out)
+        }
+        Mode::Spi => run_spi(dispatcher.spi_mode(), steps, delay, out),
+        Mode::Uart => run_uart(dispatcher.uart_mode(), steps, delay, out),
+        _ => Err(ShellError::NoMode),
+    }
+}
+
+fn run_i2c<I: I2c<Error: I2cFault>, D: DelayNs, W: fmt::Write>(
+    i2c: &mut I2cMode<I>,
+    steps: &[Step],
+    delay: &mut D,
+    out: &mut W,
+) -> Result<(), ShellError> {
+    let mut open: Option<Transaction> = None;
+    // Whether the next byte is an address byte, and after a repeated start
+    let mut address_next = None;
+
+    for step in steps {
+        match step.op {
+            Op::Start | Op::StartRead => {
+                let restart = open.as_ref().is_some_and(|txn| txn.address().is_some());
+                open.get_or_insert_with(|| Transaction::new(true, false));
+                address_next = Some(restart);
+            }
+            Op::Write(byte) => {
+                let txn = open.as_mut().ok_or(ShellError::NoTransaction)?;
+                let mut count = step.repeat;
+                if let Some(restart) = address_next.take() {
+                    if txn.address().is_some_and(|addr| addr != byte >> 1) {
+                        // A repeated start cannot change devices here
+                        let done = core::mem::replace(txn, Transaction::new(true, false));
+                        i2c_transaction(i2c, done, out)?;
+                        txn.part(Part::Address { byte, restart: false })?;
+                    } else {
+                        txn.part(Part::Address { byte, restart })?;
+                    }
+                    count -= 1;
+                }
+                if count > 0 {
+                    txn.bytes(false, byte, count)?;
+                }
+            }
+            Op::Read => {
+                let txn = open.as_mut().filter(|txn| txn.address().is_some());
+                let txn = txn.ok_or(ShellError::NoTransaction)?;
+                if address_next.is_some() {
+                    return Err(ShellError::NoTransaction);
+                }
+                txn.bytes(true, 0, step.repeat)?;
+            }
+            Op::DelayUs | Op::DelayMs => {
+                if open.is_some() {
+                    return Err(ShellError::DelayInTransaction);
+                }
+                wait(step, delay, out);
+            }
+            Op::Stop => {
+                address_next = None;
+                if let Some(txn) = open.take() {
+                    i2c_transaction(i2c, txn, out)?;
+                }
+            }
+        }
+    }
+    match open {
+        Some(txn) => i2c_transaction(i2c, txn, out),
+        None => Ok(()),
+    }
+}
+
+fn i2c_transaction<I: I2c<Error: I2cFault>, W: fmt::Write>(
+    i2c: &mut I2cMode<I>,
+    mut txn: Transaction,
+    out: &mut W,
+) -> Result<(), ShellError> {
+    let Some(addr) = txn.address() else {
+        // `[]` has nothing to send
+        return Ok(());
+    };
+
+    {
+        let mut operations: Vec<i2c::Operation<'_>, TRANSACTION_PARTS> = Vec::new();
+        let mut rest = txn.data.as_mut_slice();
+        for part in &txn.parts {
+            let operation = match *part {
+                Part::Write(len) => i2c::Operation::Write(take(&mut rest, len)),
+                Part::Read(len) => i2c::Operation::Read(take(&mut rest, len)),
+                _ => continue,
+            };
+            // At most one operation per part
+            let _ = operations.push(operation);
+        }
+        if operations.is_empty() {
+            // Address only: probe for an ACK
+            let _ = operations.push(i2c::Operation::Write(&[]));
+        }
+        i2c.transaction(addr, &mut operations).map_err(ErrorCode::from)?;
+    }
+
+    say(out, format_args!("I2C START"));
+    let mut offset = 0;
+    for part in &txn.parts {
+        match *part {
+            Part::Address { byte, restart } => {
+                if restart {
+                    say(out, format_args!("I2C RESTART"));
+                }
+                let direction = if byte & 1 == 0 { "WRITE" } else { "READ" };
+                say(out, format_args!("ADDRESS: 0x{:02X} ({direction})", byte >> 1));
+            }
+            Part::Write(len) | Part::Read(len) => {
+                let label = if matches!(part, Part::Write(_)) { "WRITE" } else { "READ" };
+                print_bytes(out, label, &txn.data[offset..offset + len]);
+                offset += len;
+            }
+            Part::Delay(_) => {}
+        }
+    }
+    say(out, format_args!("I2C STOP"));
+    Ok(())
+}
+
+fn run_spi<S: SpiDevice, D: DelayNs, W: fmt::Write>(
+    spi: &mut SpiMode<S>,
+    steps: &[Step],
+    delay: &mut D,
+    out: &mut W,
+) -> Result<(), ShellError> {
+    let mut open: Option<Transaction> = None;
+
+    for step in steps {
+        match step.op {
+            Op::Start | Op::StartRead => {
+                if let Some(txn) = open.take() {
+                    spi_transaction(spi, txn, out)?;
+                }
+                open = Some(Transaction::new(true, step.op == Op::StartRead));
+            }
+            Op::Write(_) | Op::Read => {
+                let (read, byte) = match step.op {
+                    Op::Write(byte) => (false, byte),
+                    _ => (true, SPI_FILL),
+                };
+                match &mut open {
+                    Some(txn) => txn.bytes(read, byte, step.repeat)?,
+                    None => {
+                        let mut txn = Transaction::new(false, false);
+                        txn.bytes(read, byte, step.repeat)?;
+                        spi_transaction(spi, txn, out)?;
+                    }
+                }
+            }
+            Op::DelayUs | Op::DelayMs => match &mut open {
+                Some(txn) => txn.part(Part::Delay(delay_ns(step)))?,
+                None => wait(step, delay, out),
+            },
+            Op::Stop => {
+                if let Some(txn) = open.take() {
+                    spi_transaction(spi, txn, out)?;
+                }
+            }
+        }
+    }
+    match open {
+        Some(txn) => spi_transaction(spi, txn, out),
+        None => Ok(()),
+    }
+}
+
+fn spi_transaction<S: SpiDevice, W: fmt::Write>(
+    spi: &mut SpiMode<S>,
+    mut txn: Transaction,
+    out: &mut W,
+) -> Result<(), ShellError> {
+    let sent = txn.data.clone();
+
+    {
+        let mut operations: Vec<spi::Operation<'_, u8>, TRANSACTION_PARTS> = Vec::new();
+        let mut rest = txn.data.as_mut_slice();
+        for part in &txn.parts {
+            let operation = match *part {
+                Part::Write(len) | Part::Read(len) => spi::Operation::TransferInPlace(take(&mut rest, len)),
+                Part::Delay(ns) => spi::Operation::DelayNs(ns),
+                Part::Address { .. } => continue,
+            };
+            // At most one operation per part
+            let _ = operations.push(operation);
+        }
+        spi.transaction(&mut operations).map_err(ErrorCode::from)?;
+    }
+
+    if txn.framed {
+        say(out, format_args!("CS ENABLE"));
+    }
+    let mut offset = 0;
+    for part in &txn.parts {
+        match *part {
+            Part::Write(len) => {
+                print_bytes(out, "WRITE", &sent[offset..offset + len]);
+                if txn.show_read {
+                    print_bytes(out, "READ", &txn.data[offset..offset + len]);
+                }
+                offset += len;
+            }
+            Part::Read(len) => {
+                print_bytes(out, "READ", &txn.data[offset..offset + len]);
+                offset += len;
+            }
+            Part::Delay(ns) => print_delay(out, ns),
+            Part::Address { .. } => {}
+        }
+    }
+    if txn.framed {
+        say(out, format_args!("CS DISABLE"));
+    }
+    Ok(())
+}
+
+fn run_uart<U, D, W>(uart: &mut UartMode<U>, steps: &[Step], delay: &mut D, out: &mut W) -> Result<(), ShellError>
+where
+    U: Read + ReadReady + Write,
+    D: DelayNs,
+    W: fmt::Write,
+{
+    // Consecutive writes go out together
+    let mut pending = Transaction::new(false, false);
+
+    for step in steps {
+        if !matches!(step.op, Op::Write(_)) {
+            uart_write(uart, &mut pending, out)?;
+        }
+        match step.op {
+            Op::Write(byte) => pending.bytes(false, byte, step.repeat)?,
+            Op::Read => {
+                let mut buf = [0u8; TRANSACTION_SIZE];
+                let buf = buf.get_mut(..usize::from(step.repeat)).ok_or(ShellError::TooLong)?;
+                let n = uart.read_available(buf).map_err(ErrorCode::from)?;
+                if n == 0 {
+                    say(out, format_args!("READ: (none)"));
+                } else {
+                    print_bytes(out, "READ", &buf[..n]);
+                }
+            }
+            Op::DelayUs | Op::DelayMs => wait(step, delay, out),
+            // No framing on a UART
+            Op::Start | Op::StartRead | Op::Stop => {}
+        }
+    }
+    uart_write(uart, &mut pending, out)
+}
+
+fn uart_write<U: Write, W: fmt::Write>(
+    uart: &mut UartMode<U>,
+    pending: &mut Transaction,
+    out: &mut W,
+) -> Result<(), ShellError> {
+    if pending.data.is_empty() {
+        return Ok(());
+    }
+    uart.write(&pending.data).map_err(ErrorCode::from)?;
+    print_bytes(out, "WRITE", &pending.data);
+    *pending = Transaction::new(false, false);
+    Ok(())
+}
+
+/// Split the first `len` bytes off `rest`
+fn take<'a>(rest: &mut &'a mut [u8], len: usize) -> &'a mut [u8] {
+    let (head, tail) = core::mem::take(rest).split_at_mut(len);
+    *rest = tail;
+    head
+}
+
+fn delay_ns(step: &Step) -> u32 {
+    let unit = if step.op == Op::DelayMs { 1_000_000 } else { 1_000 };
+    unit * u32::from(step.repeat)
+}
+
+fn wait<D: DelayNs, W: fmt::Write>(step: &Step, delay: &mut D, out: &mut W) {
+    let ns = delay_ns(step);
+    delay.delay_ns(ns);
+    print_delay(out, ns);
+}
+
+fn print_delay<W: fmt::Write>(out: &mut W, ns: u32) {
+    if ns.is_multiple_of(1_000_000) {
+        say(out, format_args!("DELAY: {}ms", ns / 1_000_000));
+    } else {
+        say(out, format_args!("DELAY: {}us", ns / 1_000));
+    }
+}
+
+fn print_bytes<W: fmt::Write>(out: &mut W, label: &str, bytes: &[u8]) {
+    struct Hex<'a>(&'a [u8]);
+
+    impl fmt::Display for Hex<'_> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            for (i, byte) in self.0.iter().enumerate() {
+                let sep = if i == 0 { "" } else { " " };
+                write!(f, "{sep}0x{byte:02X}")?;
+            }
+            Ok(())
+        }
+    }
+
+    say(out, format_args!("{label}: {}", Hex(bytes)));
+}
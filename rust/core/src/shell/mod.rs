@@ -0,0 +1,332 @@
+//! Interactive text shell with the classic Bus Pirate syntax
+//!
+//! A terminal on the serial console gets a prompt showing the active mode
+//! and a few commands:
+//!
+//! | Command          | Action                                  |
+//! |------------------|-----------------------------------------|
+//! | `?`              | Help                                    |
+//! | `i`              | Firmware and board information          |
+//! | `m`              | List modes                              |
+//! | `m 2`, `m i2c`   | Switch mode                             |
+//! | anything else    | Bus [`syntax`], e.g. `[0xA0 0x00 r:8]`  |
+//!
+//! The shell runs commands through the same [`Dispatcher`] as the binary
+//! protocol and takes part in the bus lease like any other client: mode
+//! changes and bus syntax fail with a "bus is in use" error while another
+//! client holds the bus. [`ConsolePort`](crate::transport::ConsolePort)
+//! tells terminal input apart from protocol frames.
+//!
+//! ```rust,ignore
+//! let mut input = [0u8; 64];
+//! let n = usb.console_mut().read_text(&mut input);
+//! if let (true, Some(client)) = (n > 0, usb.current_client()) {
+//!     let mut ctx = ShellContext {
+//!         dispatcher: &mut dispatcher,
+//!         sessions: &mut sessions,
+//!         id: SessionId::new(USB, client),
+//!         delay: &mut delay,
+//!     };
+//!     shell.input(&input[..n], &mut ctx, usb.console_mut());
+//! }
+//! ```
+
+mod exec;
+pub mod syntax;
+
+pub use syntax::{parse, Op, Step, SyntaxError};
+
+use crate::dispatcher::{Dispatcher, SUPPORTED_MODES};
+use crate::session::{SessionId, SessionManager, MAX_SESSIONS};
+use crate::platform::Platform;
+use crate::storage::Storage;
+use core::fmt;
+use embedded_hal::{delay::DelayNs, i2c::I2c, spi::SpiDevice};
+use embedded_io::{Read, ReadReady, Write};
+use esp32_bus_pirate_bus_modes::i2c::I2cFault;
+use esp32_bus_pirate_protocol::{version::SUPPORTED_VERSIONS, ErrorCode, Message, Mode, Response};
+use heapless::String;
+
+/// Longest command line
+pub const LINE_SIZE: usize = 128;
+
+const HELP: &str = "\
+?              this help\r
+i              firmware and board information\r
+m [mode]       list modes, or switch to one by number or name\r
+[ ] { }        I2C start/stop, SPI chip select (braces show reads)\r
+0x1F 0b1 42    write a byte; \"text\" writes each character\r
+r              read a byte\r
+& %            wait 1us / 1ms\r
+:N             repeat a value, r, & or % N times, e.g. r:8\r
+";
+
+/// Why a command failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellError {
+    /// The line is not valid syntax
+    Syntax(SyntaxError),
+    /// Not a command the shell knows
+    UnknownCommand,
+    /// Bus syntax in HiZ
+    NoMode,
+    /// I2C bytes without `[` and an address byte first
+    NoTransaction,
+    /// A delay inside an I2C transaction
+    DelayInTransaction,
+    /// A transaction larger than the shell can buffer
+    TooLong,
+    /// The device refused the command or the bus failed
+    Device(ErrorCode),
+}
+
+impl From<SyntaxError> for ShellError {
+    fn from(err: SyntaxError) -> Self {
+        ShellError::Syntax(err)
+    }
+}
+
+impl From<ErrorCode> for ShellError {
+    fn from(code: ErrorCode) -> Self {
+        ShellError::Device(code)
+    }
+}
+
+impl fmt::Display for ShellError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShellError::Syntax(err) => match err.position() {
+                Some(pos) => write!(f, "syntax error at column {}", pos + 1),
+                None => f.write_str("line is too long"),
+            },
+            ShellError::UnknownCommand => f.write_str("unknown command, ? for help"),
+            ShellError::NoMode => f.write_str("select a mode first (m)"),
+            ShellError::NoTransaction => f.write_str("I2C needs [ and an address byte first"),
+            ShellError::DelayInTransaction => f.write_str("delays cannot go inside an I2C transaction"),
+            ShellError::TooLong => f.write_str("transaction is too long"),
+            ShellError::Device(code) => match code {
+                ErrorCode::Busy | ErrorCode::PermissionDenied => f.write_str("bus is in use by another client"),
+                ErrorCode::BusError => f.write_str("bus error"),
+                ErrorCode::Timeout => f.write_str("timeout"),
+                ErrorCode::InvalidParameter => f.write_str("invalid parameter"),
+                code => write!(f, "{code:?}"),
+            },
+        }
+    }
+}
+
+/// What the shell, and the [`Bitbang`](crate::bitbang::Bitbang) front-end,
+/// run commands against
+pub struct ShellContext<'a, I, S, U, F, P, D, const N: usize = MAX_SESSIONS> {
+    /// Dispatcher owning the buses
+    pub dispatcher: &'a mut Dispatcher<I, S, U, F, P>,
+    /// Sessions, for the bus lease
+    pub sessions: &'a mut SessionManager<N>,
+    /// The front-end's own session
+    pub id: SessionId,
+    /// Delay provider for `&` and `%`
+    pub delay: &'a mut D,
+}
+
+/// Where an escape sequence from the terminal has got to
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Escape {
+    None,
+    /// After ESC
+    Started,
+    /// After `ESC [`, until the final byte
+    Csi,
+}
+
+/// Line editor and command interpreter
+pub struct Shell {
+    line: String<LINE_SIZE>,
+    after_cr: bool,
+    escape: Escape,
+}
+
+impl Default for Shell {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shell {
+    /// Create a shell with an empty line
+    pub const fn new() -> Self {
+        Self {
+            line: String::new(),
+            after_cr: false,
+            escape: Escape::None,
+        }
+    }
+
+    /// Edit the line with typed `input`, echoing to `out`, and run each
+    /// line as Enter completes it
+    pub fn input<I, S, U, F, P, D, W, const N: usize>(
+        &mut self,
+        input: &[u8],
+        ctx: &mut ShellContext<'_, I, S, U, F, P, D, N>,
+        out: &mut W,
+    ) where
+        I: I2c<Error: I2cFault>,
+        S: SpiDevice,
+        U: Read + ReadReady + Write,
+        F: Storage,
+        P: Platform,
+        D: DelayNs,
+        W: fmt::Write,
+    {
+        for &byte in input {
+            let after_cr = core::mem::take(&mut self.after_cr);
+            match self.escape {
+                Escape::Started => {
+                    self.escape = if byte == b'[' { Escape::Csi } else { Escape::None };
+                    continue;
+                }
+                Escape::Csi => {
+                    if (0x40..=0x7E).contains(&byte) {
+                        self.escape = Escape::None;
+                    }
+                    continue;
+                }
+                Escape::None => {}
+            }
+
+            match byte {
+                b'\n' if after_cr => {}
+                b'\r' | b'\n' => {
+                    self.after_cr = byte == b'\r';
+                    say(out, format_args!(""));
+                    let line = core::mem::take(&mut self.line);
+                    if let Err(err) = self.execute(line.trim(), ctx, out) {
+                        say(out, format_args!("Error: {err}"));
+                    }
+                    self.prompt(ctx.dispatcher.mode(), out);
+                }
+                // Backspace and delete
+                0x08 | 0x7F if self.line.pop().is_some() => {
+                    let _ = out.write_str("\x08 \x08");
+                }
+                // Ctrl-C
+                0x03 => {
+                    self.line.clear();
+                    say(out, format_args!("^C"));
+                    self.prompt(ctx.dispatcher.mode(), out);
+                }
+                0x1B => self.escape = Escape::Started,
+                b' '..=b'~' if self.line.push(char::from(byte)).is_ok() => {
+                    let _ = out.write_char(char::from(byte));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Show the prompt for `mode`
+    pub fn prompt<W: fmt::Write>(&self, mode: Mode, out: &mut W) {
+        let _ = write!(out, "{}> ", mode_name(mode));
+    }
+
+    fn execute<I, S, U, F, P, D, W, const N: usize>(
+        &mut self,
+        line: &str,
+        ctx: &mut ShellContext<'_, I, S, U, F, P, D, N>,
+        out: &mut W,
+    ) -> Result<(), ShellError>
+    where
+        I: I2c<Error: I2cFault>,
+        S: SpiDevice,
+        U: Read + ReadReady + Write,
+        F: Storage,
+        P: Platform,
+        D: DelayNs,
+        W: fmt::Write,
+    {
+        let (command, arg) = line.split_once(' ').unwrap_or((line, ""));
+        match command {
+            "" => Ok(()),
+            "?" => {
+                let _ = out.write_str(HELP);
+                Ok(())
+            }
+            "i" => {
+                let info = ctx.dispatcher.device_info(SUPPORTED_VERSIONS, u16::MAX)?;
+                say(out, format_args!("ESP32 Bus Pirate {}", info.firmware_version));
+                say(out, format_args!("Board: {}", info.board));
+                say(out, format_args!("Mode: {}", mode_name(ctx.dispatcher.mode())));
+                Ok(())
+            }
+            "m" => set_mode(arg.trim(), ctx, out),
+            _ if line.starts_with(|c: char| c.is_ascii_alphabetic() && !"rdD".contains(c)) => {
+                Err(ShellError::UnknownCommand)
+            }
+            _ => {
+                let steps = parse(line)?;
+                ctx.sessions.acquire(ctx.id)?;
+                exec::run(ctx.dispatcher, &steps, ctx.delay, out)
+            }
+        }
+    }
+}
+
+/// List the modes, or switch to the one `arg` names
+fn set_mode<I, S, U, F, P, D, W, const N: usize>(
+    arg: &str,
+    ctx: &mut ShellContext<'_, I, S, U, F, P, D, N>,
+    out: &mut W,
+) -> Result<(), ShellError>
+where
+    I: I2c<Error: I2cFault>,
+    S: SpiDevice,
+    U: Read + ReadReady + Write,
+    F: Storage,
+    P: Platform,
+    W: fmt::Write,
+{
+    if arg.is_empty() {
+        for (i, &mode) in SUPPORTED_MODES.iter().enumerate() {
+            say(out, format_args!("{}. {}", i + 1, mode_name(mode)));
+        }
+        return Ok(());
+    }
+
+    let mode = SUPPORTED_MODES
+        .iter()
+        .enumerate()
+        .find(|(i, mode)| arg.parse() == Ok(i + 1) || arg.eq_ignore_ascii_case(mode_name(**mode)))
+        .map(|(_, &mode)| mode)
+        .ok_or(ShellError::Device(ErrorCode::InvalidParameter))?;
+    match ctx.sessions.handle(ctx.dispatcher, ctx.id, &Message::SetMode { mode }) {
+        Message::Response(Response::Success) => {
+            say(out, format_args!("Mode: {}", mode_name(mode)));
+            Ok(())
+        }
+        Message::Error(code) => Err(ShellError::Device(code)),
+        _ => Err(ShellError::Device(ErrorCode::ProtocolError)),
+    }
+}
+
+/// Name of `mode` as the prompt shows it
+pub fn mode_name(mode: Mode) -> &'static str {
+    match mode {
+        Mode::HiZ => "HiZ",
+        Mode::I2c => "I2C",
+        Mode::Spi => "SPI",
+        Mode::Uart => "UART",
+        Mode::OneWire => "1-WIRE",
+        Mode::TwoWire => "2-WIRE",
+        Mode::ThreeWire => "3-WIRE",
+        Mode::Dio => "DIO",
+        _ => "?",
+    }
+}
+
+/// Write one line of output
+///
+/// Console output is best effort: a full console buffer drops the rest of
+/// the line rather than failing the command that printed it.
+fn say<W: fmt::Write>(out: &mut W, args: fmt::Arguments<'_>) {
+    let _ = out.write_fmt(args);
+    let _ = out.write_str("\r\n");
+}
@@ -0,0 +1,177 @@
+//! Bus Pirate command syntax
+//!
+//! | Syntax                | Meaning                                      |
+//! |-----------------------|----------------------------------------------|
+//! | `[` / `]`             | I2C start / stop, SPI chip select / deselect |
+//! | `{` / `}`             | Same, also showing bytes read while writing  |
+//! | `0x1F` `0b101` `42`   | Write a byte                                 |
+//! | `"text"` `'text'`     | Write each character                         |
+//! | `r`                   | Read a byte                                  |
+//! | `&` / `%`             | Wait 1 µs / 1 ms (`d` / `D` also work)       |
+//! | `:N`                  | Repeat a value, `r`, `&` or `%` N times      |
+//!
+//! Spaces and commas separate values; single-character symbols need no
+//! separator, so `rrr` reads three bytes.
+//!
+//! ```
+//! use esp32_bus_pirate_core::shell::syntax::{parse, Op, Step};
+//!
+//! let steps = parse("[0xA0 0x00 r:8]").unwrap();
+//! assert_eq!(steps[3], Step { op: Op::Read, repeat: 8 });
+//! ```
+
+use heapless::Vec;
+
+/// Steps one line can expand to
+pub const MAX_STEPS: usize = 128;
+
+/// Largest repeat count
+pub const MAX_REPEAT: u16 = 1024;
+
+/// One bus action
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    /// `[`: I2C start or SPI chip select
+    Start,
+    /// `{`: like `Start`, also showing the bytes read while writing
+    StartRead,
+    /// `]` or `}`: I2C stop or SPI chip deselect
+    Stop,
+    /// Write a byte
+    Write(u8),
+    /// `r`: read a byte
+    Read,
+    /// `&`: wait 1 µs
+    DelayUs,
+    /// `%`: wait 1 ms
+    DelayMs,
+}
+
+impl Op {
+    fn can_repeat(self) -> bool {
+        matches!(self, Op::Write(_) | Op::Read | Op::DelayUs | Op::DelayMs)
+    }
+}
+
+/// An action and how many times to perform it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Step {
+    /// The action
+    pub op: Op,
+    /// Times to perform it, at least 1
+    pub repeat: u16,
+}
+
+/// Why a line could not be parsed
+///
+/// Positions are byte offsets into the line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxError {
+    /// No token starts here
+    Unexpected(usize),
+    /// Number is malformed or above 255
+    BadValue(usize),
+    /// Repeat count is missing, zero or above [`MAX_REPEAT`]
+    BadRepeat(usize),
+    /// String is not closed
+    Unterminated(usize),
+    /// Line expands to more than [`MAX_STEPS`] steps
+    TooLong,
+}
+
+impl SyntaxError {
+    /// Byte offset the error points at, if any
+    pub fn position(&self) -> Option<usize> {
+        match *self {
+            SyntaxError::Unexpected(pos)
+            | SyntaxError::BadValue(pos)
+            | SyntaxError::BadRepeat(pos)
+            | SyntaxError::Unterminated(pos) => Some(pos),
+            SyntaxError::TooLong => None,
+        }
+    }
+}
+
+/// Parse one line of syntax into steps
+pub fn parse(line: &str) -> Result<Vec<Step, MAX_STEPS>, SyntaxError> {
+    let bytes = line.as_bytes();
+    let mut steps = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let op = match bytes[i] {
+            b' ' | b'\t' | b',' => {
+                i += 1;
+                continue;
+            }
+            quote @ (b'"' | b'\'') => {
+                let len = bytes[i + 1..]
+                    .iter()
+                    .position(|&b| b == quote)
+                    .ok_or(SyntaxError::Unterminated(i))?;
+                for &byte in &bytes[i + 1..i + 1 + len] {
+                    push(&mut steps, Op::Write(byte), 1)?;
+                }
+                i += len + 2;
+                continue;
+            }
+            b'0'..=b'9' => {
+                let end = token_end(bytes, i);
+                let value = parse_value(&line[i..end]).ok_or(SyntaxError::BadValue(i))?;
+                i = end;
+                Op::Write(value)
+            }
+            symbol => {
+                let op = match symbol {
+                    b'[' => Op::Start,
+                    b'{' => Op::StartRead,
+                    b']' | b'}' => Op::Stop,
+                    b'r' => Op::Read,
+                    b'&' | b'd' => Op::DelayUs,
+                    b'%' | b'D' => Op::DelayMs,
+                    _ => return Err(SyntaxError::Unexpected(i)),
+                };
+                i += 1;
+                op
+            }
+        };
+
+        let mut repeat = 1;
+        if op.can_repeat() && bytes.get(i) == Some(&b':') {
+            let end = bytes[i + 1..]
+                .iter()
+                .position(|b| !b.is_ascii_digit())
+                .map_or(bytes.len(), |len| i + 1 + len);
+            repeat = line[i + 1..end]
+                .parse()
+                .ok()
+                .filter(|n| (1..=MAX_REPEAT).contains(n))
+                .ok_or(SyntaxError::BadRepeat(i))?;
+            i = end;
+        }
+        push(&mut steps, op, repeat)?;
+    }
+    Ok(steps)
+}
+
+fn push(steps: &mut Vec<Step, MAX_STEPS>, op: Op, repeat: u16) -> Result<(), SyntaxError> {
+    steps.push(Step { op, repeat }).map_err(|_| SyntaxError::TooLong)
+}
+
+/// End of the alphanumeric run starting at `start`
+fn token_end(bytes: &[u8], start: usize) -> usize {
+    bytes[start..]
+        .iter()
+        .position(|b| !b.is_ascii_alphanumeric())
+        .map_or(bytes.len(), |len| start + len)
+}
+
+/// A byte in hex (`0x`), binary (`0b`) or decimal
+fn parse_value(token: &str) -> Option<u8> {
+    let (digits, radix) = match token.get(..2) {
+        Some("0x" | "0X") => (&token[2..], 16),
+        Some("0b" | "0B") => (&token[2..], 2),
+        _ => (token, 10),
+    };
+    u8::from_str_radix(digits, radix).ok()
+}
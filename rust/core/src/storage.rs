@@ -0,0 +1,56 @@
+//! File storage backing the protocol's file and bulk transfer messages
+
+use esp32_bus_pirate_protocol::ErrorCode;
+use heapless::{String, Vec};
+
+/// Files reachable through `FileList`, `FileRead`, `FileWrite` and bulk transfers
+pub trait Storage {
+    /// Whether files are backed by real storage
+    ///
+    /// Reported to hosts as `Features::FILESYSTEM`.
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    /// Names of the entries in directory `path`
+    fn list(&mut self, path: &str) -> Result<Vec<String<64>, 32>, ErrorCode>;
+
+    /// Size of file `path` in bytes
+    fn size(&mut self, path: &str) -> Result<u32, ErrorCode>;
+
+    /// Fill `buf` with the contents of `path` starting at `offset`
+    ///
+    /// Returns the number of bytes read, which is short only at end of file.
+    fn read(&mut self, path: &str, offset: u32, buf: &mut [u8]) -> Result<usize, ErrorCode>;
+
+    /// Write `data` to `path` at `offset`
+    ///
+    /// A write at offset 0 creates the file or truncates an existing one.
+    fn write(&mut self, path: &str, offset: u32, data: &[u8]) -> Result<(), ErrorCode>;
+}
+
+/// Storage for boards without a filesystem; every operation fails
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoStorage;
+
+impl Storage for NoStorage {
+    fn is_available(&self) -> bool {
+        false
+    }
+
+    fn list(&mut self, _path: &str) -> Result<Vec<String<64>, 32>, ErrorCode> {
+        Err(ErrorCode::NotConfigured)
+    }
+
+    fn size(&mut self, _path: &str) -> Result<u32, ErrorCode> {
+        Err(ErrorCode::NotConfigured)
+    }
+
+    fn read(&mut self, _path: &str, _offset: u32, _buf: &mut [u8]) -> Result<usize, ErrorCode> {
+        Err(ErrorCode::NotConfigured)
+    }
+
+    fn write(&mut self, _path: &str, _offset: u32, _data: &[u8]) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NotConfigured)
+    }
+}
@@ -0,0 +1,254 @@
+//! [`SerialPort`] shared by the binary protocol, the text shell and bitbang mode
+//!
+//! The host on a serial console is either a program speaking the framed
+//! protocol or a person at a terminal. [`ConsolePort`] sits between the
+//! port and the [`StreamTransport`](super::StreamTransport) and tells them
+//! apart by the bytes that arrive:
+//!
+//! - A frame starts with [`START_BYTE`] (`0xAA`), which no key produces, so
+//!   it switches the console to [`ConsoleMode::Binary`]: everything after it
+//!   goes to the transport.
+//! - Printable characters, Enter, backspace, tab, escape and Ctrl-C switch
+//!   it to [`ConsoleMode::Text`]: typed bytes are kept for the shell, which
+//!   collects them with [`read_text`](ConsolePort::read_text) and answers
+//!   through [`fmt::Write`].
+//! - [`BITBANG_ENTRY_NULLS`] `0x00` bytes in a row, the way tools written
+//!   for the original Bus Pirate enter its binary mode, switch it to
+//!   [`ConsoleMode::Bitbang`]: from then on every byte, starting with the
+//!   last `0x00`, is kept for the [`Bitbang`](crate::bitbang::Bitbang)
+//!   front-end until it calls [`leave_bitbang`](ConsolePort::leave_bitbang).
+//!
+//! After [`BINARY_IDLE_MS`] without input in binary mode, and whenever the
+//! host disconnects, the console goes back to [`ConsoleMode::Detect`], so a
+//! terminal can be used once a protocol client is done with the port.
+
+use super::{Clock, RingBuffer, SerialPort, TransportError};
+use core::fmt;
+use esp32_bus_pirate_protocol::START_BYTE;
+
+/// Quiet time after which a binary client counts as finished
+pub const BINARY_IDLE_MS: u64 = 2_000;
+
+/// `0x00` bytes in a row that enter bitbang mode
+pub const BITBANG_ENTRY_NULLS: u8 = 20;
+
+/// Typed bytes waiting for the shell
+pub const TEXT_RX_SIZE: usize = 256;
+
+/// Shell output waiting for the port
+pub const TEXT_TX_SIZE: usize = 1024;
+
+/// Who the console is talking to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleMode {
+    /// Nothing received yet
+    Detect,
+    /// A protocol client: bytes go to the transport
+    Binary,
+    /// A terminal: bytes go to the shell
+    Text,
+    /// A tool for the original Bus Pirate: bytes go to the bitbang front-end
+    Bitbang,
+}
+
+/// [`SerialPort`] that splits terminal input from protocol frames
+pub struct ConsolePort<P, C> {
+    port: P,
+    clock: C,
+    mode: ConsoleMode,
+    /// When the last byte arrived in binary mode
+    last_binary_ms: u64,
+    /// `0x00` bytes received in a row
+    nulls: u8,
+    text_rx: RingBuffer<TEXT_RX_SIZE>,
+    text_tx: RingBuffer<TEXT_TX_SIZE>,
+}
+
+impl<P: SerialPort, C: Clock> ConsolePort<P, C> {
+    /// Wrap `port`, using `clock` for the binary idle timeout
+    pub fn new(port: P, clock: C) -> Self {
+        Self {
+            port,
+            clock,
+            mode: ConsoleMode::Detect,
+            last_binary_ms: 0,
+            nulls: 0,
+            text_rx: RingBuffer::new(),
+            text_tx: RingBuffer::new(),
+        }
+    }
+
+    /// Who the console is talking to
+    pub fn mode(&self) -> ConsoleMode {
+        self.mode
+    }
+
+    /// Take typed bytes for the shell, or bitbang commands; returns the
+    /// number copied into `buf`
+    pub fn read_text(&mut self, buf: &mut [u8]) -> usize {
+        let n = self.text_rx.peek(buf);
+        self.text_rx.consume(n);
+        n
+    }
+
+    /// Queue shell or bitbang output; returns the number of bytes that fit
+    ///
+    /// Output is sent the next time the transport polls the port.
+    pub fn write_text(&mut self, text: &[u8]) -> usize {
+        self.text_tx.push(text)
+    }
+
+    /// Go back to detecting who is on the console after bitbang mode ends
+    pub fn leave_bitbang(&mut self) {
+        self.mode = ConsoleMode::Detect;
+        self.nulls = 0;
+        self.text_rx.clear();
+    }
+
+    /// The wrapped port
+    pub fn inner(&self) -> &P {
+        &self.port
+    }
+
+    /// The wrapped port, e.g. to reconfigure it
+    pub fn inner_mut(&mut self) -> &mut P {
+        &mut self.port
+    }
+
+    fn reset(&mut self) {
+        self.mode = ConsoleMode::Detect;
+        self.nulls = 0;
+        self.text_rx.clear();
+        self.text_tx.clear();
+    }
+
+    fn wait_for_room(&mut self) -> Result<(), TransportError> {
+        self.flush_text()?;
+        if self.port.is_connected() {
+            Ok(())
+        } else {
+            Err(TransportError::Disconnected)
+        }
+    }
+
+    fn flush_text(&mut self) -> Result<(), TransportError> {
+        while !self.text_tx.is_empty() {
+            match self.port.write(self.text_tx.front_slice())? {
+                0 => break,
+                n => self.text_tx.consume(n),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<P: SerialPort, C: Clock> SerialPort for ConsolePort<P, C> {
+    fn is_connected(&mut self) -> bool {
+        let connected = self.port.is_connected();
+        if !connected && self.mode != ConsoleMode::Detect {
+            self.reset();
+        }
+        connected
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, TransportError> {
+        self.flush_text()?;
+
+        let now = self.clock.now_ms();
+        if self.mode == ConsoleMode::Binary && now.saturating_sub(self.last_binary_ms) >= BINARY_IDLE_MS {
+            self.mode = ConsoleMode::Detect;
+        }
+
+        // Text has to fit the text ring; reading no more than that holds a
+        // fast typist off at the port instead of dropping keys
+        let limit = match self.mode {
+            ConsoleMode::Binary => buf.len(),
+            _ => buf.len().min(self.text_rx.free()),
+        };
+        let n = self.port.read(&mut buf[..limit])?;
+
+        // Bytes for the transport are moved to the front of `buf`
+        let mut kept = 0;
+        for i in 0..n {
+            let byte = buf[i];
+            if self.mode == ConsoleMode::Bitbang {
+                self.text_rx.push(&[byte]);
+                continue;
+            }
+            if self.mode != ConsoleMode::Binary {
+                self.nulls = if byte == 0 { self.nulls.saturating_add(1) } else { 0 };
+                if self.nulls >= BITBANG_ENTRY_NULLS {
+                    // Drop any half-typed line; the front-end answers this
+                    // last `0x00` with its greeting
+                    self.mode = ConsoleMode::Bitbang;
+                    self.text_rx.clear();
+                    self.text_rx.push(&[byte]);
+                    continue;
+                }
+            }
+
+            if byte == START_BYTE {
+                self.mode = ConsoleMode::Binary;
+            } else if self.mode != ConsoleMode::Binary && (self.mode == ConsoleMode::Text || is_text(byte)) {
+                self.mode = ConsoleMode::Text;
+                self.text_rx.push(&[byte]);
+                continue;
+            }
+            buf[kept] = byte;
+            kept += 1;
+        }
+        if self.mode == ConsoleMode::Binary && kept > 0 {
+            self.last_binary_ms = now;
+        }
+        Ok(kept)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<usize, TransportError> {
+        self.port.write(data)
+    }
+}
+
+/// Shell output; fails when the text ring is full, keeping what fit
+impl<P: SerialPort, C: Clock> fmt::Write for ConsolePort<P, C> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.write_text(s.as_bytes()) == s.len() {
+            Ok(())
+        } else {
+            Err(fmt::Error)
+        }
+    }
+}
+
+/// Whether `byte` is something a terminal sends
+fn is_text(byte: u8) -> bool {
+    matches!(byte, b' '..=b'~' | b'\r' | b'\n' | b'\t' | 0x03 | 0x08 | 0x1B | 0x7F)
+}
+
+impl<P, C> embedded_io::ErrorType for ConsolePort<P, C> {
+    type Error = TransportError;
+}
+
+/// Bitbang output, which may be larger than the text ring
+///
+/// Writes wait for the host to make room, sending queued output to the
+/// port meanwhile, and fail once the host has gone.
+impl<P: SerialPort, C: Clock> embedded_io::Write for ConsolePort<P, C> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, TransportError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            match self.write_text(buf) {
+                0 => self.wait_for_room()?,
+                n => return Ok(n),
+            }
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), TransportError> {
+        while !self.text_tx.is_empty() {
+            self.wait_for_room()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+//! [`SerialPort`] for `embedded-io` byte streams such as UARTs
+
+use super::{SerialPort, TransportError};
+use embedded_io::{Read, ReadReady, Write, WriteReady};
+
+/// Non-blocking [`SerialPort`] over a blocking `embedded-io` stream
+///
+/// Reads and writes only happen when the stream reports it is ready, so
+/// they never block. A UART has no notion of a host attaching, so the port
+/// always counts as connected.
+pub struct IoPort<T> {
+    io: T,
+}
+
+impl<T> IoPort<T> {
+    /// Wrap `io`
+    pub fn new(io: T) -> Self {
+        Self { io }
+    }
+
+    /// The wrapped stream
+    pub fn inner(&self) -> &T {
+        &self.io
+    }
+
+    /// The wrapped stream, e.g. to reconfigure it
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.io
+    }
+
+    /// Release the wrapped stream
+    pub fn into_inner(self) -> T {
+        self.io
+    }
+}
+
+impl<T: Read + ReadReady + Write + WriteReady> SerialPort for IoPort<T> {
+    fn is_connected(&mut self) -> bool {
+        true
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, TransportError> {
+        if buf.is_empty() || !self.io.read_ready().map_err(|_| TransportError::IoError)? {
+            return Ok(0);
+        }
+        self.io.read(buf).map_err(|_| TransportError::IoError)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<usize, TransportError> {
+        if data.is_empty() || !self.io.write_ready().map_err(|_| TransportError::IoError)? {
+            return Ok(0);
+        }
+        self.io.write(data).map_err(|_| TransportError::IoError)
+    }
+}
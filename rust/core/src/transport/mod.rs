@@ -0,0 +1,146 @@
+//! Transport layer: carrying protocol frames over byte streams
+//!
+//! The hardware-specific part of a transport is a [`SerialPort`]: something
+//! that moves bytes without blocking and knows whether a host is attached.
+//! [`StreamTransport`] turns any port into a [`Transport`] with RX/TX ring
+//! buffers and backpressure, framing received bytes with the protocol's
+//! [`FrameDecoder`], so the firmware's USB and UART transports only differ
+//! in their port.
+//! [`IoPort`] adapts any `embedded-io` stream, such as a UART, and
+//! [`ConsolePort`] lets a terminal share a port with the protocol by
+//! routing typed text to the shell.
+//!
+//! Network transports serve several clients at once: [`NetTransport`] keeps
+//! a session per connection from a [`Listener`], carrying frames as they
+//! are ([`TcpTransport`]) or in WebSocket messages ([`WebSocketTransport`]).
+//!
+//! ```text
+//!  Transport::receive ◄── FrameDecoder ◄── RX ring ◄──┐
+//!                                                     SerialPort
+//!  Transport::send ──────────────────────► TX ring ──┘
+//! ```
+
+pub mod console;
+pub mod io_port;
+pub mod net;
+pub mod ring;
+pub mod stream;
+pub mod websocket;
+
+pub use console::{ConsoleMode, ConsolePort};
+pub use io_port::IoPort;
+pub use net::{Encapsulation, Listener, NetStats, NetTransport, Raw, TcpTransport};
+pub use ring::RingBuffer;
+pub use stream::StreamTransport;
+pub use websocket::{WebSocket, WebSocketTransport};
+
+pub use esp32_bus_pirate_protocol::{frame_timeout_for_baud, DecoderStats, FrameDecoder};
+
+/// Transport trait for sending and receiving protocol messages
+pub trait Transport {
+    /// Send a message frame
+    fn send(&mut self, frame: &[u8]) -> Result<(), TransportError>;
+
+    /// Receive a message frame
+    /// Returns None if no complete frame is available
+    fn receive(&mut self) -> Result<Option<&[u8]>, TransportError>;
+
+    /// Check if the transport is connected
+    fn is_connected(&self) -> bool;
+
+    /// The client that sent the last received frame, which `send` replies to
+    ///
+    /// Transports with a single host report one client while connected.
+    fn current_client(&self) -> Option<ClientId> {
+        self.is_connected().then_some(ClientId(0))
+    }
+
+    /// Check whether `client` is still connected
+    fn is_client_connected(&self, client: ClientId) -> bool {
+        self.current_client() == Some(client)
+    }
+}
+
+/// Identifies a client of a transport for as long as its connection lasts
+///
+/// Ids are not reused, so a reply meant for a client that has left cannot
+/// reach the next one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClientId(pub u32);
+
+/// Transport error types
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportError {
+    /// Buffer overflow
+    BufferFull,
+    /// Transport disconnected
+    Disconnected,
+    /// I/O error
+    IoError,
+    /// Timeout
+    Timeout,
+}
+
+impl embedded_io::Error for TransportError {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        match self {
+            TransportError::BufferFull => embedded_io::ErrorKind::OutOfMemory,
+            TransportError::Disconnected => embedded_io::ErrorKind::NotConnected,
+            TransportError::IoError => embedded_io::ErrorKind::Other,
+            TransportError::Timeout => embedded_io::ErrorKind::TimedOut,
+        }
+    }
+}
+
+/// Non-blocking byte link underneath a [`StreamTransport`]
+pub trait SerialPort {
+    /// Whether a host is attached
+    ///
+    /// Ports that cannot tell (a plain UART) always return `true`.
+    fn is_connected(&mut self) -> bool;
+
+    /// Read the bytes that are available now; `Ok(0)` when there are none
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, TransportError>;
+
+    /// Write as many bytes as the port accepts now; `Ok(0)` when it is busy
+    fn write(&mut self, data: &[u8]) -> Result<usize, TransportError>;
+}
+
+/// Millisecond time source for timeouts
+pub trait Clock {
+    /// Milliseconds since an arbitrary, fixed point
+    fn now_ms(&self) -> u64;
+}
+
+impl<F: Fn() -> u64> Clock for F {
+    fn now_ms(&self) -> u64 {
+        self()
+    }
+}
+
+/// Next complete frame, moving bytes from `rx` into `decoder` as it has room
+///
+/// `now_ms` drives the decoder's incomplete-frame timeout.
+fn next_frame<'a, const N: usize>(
+    rx: &mut RingBuffer<N>,
+    decoder: &'a mut FrameDecoder,
+    now_ms: u64,
+) -> Option<&'a [u8]> {
+    loop {
+        let mut moved = 0;
+        loop {
+            let n = decoder.push(rx.front_slice());
+            if n == 0 {
+                break;
+            }
+            rx.consume(n);
+            moved += n;
+        }
+        if decoder.poll_raw().is_some() {
+            return Some(decoder.last_frame());
+        }
+        if moved == 0 && !decoder.expire(now_ms) {
+            return None;
+        }
+    }
+}
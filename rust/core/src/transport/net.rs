@@ -0,0 +1,349 @@
+//! [`Transport`] over a network listener with one session per client
+//!
+//! A [`Listener`] hands out accepted connections, each a non-blocking
+//! [`SerialPort`]. Every connection gets its own session: an RX frame
+//! decoder, a TX ring and the [`Encapsulation`] that carries frames on it
+//! ([`Raw`] for plain TCP, [`WebSocket`](super::WebSocket) for browsers).
+//!
+//! Frames from all clients are delivered in turn through
+//! [`Transport::receive`]; [`Transport::send`] replies to the client whose
+//! frame was received last. [`NetTransport::send_to`] reaches any client.
+
+use super::{next_frame, ClientId, Clock, FrameDecoder, RingBuffer, SerialPort, Transport, TransportError};
+use esp32_bus_pirate_protocol::MAX_MESSAGE_SIZE;
+
+/// Clients served at once unless configured otherwise
+pub const DEFAULT_MAX_CLIENTS: usize = 4;
+
+/// Per-session RX ring size
+pub const SESSION_RX_SIZE: usize = MAX_MESSAGE_SIZE;
+
+/// Per-session TX ring size, enough for a reply and an event
+pub const SESSION_TX_SIZE: usize = 2 * MAX_MESSAGE_SIZE;
+
+/// Source of new connections, such as a listening TCP socket
+pub trait Listener {
+    /// An accepted connection
+    type Connection: SerialPort;
+
+    /// The next pending connection, without blocking
+    fn accept(&mut self) -> Result<Option<Self::Connection>, TransportError>;
+
+    /// Close a connection the transport is done with
+    ///
+    /// The default drops it, which is enough for sockets that close on drop.
+    fn close(&mut self, _connection: Self::Connection) {}
+}
+
+/// How protocol frames are carried on a connection
+pub trait Encapsulation: Default {
+    /// Read what `port` has and move frame bytes into `rx`
+    ///
+    /// Anything the encapsulation answers on its own, such as a handshake,
+    /// is queued in `tx`.
+    fn receive<P: SerialPort, const RX: usize, const TX: usize>(
+        &mut self,
+        port: &mut P,
+        rx: &mut RingBuffer<RX>,
+        tx: &mut RingBuffer<TX>,
+    ) -> Result<(), TransportError>;
+
+    /// Queue `frame` in `tx`, or fail with `BufferFull` if it does not fit
+    fn send<const TX: usize>(&mut self, frame: &[u8], tx: &mut RingBuffer<TX>) -> Result<(), TransportError>;
+
+    /// Start closing the connection, queueing any goodbye in `tx`
+    fn close<const TX: usize>(&mut self, tx: &mut RingBuffer<TX>);
+
+    /// Whether protocol frames can be exchanged
+    fn is_open(&self) -> bool;
+
+    /// Whether the connection ends once `tx` has been flushed
+    fn is_closing(&self) -> bool;
+
+    /// Turn away a connection the transport has no room for
+    fn reject<P: SerialPort>(_port: &mut P) {}
+}
+
+/// Frames sent as they are, as on a serial link
+#[derive(Default)]
+pub struct Raw {
+    closing: bool,
+}
+
+impl Encapsulation for Raw {
+    fn receive<P: SerialPort, const RX: usize, const TX: usize>(
+        &mut self,
+        port: &mut P,
+        rx: &mut RingBuffer<RX>,
+        _tx: &mut RingBuffer<TX>,
+    ) -> Result<(), TransportError> {
+        loop {
+            let space = rx.free_slice();
+            if space.is_empty() {
+                return Ok(());
+            }
+            match port.read(space)? {
+                0 => return Ok(()),
+                n => rx.commit(n),
+            }
+        }
+    }
+
+    fn send<const TX: usize>(&mut self, frame: &[u8], tx: &mut RingBuffer<TX>) -> Result<(), TransportError> {
+        if self.closing {
+            return Err(TransportError::Disconnected);
+        }
+        if frame.len() > tx.free() {
+            return Err(TransportError::BufferFull);
+        }
+        tx.push(frame);
+        Ok(())
+    }
+
+    fn close<const TX: usize>(&mut self, _tx: &mut RingBuffer<TX>) {
+        self.closing = true;
+    }
+
+    fn is_open(&self) -> bool {
+        !self.closing
+    }
+
+    fn is_closing(&self) -> bool {
+        self.closing
+    }
+}
+
+/// Connection counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NetStats {
+    /// Connections that got a session
+    pub accepted: u32,
+    /// Connections turned away by the client limit or the single-client lock
+    pub rejected: u32,
+}
+
+struct Session<P, E, const RX: usize, const TX: usize> {
+    id: ClientId,
+    port: P,
+    link: E,
+    rx: RingBuffer<RX>,
+    decoder: FrameDecoder,
+    tx: RingBuffer<TX>,
+}
+
+impl<P: SerialPort, E: Encapsulation, const RX: usize, const TX: usize> Session<P, E, RX, TX> {
+    /// Move bytes; an error means the session is over
+    fn poll(&mut self) -> Result<(), TransportError> {
+        if !self.port.is_connected() {
+            return Err(TransportError::Disconnected);
+        }
+        self.flush()?;
+        if !self.link.is_closing() {
+            self.link.receive(&mut self.port, &mut self.rx, &mut self.tx)?;
+            self.flush()?;
+        }
+        if self.link.is_closing() && self.tx.is_empty() {
+            return Err(TransportError::Disconnected);
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, frame: &[u8]) -> Result<(), TransportError> {
+        self.link.send(frame, &mut self.tx)?;
+        self.flush()
+    }
+
+    fn flush(&mut self) -> Result<(), TransportError> {
+        while !self.tx.is_empty() {
+            match self.port.write(self.tx.front_slice())? {
+                0 => break,
+                n => self.tx.consume(n),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Network [`Transport`] serving up to `CLIENTS` clients at once
+///
+/// With the single-client lock on, a connection is only accepted while no
+/// other client is connected, so one host cannot be interrupted by another.
+/// Connections over the limit are closed straight away.
+pub struct NetTransport<L: Listener, C, E, const CLIENTS: usize, const RX: usize, const TX: usize> {
+    listener: L,
+    clock: C,
+    sessions: [Option<Session<L::Connection, E, RX, TX>>; CLIENTS],
+    single_client: bool,
+    next_id: u32,
+    next_session: usize,
+    current: Option<ClientId>,
+    stats: NetStats,
+}
+
+/// Framed protocol over raw TCP, the way serial tools expect it
+pub type TcpTransport<L, C, const CLIENTS: usize = DEFAULT_MAX_CLIENTS> =
+    NetTransport<L, C, Raw, CLIENTS, SESSION_RX_SIZE, SESSION_TX_SIZE>;
+
+impl<L: Listener, C: Clock, E: Encapsulation, const CLIENTS: usize, const RX: usize, const TX: usize>
+    NetTransport<L, C, E, CLIENTS, RX, TX>
+{
+    /// Serve connections from `listener`, using `clock` for frame timeouts
+    pub fn new(listener: L, clock: C) -> Self {
+        Self {
+            listener,
+            clock,
+            sessions: core::array::from_fn(|_| None),
+            single_client: false,
+            next_id: 1,
+            next_session: 0,
+            current: None,
+            stats: NetStats::default(),
+        }
+    }
+
+    /// Only accept a client while no other client is connected
+    pub fn with_single_client(mut self, single_client: bool) -> Self {
+        self.single_client = single_client;
+        self
+    }
+
+    /// Accept new clients and move bytes for every session
+    ///
+    /// [`send`](Transport::send) and [`receive`](Transport::receive) do this
+    /// too; call it directly to keep queued output moving while idle.
+    pub fn poll(&mut self) -> Result<(), TransportError> {
+        while let Some(mut connection) = self.listener.accept()? {
+            let slot = self.sessions.iter().position(Option::is_none);
+            match slot {
+                Some(slot) if !(self.single_client && self.client_count() > 0) => {
+                    self.sessions[slot] = Some(Session {
+                        id: ClientId(self.next_id),
+                        port: connection,
+                        link: E::default(),
+                        rx: RingBuffer::new(),
+                        decoder: FrameDecoder::new(),
+                        tx: RingBuffer::new(),
+                    });
+                    self.next_id = self.next_id.wrapping_add(1).max(1);
+                    self.stats.accepted += 1;
+                }
+                _ => {
+                    E::reject(&mut connection);
+                    self.listener.close(connection);
+                    self.stats.rejected += 1;
+                }
+            }
+        }
+
+        for slot in 0..CLIENTS {
+            let Some(session) = &mut self.sessions[slot] else {
+                continue;
+            };
+            if session.poll().is_err() {
+                self.close_slot(slot);
+            }
+        }
+        Ok(())
+    }
+
+    /// Connected clients
+    pub fn clients(&self) -> impl Iterator<Item = ClientId> + '_ {
+        self.sessions.iter().flatten().map(|session| session.id)
+    }
+
+    /// Number of connected clients
+    pub fn client_count(&self) -> usize {
+        self.sessions.iter().flatten().count()
+    }
+
+    /// Queue `frame` for `client`
+    ///
+    /// Fails with `Disconnected` if the client has left and with
+    /// `BufferFull` if its TX ring cannot take the frame yet.
+    pub fn send_to(&mut self, client: ClientId, frame: &[u8]) -> Result<(), TransportError> {
+        let slot = self.slot_of(client).ok_or(TransportError::Disconnected)?;
+        let Some(session) = &mut self.sessions[slot] else {
+            return Err(TransportError::Disconnected);
+        };
+        match session.send(frame) {
+            Err(TransportError::IoError) => {
+                self.close_slot(slot);
+                Err(TransportError::Disconnected)
+            }
+            result => result,
+        }
+    }
+
+    /// Close `client`'s connection once its queued output is sent
+    pub fn disconnect(&mut self, client: ClientId) {
+        if let Some(session) = self.slot_of(client).and_then(|slot| self.sessions[slot].as_mut()) {
+            session.link.close(&mut session.tx);
+        }
+    }
+
+    /// Connection counters
+    pub fn stats(&self) -> &NetStats {
+        &self.stats
+    }
+
+    /// The listener, e.g. to inspect its address
+    pub fn listener(&self) -> &L {
+        &self.listener
+    }
+
+    fn slot_of(&self, client: ClientId) -> Option<usize> {
+        self.sessions
+            .iter()
+            .position(|session| session.as_ref().is_some_and(|session| session.id == client))
+    }
+
+    fn close_slot(&mut self, slot: usize) {
+        if let Some(session) = self.sessions[slot].take() {
+            self.listener.close(session.port);
+        }
+    }
+}
+
+impl<L: Listener, C: Clock, E: Encapsulation, const CLIENTS: usize, const RX: usize, const TX: usize> Transport
+    for NetTransport<L, C, E, CLIENTS, RX, TX>
+{
+    fn send(&mut self, frame: &[u8]) -> Result<(), TransportError> {
+        self.poll()?;
+        let client = self.current.ok_or(TransportError::Disconnected)?;
+        self.send_to(client, frame)
+    }
+
+    fn receive(&mut self) -> Result<Option<&[u8]>, TransportError> {
+        self.poll()?;
+        let now = self.clock.now_ms();
+
+        // Start after the client served last, so a busy client cannot
+        // starve the others
+        let ready = (0..CLIENTS).map(|i| (self.next_session + i) % CLIENTS).find(|&slot| {
+            self.sessions[slot].as_mut().is_some_and(|session| {
+                session.link.is_open() && next_frame(&mut session.rx, &mut session.decoder, now).is_some()
+            })
+        });
+        let Some(slot) = ready else {
+            return Ok(None);
+        };
+        let Some(session) = &self.sessions[slot] else {
+            return Ok(None);
+        };
+        self.next_session = (slot + 1) % CLIENTS;
+        self.current = Some(session.id);
+        Ok(Some(session.decoder.last_frame()))
+    }
+
+    fn is_connected(&self) -> bool {
+        self.sessions.iter().flatten().any(|session| session.link.is_open())
+    }
+
+    fn current_client(&self) -> Option<ClientId> {
+        self.current.filter(|&client| self.is_client_connected(client))
+    }
+
+    fn is_client_connected(&self, client: ClientId) -> bool {
+        self.slot_of(client).is_some()
+    }
+}
@@ -0,0 +1,112 @@
+//! Fixed-size byte ring buffer
+
+/// Byte FIFO of `N` bytes backed by an array
+///
+/// Unlike a queue of single bytes, it hands out contiguous slices so ports
+/// can read into and write from it in bulk.
+pub struct RingBuffer<const N: usize> {
+    buf: [u8; N],
+    head: usize,
+    len: usize,
+}
+
+impl<const N: usize> Default for RingBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> RingBuffer<N> {
+    /// Create an empty buffer
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Number of buffered bytes
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether no bytes are buffered
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Room left, in bytes
+    pub fn free(&self) -> usize {
+        N - self.len
+    }
+
+    /// Append as much of `data` as fits; returns the number of bytes taken
+    pub fn push(&mut self, data: &[u8]) -> usize {
+        let mut taken = 0;
+        while taken < data.len() {
+            let space = self.free_slice();
+            if space.is_empty() {
+                break;
+            }
+            let n = space.len().min(data.len() - taken);
+            space[..n].copy_from_slice(&data[taken..taken + n]);
+            self.commit(n);
+            taken += n;
+        }
+        taken
+    }
+
+    /// The byte `index` positions from the front
+    pub fn get(&self, index: usize) -> Option<u8> {
+        (index < self.len).then(|| self.buf[(self.head + index) % N])
+    }
+
+    /// Copy bytes from the front into `out` without removing them
+    ///
+    /// Returns the number of bytes copied.
+    pub fn peek(&self, out: &mut [u8]) -> usize {
+        let n = out.len().min(self.len);
+        for (i, byte) in out[..n].iter_mut().enumerate() {
+            *byte = self.buf[(self.head + i) % N];
+        }
+        n
+    }
+
+    /// Bytes at the front that are contiguous in memory
+    pub fn front_slice(&self) -> &[u8] {
+        let end = (self.head + self.len).min(N);
+        &self.buf[self.head..end]
+    }
+
+    /// Remove up to `n` bytes from the front
+    pub fn consume(&mut self, n: usize) {
+        let n = n.min(self.len);
+        self.head = (self.head + n) % N;
+        self.len -= n;
+        if self.len == 0 {
+            self.head = 0;
+        }
+    }
+
+    /// Free space after the last byte that is contiguous in memory
+    ///
+    /// Fill it and then call [`commit`](Self::commit) with the number of
+    /// bytes written.
+    pub fn free_slice(&mut self) -> &mut [u8] {
+        let tail = (self.head + self.len) % N;
+        let end = if tail < self.head || self.len == N { self.head } else { N };
+        &mut self.buf[tail..end]
+    }
+
+    /// Mark `n` bytes of [`free_slice`](Self::free_slice) as filled
+    pub fn commit(&mut self, n: usize) {
+        self.len = (self.len + n).min(N);
+    }
+
+    /// Drop every buffered byte
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+}
@@ -0,0 +1,145 @@
+//! [`Transport`] over any non-blocking [`SerialPort`]
+
+use super::{next_frame, ClientId, Clock, DecoderStats, FrameDecoder, RingBuffer, SerialPort, Transport, TransportError};
+
+/// Framed transport with an `RX`-byte receive ring and a `TX`-byte send ring
+///
+/// Flow control works in both directions without blocking:
+///
+/// - **Backpressure**: bytes are only read from the port while the RX ring
+///   has room, so a host that sends faster than frames are handled is held
+///   off by the port (USB NAKs, UART RTS) instead of losing data.
+/// - **TX throttling**: [`send`](Transport::send) queues whole frames only.
+///   When the TX ring cannot take a frame it returns
+///   [`TransportError::BufferFull`] and the caller retries after yielding.
+///
+/// On connect and disconnect both rings are cleared, so a new host never
+/// sees the tail of the previous session. Each connection counts as a new
+/// client.
+pub struct StreamTransport<P, C, const RX: usize, const TX: usize> {
+    port: P,
+    clock: C,
+    rx: RingBuffer<RX>,
+    decoder: FrameDecoder,
+    tx: RingBuffer<TX>,
+    connected: bool,
+    connections: u32,
+}
+
+impl<P: SerialPort, C: Clock, const RX: usize, const TX: usize> StreamTransport<P, C, RX, TX> {
+    /// Wrap `port`, using `clock` for the incomplete-frame timeout
+    pub fn new(port: P, clock: C) -> Self {
+        Self {
+            port,
+            clock,
+            rx: RingBuffer::new(),
+            decoder: FrameDecoder::new(),
+            tx: RingBuffer::new(),
+            connected: false,
+            connections: 0,
+        }
+    }
+
+    /// Set how long an incomplete frame may wait for its remaining bytes
+    pub fn with_frame_timeout(mut self, timeout_ms: u64) -> Self {
+        self.decoder = self.decoder.with_frame_timeout(timeout_ms);
+        self
+    }
+
+    /// Move bytes between the port and the rings
+    ///
+    /// [`send`](Transport::send) and [`receive`](Transport::receive) do this
+    /// too; call it directly to keep queued output moving while idle.
+    pub fn poll(&mut self) -> Result<(), TransportError> {
+        let connected = self.port.is_connected();
+        if connected != self.connected {
+            self.rx.clear();
+            self.decoder.reset();
+            self.tx.clear();
+            self.connected = connected;
+            if connected {
+                self.connections = self.connections.wrapping_add(1);
+            }
+        }
+        if !connected {
+            return Ok(());
+        }
+
+        self.flush_tx()?;
+
+        loop {
+            let space = self.rx.free_slice();
+            if space.is_empty() {
+                break;
+            }
+            match self.port.read(space)? {
+                0 => break,
+                n => self.rx.commit(n),
+            }
+        }
+        Ok(())
+    }
+
+    /// Bytes queued for sending
+    pub fn pending_tx(&self) -> usize {
+        self.tx.len()
+    }
+
+    /// Link statistics
+    pub fn stats(&self) -> &DecoderStats {
+        self.decoder.stats()
+    }
+
+    /// The underlying port
+    pub fn port(&self) -> &P {
+        &self.port
+    }
+
+    /// The underlying port, e.g. to reconfigure it
+    pub fn port_mut(&mut self) -> &mut P {
+        &mut self.port
+    }
+
+    /// Release the port
+    pub fn into_port(self) -> P {
+        self.port
+    }
+
+    fn flush_tx(&mut self) -> Result<(), TransportError> {
+        while !self.tx.is_empty() {
+            match self.port.write(self.tx.front_slice())? {
+                0 => break,
+                n => self.tx.consume(n),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<P: SerialPort, C: Clock, const RX: usize, const TX: usize> Transport for StreamTransport<P, C, RX, TX> {
+    fn send(&mut self, frame: &[u8]) -> Result<(), TransportError> {
+        self.poll()?;
+        if !self.connected {
+            return Err(TransportError::Disconnected);
+        }
+        if frame.len() > self.tx.free() {
+            return Err(TransportError::BufferFull);
+        }
+        self.tx.push(frame);
+        self.flush_tx()
+    }
+
+    fn receive(&mut self) -> Result<Option<&[u8]>, TransportError> {
+        self.poll()?;
+        let now = self.clock.now_ms();
+        Ok(next_frame(&mut self.rx, &mut self.decoder, now))
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    fn current_client(&self) -> Option<ClientId> {
+        self.connected.then_some(ClientId(self.connections))
+    }
+}
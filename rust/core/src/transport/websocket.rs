@@ -0,0 +1,397 @@
+//! WebSocket encapsulation (RFC 6455) for browser clients
+//!
+//! A connection starts with an HTTP `Upgrade` request; the request path is
+//! not checked, so the legacy `ws://<device-ip>/ws` URL keeps working. After
+//! the handshake, protocol frames travel in binary messages, one frame per
+//! message when sending. Received messages are treated as a byte stream, so
+//! a client may also split a frame across messages or batch several.
+//!
+//! Pings are answered, a close is echoed before the connection ends, and
+//! text messages are refused with close code 1003 since the protocol is
+//! binary. Everything is streamed through small buffers: neither the
+//! handshake nor a message has to fit in memory at once.
+
+use super::net::{Encapsulation, NetTransport, DEFAULT_MAX_CLIENTS, SESSION_RX_SIZE, SESSION_TX_SIZE};
+use super::{RingBuffer, SerialPort, TransportError};
+use base64::Engine;
+use heapless::Vec;
+
+/// Framed protocol over WebSocket binary messages
+pub type WebSocketTransport<L, C, const CLIENTS: usize = DEFAULT_MAX_CLIENTS> =
+    NetTransport<L, C, WebSocket, CLIENTS, SESSION_RX_SIZE, SESSION_TX_SIZE>;
+
+/// GUID appended to the client's key for `Sec-WebSocket-Accept`
+const ACCEPT_GUID: &[u8] = b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Length of a `Sec-WebSocket-Key`: 16 random bytes in base64
+const KEY_LEN: usize = 24;
+
+/// Longest request line or header kept; longer ones (cookies) are cut off
+const MAX_LINE: usize = 128;
+
+/// Received bytes waiting to be parsed
+const INPUT_SIZE: usize = 256;
+
+/// Largest control frame payload allowed by RFC 6455
+const MAX_CONTROL_PAYLOAD: usize = 125;
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+const FIN: u8 = 0x80;
+const MASKED: u8 = 0x80;
+
+/// Close status codes
+const CLOSE_NORMAL: u16 = 1000;
+const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+const CLOSE_UNSUPPORTED_DATA: u16 = 1003;
+
+const RESPONSE_SWITCHING: &[u8] =
+    b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: ";
+const RESPONSE_BAD_REQUEST: &[u8] =
+    b"HTTP/1.1 400 Bad Request\r\nSec-WebSocket-Version: 13\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+const RESPONSE_BUSY: &[u8] = b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+
+/// `Sec-WebSocket-Accept` value for a client's `Sec-WebSocket-Key`
+pub fn accept_key(key: &[u8]) -> [u8; 28] {
+    let mut sha = sha1_smol::Sha1::new();
+    sha.update(key);
+    sha.update(ACCEPT_GUID);
+    let mut accept = [0; 28];
+    base64::engine::general_purpose::STANDARD
+        .encode_slice(sha.digest().bytes(), &mut accept)
+        .expect("a SHA-1 digest is 28 base64 characters");
+    accept
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Handshake,
+    Open,
+    Closing,
+}
+
+/// The parts of the upgrade request that matter, parsed line by line
+#[derive(Debug, Default)]
+struct Request {
+    line: Vec<u8, MAX_LINE>,
+    lines: usize,
+    get: bool,
+    upgrade: bool,
+    connection_upgrade: bool,
+    version_13: bool,
+    key: Option<[u8; KEY_LEN]>,
+}
+
+impl Request {
+    fn header(&mut self, line: &[u8]) {
+        let Some(colon) = line.iter().position(|&b| b == b':') else {
+            return;
+        };
+        let name = line[..colon].trim_ascii();
+        let value = line[colon + 1..].trim_ascii();
+        if name.eq_ignore_ascii_case(b"upgrade") {
+            self.upgrade = value.eq_ignore_ascii_case(b"websocket");
+        } else if name.eq_ignore_ascii_case(b"connection") {
+            self.connection_upgrade = value
+                .split(|&b| b == b',')
+                .any(|token| token.trim_ascii().eq_ignore_ascii_case(b"upgrade"));
+        } else if name.eq_ignore_ascii_case(b"sec-websocket-version") {
+            self.version_13 = value == b"13";
+        } else if name.eq_ignore_ascii_case(b"sec-websocket-key") {
+            self.key = value.try_into().ok();
+        }
+    }
+
+    fn is_valid(&self) -> bool {
+        self.get && self.upgrade && self.connection_upgrade && self.version_13
+    }
+}
+
+/// Header of the client frame whose payload is being received
+#[derive(Debug)]
+struct FrameHeader {
+    opcode: u8,
+    remaining: u64,
+    mask: [u8; 4],
+    offset: usize,
+}
+
+/// WebSocket server side of one connection
+pub struct WebSocket {
+    state: State,
+    input: RingBuffer<INPUT_SIZE>,
+    request: Request,
+    frame: Option<FrameHeader>,
+    in_message: bool,
+    control: Vec<u8, MAX_CONTROL_PAYLOAD>,
+}
+
+impl Default for WebSocket {
+    fn default() -> Self {
+        Self {
+            state: State::Handshake,
+            input: RingBuffer::new(),
+            request: Request::default(),
+            frame: None,
+            in_message: false,
+            control: Vec::new(),
+        }
+    }
+}
+
+impl WebSocket {
+    /// Parse what is buffered until nothing more can be done
+    fn process<const RX: usize, const TX: usize>(&mut self, rx: &mut RingBuffer<RX>, tx: &mut RingBuffer<TX>) {
+        loop {
+            let progress = match self.state {
+                State::Handshake => self.read_request(tx),
+                State::Open => self.read_frame(rx, tx),
+                State::Closing => {
+                    self.input.clear();
+                    false
+                }
+            };
+            if !progress {
+                return;
+            }
+        }
+    }
+
+    /// Consume request lines; true once the handshake is answered
+    fn read_request<const TX: usize>(&mut self, tx: &mut RingBuffer<TX>) -> bool {
+        while let Some(byte) = self.input.get(0) {
+            self.input.consume(1);
+            if byte != b'\n' {
+                // Overlong lines are cut off; none of the headers we need is long
+                let _ = self.request.line.push(byte);
+                continue;
+            }
+
+            let request = &mut self.request;
+            let line = core::mem::take(&mut request.line);
+            let line = line.strip_suffix(b"\r").unwrap_or(&line[..]);
+            request.lines += 1;
+            if request.lines == 1 {
+                request.get = line.starts_with(b"GET ");
+            } else if !line.is_empty() {
+                request.header(line);
+            } else {
+                self.finish_handshake(tx);
+                return true;
+            }
+        }
+        false
+    }
+
+    fn finish_handshake<const TX: usize>(&mut self, tx: &mut RingBuffer<TX>) {
+        // The TX ring of a new session is empty and larger than either response
+        match self.request.key.filter(|_| self.request.is_valid()) {
+            Some(key) => {
+                tx.push(RESPONSE_SWITCHING);
+                tx.push(&accept_key(&key));
+                tx.push(b"\r\n\r\n");
+                self.state = State::Open;
+            }
+            None => {
+                tx.push(RESPONSE_BAD_REQUEST);
+                self.state = State::Closing;
+            }
+        }
+        self.request = Request::default();
+    }
+
+    /// Parse a frame header or move payload; false when stalled
+    fn read_frame<const RX: usize, const TX: usize>(&mut self, rx: &mut RingBuffer<RX>, tx: &mut RingBuffer<TX>) -> bool {
+        let Some(frame) = &mut self.frame else {
+            return self.read_header(tx);
+        };
+
+        if frame.remaining > 0 {
+            let available = frame.remaining.min(self.input.len() as u64) as usize;
+            let n = match frame.opcode {
+                OP_BINARY => available.min(rx.free()),
+                _ => available.min(self.control.capacity() - self.control.len()),
+            };
+            if n == 0 {
+                return false;
+            }
+            for i in 0..n {
+                let byte = self.input.get(i).unwrap_or(0) ^ frame.mask[frame.offset % 4];
+                frame.offset += 1;
+                if frame.opcode == OP_BINARY {
+                    rx.push(&[byte]);
+                } else {
+                    let _ = self.control.push(byte);
+                }
+            }
+            self.input.consume(n);
+            frame.remaining -= n as u64;
+            if frame.remaining > 0 {
+                return true;
+            }
+        }
+
+        match frame.opcode {
+            // Answer once the TX ring has room
+            OP_PING if !queue_frame(tx, OP_PONG, &self.control) => return false,
+            OP_CLOSE => {
+                // Echo the status code, as RFC 6455 asks
+                let code = self.control.get(..2).unwrap_or(&[]);
+                queue_frame(tx, OP_CLOSE, code);
+                self.state = State::Closing;
+            }
+            _ => {}
+        }
+        self.frame = None;
+        self.control.clear();
+        true
+    }
+
+    /// Parse the next frame header; false until it is complete
+    fn read_header<const TX: usize>(&mut self, tx: &mut RingBuffer<TX>) -> bool {
+        let (Some(b0), Some(b1)) = (self.input.get(0), self.input.get(1)) else {
+            return false;
+        };
+        let extended = match b1 & 0x7F {
+            126 => 2,
+            127 => 8,
+            _ => 0,
+        };
+        let header_len = 2 + extended + 4;
+        if b1 & MASKED == 0 {
+            // Clients must mask every frame
+            self.fail(tx, CLOSE_PROTOCOL_ERROR);
+            return true;
+        }
+        if self.input.len() < header_len {
+            return false;
+        }
+
+        let mut header = [0; 14];
+        self.input.peek(&mut header[..header_len]);
+        self.input.consume(header_len);
+        let len = match extended {
+            2 => u16::from_be_bytes([header[2], header[3]]) as u64,
+            8 => u64::from_be_bytes(header[2..10].try_into().unwrap_or_default()),
+            _ => (b1 & 0x7F) as u64,
+        };
+        let mut mask = [0; 4];
+        mask.copy_from_slice(&header[header_len - 4..header_len]);
+
+        let fin = b0 & FIN != 0;
+        let opcode = b0 & 0x0F;
+        let valid = b0 & 0x70 == 0
+            && len >> 63 == 0
+            && match opcode {
+                OP_BINARY => !self.in_message,
+                OP_CONTINUATION => self.in_message,
+                OP_TEXT => true,
+                OP_CLOSE | OP_PING | OP_PONG => fin && len <= MAX_CONTROL_PAYLOAD as u64,
+                _ => false,
+            };
+        if !valid {
+            self.fail(tx, CLOSE_PROTOCOL_ERROR);
+            return true;
+        }
+        if opcode == OP_TEXT {
+            self.fail(tx, CLOSE_UNSUPPORTED_DATA);
+            return true;
+        }
+
+        if matches!(opcode, OP_BINARY | OP_CONTINUATION) {
+            self.in_message = !fin;
+        }
+        self.frame = Some(FrameHeader {
+            // Continuations only ever continue a binary message
+            opcode: if opcode == OP_CONTINUATION { OP_BINARY } else { opcode },
+            remaining: len,
+            mask,
+            offset: 0,
+        });
+        true
+    }
+
+    /// Close the connection with `code` after a client error
+    fn fail<const TX: usize>(&mut self, tx: &mut RingBuffer<TX>, code: u16) {
+        queue_frame(tx, OP_CLOSE, &code.to_be_bytes());
+        self.state = State::Closing;
+    }
+}
+
+/// Queue an unmasked, unfragmented server frame; false if `tx` lacks room
+fn queue_frame<const TX: usize>(tx: &mut RingBuffer<TX>, opcode: u8, payload: &[u8]) -> bool {
+    let mut header = [FIN | opcode, 0, 0, 0];
+    let header = match payload.len() {
+        len @ 0..=125 => {
+            header[1] = len as u8;
+            &header[..2]
+        }
+        len => {
+            header[1] = 126;
+            header[2..].copy_from_slice(&(len as u16).to_be_bytes());
+            &header[..]
+        }
+    };
+    if header.len() + payload.len() > tx.free() {
+        return false;
+    }
+    tx.push(header);
+    tx.push(payload);
+    true
+}
+
+impl Encapsulation for WebSocket {
+    fn receive<P: SerialPort, const RX: usize, const TX: usize>(
+        &mut self,
+        port: &mut P,
+        rx: &mut RingBuffer<RX>,
+        tx: &mut RingBuffer<TX>,
+    ) -> Result<(), TransportError> {
+        while self.state != State::Closing {
+            let space = self.input.free_slice();
+            let read = if space.is_empty() { 0 } else { port.read(space)? };
+            self.input.commit(read);
+            let buffered = self.input.len();
+            self.process(rx, tx);
+            if read == 0 && self.input.len() == buffered {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn send<const TX: usize>(&mut self, frame: &[u8], tx: &mut RingBuffer<TX>) -> Result<(), TransportError> {
+        if self.state != State::Open {
+            return Err(TransportError::Disconnected);
+        }
+        if !queue_frame(tx, OP_BINARY, frame) {
+            return Err(TransportError::BufferFull);
+        }
+        Ok(())
+    }
+
+    fn close<const TX: usize>(&mut self, tx: &mut RingBuffer<TX>) {
+        if self.state == State::Open {
+            queue_frame(tx, OP_CLOSE, &CLOSE_NORMAL.to_be_bytes());
+        }
+        self.state = State::Closing;
+    }
+
+    fn is_open(&self) -> bool {
+        self.state == State::Open
+    }
+
+    fn is_closing(&self) -> bool {
+        self.state == State::Closing
+    }
+
+    fn reject<P: SerialPort>(port: &mut P) {
+        // Best effort: a fresh socket takes a short response without blocking
+        let _ = port.write(RESPONSE_BUSY);
+    }
+}
@@ -0,0 +1,414 @@
+//! Bitbang (BBIO) tests: byte transcripts as the original Bus Pirate tools
+//! send them, against mock buses
+
+use embedded_hal::i2c::{ErrorKind as I2cErrorKind, NoAcknowledgeSource};
+use embedded_hal_mock::eh1::i2c::{Mock as I2cMock, Transaction as I2cTransaction};
+use embedded_hal_mock::eh1::spi::{Mock as SpiMock, Transaction as SpiTransaction};
+use embedded_io::{ErrorType, Read, ReadReady, Write};
+use esp32_bus_pirate_bus_modes::Error;
+use esp32_bus_pirate_core::bitbang::{BitbangMode, Control};
+use esp32_bus_pirate_core::transport::ClientId;
+use esp32_bus_pirate_core::{Bitbang, Dispatcher, NoStorage, SessionId, SessionManager, ShellContext};
+use esp32_bus_pirate_protocol::{Message, Mode, Response};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::rc::Rc;
+
+/// What the scripted UART has been given to receive and has sent
+#[derive(Default)]
+struct UartLine {
+    rx: VecDeque<u8>,
+    tx: Vec<u8>,
+}
+
+/// UART backed by a shared [`UartLine`]
+#[derive(Clone, Default)]
+struct ScriptedUart(Rc<RefCell<UartLine>>);
+
+impl ErrorType for ScriptedUart {
+    type Error = Infallible;
+}
+
+impl Read for ScriptedUart {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+        let mut line = self.0.borrow_mut();
+        let n = buf.len().min(line.rx.len());
+        for (slot, byte) in buf.iter_mut().zip(line.rx.drain(..n)) {
+            *slot = byte;
+        }
+        Ok(n)
+    }
+}
+
+impl ReadReady for ScriptedUart {
+    fn read_ready(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.0.borrow().rx.is_empty())
+    }
+}
+
+impl Write for ScriptedUart {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+        self.0.borrow_mut().tx.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+/// Bytes sent back to the host
+#[derive(Default)]
+struct Replies(Vec<u8>);
+
+impl ErrorType for Replies {
+    type Error = Infallible;
+}
+
+impl Write for Replies {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+/// Bitbang never waits, so any delay will do
+struct NoDelay;
+
+impl embedded_hal::delay::DelayNs for NoDelay {
+    fn delay_ns(&mut self, _ns: u32) {}
+}
+
+type TestDispatcher = Dispatcher<I2cMock, SpiMock<u8>, ScriptedUart, NoStorage>;
+
+const TOOL: SessionId = SessionId::new(0, ClientId(1));
+const HOST: SessionId = SessionId::new(1, ClientId(1));
+
+/// A bitbang front-end on a dispatcher whose buses expect `i2c` and `spi`
+struct Bench {
+    i2c: I2cMock,
+    spi: SpiMock<u8>,
+    uart: ScriptedUart,
+    dispatcher: TestDispatcher,
+    sessions: SessionManager,
+    bitbang: Box<Bitbang>,
+}
+
+impl Bench {
+    fn new(i2c: &[I2cTransaction], spi: &[SpiTransaction<u8>]) -> Self {
+        let i2c = I2cMock::new(i2c);
+        let spi = SpiMock::new(spi);
+        let uart = ScriptedUart::default();
+        let dispatcher = Dispatcher::new(i2c.clone(), spi.clone(), uart.clone(), NoStorage);
+        Self {
+            i2c,
+            spi,
+            uart,
+            dispatcher,
+            sessions: SessionManager::new(),
+            bitbang: Box::default(),
+        }
+    }
+
+    /// Send `input` and return the replies, checking bitbang mode goes on
+    fn send(&mut self, input: &[u8]) -> Vec<u8> {
+        let (control, replies) = self.send_control(input);
+        assert_eq!(control, Control::Continue);
+        replies
+    }
+
+    fn send_control(&mut self, input: &[u8]) -> (Control, Vec<u8>) {
+        let mut ctx = ShellContext {
+            dispatcher: &mut self.dispatcher,
+            sessions: &mut self.sessions,
+            id: TOOL,
+            delay: &mut NoDelay,
+        };
+        let mut replies = Replies::default();
+        let control = self.bitbang.input(input, &mut ctx, &mut replies).unwrap();
+        (control, replies.0)
+    }
+
+    fn poll(&mut self) -> Vec<u8> {
+        let mut replies = Replies::default();
+        self.bitbang.poll(&mut self.dispatcher, &mut replies).unwrap();
+        replies.0
+    }
+
+    /// Check every expected bus transaction happened
+    fn done(mut self) {
+        self.i2c.done();
+        self.spi.done();
+    }
+}
+
+// ===== Top Level =====
+
+#[test]
+fn test_entry_and_exit() {
+    let mut bench = Bench::new(&[], &[]);
+    // The console hands over the last of the entry NULs; tools often send
+    // a few more before they see the reply
+    assert_eq!(bench.send(&[0x00]), b"BBIO1");
+    assert_eq!(bench.send(&[0x00, 0x00]), b"BBIO1BBIO1");
+    assert_eq!(bench.send(&[0x20]), [0x00]);
+
+    let (control, replies) = bench.send_control(&[0x0F, 0x00]);
+    assert_eq!(control, Control::Exit);
+    assert_eq!(replies, [0x01]);
+    assert_eq!(bench.dispatcher.mode(), Mode::HiZ);
+    bench.done();
+}
+
+#[test]
+fn test_sub_modes_respect_the_bus_lease() {
+    let mut bench = Bench::new(&[], &[]);
+    let set_mode = Message::SetMode { mode: Mode::I2c };
+    assert_eq!(
+        bench.sessions.handle(&mut bench.dispatcher, HOST, &set_mode),
+        Message::Response(Response::Success)
+    );
+
+    assert_eq!(bench.send(&[0x01]), [0x00]);
+    assert_eq!(bench.bitbang.mode(), BitbangMode::Bbio);
+    assert_eq!(bench.dispatcher.mode(), Mode::I2c);
+    bench.done();
+}
+
+// ===== SPI =====
+
+#[test]
+fn test_spi_flashrom_session() {
+    let mut bench = Bench::new(
+        &[],
+        &[
+            // RDID through write-then-read
+            SpiTransaction::transaction_start(),
+            SpiTransaction::write_vec(vec![0x9F]),
+            SpiTransaction::read_vec(vec![0xEF, 0x40, 0x18]),
+            SpiTransaction::transaction_end(),
+            // READ of two bytes at 0x000100
+            SpiTransaction::transaction_start(),
+            SpiTransaction::write_vec(vec![0x03, 0x00, 0x01, 0x00]),
+            SpiTransaction::read_vec(vec![0xDE, 0xAD]),
+            SpiTransaction::transaction_end(),
+            // WREN with nothing to read
+            SpiTransaction::transaction_start(),
+            SpiTransaction::write_vec(vec![0x06]),
+            SpiTransaction::read_vec(vec![]),
+            SpiTransaction::transaction_end(),
+        ],
+    );
+    assert_eq!(bench.send(&[0x00, 0x01]), b"BBIO1SPI1");
+    assert_eq!(bench.dispatcher.mode(), Mode::Spi);
+
+    // Power and CS on, 1 MHz, SPI mode 0 with 3.3 V outputs
+    assert_eq!(bench.send(&[0x4B, 0x63, 0x8A]), [0x01, 0x01, 0x01]);
+
+    assert_eq!(bench.send(&[0x04, 0x00, 0x01, 0x00, 0x03, 0x9F]), [0x01, 0xEF, 0x40, 0x18]);
+
+    // Commands may arrive split anywhere
+    assert_eq!(bench.send(&[0x04, 0x00, 0x04]), []);
+    assert_eq!(bench.send(&[0x00, 0x02, 0x03, 0x00]), []);
+    assert_eq!(bench.send(&[0x01, 0x00]), [0x01, 0xDE, 0xAD]);
+
+    assert_eq!(bench.send(&[0x04, 0x00, 0x01, 0x00, 0x00, 0x06]), [0x01]);
+
+    // Back to the top level releases the bus
+    assert_eq!(bench.send(&[0x00]), b"BBIO1");
+    assert_eq!(bench.dispatcher.mode(), Mode::HiZ);
+    assert_eq!(bench.sessions.holder(), None);
+    bench.done();
+}
+
+thread_local! {
+    /// Chip select changes asked of the SPI bus, in order
+    static CHIP_SELECT: RefCell<Vec<bool>> = const { RefCell::new(Vec::new()) };
+}
+
+fn record_chip_select(_spi: &mut SpiMock<u8>, asserted: bool) -> Result<(), Error> {
+    CHIP_SELECT.with(|log| log.borrow_mut().push(asserted));
+    Ok(())
+}
+
+#[test]
+fn test_spi_bulk_transfer_and_chip_select() {
+    let mut bench = Bench::new(
+        &[],
+        &[
+            SpiTransaction::transaction_start(),
+            SpiTransaction::transfer_in_place(vec![0xAC, 0x53, 0x00], vec![0xFF, 0xAC, 0x53]),
+            SpiTransaction::transaction_end(),
+            SpiTransaction::transaction_start(),
+            SpiTransaction::transfer_in_place(vec![0x00], vec![0x69]),
+            SpiTransaction::transaction_end(),
+        ],
+    );
+    bench.dispatcher = bench.dispatcher.with_spi_chip_select(record_chip_select);
+    bench.send(&[0x01]);
+
+    // avrdude style: CS low, a command and its reply in two bulk
+    // transfers, CS high
+    assert_eq!(bench.send(&[0x02, 0x12, 0xAC, 0x53, 0x00]), [0x01, 0x01, 0xFF, 0xAC, 0x53]);
+    assert_eq!(CHIP_SELECT.with(|log| log.borrow().clone()), [true]);
+    assert_eq!(bench.send(&[0x10, 0x00, 0x03]), [0x01, 0x69, 0x01]);
+    assert_eq!(CHIP_SELECT.with(|log| log.borrow().clone()), [true, false]);
+
+    // Leaving with chip select held lets go of it
+    bench.send(&[0x02]);
+    assert_eq!(bench.send(&[0x00]), b"BBIO1");
+    assert_eq!(CHIP_SELECT.with(|log| log.borrow().clone()), [true, false, true, false]);
+    bench.done();
+}
+
+#[test]
+fn test_spi_chip_select_needs_a_routine() {
+    let mut bench = Bench::new(&[], &[]);
+    bench.send(&[0x01]);
+
+    // Each transfer would get its own pulse, so holding it is refused
+    assert_eq!(bench.send(&[0x02, 0x03]), [0x00, 0x00]);
+    assert_eq!(bench.send(&[0x01]), b"SPI1");
+    bench.done();
+}
+
+#[test]
+fn test_spi_rejects_what_it_cannot_do() {
+    let mut bench = Bench::new(&[], &[]);
+    bench.send(&[0x01]);
+
+    // Clock idling high, and the sniffer
+    assert_eq!(bench.send(&[0x8C, 0x0E]), [0x00, 0x00]);
+
+    // Write-then-read larger than the buffer
+    assert_eq!(bench.send(&[0x04, 0x10, 0x00, 0x00, 0x01]), [0x00]);
+    assert_eq!(bench.send(&[0x01]), b"SPI1");
+    bench.done();
+}
+
+// ===== I2C =====
+
+#[test]
+fn test_i2c_eeprom_random_read() {
+    let mut bench = Bench::new(
+        &[
+            // Address probe
+            I2cTransaction::write(0x50, vec![]),
+            // Held address bytes go out with the first read
+            I2cTransaction::transaction_start(0x50),
+            I2cTransaction::write(0x50, vec![0x00, 0x10]),
+            I2cTransaction::read(0x50, vec![0xAB]),
+            I2cTransaction::transaction_end(0x50),
+            I2cTransaction::read(0x50, vec![0xCD]),
+        ],
+        &[],
+    );
+    assert_eq!(bench.send(&[0x02]), b"I2C1");
+    assert_eq!(bench.send(&[0x4C, 0x62]), [0x01, 0x01]);
+
+    // [0xA0 0x00 0x10 [0xA1 r r]
+    assert_eq!(bench.send(&[0x02, 0x12, 0xA0, 0x00, 0x10]), [0x01, 0x01, 0x00, 0x00, 0x00]);
+    assert_eq!(bench.send(&[0x02, 0x10, 0xA1]), [0x01, 0x01, 0x00]);
+    assert_eq!(bench.send(&[0x04, 0x06, 0x04, 0x07, 0x03]), [0xAB, 0x01, 0xCD, 0x01, 0x01]);
+    bench.done();
+}
+
+#[test]
+fn test_i2c_write_is_sent_at_stop() {
+    let nack = I2cErrorKind::NoAcknowledge(NoAcknowledgeSource::Address);
+    let data_nack = I2cErrorKind::NoAcknowledge(NoAcknowledgeSource::Data);
+    let mut bench = Bench::new(
+        &[
+            I2cTransaction::write(0x50, vec![]),
+            I2cTransaction::write(0x50, vec![0x00, 0x20, 0x55, 0x66]),
+            I2cTransaction::write(0x51, vec![]).with_error(nack),
+            I2cTransaction::write(0x50, vec![]),
+            I2cTransaction::write(0x50, vec![0x7F, 0x00]).with_error(data_nack),
+            I2cTransaction::write(0x50, vec![]),
+        ],
+        &[],
+    );
+    bench.send(&[0x02]);
+
+    assert_eq!(bench.send(&[0x02, 0x11, 0xA0, 0x00]), [0x01, 0x01, 0x00, 0x00]);
+    assert_eq!(bench.send(&[0x11, 0x20, 0x55, 0x10, 0x66]), [0x01, 0x00, 0x00, 0x01, 0x00]);
+    assert_eq!(bench.send(&[0x03]), [0x01]);
+
+    // Nobody answers at 0x51, so nothing after the address is acknowledged
+    assert_eq!(bench.send(&[0x02, 0x11, 0xA2, 0x00, 0x03]), [0x01, 0x01, 0x01, 0x01, 0x01]);
+
+    // A refused write fails the stop, and only that one
+    assert_eq!(bench.send(&[0x02, 0x12, 0xA0, 0x7F, 0x00]), [0x01, 0x01, 0x00, 0x00, 0x00]);
+    assert_eq!(bench.send(&[0x03]), [0x00]);
+    assert_eq!(bench.send(&[0x02, 0x10, 0xA0, 0x03]), [0x01, 0x01, 0x00, 0x01]);
+    bench.done();
+}
+
+#[test]
+fn test_i2c_write_then_read() {
+    let mut bench = Bench::new(
+        &[
+            I2cTransaction::transaction_start(0x50),
+            I2cTransaction::write(0x50, vec![0x00, 0x20]),
+            I2cTransaction::read(0x50, vec![0x12, 0x34]),
+            I2cTransaction::transaction_end(0x50),
+            I2cTransaction::write(0x50, vec![0x00, 0x20, 0x99]),
+        ],
+        &[],
+    );
+    bench.send(&[0x02]);
+
+    assert_eq!(bench.send(&[0x08, 0x00, 0x03, 0x00, 0x02, 0xA0, 0x00, 0x20]), [0x01, 0x12, 0x34]);
+    assert_eq!(bench.send(&[0x08, 0x00, 0x04, 0x00, 0x00, 0xA0, 0x00, 0x20, 0x99]), [0x01]);
+
+    // There has to be an address byte
+    assert_eq!(bench.send(&[0x08, 0x00, 0x00, 0x00, 0x01]), [0x00]);
+
+    // Reading with nothing addressed gets an idle bus
+    assert_eq!(bench.send(&[0x04]), [0xFF]);
+    bench.done();
+}
+
+// ===== UART =====
+
+#[test]
+fn test_uart_write_speed_and_echo() {
+    let mut bench = Bench::new(&[], &[]);
+    assert_eq!(bench.send(&[0x03]), b"ART1");
+
+    // 9600 baud, 8N1, then 8E1 which is not supported
+    assert_eq!(bench.send(&[0x64, 0x80, 0x84]), [0x01, 0x01, 0x00]);
+    // A BRG of 34 is 115200 baud on the original
+    assert_eq!(bench.send(&[0x07, 0x00, 0x22]), [0x01]);
+
+    assert_eq!(bench.send(&[0x11, b'A', b'T']), [0x01, 0x01, 0x01]);
+    assert_eq!(bench.uart.0.borrow().tx, b"AT");
+
+    // Received bytes only reach the host while echo is on
+    bench.uart.0.borrow_mut().rx.extend(b"OK");
+    assert_eq!(bench.poll(), []);
+    assert_eq!(bench.send(&[0x02]), [0x01]);
+    assert_eq!(bench.poll(), b"OK");
+    bench.done();
+}
+
+#[test]
+fn test_uart_bridge() {
+    let mut bench = Bench::new(&[], &[]);
+    bench.send(&[0x03]);
+
+    // No reply, and from then on every byte is data, even 0x00
+    assert_eq!(bench.send(&[0x0F, b'h', b'i']), []);
+    assert_eq!(bench.send(&[0x00, 0x0F]), []);
+    assert_eq!(bench.bitbang.mode(), BitbangMode::UartBridge);
+    assert_eq!(bench.uart.0.borrow().tx, [b'h', b'i', 0x00, 0x0F]);
+
+    bench.uart.0.borrow_mut().rx.extend(b"yo");
+    assert_eq!(bench.poll(), b"yo");
+    bench.done();
+}
@@ -0,0 +1,630 @@
+//! Dispatcher tests against mock I2C and SPI buses and a scripted UART
+
+use embedded_hal::i2c::{self, ErrorKind as I2cErrorKind, NoAcknowledgeSource};
+use embedded_hal::spi::{self, ErrorKind as SpiErrorKind, SpiDevice};
+use embedded_hal_mock::eh1::i2c::{Mock as I2cMock, Transaction as I2cTransaction};
+use embedded_hal_mock::eh1::spi::{Mock as SpiMock, Transaction as SpiTransaction};
+use embedded_io::{ErrorType, Read, ReadReady, Write};
+use esp32_bus_pirate_bus_modes::{i2c::I2cFault, Error};
+use esp32_bus_pirate_core::{Dispatcher, NoStorage};
+use esp32_bus_pirate_protocol::{
+    message::BulkTarget, ErrorCode, I2cAddress, I2cLineState, I2cNack, I2cOp, I2cRecoveryReport, I2cRegisterMap,
+    I2cScanOptions, I2cScanProbe, I2cTransactionReport, Message, Mode, NackSource, Response,
+};
+use heapless::Vec as HVec;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::rc::Rc;
+
+/// What the scripted UART has been given to receive and has sent
+#[derive(Default)]
+struct UartLine {
+    rx: VecDeque<u8>,
+    tx: Vec<u8>,
+}
+
+/// UART backed by a shared [`UartLine`]
+#[derive(Clone, Default)]
+struct ScriptedUart(Rc<RefCell<UartLine>>);
+
+impl ErrorType for ScriptedUart {
+    type Error = Infallible;
+}
+
+impl Read for ScriptedUart {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+        let mut line = self.0.borrow_mut();
+        let n = buf.len().min(line.rx.len());
+        for (slot, byte) in buf.iter_mut().zip(line.rx.drain(..n)) {
+            *slot = byte;
+        }
+        Ok(n)
+    }
+}
+
+impl ReadReady for ScriptedUart {
+    fn read_ready(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.0.borrow().rx.is_empty())
+    }
+}
+
+impl Write for ScriptedUart {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+        self.0.borrow_mut().tx.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+/// SPI device whose every transaction fails (the SPI mock cannot)
+struct BrokenSpi;
+
+impl spi::ErrorType for BrokenSpi {
+    type Error = SpiErrorKind;
+}
+
+impl SpiDevice for BrokenSpi {
+    fn transaction(&mut self, _operations: &mut [spi::Operation<'_, u8>]) -> Result<(), SpiErrorKind> {
+        Err(SpiErrorKind::Other)
+    }
+}
+
+/// I2C bus whose every transaction fails with the same error (the I2C mock
+/// panics on errors inside a transaction)
+struct RefusingI2c<E>(E);
+
+impl<E: i2c::Error> i2c::ErrorType for RefusingI2c<E> {
+    type Error = E;
+}
+
+impl<E: i2c::Error + Copy> i2c::I2c for RefusingI2c<E> {
+    fn transaction(&mut self, _address: u8, _operations: &mut [i2c::Operation<'_>]) -> Result<(), E> {
+        Err(self.0)
+    }
+}
+
+/// Error of a bus held low past its timeout
+#[derive(Debug, Clone, Copy)]
+struct StuckBus;
+
+impl i2c::Error for StuckBus {
+    fn kind(&self) -> I2cErrorKind {
+        I2cErrorKind::Other
+    }
+}
+
+impl I2cFault for StuckBus {
+    fn is_timeout(&self) -> bool {
+        true
+    }
+}
+
+type TestDispatcher = Dispatcher<I2cMock, SpiMock<u8>, ScriptedUart, NoStorage>;
+
+/// Mocks expecting `i2c` and `spi`, and a dispatcher that owns clones of them
+struct Bench {
+    i2c: I2cMock,
+    spi: SpiMock<u8>,
+    uart: ScriptedUart,
+    dispatcher: TestDispatcher,
+}
+
+impl Bench {
+    fn new(i2c: &[I2cTransaction], spi: &[SpiTransaction<u8>]) -> Self {
+        let i2c = I2cMock::new(i2c);
+        let spi = SpiMock::new(spi);
+        let uart = ScriptedUart::default();
+        let dispatcher = Dispatcher::new(i2c.clone(), spi.clone(), uart.clone(), NoStorage);
+        Self { i2c, spi, uart, dispatcher }
+    }
+
+    fn in_mode(mode: Mode, i2c: &[I2cTransaction], spi: &[SpiTransaction<u8>]) -> Self {
+        let mut bench = Self::new(i2c, spi);
+        assert_eq!(bench.handle(Message::SetMode { mode }), success());
+        bench
+    }
+
+    fn handle(&mut self, msg: Message) -> Message {
+        self.dispatcher.handle(&msg)
+    }
+
+    /// Check every expected bus transaction happened
+    fn done(mut self) {
+        self.i2c.done();
+        self.spi.done();
+    }
+}
+
+fn success() -> Message {
+    Message::Response(Response::Success)
+}
+
+fn data(bytes: &[u8]) -> Message {
+    Message::Response(Response::Data(HVec::from_slice(bytes).unwrap()))
+}
+
+fn error(code: ErrorCode) -> Message {
+    Message::Error(code)
+}
+
+fn nack() -> I2cErrorKind {
+    I2cErrorKind::NoAcknowledge(NoAcknowledgeSource::Address)
+}
+
+fn write(bytes: &[u8]) -> I2cOp {
+    I2cOp::Write(HVec::from_slice(bytes).unwrap())
+}
+
+fn transaction(addr: impl Into<I2cAddress>, ops: &[I2cOp]) -> Message {
+    Message::I2cTransaction { addr: addr.into(), ops: HVec::from_slice(ops).unwrap() }
+}
+
+// ============================================================================
+// Mode Transitions
+// ============================================================================
+
+#[test]
+fn test_set_mode_transitions() {
+    let mut bench = Bench::new(&[], &[]);
+    assert_eq!(bench.dispatcher.mode(), Mode::HiZ);
+
+    for mode in [Mode::I2c, Mode::Spi, Mode::Uart, Mode::Uart, Mode::HiZ] {
+        assert_eq!(bench.handle(Message::SetMode { mode }), success());
+        assert_eq!(bench.handle(Message::GetMode), Message::Response(Response::CurrentMode(mode)));
+    }
+    bench.done();
+}
+
+#[test]
+fn test_unsupported_mode_keeps_current_mode() {
+    let mut bench = Bench::in_mode(Mode::I2c, &[], &[]);
+    assert_eq!(
+        bench.handle(Message::SetMode { mode: Mode::OneWire }),
+        error(ErrorCode::InvalidParameter)
+    );
+    assert_eq!(bench.dispatcher.mode(), Mode::I2c);
+    bench.done();
+}
+
+#[test]
+fn test_commands_for_inactive_bus_are_not_configured() {
+    // No expectations: the buses must not be touched
+    let mut bench = Bench::in_mode(Mode::Spi, &[], &[]);
+    for msg in [
+        Message::I2cScan,
+        Message::I2cWrite { addr: 0x50, data: HVec::from_slice(&[1]).unwrap() },
+        Message::I2cReadRegister { addr: 0x50, reg: 0 },
+        transaction(0x50, &[I2cOp::Read(1)]),
+        Message::UartWrite { data: HVec::from_slice(b"x").unwrap() },
+        Message::UartConfig { baudrate: 9600 },
+    ] {
+        assert_eq!(bench.handle(msg.clone()), error(ErrorCode::NotConfigured), "{msg:?}");
+    }
+
+    bench.handle(Message::SetMode { mode: Mode::HiZ });
+    let transfer = Message::SpiTransfer { data: HVec::from_slice(&[0x9F]).unwrap() };
+    assert_eq!(bench.handle(transfer), error(ErrorCode::NotConfigured));
+    bench.done();
+}
+
+#[test]
+fn test_leaving_spi_abandons_flash_transfer() {
+    let jedec_id = [
+        SpiTransaction::transaction_start(),
+        SpiTransaction::transfer_in_place(vec![0x9F, 0, 0, 0], vec![0xFF, 0xEF, 0x40, 0x18]),
+        SpiTransaction::transaction_end(),
+    ];
+    let mut bench = Bench::in_mode(Mode::Spi, &[], &jedec_id);
+
+    let request = Message::BulkRequest {
+        id: 1,
+        target: BulkTarget::SpiFlash { address: 0 },
+        offset: 0,
+        len: 16,
+    };
+    assert!(matches!(bench.handle(request), Message::BulkBegin { id: 1, total_len: 16, .. }));
+
+    bench.handle(Message::SetMode { mode: Mode::I2c });
+    assert_eq!(bench.handle(Message::BulkAck { id: 1, offset: 0 }), error(ErrorCode::UnknownTransfer));
+    bench.done();
+}
+
+// ============================================================================
+// I2C
+// ============================================================================
+
+#[test]
+fn test_i2c_commands() {
+    let mut bench = Bench::in_mode(
+        Mode::I2c,
+        &[
+            I2cTransaction::write(0x50, vec![0x00, 0x10]),
+            I2cTransaction::read(0x50, vec![0xAA, 0xBB, 0xCC]),
+            I2cTransaction::write_read(0x68, vec![0x75], vec![0x71]),
+            I2cTransaction::write(0x68, vec![0x6B, 0x01]),
+        ],
+        &[],
+    );
+
+    let write = Message::I2cWrite { addr: 0x50, data: HVec::from_slice(&[0x00, 0x10]).unwrap() };
+    assert_eq!(bench.handle(write), success());
+    assert_eq!(bench.handle(Message::I2cRead { addr: 0x50, len: 3 }), data(&[0xAA, 0xBB, 0xCC]));
+    assert_eq!(bench.handle(Message::I2cReadRegister { addr: 0x68, reg: 0x75 }), data(&[0x71]));
+    assert_eq!(
+        bench.handle(Message::I2cWriteRegister { addr: 0x68, reg: 0x6B, value: 0x01 }),
+        success()
+    );
+    bench.done();
+}
+
+#[test]
+fn test_i2c_errors_are_classified() {
+    let mut bench = Bench::in_mode(
+        Mode::I2c,
+        &[
+            I2cTransaction::write_read(0x42, vec![0x00], vec![0x00]).with_error(nack()),
+            I2cTransaction::write(0x50, vec![0x00, 0x01])
+                .with_error(I2cErrorKind::NoAcknowledge(NoAcknowledgeSource::Data)),
+            I2cTransaction::read(0x50, vec![0x00]).with_error(I2cErrorKind::ArbitrationLoss),
+            I2cTransaction::read(0x42, vec![0x00]).with_error(I2cErrorKind::Bus),
+        ],
+        &[],
+    );
+
+    assert_eq!(
+        bench.handle(Message::I2cReadRegister { addr: 0x42, reg: 0 }),
+        error(ErrorCode::AddressNack)
+    );
+    assert_eq!(
+        bench.handle(Message::I2cWriteRegister { addr: 0x50, reg: 0, value: 1 }),
+        error(ErrorCode::DataNack)
+    );
+    assert_eq!(bench.handle(Message::I2cRead { addr: 0x50, len: 1 }), error(ErrorCode::ArbitrationLost));
+    assert_eq!(bench.handle(Message::I2cRead { addr: 0x42, len: 1 }), error(ErrorCode::BusError));
+    // A failed command leaves the mode alone
+    assert_eq!(bench.dispatcher.mode(), Mode::I2c);
+    bench.done();
+}
+
+#[test]
+fn test_i2c_transaction() {
+    // 16-bit register address, then two reads after one repeated start
+    let mut bench = Bench::in_mode(
+        Mode::I2c,
+        &[
+            I2cTransaction::transaction_start(0x50),
+            I2cTransaction::write(0x50, vec![0x01, 0x00]),
+            I2cTransaction::read(0x50, vec![0xAA, 0xBB]),
+            I2cTransaction::read(0x50, vec![0xCC]),
+            I2cTransaction::transaction_end(0x50),
+        ],
+        &[],
+    );
+
+    let reply = bench.handle(transaction(0x50, &[write(&[0x01, 0x00]), I2cOp::Read(2), I2cOp::Read(1)]));
+    assert_eq!(
+        reply,
+        Message::Response(Response::I2cTransaction(I2cTransactionReport {
+            data: HVec::from_slice(&[0xAA, 0xBB, 0xCC]).unwrap(),
+            nack: None,
+        }))
+    );
+    bench.done();
+}
+
+#[test]
+fn test_i2c_transaction_limits() {
+    // Rejected before the bus is touched
+    let mut bench = Bench::in_mode(Mode::I2c, &[], &[]);
+    for ops in [
+        &[][..],
+        &[write(&[0x00]), I2cOp::Read(0)],
+        &[I2cOp::Read(500), I2cOp::Read(13)],
+    ] {
+        assert_eq!(bench.handle(transaction(0x50, ops)), error(ErrorCode::InvalidParameter), "{ops:?}");
+    }
+    bench.done();
+}
+
+#[test]
+fn test_i2c_transaction_reports_nacked_segment() {
+    let refused_in = |ops: &[I2cOp], err| {
+        let mut spi = SpiMock::new(&[]);
+        let mut dispatcher = Dispatcher::new(RefusingI2c(err), spi.clone(), ScriptedUart::default(), NoStorage);
+        dispatcher.handle(&Message::SetMode { mode: Mode::I2c });
+        let reply = dispatcher.handle(&transaction(0x50, ops));
+        spi.done();
+        reply
+    };
+    let refused = |err| refused_in(&[I2cOp::Read(1), write(&[0x10]), I2cOp::Read(1)], err);
+    let nacked = |segment, source| {
+        Message::Response(Response::I2cTransaction(I2cTransactionReport {
+            data: HVec::new(),
+            nack: Some(I2cNack { segment, source }),
+        }))
+    };
+
+    // The address goes out first; only a write segment can refuse data
+    assert_eq!(refused(nack()), nacked(Some(0), NackSource::Address));
+    assert_eq!(
+        refused(I2cErrorKind::NoAcknowledge(NoAcknowledgeSource::Data)),
+        nacked(Some(1), NackSource::Data)
+    );
+    assert_eq!(
+        refused(I2cErrorKind::NoAcknowledge(NoAcknowledgeSource::Unknown)),
+        nacked(None, NackSource::Unknown)
+    );
+    // With two writes the bus cannot say which one was refused
+    assert_eq!(
+        refused_in(
+            &[write(&[0x10]), I2cOp::Read(1), write(&[0x20])],
+            I2cErrorKind::NoAcknowledge(NoAcknowledgeSource::Data)
+        ),
+        nacked(None, NackSource::Data)
+    );
+    // Anything but a NACK fails the command
+    assert_eq!(refused(I2cErrorKind::Bus), error(ErrorCode::BusError));
+}
+
+#[test]
+fn test_i2c_stuck_bus_times_out() {
+    let mut spi = SpiMock::new(&[]);
+    let mut dispatcher = Dispatcher::new(RefusingI2c(StuckBus), spi.clone(), ScriptedUart::default(), NoStorage);
+    dispatcher.handle(&Message::SetMode { mode: Mode::I2c });
+
+    assert_eq!(dispatcher.handle(&Message::I2cRead { addr: 0x50, len: 1 }), error(ErrorCode::Timeout));
+    // A scan that cannot drive the bus fails instead of finding nothing
+    assert_eq!(dispatcher.handle(&Message::I2cScan), error(ErrorCode::Timeout));
+    spi.done();
+}
+
+#[test]
+fn test_i2c_ten_bit_transaction() {
+    // Address 0x2A5: `11110 10` as the 7-bit address, then 0xA5. A write
+    // re-sends both bytes after a repeated start, a read only the first
+    let mut bench = Bench::in_mode(
+        Mode::I2c,
+        &[
+            I2cTransaction::transaction_start(0x7A),
+            I2cTransaction::write(0x7A, vec![0xA5]),
+            I2cTransaction::write(0x7A, vec![0x10]),
+            I2cTransaction::read(0x7A, vec![0x11, 0x22]),
+            I2cTransaction::write(0x7A, vec![0xA5]),
+            I2cTransaction::write(0x7A, vec![0x20, 0x01]),
+            I2cTransaction::transaction_end(0x7A),
+            // A read on its own is preceded by the full address
+            I2cTransaction::transaction_start(0x78),
+            I2cTransaction::write(0x78, vec![0x50]),
+            I2cTransaction::read(0x78, vec![0x33]),
+            I2cTransaction::transaction_end(0x78),
+        ],
+        &[],
+    );
+
+    let ops = [write(&[0x10]), I2cOp::Read(2), write(&[0x20, 0x01])];
+    let reply = bench.handle(transaction(I2cAddress::TenBit(0x2A5), &ops));
+    assert_eq!(
+        reply,
+        Message::Response(Response::I2cTransaction(I2cTransactionReport {
+            data: HVec::from_slice(&[0x11, 0x22]).unwrap(),
+            nack: None,
+        }))
+    );
+
+    let reply = bench.handle(transaction(I2cAddress::TenBit(0x050), &[I2cOp::Read(1)]));
+    assert_eq!(
+        reply,
+        Message::Response(Response::I2cTransaction(I2cTransactionReport {
+            data: HVec::from_slice(&[0x33]).unwrap(),
+            nack: None,
+        }))
+    );
+
+    // Beyond 10 bits, before the bus is touched
+    let reply = bench.handle(transaction(I2cAddress::TenBit(0x400), &[I2cOp::Read(1)]));
+    assert_eq!(reply, error(ErrorCode::InvalidParameter));
+    bench.done();
+}
+
+#[test]
+fn test_i2c_ten_bit_scan() {
+    let probes: Vec<_> = (0x000..=0x3FFu16)
+        .map(|addr| {
+            let probe = I2cTransaction::write(0x78 | (addr >> 8) as u8, vec![addr as u8]);
+            match addr {
+                0x150 | 0x3FF => probe,
+                _ => probe.with_error(I2cErrorKind::NoAcknowledge(NoAcknowledgeSource::Data)),
+            }
+        })
+        .collect();
+    let mut bench = Bench::in_mode(Mode::I2c, &probes, &[]);
+
+    assert_eq!(
+        bench.handle(Message::I2cScanTenBit),
+        Message::Response(Response::I2cTenBitDevices(HVec::from_slice(&[0x150, 0x3FF]).unwrap()))
+    );
+    bench.done();
+}
+
+/// What a bus with SDA held low past nine clocks reports
+fn still_stuck(_: &mut I2cMock) -> Result<I2cRecoveryReport, Error> {
+    Ok(I2cRecoveryReport {
+        scl: I2cLineState { pull_up: true, held_low: false },
+        sda: I2cLineState { pull_up: false, held_low: true },
+        clocks: 9,
+        recovered: false,
+    })
+}
+
+#[test]
+fn test_i2c_recovery() {
+    // Without a way to drive the lines there is nothing to do
+    let mut bench = Bench::in_mode(Mode::I2c, &[], &[]);
+    assert_eq!(bench.handle(Message::I2cRecover), error(ErrorCode::NotConfigured));
+    bench.done();
+
+    let mut i2c = I2cMock::new(&[]);
+    let mut spi = SpiMock::new(&[]);
+    let mut dispatcher = Dispatcher::new(i2c.clone(), spi.clone(), ScriptedUart::default(), NoStorage)
+        .with_i2c_recovery(still_stuck);
+    assert_eq!(dispatcher.handle(&Message::I2cRecover), error(ErrorCode::NotConfigured));
+    dispatcher.handle(&Message::SetMode { mode: Mode::I2c });
+
+    let report = still_stuck(&mut i2c).unwrap();
+    assert_eq!(
+        dispatcher.handle(&Message::I2cRecover),
+        Message::Response(Response::I2cRecovery(report))
+    );
+    // Scans try to free the bus first, and probe nothing if they cannot
+    assert_eq!(dispatcher.handle(&Message::I2cScan), error(ErrorCode::BusError));
+    assert_eq!(dispatcher.handle(&Message::I2cScanTenBit), error(ErrorCode::BusError));
+    i2c.done();
+    spi.done();
+}
+
+#[test]
+fn test_i2c_scan() {
+    let probes: Vec<_> = (0x08..=0x77)
+        .map(|addr| match addr {
+            0x3C | 0x50 => I2cTransaction::write(addr, vec![]),
+            _ => I2cTransaction::write(addr, vec![]).with_error(nack()),
+        })
+        .collect();
+    let mut bench = Bench::in_mode(Mode::I2c, &probes, &[]);
+
+    assert_eq!(
+        bench.handle(Message::I2cScan),
+        Message::Response(Response::I2cDevices(HVec::from_slice(&[0x3C, 0x50]).unwrap()))
+    );
+    bench.done();
+}
+
+// ============================================================================
+// SPI
+// ============================================================================
+
+#[test]
+fn test_i2c_sweep_needs_frequency_control() {
+    let mut bench = Bench::in_mode(Mode::I2c, &[], &[]);
+    let sweep = Message::I2cScanSweep { options: I2cScanOptions::default() };
+    assert_eq!(bench.handle(sweep), error(ErrorCode::NotConfigured));
+    bench.done();
+}
+
+#[test]
+fn test_i2c_quick_probe_can_be_refused() {
+    let mut bench = Bench::in_mode(Mode::I2c, &[], &[]);
+    bench.dispatcher = bench.dispatcher.with_i2c_frequency(|_, _| Ok(())).without_i2c_quick_probe();
+    let options = I2cScanOptions { probe: I2cScanProbe::Quick, reserved: false };
+
+    // Refused before anything reaches the bus, rather than finding nothing
+    assert_eq!(bench.handle(Message::I2cScanAnnotated { options, identify: false }), error(ErrorCode::Unsupported));
+    assert_eq!(bench.handle(Message::I2cScanSweep { options }), error(ErrorCode::Unsupported));
+    bench.done();
+}
+
+#[test]
+fn test_i2c_target_needs_a_peripheral() {
+    let map = I2cRegisterMap { addr: 0x48, auto_increment: true, registers: HVec::new() };
+    let mut bench = Bench::new(&[], &[]);
+    assert_eq!(bench.handle(Message::I2cTargetStart { map: map.clone() }), error(ErrorCode::NotConfigured));
+    bench.done();
+
+    // Checked in I2C mode too, after the map itself
+    let mut bench = Bench::in_mode(Mode::I2c, &[], &[]);
+    let reserved = I2cRegisterMap { addr: 0x00, ..map.clone() };
+    assert_eq!(bench.handle(Message::I2cTargetStart { map: reserved }), error(ErrorCode::InvalidParameter));
+    assert_eq!(bench.handle(Message::I2cTargetStart { map }), error(ErrorCode::NotConfigured));
+    assert_eq!(bench.handle(Message::I2cTargetRegisters), error(ErrorCode::NotConfigured));
+    assert_eq!(bench.handle(Message::I2cTargetStop), success());
+    bench.done();
+}
+
+#[test]
+fn test_spi_transfer() {
+    let mut bench = Bench::in_mode(
+        Mode::Spi,
+        &[],
+        &[
+            SpiTransaction::transaction_start(),
+            SpiTransaction::transfer_in_place(vec![0x9F, 0, 0, 0], vec![0xFF, 0xEF, 0x40, 0x18]),
+            SpiTransaction::transaction_end(),
+        ],
+    );
+
+    let transfer = Message::SpiTransfer { data: HVec::from_slice(&[0x9F, 0, 0, 0]).unwrap() };
+    assert_eq!(bench.handle(transfer), data(&[0xFF, 0xEF, 0x40, 0x18]));
+    bench.done();
+}
+
+#[test]
+fn test_spi_failure_is_bus_error() {
+    let mut i2c = I2cMock::new(&[]);
+    let mut dispatcher = Dispatcher::new(i2c.clone(), BrokenSpi, ScriptedUart::default(), NoStorage);
+    dispatcher.handle(&Message::SetMode { mode: Mode::Spi });
+
+    let transfer = Message::SpiTransfer { data: HVec::from_slice(&[0x05, 0]).unwrap() };
+    assert_eq!(dispatcher.handle(&transfer), error(ErrorCode::BusError));
+    assert_eq!(dispatcher.mode(), Mode::Spi);
+    i2c.done();
+}
+
+// ============================================================================
+// UART
+// ============================================================================
+
+#[test]
+fn test_uart_write_and_read() {
+    let mut bench = Bench::in_mode(Mode::Uart, &[], &[]);
+
+    let write = Message::UartWrite { data: HVec::from_slice(b"AT\r\n").unwrap() };
+    assert_eq!(bench.handle(write), success());
+    assert_eq!(bench.uart.0.borrow().tx, b"AT\r\n");
+
+    // Nothing received yet
+    assert_eq!(bench.handle(Message::UartRead { len: 16 }), data(&[]));
+
+    bench.uart.0.borrow_mut().rx.extend(b"OK\r\n");
+    assert_eq!(bench.handle(Message::UartRead { len: 2 }), data(b"OK"));
+    assert_eq!(bench.handle(Message::UartRead { len: 16 }), data(b"\r\n"));
+    bench.done();
+}
+
+#[test]
+fn test_uart_config_rejects_zero_baud() {
+    let mut bench = Bench::in_mode(Mode::Uart, &[], &[]);
+
+    assert_eq!(
+        bench.handle(Message::UartConfig { baudrate: 0 }),
+        error(ErrorCode::InvalidParameter)
+    );
+    assert_eq!(bench.dispatcher.mode(), Mode::Uart);
+    assert_eq!(bench.handle(Message::UartConfig { baudrate: 9600 }), success());
+    bench.done();
+}
+
+// ============================================================================
+// Error Mapping
+// ============================================================================
+
+#[test]
+fn test_bus_errors_map_to_error_codes() {
+    for (err, code) in [
+        (Error::Communication, ErrorCode::BusError),
+        (Error::NoDevice, ErrorCode::BusError),
+        (Error::Timeout, ErrorCode::Timeout),
+        (Error::InvalidConfig, ErrorCode::InvalidParameter),
+        (Error::Busy, ErrorCode::Busy),
+        (Error::Nack { segment: Some(0), source: NoAcknowledgeSource::Address }, ErrorCode::AddressNack),
+        (Error::Nack { segment: Some(1), source: NoAcknowledgeSource::Data }, ErrorCode::DataNack),
+        (Error::Nack { segment: None, source: NoAcknowledgeSource::Unknown }, ErrorCode::BusError),
+        (Error::ArbitrationLost, ErrorCode::ArbitrationLost),
+        (Error::BusFault, ErrorCode::BusError),
+    ] {
+        assert_eq!(ErrorCode::from(err), code);
+    }
+}
@@ -0,0 +1,556 @@
+//! Network transport tests: TCP and WebSocket sessions over loopback sockets
+
+use esp32_bus_pirate_core::transport::websocket::accept_key;
+use esp32_bus_pirate_core::transport::{
+    ClientId, Listener, SerialPort, TcpTransport, Transport, TransportError, WebSocketTransport,
+};
+use esp32_bus_pirate_protocol::{Message, MessageCodec};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tungstenite::Message as WsMessage;
+
+/// Non-blocking std listener, as a Wi-Fi stack adapter would provide
+struct LoopbackListener(TcpListener);
+
+impl LoopbackListener {
+    fn bind() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        Self(listener)
+    }
+
+    fn addr(&self) -> SocketAddr {
+        self.0.local_addr().unwrap()
+    }
+}
+
+struct LoopbackConnection {
+    stream: TcpStream,
+    open: bool,
+}
+
+impl Listener for LoopbackListener {
+    type Connection = LoopbackConnection;
+
+    fn accept(&mut self) -> Result<Option<LoopbackConnection>, TransportError> {
+        match self.0.accept() {
+            Ok((stream, _)) => {
+                stream.set_nonblocking(true).unwrap();
+                Ok(Some(LoopbackConnection { stream, open: true }))
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(None),
+            Err(_) => Err(TransportError::IoError),
+        }
+    }
+}
+
+impl SerialPort for LoopbackConnection {
+    fn is_connected(&mut self) -> bool {
+        self.open
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, TransportError> {
+        match self.stream.read(buf) {
+            Ok(0) if !buf.is_empty() => {
+                self.open = false;
+                Ok(0)
+            }
+            Ok(n) => Ok(n),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(0),
+            Err(_) => Err(TransportError::IoError),
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<usize, TransportError> {
+        match self.stream.write(data) {
+            Ok(n) => Ok(n),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(0),
+            Err(_) => Err(TransportError::IoError),
+        }
+    }
+}
+
+fn clock() -> fn() -> u64 {
+    fn now_ms() -> u64 {
+        std::time::UNIX_EPOCH.elapsed().unwrap().as_millis() as u64
+    }
+    now_ms
+}
+
+fn tcp_transport() -> TcpTransport<LoopbackListener, fn() -> u64> {
+    TcpTransport::new(LoopbackListener::bind(), clock())
+}
+
+fn ws_transport() -> WebSocketTransport<LoopbackListener, fn() -> u64> {
+    WebSocketTransport::new(LoopbackListener::bind(), clock())
+}
+
+fn frame(msg: &Message) -> Vec<u8> {
+    MessageCodec::encode(msg).unwrap().to_vec()
+}
+
+fn uart_read(len: u16) -> Vec<u8> {
+    frame(&Message::UartRead { len })
+}
+
+/// Poll `transport` until `done` holds, failing after a few seconds
+fn poll_until<T: Transport>(transport: &mut T, mut done: impl FnMut(&mut T) -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !done(transport) {
+        assert!(Instant::now() < deadline, "timed out");
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+fn receive_frame<T: Transport>(transport: &mut T) -> Vec<u8> {
+    let mut received = None;
+    poll_until(transport, |t| {
+        received = t.receive().unwrap().map(<[u8]>::to_vec);
+        received.is_some()
+    });
+    received.unwrap()
+}
+
+/// Echo every frame back to its sender until `client` finishes
+fn echo_until_done<T: Transport>(transport: &mut T, client: JoinHandle<()>) {
+    poll_until(transport, |t| {
+        if let Some(frame) = t.receive().unwrap().map(<[u8]>::to_vec) {
+            t.send(&frame).unwrap();
+        }
+        client.is_finished()
+    });
+    client.join().unwrap();
+}
+
+fn read_exact(stream: &mut TcpStream, len: usize) -> Vec<u8> {
+    let mut buf = vec![0; len];
+    stream.read_exact(&mut buf).unwrap();
+    buf
+}
+
+/// Whether the server has closed `stream`
+fn is_closed(stream: &mut TcpStream) -> bool {
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    matches!(stream.read(&mut [0; 256]), Ok(0) | Err(_))
+}
+
+fn ws_connect(addr: SocketAddr) -> tungstenite::WebSocket<TcpStream> {
+    let stream = TcpStream::connect(addr).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let (socket, _) = tungstenite::client(format!("ws://{addr}/ws"), stream).unwrap();
+    socket
+}
+
+/// Next binary message, skipping control frames
+fn ws_read_binary(socket: &mut tungstenite::WebSocket<TcpStream>) -> Vec<u8> {
+    loop {
+        match socket.read().unwrap() {
+            WsMessage::Binary(data) => return data.to_vec(),
+            WsMessage::Ping(_) | WsMessage::Pong(_) => {}
+            other => panic!("unexpected message {other:?}"),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// TCP
+// ---------------------------------------------------------------------------
+
+#[test]
+fn test_tcp_round_trip() {
+    let mut transport = tcp_transport();
+    let addr = transport.listener().addr();
+    let client = thread::spawn(move || {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let request = uart_read(1);
+        stream.write_all(&request).unwrap();
+        assert_eq!(read_exact(&mut stream, request.len()), request);
+    });
+    echo_until_done(&mut transport, client);
+    assert_eq!(transport.stats().accepted, 1);
+}
+
+#[test]
+fn test_tcp_replies_reach_the_sender() {
+    let mut transport = tcp_transport();
+    let addr = transport.listener().addr();
+    let mut first = TcpStream::connect(addr).unwrap();
+    let mut second = TcpStream::connect(addr).unwrap();
+    poll_until(&mut transport, |t| {
+        t.poll().unwrap();
+        t.client_count() == 2
+    });
+
+    second.write_all(&uart_read(2)).unwrap();
+    assert_eq!(receive_frame(&mut transport), uart_read(2));
+    let from_second = transport.current_client().unwrap();
+    transport.send(&uart_read(20)).unwrap();
+
+    first.write_all(&uart_read(1)).unwrap();
+    assert_eq!(receive_frame(&mut transport), uart_read(1));
+    assert_ne!(transport.current_client(), Some(from_second));
+    transport.send(&uart_read(10)).unwrap();
+
+    // Frames can also be addressed to a client explicitly
+    transport.send_to(from_second, &uart_read(30)).unwrap();
+
+    assert_eq!(read_exact(&mut first, uart_read(10).len()), uart_read(10));
+    let expected = [uart_read(20), uart_read(30)].concat();
+    assert_eq!(read_exact(&mut second, expected.len()), expected);
+}
+
+#[test]
+fn test_tcp_clients_are_served_in_turn() {
+    let mut transport = tcp_transport();
+    let addr = transport.listener().addr();
+    let mut busy = TcpStream::connect(addr).unwrap();
+    let mut quiet = TcpStream::connect(addr).unwrap();
+    poll_until(&mut transport, |t| {
+        t.poll().unwrap();
+        t.client_count() == 2
+    });
+
+    busy.write_all(&[uart_read(1), uart_read(1), uart_read(1)].concat()).unwrap();
+    quiet.write_all(&uart_read(2)).unwrap();
+    thread::sleep(Duration::from_millis(20));
+
+    let received: Vec<Vec<u8>> = (0..4).map(|_| receive_frame(&mut transport)).collect();
+    let quiet_at = received.iter().position(|f| *f == uart_read(2)).unwrap();
+    assert!(quiet_at <= 1, "quiet client waited behind the busy one");
+}
+
+#[test]
+fn test_tcp_client_limit() {
+    let mut transport: TcpTransport<_, _, 2> = TcpTransport::new(LoopbackListener::bind(), clock());
+    let addr = transport.listener().addr();
+    let _first = TcpStream::connect(addr).unwrap();
+    let _second = TcpStream::connect(addr).unwrap();
+    let mut third = TcpStream::connect(addr).unwrap();
+    poll_until(&mut transport, |t| {
+        t.poll().unwrap();
+        t.stats().rejected == 1
+    });
+
+    assert_eq!(transport.client_count(), 2);
+    assert!(is_closed(&mut third));
+}
+
+#[test]
+fn test_tcp_single_client_lock() {
+    let mut transport = tcp_transport().with_single_client(true);
+    let addr = transport.listener().addr();
+    let mut owner = TcpStream::connect(addr).unwrap();
+    poll_until(&mut transport, |t| {
+        t.poll().unwrap();
+        t.client_count() == 1
+    });
+
+    let mut intruder = TcpStream::connect(addr).unwrap();
+    poll_until(&mut transport, |t| {
+        t.poll().unwrap();
+        t.stats().rejected == 1
+    });
+    assert!(is_closed(&mut intruder));
+
+    // The owner is unaffected
+    owner.write_all(&uart_read(1)).unwrap();
+    assert_eq!(receive_frame(&mut transport), uart_read(1));
+
+    // Once the owner leaves the next client gets in
+    drop(owner);
+    poll_until(&mut transport, |t| {
+        t.poll().unwrap();
+        t.client_count() == 0
+    });
+    let mut next = TcpStream::connect(addr).unwrap();
+    next.write_all(&uart_read(2)).unwrap();
+    assert_eq!(receive_frame(&mut transport), uart_read(2));
+    assert_eq!(transport.stats().accepted, 2);
+}
+
+#[test]
+fn test_tcp_disconnect_flushes_and_closes() {
+    let mut transport = tcp_transport();
+    let addr = transport.listener().addr();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(&uart_read(1)).unwrap();
+    receive_frame(&mut transport);
+    let client = transport.current_client().unwrap();
+
+    transport.send(&uart_read(5)).unwrap();
+    transport.disconnect(client);
+    assert_eq!(transport.send_to(client, &uart_read(6)), Err(TransportError::Disconnected));
+    poll_until(&mut transport, |t| {
+        t.poll().unwrap();
+        t.client_count() == 0
+    });
+
+    assert_eq!(read_exact(&mut stream, uart_read(5).len()), uart_read(5));
+    assert!(is_closed(&mut stream));
+    assert_eq!(transport.send_to(client, &uart_read(6)), Err(TransportError::Disconnected));
+    assert!(!transport.is_connected());
+    assert!(!transport.is_client_connected(client));
+    assert_eq!(transport.current_client(), None);
+}
+
+#[test]
+fn test_tcp_new_client_gets_new_id() {
+    let mut transport = tcp_transport();
+    let addr = transport.listener().addr();
+    let mut ids = Vec::<ClientId>::new();
+    for _ in 0..2 {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(&uart_read(1)).unwrap();
+        receive_frame(&mut transport);
+        ids.push(transport.current_client().unwrap());
+        drop(stream);
+        poll_until(&mut transport, |t| {
+            t.poll().unwrap();
+            t.client_count() == 0
+        });
+    }
+    assert_ne!(ids[0], ids[1]);
+}
+
+// ---------------------------------------------------------------------------
+// WebSocket
+// ---------------------------------------------------------------------------
+
+#[test]
+fn test_ws_accept_key() {
+    // Example from RFC 6455 section 1.3
+    assert_eq!(&accept_key(b"dGhlIHNhbXBsZSBub25jZQ=="), b"s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+}
+
+#[test]
+fn test_ws_round_trip() {
+    let mut transport = ws_transport();
+    let addr = transport.listener().addr();
+    let client = thread::spawn(move || {
+        let mut socket = ws_connect(addr);
+        for len in [1, 200] {
+            let request = frame(&Message::UartWrite { data: (0..len).map(|i| i as u8).collect() });
+            socket.send(WsMessage::binary(request.clone())).unwrap();
+            assert_eq!(ws_read_binary(&mut socket), request);
+        }
+        socket.close(None).unwrap();
+        while socket.read().is_ok() {}
+    });
+    echo_until_done(&mut transport, client);
+    poll_until(&mut transport, |t| {
+        t.poll().unwrap();
+        t.client_count() == 0
+    });
+}
+
+#[test]
+fn test_ws_frames_split_and_batched_across_messages() {
+    let mut transport = ws_transport();
+    let addr = transport.listener().addr();
+    let client = thread::spawn(move || {
+        let mut socket = ws_connect(addr);
+        let request = uart_read(7);
+        let (head, tail) = request.split_at(3);
+        socket.send(WsMessage::binary(head.to_vec())).unwrap();
+        socket.send(WsMessage::binary(tail.to_vec())).unwrap();
+        assert_eq!(ws_read_binary(&mut socket), request);
+
+        socket.send(WsMessage::binary([uart_read(8), uart_read(9)].concat())).unwrap();
+        assert_eq!(ws_read_binary(&mut socket), uart_read(8));
+        assert_eq!(ws_read_binary(&mut socket), uart_read(9));
+    });
+    echo_until_done(&mut transport, client);
+}
+
+#[test]
+fn test_ws_ping_is_answered() {
+    let mut transport = ws_transport();
+    let addr = transport.listener().addr();
+    let client = thread::spawn(move || {
+        let mut socket = ws_connect(addr);
+        socket.send(WsMessage::Ping(b"are you there".to_vec())).unwrap();
+        loop {
+            if let WsMessage::Pong(data) = socket.read().unwrap() {
+                assert_eq!(&data[..], b"are you there");
+                break;
+            }
+        }
+    });
+    echo_until_done(&mut transport, client);
+}
+
+/// Raw WebSocket client for checking the wire format byte by byte
+struct RawClient {
+    stream: TcpStream,
+}
+
+impl RawClient {
+    /// Connect and send `request`, returning the server's response head
+    fn connect(addr: SocketAddr, request: &str) -> (Self, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            let mut byte = [0];
+            if stream.read(&mut byte).unwrap() == 0 {
+                break;
+            }
+            head.push(byte[0]);
+        }
+        (Self { stream }, String::from_utf8(head).unwrap())
+    }
+
+    fn upgrade(addr: SocketAddr) -> Self {
+        let (client, head) = Self::connect(addr, &upgrade_request("dGhlIHNhbXBsZSBub25jZQ=="));
+        assert!(head.starts_with("HTTP/1.1 101"), "{head}");
+        client
+    }
+
+    /// Send a masked client frame
+    fn send(&mut self, first: u8, payload: &[u8]) {
+        let mask = [0x12, 0x34, 0x56, 0x78];
+        let mut out = vec![first];
+        match payload.len() {
+            len @ 0..=125 => out.push(0x80 | len as u8),
+            len => {
+                out.push(0x80 | 126);
+                out.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+        }
+        out.extend_from_slice(&mask);
+        out.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        self.stream.write_all(&out).unwrap();
+    }
+
+    /// Read a server frame as (first byte, payload)
+    fn read(&mut self) -> (u8, Vec<u8>) {
+        let header = read_exact(&mut self.stream, 2);
+        assert_eq!(header[1] & 0x80, 0, "server frames are not masked");
+        let len = match header[1] {
+            126 => u16::from_be_bytes(read_exact(&mut self.stream, 2).try_into().unwrap()) as usize,
+            len => len as usize,
+        };
+        (header[0], read_exact(&mut self.stream, len))
+    }
+}
+
+fn upgrade_request(key: &str) -> String {
+    format!(
+        "GET /ws HTTP/1.1\r\nHost: bus-pirate\r\nCookie: {}\r\nUpgrade: websocket\r\n\
+         Connection: keep-alive, Upgrade\r\nSec-WebSocket-Key: {key}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+        "x".repeat(500)
+    )
+}
+
+/// Run `client` against a WebSocket transport that echoes frames
+fn with_ws_server(client: impl FnOnce(SocketAddr) + Send + 'static) {
+    let mut transport = ws_transport();
+    let addr = transport.listener().addr();
+    let client = thread::spawn(move || client(addr));
+    echo_until_done(&mut transport, client);
+}
+
+#[test]
+fn test_ws_handshake_response() {
+    with_ws_server(|addr| {
+        let (_client, head) = RawClient::connect(addr, &upgrade_request("dGhlIHNhbXBsZSBub25jZQ=="));
+        assert!(head.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+        assert!(head.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+    });
+}
+
+#[test]
+fn test_ws_bad_handshake_is_refused() {
+    with_ws_server(|addr| {
+        let (mut client, head) = RawClient::connect(addr, "GET /ws HTTP/1.1\r\nHost: bus-pirate\r\n\r\n");
+        assert!(head.starts_with("HTTP/1.1 400"), "{head}");
+        assert!(is_closed(&mut client.stream));
+    });
+}
+
+#[test]
+fn test_ws_fragmented_message_with_interleaved_ping() {
+    with_ws_server(|addr| {
+        let mut client = RawClient::upgrade(addr);
+        let request = uart_read(3);
+        client.send(0x02, &request[..4]);
+        client.send(0x89, b"mid");
+        client.send(0x80, &request[4..]);
+        assert_eq!(client.read(), (0x8A, b"mid".to_vec()));
+        assert_eq!(client.read(), (0x82, request));
+    });
+}
+
+#[test]
+fn test_ws_large_frame_uses_extended_length() {
+    with_ws_server(|addr| {
+        let mut client = RawClient::upgrade(addr);
+        let request = frame(&Message::UartWrite { data: (0..200).collect() });
+        client.send(0x82, &request);
+        assert_eq!(client.read(), (0x82, request));
+    });
+}
+
+#[test]
+fn test_ws_text_is_refused() {
+    with_ws_server(|addr| {
+        let mut client = RawClient::upgrade(addr);
+        client.send(0x81, b"i2c scan");
+        assert_eq!(client.read(), (0x88, 1003u16.to_be_bytes().to_vec()));
+        assert!(is_closed(&mut client.stream));
+    });
+}
+
+#[test]
+fn test_ws_unmasked_frame_is_a_protocol_error() {
+    with_ws_server(|addr| {
+        let mut client = RawClient::upgrade(addr);
+        client.stream.write_all(&[0x82, 0x01, 0xAA]).unwrap();
+        assert_eq!(client.read(), (0x88, 1002u16.to_be_bytes().to_vec()));
+        assert!(is_closed(&mut client.stream));
+    });
+}
+
+#[test]
+fn test_ws_close_is_echoed() {
+    with_ws_server(|addr| {
+        let mut client = RawClient::upgrade(addr);
+        client.send(0x88, &1000u16.to_be_bytes());
+        assert_eq!(client.read(), (0x88, 1000u16.to_be_bytes().to_vec()));
+        assert!(is_closed(&mut client.stream));
+    });
+}
+
+#[test]
+fn test_ws_single_client_lock_answers_busy() {
+    let mut transport = ws_transport().with_single_client(true);
+    let addr = transport.listener().addr();
+    let client = thread::spawn(move || {
+        let mut owner = ws_connect(addr);
+        let (mut intruder, head) = RawClient::connect(addr, &upgrade_request("dGhlIHNhbXBsZSBub25jZQ=="));
+        assert!(head.starts_with("HTTP/1.1 503"), "{head}");
+        assert!(is_closed(&mut intruder.stream));
+
+        owner.send(WsMessage::binary(uart_read(1))).unwrap();
+        assert_eq!(ws_read_binary(&mut owner), uart_read(1));
+    });
+    echo_until_done(&mut transport, client);
+    assert_eq!(transport.stats().rejected, 1);
+}
+
+#[test]
+fn test_ws_send_before_handshake_is_refused() {
+    let mut transport = ws_transport();
+    let addr = transport.listener().addr();
+    let _stream = TcpStream::connect(addr).unwrap();
+    poll_until(&mut transport, |t| {
+        t.poll().unwrap();
+        t.client_count() == 1
+    });
+    let client = transport.clients().next().unwrap();
+    assert!(!transport.is_connected());
+    assert_eq!(transport.send_to(client, &uart_read(1)), Err(TransportError::Disconnected));
+}
@@ -0,0 +1,448 @@
+//! Stored script tests: compiling, and running through `RunScript` against
+//! mock buses, an in-memory file store and a recording platform
+
+use embedded_hal::i2c::{ErrorKind as I2cErrorKind, NoAcknowledgeSource};
+use embedded_hal_mock::eh1::i2c::{Mock as I2cMock, Transaction as I2cTransaction};
+use embedded_hal_mock::eh1::spi::{Mock as SpiMock, Transaction as SpiTransaction};
+use embedded_io::{ErrorType, Read, ReadReady, Write};
+use esp32_bus_pirate_core::{script, Dispatcher, NoStorage, Platform, Storage};
+use esp32_bus_pirate_protocol::{ErrorCode, Features, Message, Mode, Response, ScriptError, ScriptReport};
+use heapless::{String as HString, Vec as HVec};
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::rc::Rc;
+
+/// What the scripted UART has been given to receive and has sent
+#[derive(Default)]
+struct UartLine {
+    rx: VecDeque<u8>,
+    tx: Vec<u8>,
+}
+
+/// UART backed by a shared [`UartLine`]
+#[derive(Clone, Default)]
+struct ScriptedUart(Rc<RefCell<UartLine>>);
+
+impl ErrorType for ScriptedUart {
+    type Error = Infallible;
+}
+
+impl Read for ScriptedUart {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+        let mut line = self.0.borrow_mut();
+        let n = buf.len().min(line.rx.len());
+        for (slot, byte) in buf.iter_mut().zip(line.rx.drain(..n)) {
+            *slot = byte;
+        }
+        Ok(n)
+    }
+}
+
+impl ReadReady for ScriptedUart {
+    fn read_ready(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.0.borrow().rx.is_empty())
+    }
+}
+
+impl Write for ScriptedUart {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+        self.0.borrow_mut().tx.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+/// Flat in-memory file store
+#[derive(Clone, Default)]
+struct Files(Rc<RefCell<HashMap<String, Vec<u8>>>>);
+
+impl Storage for Files {
+    fn list(&mut self, _path: &str) -> Result<HVec<HString<64>, 32>, ErrorCode> {
+        Ok(HVec::new())
+    }
+
+    fn size(&mut self, path: &str) -> Result<u32, ErrorCode> {
+        let files = self.0.borrow();
+        let file = files.get(path).ok_or(ErrorCode::FileNotFound)?;
+        Ok(file.len() as u32)
+    }
+
+    fn read(&mut self, path: &str, offset: u32, buf: &mut [u8]) -> Result<usize, ErrorCode> {
+        let files = self.0.borrow();
+        let file = files.get(path).ok_or(ErrorCode::FileNotFound)?;
+        let rest = file.get(offset as usize..).unwrap_or_default();
+        let n = buf.len().min(rest.len());
+        buf[..n].copy_from_slice(&rest[..n]);
+        Ok(n)
+    }
+
+    fn write(&mut self, path: &str, offset: u32, data: &[u8]) -> Result<(), ErrorCode> {
+        let mut files = self.0.borrow_mut();
+        let file = files.entry(path.to_owned()).or_default();
+        file.truncate(offset as usize);
+        file.extend_from_slice(data);
+        Ok(())
+    }
+}
+
+/// Pin levels and delays a script has asked for
+#[derive(Default)]
+struct Board {
+    levels: [bool; 8],
+    writes: Vec<(u8, bool)>,
+    delays_us: Vec<u32>,
+}
+
+/// Platform backed by a shared [`Board`]; pins read back what was driven
+#[derive(Clone, Default)]
+struct RecordingPlatform(Rc<RefCell<Board>>);
+
+impl Platform for RecordingPlatform {
+    fn set_pin(&mut self, pin: u8, high: bool) -> Result<(), ErrorCode> {
+        let mut board = self.0.borrow_mut();
+        *board.levels.get_mut(usize::from(pin)).ok_or(ErrorCode::InvalidParameter)? = high;
+        board.writes.push((pin, high));
+        Ok(())
+    }
+
+    fn pin(&mut self, pin: u8) -> Result<bool, ErrorCode> {
+        self.0
+            .borrow()
+            .levels
+            .get(usize::from(pin))
+            .copied()
+            .ok_or(ErrorCode::InvalidParameter)
+    }
+
+    fn delay_us(&mut self, us: u32) -> Result<(), ErrorCode> {
+        self.0.borrow_mut().delays_us.push(us);
+        Ok(())
+    }
+}
+
+type TestDispatcher = Dispatcher<I2cMock, SpiMock<u8>, ScriptedUart, Files, RecordingPlatform>;
+
+/// Mocks expecting `i2c` and `spi`, and a dispatcher that owns clones of them
+struct Bench {
+    i2c: I2cMock,
+    spi: SpiMock<u8>,
+    uart: ScriptedUart,
+    files: Files,
+    platform: RecordingPlatform,
+    dispatcher: TestDispatcher,
+}
+
+impl Bench {
+    fn new(i2c: &[I2cTransaction], spi: &[SpiTransaction<u8>]) -> Self {
+        let i2c = I2cMock::new(i2c);
+        let spi = SpiMock::new(spi);
+        let uart = ScriptedUart::default();
+        let files = Files::default();
+        let platform = RecordingPlatform::default();
+        let dispatcher = Dispatcher::new(i2c.clone(), spi.clone(), uart.clone(), files.clone())
+            .with_platform(platform.clone());
+        Self {
+            i2c,
+            spi,
+            uart,
+            files,
+            platform,
+            dispatcher,
+        }
+    }
+
+    /// Store `source` and run it
+    fn run(&mut self, source: &str) -> ScriptReport {
+        self.files.write("/test.bp", 0, source.as_bytes()).unwrap();
+        let reply = self.dispatcher.handle(&Message::RunScript {
+            path: HString::try_from("/test.bp").unwrap(),
+        });
+        match reply {
+            Message::Response(Response::Script(report)) => report,
+            other => panic!("expected a script report, got {other:?}"),
+        }
+    }
+
+    /// Check every expected bus transaction happened
+    fn done(mut self) {
+        self.i2c.done();
+        self.spi.done();
+    }
+}
+
+fn finished(output: &[u8]) -> ScriptReport {
+    ScriptReport {
+        output: HVec::from_slice(output).unwrap(),
+        error: None,
+    }
+}
+
+fn stopped(output: &[u8], line: u16, code: ErrorCode) -> ScriptReport {
+    ScriptReport {
+        output: HVec::from_slice(output).unwrap(),
+        error: Some(ScriptError { line, code }),
+    }
+}
+
+fn compile_error(source: &str) -> u16 {
+    let err = script::compile(source).unwrap_err();
+    assert_eq!(err.code, ErrorCode::InvalidCommand);
+    err.line
+}
+
+// ============================================================================
+// Compiling
+// ============================================================================
+
+#[test]
+fn test_compile_errors_point_at_the_line() {
+    assert_eq!(compile_error("mode i2c\n\nfrobnicate 1\n"), 3);
+    assert_eq!(compile_error("let x = (1 + 2\n"), 1);
+    assert_eq!(compile_error("emit 1 2 3 garbage(\n"), 1);
+    assert_eq!(compile_error("i2c read 0x50\n"), 1);
+    assert_eq!(compile_error("mode jtag\n"), 1);
+    assert_eq!(compile_error("emit 1\nelse\n"), 2);
+    assert_eq!(compile_error("end\n"), 1);
+    assert_eq!(compile_error("break\n"), 1);
+    // A block left open is reported where it starts
+    assert_eq!(compile_error("loop 3\n  if 1\n  end\n"), 1);
+
+    let too_many = format!("emit{}\n", " 1".repeat(script::MAX_BYTES + 1));
+    assert_eq!(compile_error(&too_many), 1);
+}
+
+#[test]
+fn test_compile_skips_blank_lines_and_comments() {
+    let program = script::compile("# nothing\n\n   # still nothing\n").unwrap();
+    assert!(program.is_empty());
+    assert!(!script::compile("emit 1 # trailing comment\n").unwrap().is_empty());
+}
+
+// ============================================================================
+// Running
+// ============================================================================
+
+#[test]
+fn test_bme280_bring_up() {
+    let busy = [
+        I2cTransaction::transaction_start(0x76),
+        I2cTransaction::write(0x76, vec![0xF3]),
+        I2cTransaction::read(0x76, vec![0x01]),
+        I2cTransaction::transaction_end(0x76),
+    ];
+    let ready = [
+        I2cTransaction::transaction_start(0x76),
+        I2cTransaction::write(0x76, vec![0xF3]),
+        I2cTransaction::read(0x76, vec![0x00]),
+        I2cTransaction::transaction_end(0x76),
+    ];
+    let chip_id = [
+        I2cTransaction::transaction_start(0x76),
+        I2cTransaction::write(0x76, vec![0xD0]),
+        I2cTransaction::read(0x76, vec![0x60]),
+        I2cTransaction::transaction_end(0x76),
+    ];
+    let mut expected = vec![I2cTransaction::write(0x76, vec![0xE0, 0xB6])];
+    expected.extend(busy);
+    expected.extend(ready);
+    expected.extend(chip_id);
+
+    let mut bench = Bench::new(&expected, &[]);
+    let report = bench.run(
+        "# Soft-reset a BME280 and wait for it to come back\n\
+         mode i2c\n\
+         i2c write 0x76 0xE0 0xB6\n\
+         loop 50\n\
+         \x20   delay 2 ms\n\
+         \x20   i2c writeread 0x76 0xF3 -> status\n\
+         \x20   if status & 0x01 == 0\n\
+         \x20       break\n\
+         \x20   end\n\
+         end\n\
+         assert status & 0x01 == 0\n\
+         i2c writeread 0x76 0xD0 -> id\n\
+         assert id == 0x60\n\
+         emit id\n",
+    );
+    assert_eq!(report, finished(&[0x60]));
+    assert_eq!(bench.dispatcher.mode(), Mode::I2c);
+    assert_eq!(bench.platform.0.borrow().delays_us, [2000, 2000]);
+    bench.done();
+}
+
+#[test]
+fn test_expressions_and_variables() {
+    let mut bench = Bench::new(&[], &[]);
+    let report = bench.run(
+        "let x = 3\n\
+         let y = x * 2 + 1\n\
+         emit y (x << 4) (-1) (7 / 2) (7 % 2) (1 + 2 * 3) ((1 + 2) * 3)\n\
+         emit (y > x && x != 0) !x ~0 (0x0F ^ 0b1010 | 0x80) (6 & 2 == 2) unset \"ok\"\n",
+    );
+    assert_eq!(
+        report,
+        finished(&[7, 0x30, 0xFF, 3, 1, 7, 9, 1, 0, 0xFF, 0x85, 1, 0, b'o', b'k'])
+    );
+
+    let report = bench.run("let zero = 0\nemit 1\nemit 1 / zero\n");
+    assert_eq!(report, stopped(&[1], 3, ErrorCode::InvalidParameter));
+    bench.done();
+}
+
+#[test]
+fn test_loops_and_branches() {
+    let mut bench = Bench::new(&[], &[]);
+    let report = bench.run(
+        "loop 3\n\
+         \x20   loop 2\n\
+         \x20       let n = n + 1\n\
+         \x20   end\n\
+         end\n\
+         loop 0\n\
+         \x20   let n = 100\n\
+         end\n\
+         while i < 5\n\
+         \x20   if i % 2 == 0\n\
+         \x20       emit i\n\
+         \x20   else\n\
+         \x20       emit 0xF0 + i\n\
+         \x20   end\n\
+         \x20   let i = i + 1\n\
+         end\n\
+         loop\n\
+         \x20   let k = k + 1\n\
+         \x20   if k == 4\n\
+         \x20       break\n\
+         \x20   end\n\
+         end\n\
+         emit n k\n",
+    );
+    assert_eq!(report, finished(&[0, 0xF1, 2, 0xF3, 4, 6, 4]));
+    bench.done();
+}
+
+#[test]
+fn test_failed_assertion_reports_line_and_output() {
+    let mut bench = Bench::new(&[], &[]);
+    let report = bench.run("emit 1\n\nassert 1 + 1 == 3\nemit 2\n");
+    assert_eq!(report, stopped(&[1], 3, ErrorCode::AssertionFailed));
+    bench.done();
+}
+
+#[test]
+fn test_bus_failure_stops_the_script() {
+    let nack = I2cErrorKind::NoAcknowledge(NoAcknowledgeSource::Address);
+    let mut bench = Bench::new(&[I2cTransaction::write(0x42, vec![0x00]).with_error(nack)], &[]);
+    let report = bench.run("mode i2c\ni2c write 0x42 0\nemit 1\n");
+    assert_eq!(report, stopped(&[], 2, ErrorCode::AddressNack));
+    bench.done();
+}
+
+#[test]
+fn test_operations_need_their_mode() {
+    let mut bench = Bench::new(&[], &[]);
+    assert_eq!(bench.run("spi transfer 0x9F\n"), stopped(&[], 1, ErrorCode::NotConfigured));
+    assert_eq!(
+        bench.run("mode i2c\nuart write \"hi\"\n"),
+        stopped(&[], 2, ErrorCode::NotConfigured)
+    );
+    assert_eq!(bench.run("mode i2c\ni2c write 0x80 0\n"), stopped(&[], 2, ErrorCode::InvalidParameter));
+    bench.done();
+}
+
+#[test]
+fn test_spi_transfer_into_variables() {
+    let spi = [
+        SpiTransaction::transaction_start(),
+        SpiTransaction::transfer_in_place(vec![0x9F, 0, 0, 0], vec![0xFF, 0xEF, 0x40, 0x16]),
+        SpiTransaction::transaction_end(),
+        SpiTransaction::transaction_start(),
+        SpiTransaction::transfer_in_place(vec![0x06], vec![0xFF]),
+        SpiTransaction::transaction_end(),
+    ];
+    let mut bench = Bench::new(&[], &spi);
+    let report = bench.run(
+        "mode spi\n\
+         spi transfer 0x9F 0 0 0 -> _ maker kind size\n\
+         assert maker == 0xEF\n\
+         spi transfer 0x06\n\
+         emit maker kind size\n",
+    );
+    assert_eq!(report, finished(&[0xEF, 0x40, 0x16]));
+    bench.done();
+}
+
+#[test]
+fn test_uart_and_pins() {
+    let mut bench = Bench::new(&[], &[]);
+    bench.uart.0.borrow_mut().rx.push_back(b'A');
+    let report = bench.run(
+        "mode uart\n\
+         uart write \"AT\" 13\n\
+         uart read -> first second\n\
+         pin write 4 1\n\
+         pin read 4 -> level\n\
+         pin write 4 !level\n\
+         delay 50 us\n\
+         emit first second level\n",
+    );
+    // Nothing was waiting for `second`
+    assert_eq!(report, finished(&[b'A', 0xFF, 1]));
+    assert_eq!(bench.uart.0.borrow().tx, b"AT\r");
+
+    let board = bench.platform.0.borrow();
+    assert_eq!(board.writes, [(4, true), (4, false)]);
+    assert_eq!(board.delays_us, [50]);
+    drop(board);
+
+    assert_eq!(bench.run("pin write 9 1\n"), stopped(&[], 1, ErrorCode::InvalidParameter));
+    bench.done();
+}
+
+#[test]
+fn test_runaway_loop_is_stopped() {
+    let mut bench = Bench::new(&[], &[]);
+    let report = bench.run("emit 1\nloop\n    let x = x + 1\nend\n");
+    assert_eq!(report, stopped(&[1], 3, ErrorCode::Timeout));
+    bench.done();
+}
+
+#[test]
+fn test_compile_errors_run_nothing() {
+    let mut bench = Bench::new(&[], &[]);
+    let report = bench.run("mode i2c\nemit 1\nloop 3\n");
+    assert_eq!(report, stopped(&[], 3, ErrorCode::InvalidCommand));
+    assert_eq!(bench.dispatcher.mode(), Mode::HiZ);
+    bench.done();
+}
+
+#[test]
+fn test_script_must_be_readable() {
+    let mut bench = Bench::new(&[], &[]);
+    let missing = Message::RunScript {
+        path: HString::try_from("/missing.bp").unwrap(),
+    };
+    assert_eq!(bench.dispatcher.handle(&missing), Message::Error(ErrorCode::FileNotFound));
+
+    bench.files.write("/big.bp", 0, &vec![b'#'; script::SCRIPT_SIZE + 1]).unwrap();
+    let big = Message::RunScript {
+        path: HString::try_from("/big.bp").unwrap(),
+    };
+    assert_eq!(bench.dispatcher.handle(&big), Message::Error(ErrorCode::InvalidParameter));
+    let info = bench.dispatcher.device_info(&[2], 1024).unwrap();
+    assert!(info.features.contains(Features::SCRIPTS));
+    bench.done();
+
+    // Without storage there is nothing to run, and hosts are told so
+    let (mut i2c, mut spi) = (I2cMock::new(&[]), SpiMock::new(&[]));
+    let mut dispatcher = Dispatcher::new(i2c.clone(), spi.clone(), ScriptedUart::default(), NoStorage);
+    assert_eq!(dispatcher.handle(&missing), Message::Error(ErrorCode::NotConfigured));
+    let info = dispatcher.device_info(&[2], 1024).unwrap();
+    assert!(!info.features.contains(Features::SCRIPTS));
+    i2c.done();
+    spi.done();
+}
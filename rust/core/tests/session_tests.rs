@@ -0,0 +1,329 @@
+//! Session manager tests: bus lease, monitor view and per-client state
+
+use embedded_hal::i2c::{self, I2c};
+use embedded_hal::spi::{self, SpiDevice};
+use embedded_io::{ErrorType, Read, ReadReady, Write};
+use esp32_bus_pirate_core::session::is_read_only;
+use esp32_bus_pirate_core::transport::{ClientId, Transport, TransportError};
+use esp32_bus_pirate_core::{Dispatcher, NoStorage, SessionId, SessionManager};
+use esp32_bus_pirate_protocol::{
+    message::Topic, Error, ErrorCode, Event, Message, MessageCodec, Mode, Response, PROTOCOL_VERSION_V1,
+    PROTOCOL_VERSION_V2,
+};
+use heapless::Vec as HVec;
+use std::convert::Infallible;
+
+/// I2C bus where every address acknowledges
+struct AckBus;
+
+impl i2c::ErrorType for AckBus {
+    type Error = Infallible;
+}
+
+impl I2c for AckBus {
+    fn transaction(&mut self, _address: u8, _operations: &mut [i2c::Operation<'_>]) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+/// SPI device that reads back zeros
+struct NullSpi;
+
+impl spi::ErrorType for NullSpi {
+    type Error = Infallible;
+}
+
+impl SpiDevice for NullSpi {
+    fn transaction(&mut self, _operations: &mut [spi::Operation<'_, u8>]) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+/// UART with nothing to read
+struct IdleUart;
+
+impl ErrorType for IdleUart {
+    type Error = Infallible;
+}
+
+impl Read for IdleUart {
+    fn read(&mut self, _buf: &mut [u8]) -> Result<usize, Infallible> {
+        Ok(0)
+    }
+}
+
+impl ReadReady for IdleUart {
+    fn read_ready(&mut self) -> Result<bool, Infallible> {
+        Ok(false)
+    }
+}
+
+impl Write for IdleUart {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+/// Transport stub that only knows which clients are connected
+struct Clients(Vec<ClientId>);
+
+impl Transport for Clients {
+    fn send(&mut self, _frame: &[u8]) -> Result<(), TransportError> {
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Option<&[u8]>, TransportError> {
+        Ok(None)
+    }
+
+    fn is_connected(&self) -> bool {
+        !self.0.is_empty()
+    }
+
+    fn is_client_connected(&self, client: ClientId) -> bool {
+        self.0.contains(&client)
+    }
+}
+
+type TestDispatcher = Dispatcher<AckBus, NullSpi, IdleUart, NoStorage>;
+
+const USB: u8 = 0;
+const TCP: u8 = 1;
+
+const HOST: SessionId = SessionId::new(USB, ClientId(1));
+const MONITOR: SessionId = SessionId::new(TCP, ClientId(1));
+const OTHER: SessionId = SessionId::new(TCP, ClientId(2));
+
+fn setup() -> (SessionManager, TestDispatcher) {
+    (SessionManager::new(), Dispatcher::new(AckBus, NullSpi, IdleUart, NoStorage))
+}
+
+fn hello() -> Message {
+    Message::Hello {
+        versions: HVec::from_slice(&[PROTOCOL_VERSION_V1, PROTOCOL_VERSION_V2]).unwrap(),
+        max_frame: 1024,
+    }
+}
+
+fn set_mode(mode: Mode) -> Message {
+    Message::SetMode { mode }
+}
+
+fn success() -> Message {
+    Message::Response(Response::Success)
+}
+
+fn busy() -> Message {
+    Message::Error(ErrorCode::Busy)
+}
+
+fn uart_rx() -> Event {
+    Event::UartRx {
+        data: HVec::from_slice(b"hi").unwrap(),
+    }
+}
+
+#[test]
+fn test_first_writer_takes_the_lease() {
+    let (mut sessions, mut dispatcher) = setup();
+    assert_eq!(sessions.holder(), None);
+
+    assert_eq!(sessions.handle(&mut dispatcher, HOST, &set_mode(Mode::I2c)), success());
+    assert_eq!(sessions.holder(), Some(HOST));
+
+    // Another client cannot change the mode or use the bus
+    assert_eq!(sessions.handle(&mut dispatcher, MONITOR, &set_mode(Mode::Spi)), busy());
+    let write = Message::I2cWrite { addr: 0x50, data: HVec::from_slice(&[0]).unwrap() };
+    assert_eq!(sessions.handle(&mut dispatcher, MONITOR, &write), busy());
+    assert_eq!(dispatcher.mode(), Mode::I2c);
+
+    // The holder still can
+    assert_eq!(sessions.handle(&mut dispatcher, HOST, &write), success());
+}
+
+#[test]
+fn test_monitor_view_is_read_only() {
+    let (mut sessions, mut dispatcher) = setup();
+    sessions.handle(&mut dispatcher, HOST, &set_mode(Mode::Uart));
+
+    assert!(matches!(
+        sessions.handle(&mut dispatcher, MONITOR, &hello()),
+        Message::Response(Response::DeviceInfo(_))
+    ));
+    assert_eq!(
+        sessions.handle(&mut dispatcher, MONITOR, &Message::GetMode),
+        Message::Response(Response::CurrentMode(Mode::Uart))
+    );
+    assert_eq!(
+        sessions.handle(&mut dispatcher, MONITOR, &Message::Subscribe { topic: Topic::UartRx }),
+        success()
+    );
+    assert_eq!(sessions.holder(), Some(HOST));
+
+    for msg in [
+        Message::UartRead { len: 4 },
+        Message::UartConfig { baudrate: 9600 },
+        Message::SetConfig {
+            key: "k".try_into().unwrap(),
+            value: "v".try_into().unwrap(),
+        },
+    ] {
+        assert!(!is_read_only(&msg));
+        assert_eq!(sessions.handle(&mut dispatcher, MONITOR, &msg), busy(), "{msg:?}");
+    }
+}
+
+#[test]
+fn test_lease_released_when_holder_leaves() {
+    let (mut sessions, mut dispatcher) = setup();
+    sessions.handle(&mut dispatcher, MONITOR, &Message::GetMode);
+    sessions.handle(&mut dispatcher, OTHER, &set_mode(Mode::I2c));
+    assert_eq!(sessions.holder(), Some(OTHER));
+
+    // OTHER disconnects from the TCP transport
+    let tcp = Clients(vec![MONITOR.client]);
+    sessions.prune(&mut dispatcher, TCP, &tcp);
+    assert_eq!(sessions.holder(), None);
+    assert_eq!(sessions.sessions().collect::<Vec<_>>(), [MONITOR]);
+
+    // The bus stays in its mode for whoever takes it next
+    assert_eq!(dispatcher.mode(), Mode::I2c);
+    assert_eq!(sessions.handle(&mut dispatcher, MONITOR, &set_mode(Mode::Spi)), success());
+    assert_eq!(sessions.holder(), Some(MONITOR));
+}
+
+#[test]
+fn test_prune_only_touches_its_transport() {
+    let (mut sessions, mut dispatcher) = setup();
+    sessions.handle(&mut dispatcher, HOST, &set_mode(Mode::I2c));
+    sessions.handle(&mut dispatcher, MONITOR, &Message::GetMode);
+
+    // USB client 1 and TCP client 1 share a client id but not a transport
+    sessions.prune(&mut dispatcher, TCP, &Clients(vec![]));
+    assert_eq!(sessions.sessions().collect::<Vec<_>>(), [HOST]);
+    assert_eq!(sessions.holder(), Some(HOST));
+}
+
+#[test]
+fn test_hiz_releases_the_lease() {
+    let (mut sessions, mut dispatcher) = setup();
+    sessions.handle(&mut dispatcher, HOST, &set_mode(Mode::Spi));
+    assert_eq!(sessions.handle(&mut dispatcher, HOST, &set_mode(Mode::HiZ)), success());
+    assert_eq!(sessions.holder(), None);
+
+    assert_eq!(sessions.handle(&mut dispatcher, MONITOR, &set_mode(Mode::I2c)), success());
+    assert_eq!(sessions.holder(), Some(MONITOR));
+}
+
+#[test]
+fn test_busy_for_v1_hosts_is_permission_denied() {
+    let (mut sessions, mut dispatcher) = setup();
+    sessions.handle(&mut dispatcher, HOST, &set_mode(Mode::I2c));
+
+    let v1 = MessageCodec::encode_v1(&set_mode(Mode::Spi)).unwrap();
+    let reply = sessions
+        .handle_frame(&mut dispatcher, MONITOR, &MessageCodec::decode_frame(&v1).unwrap())
+        .unwrap();
+    let reply = MessageCodec::decode_frame(&reply).unwrap();
+    assert_eq!(reply.version, PROTOCOL_VERSION_V1);
+    assert_eq!(reply.message, Message::Error(ErrorCode::PermissionDenied));
+
+    let v2 = MessageCodec::encode_with_seq(&set_mode(Mode::Spi), 7).unwrap();
+    let reply = sessions
+        .handle_frame(&mut dispatcher, MONITOR, &MessageCodec::decode_frame(&v2).unwrap())
+        .unwrap();
+    let reply = MessageCodec::decode_frame(&reply).unwrap();
+    assert_eq!((reply.seq, reply.message), (7, busy()));
+}
+
+#[test]
+fn test_repeated_sequence_is_not_executed() {
+    let (mut sessions, mut dispatcher) = setup();
+    let frame = |msg: &Message, seq| {
+        let bytes = MessageCodec::encode_with_seq(msg, seq).unwrap();
+        MessageCodec::decode_frame(&bytes).unwrap()
+    };
+
+    sessions.handle_frame(&mut dispatcher, HOST, &frame(&hello(), 1)).unwrap();
+    sessions.handle_frame(&mut dispatcher, HOST, &frame(&set_mode(Mode::I2c), 2)).unwrap();
+    sessions.handle(&mut dispatcher, HOST, &set_mode(Mode::HiZ));
+
+    // The replayed SetMode must not put the device back in I2C mode
+    let replay = sessions.handle_frame(&mut dispatcher, HOST, &frame(&set_mode(Mode::I2c), 2));
+    assert_eq!(replay, Err(Error::DuplicateSequence));
+    assert_eq!(dispatcher.mode(), Mode::HiZ);
+
+    // Other clients keep their own sequence IDs
+    assert!(sessions.handle_frame(&mut dispatcher, MONITOR, &frame(&Message::GetMode, 2)).is_ok());
+
+    // A new Hello lets the host number its requests from 1 again
+    sessions.handle_frame(&mut dispatcher, HOST, &frame(&hello(), 1)).unwrap();
+    assert!(sessions.handle_frame(&mut dispatcher, HOST, &frame(&set_mode(Mode::I2c), 2)).is_ok());
+    assert_eq!(dispatcher.mode(), Mode::I2c);
+}
+
+#[test]
+fn test_events_go_to_each_subscriber() {
+    let (mut sessions, mut dispatcher) = setup();
+    sessions.handle(&mut dispatcher, HOST, &hello());
+    sessions.handle(&mut dispatcher, HOST, &set_mode(Mode::Uart));
+    sessions.handle(&mut dispatcher, MONITOR, &hello());
+    sessions.handle(&mut dispatcher, OTHER, &hello());
+    sessions.handle(&mut dispatcher, MONITOR, &Message::Subscribe { topic: Topic::UartRx });
+
+    // Collected because the monitor wants it, though the holder does not
+    assert!(dispatcher.publish(uart_rx()));
+    let event = dispatcher.poll_event().unwrap();
+    let frames: Vec<_> = sessions.event_frames(&event).collect();
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].0, MONITOR);
+    assert_eq!(MessageCodec::decode(&frames[0].1).unwrap(), Message::Event(uart_rx()));
+
+    sessions.handle(&mut dispatcher, MONITOR, &Message::Unsubscribe { topic: Topic::UartRx });
+    assert!(!dispatcher.publish(uart_rx()));
+}
+
+#[test]
+fn test_hello_resets_only_its_own_session() {
+    let (mut sessions, mut dispatcher) = setup();
+    sessions.handle(&mut dispatcher, HOST, &hello());
+    sessions.handle(&mut dispatcher, HOST, &Message::Subscribe { topic: Topic::UartRx });
+    sessions.handle(&mut dispatcher, HOST, &set_mode(Mode::Uart));
+
+    // A monitor connecting does not cost the holder its subscriptions or lease
+    sessions.handle(&mut dispatcher, MONITOR, &hello());
+    assert_eq!(sessions.holder(), Some(HOST));
+    assert!(dispatcher.publish(uart_rx()));
+    let event = dispatcher.poll_event().unwrap();
+    let to: Vec<_> = sessions.event_frames(&event).map(|(id, _)| id).collect();
+    assert_eq!(to, [HOST]);
+}
+
+#[test]
+fn test_sessions_keep_their_own_version() {
+    let (mut sessions, mut dispatcher) = setup();
+    sessions.handle(&mut dispatcher, HOST, &hello());
+    sessions.handle(&mut dispatcher, MONITOR, &Message::GetMode);
+    assert_eq!(sessions.version(HOST), Some(PROTOCOL_VERSION_V2));
+    assert_eq!(sessions.version(MONITOR), Some(PROTOCOL_VERSION_V1));
+    assert_eq!(sessions.version(OTHER), None);
+}
+
+#[test]
+fn test_full_session_table_is_busy() {
+    let mut sessions: SessionManager<2> = SessionManager::new();
+    let mut dispatcher = Dispatcher::new(AckBus, NullSpi, IdleUart, NoStorage);
+    sessions.handle(&mut dispatcher, HOST, &Message::GetMode);
+    sessions.handle(&mut dispatcher, MONITOR, &Message::GetMode);
+    assert_eq!(sessions.handle(&mut dispatcher, OTHER, &Message::GetMode), busy());
+
+    sessions.end_session(&mut dispatcher, MONITOR);
+    assert_eq!(
+        sessions.handle(&mut dispatcher, OTHER, &Message::GetMode),
+        Message::Response(Response::CurrentMode(Mode::HiZ))
+    );
+}
@@ -0,0 +1,412 @@
+//! Text shell tests: syntax parsing, bus transcripts and line editing
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal_mock::eh1::i2c::{Mock as I2cMock, Transaction as I2cTransaction};
+use embedded_hal_mock::eh1::spi::{Mock as SpiMock, Transaction as SpiTransaction};
+use embedded_io::{ErrorType, Read, ReadReady, Write};
+use esp32_bus_pirate_core::shell::{parse, Op, Step, SyntaxError};
+use esp32_bus_pirate_core::transport::ClientId;
+use esp32_bus_pirate_core::{Dispatcher, NoStorage, SessionId, SessionManager, Shell, ShellContext};
+use esp32_bus_pirate_protocol::{Message, Mode, Response};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::rc::Rc;
+
+/// What the scripted UART has been given to receive and has sent
+#[derive(Default)]
+struct UartLine {
+    rx: VecDeque<u8>,
+    tx: Vec<u8>,
+}
+
+/// UART backed by a shared [`UartLine`]
+#[derive(Clone, Default)]
+struct ScriptedUart(Rc<RefCell<UartLine>>);
+
+impl ErrorType for ScriptedUart {
+    type Error = Infallible;
+}
+
+impl Read for ScriptedUart {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+        let mut line = self.0.borrow_mut();
+        let n = buf.len().min(line.rx.len());
+        for (slot, byte) in buf.iter_mut().zip(line.rx.drain(..n)) {
+            *slot = byte;
+        }
+        Ok(n)
+    }
+}
+
+impl ReadReady for ScriptedUart {
+    fn read_ready(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.0.borrow().rx.is_empty())
+    }
+}
+
+impl Write for ScriptedUart {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+        self.0.borrow_mut().tx.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+/// Delay that only adds up the time it was asked to wait
+#[derive(Default)]
+struct CountingDelay {
+    ns: u64,
+}
+
+impl DelayNs for CountingDelay {
+    fn delay_ns(&mut self, ns: u32) {
+        self.ns += u64::from(ns);
+    }
+}
+
+type TestDispatcher = Dispatcher<I2cMock, SpiMock<u8>, ScriptedUart, NoStorage>;
+
+const SHELL: SessionId = SessionId::new(0, ClientId(1));
+const HOST: SessionId = SessionId::new(1, ClientId(1));
+
+/// A shell on a dispatcher whose buses expect `i2c` and `spi`
+struct Bench {
+    i2c: I2cMock,
+    spi: SpiMock<u8>,
+    uart: ScriptedUart,
+    dispatcher: TestDispatcher,
+    sessions: SessionManager,
+    delay: CountingDelay,
+    shell: Shell,
+}
+
+impl Bench {
+    fn new(i2c: &[I2cTransaction], spi: &[SpiTransaction<u8>]) -> Self {
+        let i2c = I2cMock::new(i2c);
+        let spi = SpiMock::new(spi);
+        let uart = ScriptedUart::default();
+        let dispatcher = Dispatcher::new(i2c.clone(), spi.clone(), uart.clone(), NoStorage);
+        Self {
+            i2c,
+            spi,
+            uart,
+            dispatcher,
+            sessions: SessionManager::new(),
+            delay: CountingDelay::default(),
+            shell: Shell::new(),
+        }
+    }
+
+    /// Type `input` and return everything the shell printed
+    fn type_text(&mut self, input: &str) -> String {
+        let mut ctx = ShellContext {
+            dispatcher: &mut self.dispatcher,
+            sessions: &mut self.sessions,
+            id: SHELL,
+            delay: &mut self.delay,
+        };
+        let mut out = String::new();
+        self.shell.input(input.as_bytes(), &mut ctx, &mut out);
+        out
+    }
+
+    /// Run `line` and return the lines it printed, without the echo and
+    /// the next prompt
+    fn run(&mut self, line: &str) -> Vec<String> {
+        let out = self.type_text(&format!("{line}\r"));
+        let mut lines: Vec<String> = out.split("\r\n").map(String::from).collect();
+        assert_eq!(lines.remove(0), line, "echo");
+        lines.pop();
+        lines
+    }
+
+    /// Check every expected bus transaction happened
+    fn done(mut self) {
+        self.i2c.done();
+        self.spi.done();
+    }
+}
+
+fn step(op: Op, repeat: u16) -> Step {
+    Step { op, repeat }
+}
+
+// ===== Syntax =====
+
+#[test]
+fn test_parse_values_strings_and_repeats() {
+    let steps = parse("[0x1F,0b101 42 'ab' r:3 & %:2 ]").unwrap();
+    assert_eq!(
+        &steps[..],
+        &[
+            step(Op::Start, 1),
+            step(Op::Write(0x1F), 1),
+            step(Op::Write(0b101), 1),
+            step(Op::Write(42), 1),
+            step(Op::Write(b'a'), 1),
+            step(Op::Write(b'b'), 1),
+            step(Op::Read, 3),
+            step(Op::DelayUs, 1),
+            step(Op::DelayMs, 2),
+            step(Op::Stop, 1),
+        ]
+    );
+
+    // Symbols need no separator, and `d` / `D` are delays too
+    let steps = parse("{rrd0xFF:4D}").unwrap();
+    assert_eq!(
+        &steps[..],
+        &[
+            step(Op::StartRead, 1),
+            step(Op::Read, 1),
+            step(Op::Read, 1),
+            step(Op::DelayUs, 1),
+            step(Op::Write(0xFF), 4),
+            step(Op::DelayMs, 1),
+            step(Op::Stop, 1),
+        ]
+    );
+}
+
+#[test]
+fn test_parse_errors_point_at_the_problem() {
+    assert_eq!(parse("0x100"), Err(SyntaxError::BadValue(0)));
+    assert_eq!(parse("[0xA0 x]"), Err(SyntaxError::Unexpected(6)));
+    assert_eq!(parse("r:0"), Err(SyntaxError::BadRepeat(1)));
+    assert_eq!(parse("r:2000"), Err(SyntaxError::BadRepeat(1)));
+    assert_eq!(parse("[:2"), Err(SyntaxError::Unexpected(1)));
+    assert_eq!(parse("0x50 'ab"), Err(SyntaxError::Unterminated(5)));
+    assert_eq!(parse(&"r".repeat(129)), Err(SyntaxError::TooLong));
+}
+
+// ===== Commands =====
+
+#[test]
+fn test_mode_command() {
+    let mut bench = Bench::new(&[], &[]);
+    assert_eq!(bench.run("m"), ["1. HiZ", "2. I2C", "3. SPI", "4. UART"]);
+    assert_eq!(bench.run("m 2"), ["Mode: I2C"]);
+    assert_eq!(bench.dispatcher.mode(), Mode::I2c);
+    assert_eq!(bench.run("m spi"), ["Mode: SPI"]);
+    assert_eq!(bench.dispatcher.mode(), Mode::Spi);
+    assert_eq!(bench.run("m 9"), ["Error: invalid parameter"]);
+
+    // The prompt follows the mode
+    assert!(bench.type_text("\r").ends_with("\r\nSPI> "));
+    bench.done();
+}
+
+#[test]
+fn test_bad_input_is_reported() {
+    let mut bench = Bench::new(&[], &[]);
+    assert_eq!(bench.run("x"), ["Error: unknown command, ? for help"]);
+    assert_eq!(bench.run("[0xA0 $]"), ["Error: syntax error at column 7"]);
+    assert_eq!(bench.run("0x55"), ["Error: select a mode first (m)"]);
+    assert!(bench.run("?").iter().any(|line| line.starts_with("m [mode]")));
+    bench.done();
+}
+
+#[test]
+fn test_shell_respects_the_bus_lease() {
+    let mut bench = Bench::new(&[], &[]);
+    let set_mode = Message::SetMode { mode: Mode::I2c };
+    assert_eq!(
+        bench.sessions.handle(&mut bench.dispatcher, HOST, &set_mode),
+        Message::Response(Response::Success)
+    );
+
+    assert_eq!(bench.run("m 3"), ["Error: bus is in use by another client"]);
+    assert_eq!(bench.run("[0xA0 0x00]"), ["Error: bus is in use by another client"]);
+    assert_eq!(bench.dispatcher.mode(), Mode::I2c);
+    bench.done();
+}
+
+// ===== I2C =====
+
+#[test]
+fn test_i2c_write_then_read_with_restart() {
+    let mut bench = Bench::new(
+        &[
+            I2cTransaction::transaction_start(0x50),
+            I2cTransaction::write(0x50, vec![0x00, 0x10]),
+            I2cTransaction::read(0x50, vec![0xDE, 0xAD, 0xBE]),
+            I2cTransaction::transaction_end(0x50),
+        ],
+        &[],
+    );
+    bench.run("m i2c");
+
+    assert_eq!(
+        bench.run("[0xA0 0x00 0x10 [0xA1 r:3]"),
+        [
+            "I2C START",
+            "ADDRESS: 0x50 (WRITE)",
+            "WRITE: 0x00 0x10",
+            "I2C RESTART",
+            "ADDRESS: 0x50 (READ)",
+            "READ: 0xDE 0xAD 0xBE",
+            "I2C STOP",
+        ]
+    );
+    bench.done();
+}
+
+#[test]
+fn test_i2c_address_only_probes() {
+    let mut bench = Bench::new(
+        &[
+            I2cTransaction::transaction_start(0x3C),
+            I2cTransaction::write(0x3C, vec![]),
+            I2cTransaction::transaction_end(0x3C),
+        ],
+        &[],
+    );
+    bench.run("m i2c");
+
+    assert_eq!(bench.run("[0x78]"), ["I2C START", "ADDRESS: 0x3C (WRITE)", "I2C STOP"]);
+    // `[]` has no address, so nothing happens on the bus
+    assert_eq!(bench.run("[]"), Vec::<String>::new());
+    bench.done();
+}
+
+#[test]
+fn test_i2c_new_address_starts_a_new_transaction() {
+    let mut bench = Bench::new(
+        &[
+            I2cTransaction::transaction_start(0x50),
+            I2cTransaction::write(0x50, vec![0x01]),
+            I2cTransaction::transaction_end(0x50),
+            I2cTransaction::transaction_start(0x51),
+            I2cTransaction::read(0x51, vec![0x42]),
+            I2cTransaction::transaction_end(0x51),
+        ],
+        &[],
+    );
+    bench.run("m i2c");
+
+    // No closing bracket: the line end stops the bus
+    assert_eq!(
+        bench.run("[0xA0 0x01 [0xA3 r"),
+        [
+            "I2C START",
+            "ADDRESS: 0x50 (WRITE)",
+            "WRITE: 0x01",
+            "I2C STOP",
+            "I2C START",
+            "ADDRESS: 0x51 (READ)",
+            "READ: 0x42",
+            "I2C STOP",
+        ]
+    );
+    bench.done();
+}
+
+#[test]
+fn test_i2c_needs_a_transaction() {
+    let mut bench = Bench::new(&[], &[]);
+    bench.run("m i2c");
+    assert_eq!(bench.run("0xA0"), ["Error: I2C needs [ and an address byte first"]);
+    assert_eq!(bench.run("[r]"), ["Error: I2C needs [ and an address byte first"]);
+    assert_eq!(bench.run("[0xA0 % 0x00]"), ["Error: delays cannot go inside an I2C transaction"]);
+
+    // Delays between transactions are fine
+    assert_eq!(bench.run("%:5 &"), ["DELAY: 5ms", "DELAY: 1us"]);
+    assert_eq!(bench.delay.ns, 5_001_000);
+    bench.done();
+}
+
+// ===== SPI =====
+
+#[test]
+fn test_spi_chip_select_frames_a_transaction() {
+    let mut bench = Bench::new(
+        &[],
+        &[
+            SpiTransaction::transaction_start(),
+            SpiTransaction::transfer_in_place(vec![0x9F], vec![0x00]),
+            SpiTransaction::delay(1_000),
+            SpiTransaction::transfer_in_place(vec![0xFF, 0xFF, 0xFF], vec![0xEF, 0x40, 0x18]),
+            SpiTransaction::transaction_end(),
+        ],
+    );
+    bench.run("m spi");
+
+    assert_eq!(
+        bench.run("[0x9F & r:3]"),
+        [
+            "CS ENABLE",
+            "WRITE: 0x9F",
+            "DELAY: 1us",
+            "READ: 0xEF 0x40 0x18",
+            "CS DISABLE",
+        ]
+    );
+    bench.done();
+}
+
+#[test]
+fn test_spi_braces_show_bytes_read_while_writing() {
+    let mut bench = Bench::new(
+        &[],
+        &[
+            SpiTransaction::transaction_start(),
+            SpiTransaction::transfer_in_place(vec![0x05, 0x00], vec![0xFF, 0x02]),
+            SpiTransaction::transaction_end(),
+            SpiTransaction::transaction_start(),
+            SpiTransaction::transfer_in_place(vec![0x06], vec![0x00]),
+            SpiTransaction::transaction_end(),
+        ],
+    );
+    bench.run("m spi");
+
+    assert_eq!(
+        bench.run("{0x05 0x00} 0x06"),
+        ["CS ENABLE", "WRITE: 0x05 0x00", "READ: 0xFF 0x02", "CS DISABLE", "WRITE: 0x06"]
+    );
+    bench.done();
+}
+
+// ===== UART =====
+
+#[test]
+fn test_uart_writes_and_reads() {
+    let mut bench = Bench::new(&[], &[]);
+    bench.run("m uart");
+    bench.uart.0.borrow_mut().rx.extend(b"OK");
+
+    assert_eq!(
+        bench.run("\"AT\" 0x0D r:4 r"),
+        ["WRITE: 0x41 0x54 0x0D", "READ: 0x4F 0x4B", "READ: (none)"]
+    );
+    assert_eq!(bench.uart.0.borrow().tx, b"AT\r");
+    bench.done();
+}
+
+// ===== Line Editing =====
+
+#[test]
+fn test_line_editing() {
+    let mut bench = Bench::new(&[], &[]);
+
+    // Backspace erases, escape sequences (arrow keys) are ignored, and
+    // CRLF counts as one Enter
+    let out = bench.type_text("m 3\x08\x1b[A2\r\n");
+    assert_eq!(out, "m 3\x08 \x082\r\nMode: I2C\r\nI2C> ");
+
+    // Ctrl-C abandons the line
+    let out = bench.type_text("m 3\x03");
+    assert_eq!(out, "m 3^C\r\nI2C> ");
+    assert_eq!(bench.dispatcher.mode(), Mode::I2c);
+
+    // Input may arrive a byte at a time
+    let mut out = String::new();
+    for byte in "m 1\r".chars() {
+        out += &bench.type_text(&byte.to_string());
+    }
+    assert_eq!(out, "m 1\r\nMode: HiZ\r\nHiZ> ");
+    bench.done();
+}
@@ -0,0 +1,450 @@
+//! Transport core tests: ring buffer, flow control and the console
+
+use esp32_bus_pirate_core::transport::console::{BINARY_IDLE_MS, BITBANG_ENTRY_NULLS, TEXT_RX_SIZE};
+use esp32_bus_pirate_core::transport::{
+    ClientId, ConsoleMode, ConsolePort, IoPort, RingBuffer, SerialPort, StreamTransport, Transport, TransportError,
+};
+use esp32_bus_pirate_protocol::{Message, MessageCodec};
+use embedded_io::{ErrorType, Read, ReadReady, Write, WriteReady};
+use heapless::Vec as HVec;
+use std::convert::Infallible;
+use std::fmt::Write as _;
+use std::{cell::Cell, collections::VecDeque, rc::Rc};
+
+/// Port with scripted host input and captured output
+#[derive(Default)]
+struct MockPort {
+    connected: bool,
+    /// Bytes the host has sent
+    input: VecDeque<u8>,
+    /// Bytes the device has sent
+    output: Vec<u8>,
+    /// Most bytes accepted per write; `None` means unlimited
+    write_limit: Option<usize>,
+}
+
+impl SerialPort for MockPort {
+    fn is_connected(&mut self) -> bool {
+        self.connected
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, TransportError> {
+        let n = buf.len().min(self.input.len());
+        for byte in &mut buf[..n] {
+            *byte = self.input.pop_front().unwrap();
+        }
+        Ok(n)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<usize, TransportError> {
+        let n = data.len().min(self.write_limit.unwrap_or(usize::MAX));
+        self.output.extend_from_slice(&data[..n]);
+        Ok(n)
+    }
+}
+
+/// UART-like stream with a small hardware FIFO in each direction
+#[derive(Default)]
+struct MockUart {
+    rx_fifo: VecDeque<u8>,
+    tx_fifo: Vec<u8>,
+    /// TX FIFO capacity; writes stall while it is full (CTS deasserted)
+    tx_capacity: usize,
+}
+
+impl ErrorType for MockUart {
+    type Error = Infallible;
+}
+
+impl Read for MockUart {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+        assert!(!self.rx_fifo.is_empty(), "read would block");
+        let n = buf.len().min(self.rx_fifo.len());
+        for byte in &mut buf[..n] {
+            *byte = self.rx_fifo.pop_front().unwrap();
+        }
+        Ok(n)
+    }
+}
+
+impl ReadReady for MockUart {
+    fn read_ready(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.rx_fifo.is_empty())
+    }
+}
+
+impl Write for MockUart {
+    fn write(&mut self, data: &[u8]) -> Result<usize, Infallible> {
+        let room = self.tx_capacity - self.tx_fifo.len();
+        assert!(room > 0, "write would block");
+        let n = data.len().min(room);
+        self.tx_fifo.extend_from_slice(&data[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+impl WriteReady for MockUart {
+    fn write_ready(&mut self) -> Result<bool, Infallible> {
+        Ok(self.tx_fifo.len() < self.tx_capacity)
+    }
+}
+
+type TestTransport = StreamTransport<MockPort, Box<dyn Fn() -> u64>, 1024, 1024>;
+
+fn transport() -> (TestTransport, Rc<Cell<u64>>) {
+    let now = Rc::new(Cell::new(0));
+    let clock = now.clone();
+    let port = MockPort {
+        connected: true,
+        ..Default::default()
+    };
+    let clock: Box<dyn Fn() -> u64> = Box::new(move || clock.get());
+    (StreamTransport::new(port, clock), now)
+}
+
+type TestConsole = StreamTransport<ConsolePort<MockPort, Box<dyn Fn() -> u64>>, Box<dyn Fn() -> u64>, 1024, 1024>;
+
+fn console() -> (TestConsole, Rc<Cell<u64>>) {
+    let now = Rc::new(Cell::new(0));
+    let port = MockPort {
+        connected: true,
+        ..Default::default()
+    };
+    let (console_clock, transport_clock) = (now.clone(), now.clone());
+    let console = ConsolePort::new(port, Box::new(move || console_clock.get()) as Box<dyn Fn() -> u64>);
+    (StreamTransport::new(console, Box::new(move || transport_clock.get())), now)
+}
+
+fn frame(msg: &Message) -> Vec<u8> {
+    MessageCodec::encode(msg).unwrap().to_vec()
+}
+
+fn uart_write(len: usize) -> Message {
+    Message::UartWrite {
+        data: HVec::from_slice(&vec![0x5A; len]).unwrap(),
+    }
+}
+
+// ===== Ring Buffer =====
+
+#[test]
+fn test_ring_buffer_wraps_around() {
+    let mut ring = RingBuffer::<8>::new();
+    assert_eq!(ring.push(&[1, 2, 3, 4, 5, 6]), 6);
+    ring.consume(4);
+    assert_eq!(ring.push(&[7, 8, 9, 10, 11, 12]), 6);
+    assert_eq!(ring.free(), 0);
+    assert_eq!(ring.push(&[13]), 0);
+
+    // The contents wrap: [5, 6, 7, 8] at the end, [9..=12] at the start
+    assert_eq!(ring.front_slice(), &[5, 6, 7, 8]);
+    let mut out = [0; 8];
+    assert_eq!(ring.peek(&mut out), 8);
+    assert_eq!(out, [5, 6, 7, 8, 9, 10, 11, 12]);
+    assert_eq!(ring.get(7), Some(12));
+    assert_eq!(ring.get(8), None);
+}
+
+#[test]
+fn test_ring_buffer_free_slice_and_commit() {
+    let mut ring = RingBuffer::<4>::new();
+    ring.push(&[1, 2, 3]);
+    ring.consume(2);
+
+    // Free space runs to the end of the array first, then wraps
+    assert_eq!(ring.free_slice().len(), 1);
+    ring.free_slice()[0] = 4;
+    ring.commit(1);
+    assert_eq!(ring.free_slice().len(), 2);
+    ring.free_slice().copy_from_slice(&[5, 6]);
+    ring.commit(2);
+
+    let mut out = [0; 4];
+    ring.peek(&mut out);
+    assert_eq!(out, [3, 4, 5, 6]);
+    assert!(ring.free_slice().is_empty());
+}
+
+// ===== Stream Transport =====
+
+#[test]
+fn test_receive_and_send() {
+    let (mut transport, _) = transport();
+    let request = frame(&Message::GetMode);
+    transport.port_mut().input.extend(&request);
+
+    assert_eq!(transport.receive().unwrap(), Some(&request[..]));
+    assert_eq!(transport.receive().unwrap(), None);
+    assert!(transport.is_connected());
+
+    let reply = frame(&uart_write(4));
+    transport.send(&reply).unwrap();
+    assert_eq!(transport.port().output, reply);
+}
+
+#[test]
+fn test_send_while_disconnected() {
+    let (mut transport, _) = transport();
+    transport.port_mut().connected = false;
+    assert_eq!(transport.send(&frame(&Message::GetMode)), Err(TransportError::Disconnected));
+    assert_eq!(transport.receive(), Ok(None));
+    assert!(!transport.is_connected());
+}
+
+#[test]
+fn test_disconnect_clears_partial_frames() {
+    let (mut transport, _) = transport();
+    let request = frame(&Message::GetMode);
+    transport.port_mut().input.extend(&request[..4]);
+    assert_eq!(transport.receive().unwrap(), None);
+
+    // The host goes away mid-frame, and a new one sends a whole frame
+    transport.port_mut().connected = false;
+    transport.poll().unwrap();
+    transport.port_mut().connected = true;
+    transport.port_mut().input.extend(&request);
+    assert_eq!(transport.receive().unwrap(), Some(&request[..]));
+}
+
+#[test]
+fn test_each_connection_is_a_new_client() {
+    let (mut transport, _) = transport();
+    transport.poll().unwrap();
+    let first = transport.current_client().unwrap();
+    assert!(transport.is_client_connected(first));
+
+    transport.port_mut().connected = false;
+    transport.poll().unwrap();
+    assert_eq!(transport.current_client(), None);
+    assert!(!transport.is_client_connected(first));
+
+    transport.port_mut().connected = true;
+    transport.poll().unwrap();
+    let second = transport.current_client().unwrap();
+    assert_ne!(first, second);
+    assert!(!transport.is_client_connected(first));
+    assert_ne!(second, ClientId(0));
+}
+
+#[test]
+fn test_backpressure_leaves_excess_input_in_port() {
+    let (mut transport, _) = transport();
+    let request = frame(&uart_write(200));
+    for _ in 0..10 {
+        transport.port_mut().input.extend(&request);
+    }
+
+    // Only as much as the RX ring holds is read at a time
+    transport.poll().unwrap();
+    assert_eq!(transport.port().input.len(), 10 * request.len() - 1024);
+
+    let mut received = 0;
+    while let Some(frame) = transport.receive().unwrap() {
+        assert_eq!(frame, &request[..]);
+        received += 1;
+    }
+    assert_eq!(received, 10);
+    assert_eq!(transport.stats().discarded_bytes, 0);
+}
+
+#[test]
+fn test_send_throttles_when_port_is_busy() {
+    let (mut transport, _) = transport();
+    transport.port_mut().write_limit = Some(0);
+    let reply = frame(&uart_write(240));
+
+    // Four frames fit in the TX ring; the fifth has to wait
+    for _ in 0..4 {
+        transport.send(&reply).unwrap();
+    }
+    assert_eq!(transport.send(&reply), Err(TransportError::BufferFull));
+    assert_eq!(transport.pending_tx(), 4 * reply.len());
+
+    // Once the host reads again, queued frames go out whole and in order
+    transport.port_mut().write_limit = Some(100);
+    transport.poll().unwrap();
+    transport.port_mut().write_limit = None;
+    transport.send(&reply).unwrap();
+    assert_eq!(transport.pending_tx(), 0);
+    assert_eq!(transport.port().output, reply.repeat(5));
+}
+
+#[test]
+fn test_frame_timeout_uses_clock() {
+    let (transport, now) = transport();
+    let mut transport = transport.with_frame_timeout(50);
+    let request = frame(&Message::GetMode);
+
+    transport.port_mut().input.extend(&request[..3]);
+    assert_eq!(transport.receive().unwrap(), None);
+    now.set(60);
+    assert_eq!(transport.receive().unwrap(), None);
+    assert_eq!(transport.stats().timeouts, 1);
+
+    transport.port_mut().input.extend(&request);
+    assert_eq!(transport.receive().unwrap(), Some(&request[..]));
+}
+
+// ===== embedded-io Ports =====
+
+#[test]
+fn test_io_port_never_blocks() {
+    let mut port = IoPort::new(MockUart {
+        tx_capacity: 4,
+        ..Default::default()
+    });
+    let mut buf = [0; 8];
+    assert!(port.is_connected());
+    assert_eq!(port.read(&mut buf), Ok(0));
+
+    assert_eq!(port.write(&[1, 2, 3, 4, 5, 6]), Ok(4));
+    assert_eq!(port.write(&[5, 6]), Ok(0));
+    port.inner_mut().tx_fifo.clear();
+    assert_eq!(port.write(&[5, 6]), Ok(2));
+}
+
+#[test]
+fn test_transport_over_uart() {
+    let uart = MockUart {
+        tx_capacity: 16,
+        ..Default::default()
+    };
+    let clock: Box<dyn Fn() -> u64> = Box::new(|| 0);
+    let mut transport: StreamTransport<_, _, 1024, 1024> = StreamTransport::new(IoPort::new(uart), clock);
+
+    let request = frame(&Message::GetMode);
+    transport.port_mut().inner_mut().rx_fifo.extend(&request);
+    assert_eq!(transport.receive().unwrap(), Some(&request[..]));
+
+    // The reply trickles out as the FIFO drains
+    let reply = frame(&uart_write(40));
+    transport.send(&reply).unwrap();
+    let mut sent = Vec::new();
+    while sent.len() < reply.len() {
+        sent.append(&mut transport.port_mut().inner_mut().tx_fifo);
+        transport.poll().unwrap();
+    }
+    assert_eq!(sent, reply);
+}
+
+// ===== Console =====
+
+#[test]
+fn test_console_routes_typed_text_to_the_shell() {
+    let (mut transport, _) = console();
+    transport.port_mut().inner_mut().input.extend(b"m 2\r");
+
+    assert_eq!(transport.receive().unwrap(), None);
+    assert_eq!(transport.port().mode(), ConsoleMode::Text);
+    let mut text = [0u8; 16];
+    let n = transport.port_mut().read_text(&mut text);
+    assert_eq!(&text[..n], b"m 2\r");
+
+    // Shell output goes out on the next poll
+    transport.port_mut().write_str("I2C> ").unwrap();
+    transport.poll().unwrap();
+    assert_eq!(transport.port().inner().output, b"I2C> ");
+}
+
+#[test]
+fn test_console_passes_frames_to_the_transport() {
+    let (mut transport, _) = console();
+    let request = frame(&uart_write(8));
+    transport.port_mut().inner_mut().input.extend(&request);
+
+    assert_eq!(transport.receive().unwrap(), Some(&request[..]));
+    assert_eq!(transport.port().mode(), ConsoleMode::Binary);
+    assert_eq!(transport.port_mut().read_text(&mut [0u8; 16]), 0);
+}
+
+#[test]
+fn test_console_detects_again_after_binary_idle_and_disconnect() {
+    let (mut transport, now) = console();
+    let request = frame(&Message::GetMode);
+    transport.port_mut().inner_mut().input.extend(&request);
+    assert_eq!(transport.receive().unwrap(), Some(&request[..]));
+
+    // Still a protocol client shortly after, so text is passed through
+    now.set(1_000);
+    transport.port_mut().inner_mut().input.extend(b"i\r");
+    assert_eq!(transport.receive().unwrap(), None);
+    assert_eq!(transport.port().mode(), ConsoleMode::Binary);
+
+    // After a quiet spell a terminal can take over
+    now.set(1_000 + BINARY_IDLE_MS);
+    transport.port_mut().inner_mut().input.extend(b"i\r");
+    assert_eq!(transport.receive().unwrap(), None);
+    assert_eq!(transport.port().mode(), ConsoleMode::Text);
+
+    // A frame start switches back at once
+    transport.port_mut().inner_mut().input.extend(&request);
+    assert_eq!(transport.receive().unwrap(), Some(&request[..]));
+    assert_eq!(transport.port().mode(), ConsoleMode::Binary);
+
+    transport.port_mut().inner_mut().connected = false;
+    transport.poll().unwrap();
+    assert_eq!(transport.port().mode(), ConsoleMode::Detect);
+}
+
+#[test]
+fn test_console_holds_off_input_while_text_ring_is_full() {
+    let (mut transport, _) = console();
+    transport.port_mut().inner_mut().input.extend(vec![b'r'; TEXT_RX_SIZE + 10]);
+
+    transport.poll().unwrap();
+    assert_eq!(transport.port().inner().input.len(), 10);
+
+    let mut text = vec![0u8; TEXT_RX_SIZE];
+    assert_eq!(transport.port_mut().read_text(&mut text), TEXT_RX_SIZE);
+    transport.poll().unwrap();
+    assert!(transport.port().inner().input.is_empty());
+    assert_eq!(transport.port_mut().read_text(&mut text), 10);
+}
+
+#[test]
+fn test_console_enters_bitbang_after_twenty_nulls() {
+    let (mut transport, _) = console();
+
+    // Nineteen are not enough
+    transport.port_mut().inner_mut().input.extend([0u8; 19]);
+    transport.port_mut().inner_mut().input.push_back(b'\r');
+    assert_eq!(transport.receive().unwrap(), None);
+    assert_eq!(transport.port().mode(), ConsoleMode::Text);
+    transport.port_mut().read_text(&mut [0u8; 32]);
+
+    let nulls = usize::from(BITBANG_ENTRY_NULLS);
+    transport.port_mut().inner_mut().input.extend(vec![0u8; nulls]);
+    // Frame bytes are just data to the bitbang front-end
+    transport.port_mut().inner_mut().input.extend(frame(&Message::GetMode));
+    assert_eq!(transport.receive().unwrap(), None);
+    assert_eq!(transport.port().mode(), ConsoleMode::Bitbang);
+
+    let mut bytes = [0u8; 64];
+    let n = transport.port_mut().read_text(&mut bytes);
+    assert_eq!(bytes[0], 0x00);
+    assert_eq!(&bytes[1..n], &frame(&Message::GetMode)[..]);
+
+    transport.port_mut().leave_bitbang();
+    assert_eq!(transport.port().mode(), ConsoleMode::Detect);
+}
+
+#[test]
+fn test_console_bitbang_output_waits_for_the_host() {
+    let (mut transport, _) = console();
+    let reply = vec![0x5A; 3000];
+
+    embedded_io::Write::write_all(transport.port_mut(), &reply).unwrap();
+    embedded_io::Write::flush(transport.port_mut()).unwrap();
+    assert_eq!(transport.port().inner().output, reply);
+
+    transport.port_mut().inner_mut().connected = false;
+    transport.port_mut().inner_mut().write_limit = Some(0);
+    assert_eq!(
+        embedded_io::Write::write_all(transport.port_mut(), &reply),
+        Err(TransportError::Disconnected)
+    );
+}
@@ -5,39 +5,181 @@
 //!
 //! This is the main firmware for the ESP32 Bus Pirate running on the
 //! Waveshare ESP32-S3-Touch-LCD-2.8 board.
+//!
+//! The main loop hands every frame from the host to a [`SessionManager`],
+//! which runs it on the [`Dispatcher`] owning the I2C, SPI and UART modes,
+//! and forwards events to the hosts that subscribed to them. A terminal on
+//! the USB port gets the text [`Shell`] instead, and tools written for the
+//! original Bus Pirate get its binary [`Bitbang`] mode, all sharing the same
+//! buses and bus lease. The [`BoardPlatform`] gives the dispatcher delays and
+//! the I2C target, which takes turns with the I2C mode on its pins.
 
+mod platform;
 mod transport;
 
 use esp_backtrace as _;
 use esp_hal as _;
 use esp_println::println;
 
-use esp32_bus_pirate_hal::WaveshareS3Board;
+use embedded_hal::{i2c::I2c, spi::SpiDevice};
+use embedded_io::{Read, ReadReady, Write};
+use esp32_bus_pirate_bus_modes::{i2c::I2cFault, Error};
+use esp32_bus_pirate_core::{
+    bitbang::Control, transport::ConsoleMode, Bitbang, Dispatcher, NoStorage, Platform, SessionId, SessionManager,
+    Shell, ShellContext, Storage,
+};
+use esp32_bus_pirate_hal::{
+    peripherals::{
+        i2c::{I2cBus, I2cConfig},
+        i2c_target::I2cTargetBus,
+        spi::SpiDeviceWithCs,
+    },
+    pins, WaveshareS3Board,
+};
+use esp32_bus_pirate_protocol::MessageCodec;
+
+use platform::BoardPlatform;
+use transport::{now_ms, Transport, TransportError, UsbCdcTransport};
+
+/// Transport index of the USB port in session IDs
+const USB: u8 = 0;
+
+/// How long a reply may wait for room in a full TX ring before it is dropped
+const REPLY_TIMEOUT_MS: u64 = 250;
 
 #[esp_hal::main]
 fn main() -> ! {
     println!("ESP32 Bus Pirate - Rust Edition");
     println!("================================");
-    
+
     // Initialize the board
     println!("Initializing board...");
     let board = WaveshareS3Board::new();
-    
+
     println!("Board initialized successfully!");
     println!("Display: ST7789 240x320");
     println!("Touch: CST328");
     println!("Target: Waveshare ESP32-S3-Touch-LCD-2.8");
-    
+
     // TODO: Initialize display
     // TODO: Initialize touch controller
-    // TODO: Initialize USB CDC transport (blocked on Task #1)
-    // TODO: Set up protocol handler
-    // TODO: Enter main application loop
-    
+
+    let bus_i2c = I2cBus::new(board.bus_i2c, I2cConfig::default()).expect("Bus I2C configuration failed");
+    // SAFETY: the board routed `bus_i2c` to exactly these pins
+    let bus_i2c = unsafe { bus_i2c.with_pins(pins::bus::I2C_SCL, pins::bus::I2C_SDA) };
+    let bus_spi = SpiDeviceWithCs::new(board.sdcard_spi, board.bus_spi_cs);
+    // SAFETY: I2C1 is driven by `bus_i2c`, which the dispatcher leaves idle
+    // while a target runs and reconfigures once it stops
+    let i2c_target = unsafe { I2cTargetBus::new() };
+    let mut dispatcher = Dispatcher::new(bus_i2c, bus_spi, board.bus_uart, NoStorage)
+        .with_platform(BoardPlatform::new(i2c_target))
+        .with_i2c_recovery(|bus| bus.recover().map_err(|_| Error::InvalidConfig))
+        .with_i2c_frequency(|bus, hz| bus.set_frequency(hz).map_err(|_| Error::InvalidConfig))
+        .without_i2c_quick_probe()
+        .with_spi_chip_select(|spi, held| {
+            spi.hold_chip_select(held);
+            Ok(())
+        })
+        .with_board("Waveshare ESP32-S3-Touch-LCD-2.8");
+    let mut sessions: SessionManager = SessionManager::new();
+    let mut usb = UsbCdcTransport::new(board.usb);
+    let mut shell = Shell::new();
+    let mut bitbang = Bitbang::new();
+    let mut delay = board.delay;
+
     println!("\nEntering main loop...");
-    
+
+    loop {
+        serve(&mut usb, USB, &mut sessions, &mut dispatcher);
+
+        let mut typed = [0u8; 64];
+        let n = usb.console_mut().read_text(&mut typed);
+        if let Some(client) = usb.current_client() {
+            let mut ctx = ShellContext {
+                dispatcher: &mut dispatcher,
+                sessions: &mut sessions,
+                id: SessionId::new(USB, client),
+                delay: &mut delay,
+            };
+            if usb.console_mut().mode() == ConsoleMode::Bitbang {
+                match bitbang.input(&typed[..n], &mut ctx, usb.console_mut()) {
+                    Ok(Control::Continue) => {
+                        let _ = bitbang.poll(ctx.dispatcher, usb.console_mut());
+                    }
+                    // An error means the tool has gone
+                    Ok(Control::Exit) | Err(_) => usb.console_mut().leave_bitbang(),
+                }
+            } else if n > 0 {
+                shell.input(&typed[..n], &mut ctx, usb.console_mut());
+            }
+        }
+        if usb.console_mut().mode() != ConsoleMode::Bitbang {
+            bitbang.reset();
+        }
+
+        while let Some(event) = dispatcher.poll_event() {
+            for (id, frame) in sessions.event_frames(&event) {
+                if id.transport == USB {
+                    // Events are best effort; a full TX ring drops them
+                    let _ = usb.send(&frame);
+                }
+            }
+        }
+        let _ = usb.poll();
+    }
+}
+
+/// Answer every frame waiting on `link`, then end the sessions of clients
+/// that have left it
+fn serve<T, I, S, U, F, P>(
+    link: &mut T,
+    transport: u8,
+    sessions: &mut SessionManager,
+    dispatcher: &mut Dispatcher<I, S, U, F, P>,
+)
+where
+    T: Transport,
+    I: I2c<Error: I2cFault>,
+    S: SpiDevice,
+    U: Read + ReadReady + Write,
+    F: Storage,
+    P: Platform,
+{
+    loop {
+        // Malformed frames are dropped; the host times out and retries
+        let frame = match link.receive() {
+            Ok(Some(bytes)) => MessageCodec::decode_frame(bytes).ok(),
+            Ok(None) | Err(_) => break,
+        };
+        let (Some(frame), Some(client)) = (frame, link.current_client()) else {
+            continue;
+        };
+
+        // A frame repeating one of the client's recent sequence IDs is
+        // dropped unexecuted; the host already has (or will retry for) its reply
+        let id = SessionId::new(transport, client);
+        let Ok(reply) = sessions.handle_frame(dispatcher, id, &frame) else {
+            continue;
+        };
+        if !send_reply(link, &reply) {
+            break;
+        }
+    }
+    sessions.prune(dispatcher, transport, link);
+}
+
+/// Queue `reply` on `link`, waiting up to [`REPLY_TIMEOUT_MS`] for a host
+/// that is slow to read; false if the client has gone
+fn send_reply<T: Transport>(link: &mut T, reply: &[u8]) -> bool {
+    let deadline = now_ms() + REPLY_TIMEOUT_MS;
     loop {
-        // Main application loop placeholder
-        // This will be replaced with proper event handling
+        // Every attempt polls the port, so the TX ring drains as the host reads
+        match link.send(reply) {
+            Err(TransportError::BufferFull) if now_ms() < deadline => {}
+            Err(TransportError::Disconnected) => return false,
+            // Sent, or dropped for a host that stopped reading; it times out
+            // and retries, or is found gone
+            _ => return true,
+        }
     }
 }
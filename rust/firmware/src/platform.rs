@@ -0,0 +1,47 @@
+//! The board's [`Platform`]: delays for scripts and the I2C target
+//!
+//! The target is I2C1 in target role, time-shared with the I2C mode's
+//! controller on the bus header pins; see
+//! [`i2c_target`](esp32_bus_pirate_hal::peripherals::i2c_target). Script
+//! GPIO is not wired up yet, so pin operations fail with `NotConfigured`.
+
+use esp_hal::delay::Delay;
+use esp32_bus_pirate_bus_modes::i2c_target::TargetBus;
+use esp32_bus_pirate_core::Platform;
+use esp32_bus_pirate_hal::peripherals::i2c_target::I2cTargetBus;
+use esp32_bus_pirate_protocol::ErrorCode;
+
+/// Delays and the I2C target peripheral for the dispatcher
+pub struct BoardPlatform {
+    delay: Delay,
+    target: I2cTargetBus,
+}
+
+impl BoardPlatform {
+    /// Platform serving I2C target mode on `target`
+    pub fn new(target: I2cTargetBus) -> Self {
+        Self {
+            delay: Delay::new(),
+            target,
+        }
+    }
+}
+
+impl Platform for BoardPlatform {
+    fn set_pin(&mut self, _pin: u8, _high: bool) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NotConfigured)
+    }
+
+    fn pin(&mut self, _pin: u8) -> Result<bool, ErrorCode> {
+        Err(ErrorCode::NotConfigured)
+    }
+
+    fn delay_us(&mut self, us: u32) -> Result<(), ErrorCode> {
+        self.delay.delay_micros(us);
+        Ok(())
+    }
+
+    fn i2c_target(&mut self) -> Option<&mut dyn TargetBus> {
+        Some(&mut self.target)
+    }
+}
@@ -2,33 +2,24 @@
 //!
 //! This module provides transport implementations for the protocol,
 //! allowing communication over different physical interfaces.
+//!
+//! The buffering and framing logic lives in
+//! [`esp32_bus_pirate_core::transport`], where it is unit-tested on the
+//! host; each transport here only adapts an esp-hal peripheral to
+//! [`SerialPort`](esp32_bus_pirate_core::transport::SerialPort).
+//!
+//! The network transports, [`TcpTransport`] and [`WebSocketTransport`],
+//! only need a [`Listener`] whose connections are `SerialPort`s; the Wi-Fi
+//! stack's sockets will provide it once Wi-Fi is brought up.
 
+pub mod uart;
 pub mod usb_cdc;
 
+pub use esp32_bus_pirate_core::transport::{Listener, TcpTransport, Transport, TransportError, WebSocketTransport};
+pub use uart::UartTransport;
 pub use usb_cdc::UsbCdcTransport;
 
-/// Transport trait for sending and receiving protocol messages
-pub trait Transport {
-    /// Send a message frame
-    fn send(&mut self, frame: &[u8]) -> Result<(), TransportError>;
-    
-    /// Receive a message frame
-    /// Returns None if no complete frame is available
-    fn receive(&mut self) -> Result<Option<&[u8]>, TransportError>;
-    
-    /// Check if the transport is connected
-    fn is_connected(&self) -> bool;
-}
-
-/// Transport error types
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum TransportError {
-    /// Buffer overflow
-    BufferFull,
-    /// Transport disconnected
-    Disconnected,
-    /// I/O error
-    IoError,
-    /// Timeout
-    Timeout,
+/// Milliseconds since boot, for transport timeouts
+pub fn now_ms() -> u64 {
+    esp_hal::time::Instant::now().duration_since_epoch().as_millis()
 }
@@ -0,0 +1,110 @@
+//! UART transport implementation
+//!
+//! Carries the framed protocol over a hardware UART, for boards without
+//! native USB or when the Bus Pirate is wired to another MCU. Framing and
+//! buffering are shared with the USB transport through
+//! [`StreamTransport`]; this module only adapts a UART.
+//!
+//! Baud rate, parity and RTS/CTS flow control come from the
+//! [`UartConfig`](esp32_bus_pirate_hal::peripherals::uart::UartConfig) the
+//! bus was created with. With RTS/CTS, backpressure reaches the other side:
+//! while the RX ring is full the UART's FIFO fills up and RTS is released.
+//!
+//! # Example Usage
+//!
+//! ```rust,ignore
+//! use esp32_bus_pirate_hal::peripherals::uart::{FlowControl, UartBus1, UartConfig};
+//! use esp_hal::uart::Uart;
+//!
+//! let config = UartConfig::new(921_600).with_flow_control(FlowControl::RtsCts);
+//! let uart = Uart::new(peripherals.UART1, config.to_esp_config())?
+//!     .with_tx(peripherals.GPIO17)
+//!     .with_rx(peripherals.GPIO18)
+//!     .with_rts(peripherals.GPIO15)
+//!     .with_cts(peripherals.GPIO16);
+//! let mut transport = UartTransport::new(UartBus1::new(uart, config));
+//! ```
+
+use super::{now_ms, Transport, TransportError};
+use esp32_bus_pirate_core::transport::{frame_timeout_for_baud, ClientId, DecoderStats, IoPort, StreamTransport};
+use esp32_bus_pirate_hal::peripherals::uart::{UartBus0, UartBus1, UartConfig};
+use embedded_io::{Read, ReadReady, Write, WriteReady};
+
+/// RX ring size
+pub const RX_BUFFER_SIZE: usize = 1024;
+
+/// TX ring size
+pub const TX_BUFFER_SIZE: usize = 1024;
+
+/// A UART the transport can run on
+pub trait UartBus: Read + ReadReady + Write + WriteReady {
+    /// The configuration the bus was created with
+    fn uart_config(&self) -> &UartConfig;
+}
+
+impl UartBus for UartBus0<'_> {
+    fn uart_config(&self) -> &UartConfig {
+        self.config()
+    }
+}
+
+impl UartBus for UartBus1<'_> {
+    fn uart_config(&self) -> &UartConfig {
+        self.config()
+    }
+}
+
+/// Framed protocol transport over `UartBus0` or `UartBus1`
+pub struct UartTransport<U> {
+    inner: StreamTransport<IoPort<U>, fn() -> u64, RX_BUFFER_SIZE, TX_BUFFER_SIZE>,
+}
+
+impl<U: UartBus> UartTransport<U> {
+    /// Create a transport on `uart`
+    ///
+    /// The incomplete-frame timeout is scaled to the configured baud rate.
+    pub fn new(uart: U) -> Self {
+        let timeout = frame_timeout_for_baud(uart.uart_config().baudrate);
+        Self {
+            inner: StreamTransport::new(IoPort::new(uart), now_ms as fn() -> u64).with_frame_timeout(timeout),
+        }
+    }
+
+    /// Keep queued output moving while no frames are being exchanged
+    pub fn poll(&mut self) -> Result<(), TransportError> {
+        self.inner.poll()
+    }
+
+    /// Link statistics
+    pub fn stats(&self) -> &DecoderStats {
+        self.inner.stats()
+    }
+
+    /// The UART's configuration
+    pub fn config(&self) -> &UartConfig {
+        self.inner.port().inner().uart_config()
+    }
+
+    /// Release the UART
+    pub fn into_inner(self) -> U {
+        self.inner.into_port().into_inner()
+    }
+}
+
+impl<U: UartBus> Transport for UartTransport<U> {
+    fn send(&mut self, frame: &[u8]) -> Result<(), TransportError> {
+        self.inner.send(frame)
+    }
+
+    fn receive(&mut self) -> Result<Option<&[u8]>, TransportError> {
+        self.inner.receive()
+    }
+
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    fn current_client(&self) -> Option<ClientId> {
+        self.inner.current_client()
+    }
+}
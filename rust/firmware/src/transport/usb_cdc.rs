@@ -1,37 +1,16 @@
 //! USB CDC (Communication Device Class) transport implementation
 //!
-//! This module implements USB serial communication for the Bus Pirate protocol.
-//! It provides frame buffering, flow control, and connection management.
+//! This module implements USB serial communication for the Bus Pirate protocol
+//! over the ESP32-S3's native USB port, using its USB-Serial-JTAG controller.
+//! The host sees a standard CDC-ACM serial port (Espressif VID 0x303A,
+//! PID 0x1001); the controller handles enumeration and descriptors in
+//! hardware.
 //!
-//! # Implementation Status
-//!
-//! ⚠️ **BLOCKED**: Waiting for Task #1 (HAL Implementation) to complete USB peripheral support.
-//!
-//! # Requirements
-//!
-//! ## Hardware
-//! - ESP32-S3 USB OTG peripheral
-//! - Native USB port (not UART-USB bridge)
-//!
-//! ## Buffer Configuration
+//! # Buffer Configuration
 //! - RX circular buffer: 1KB
 //! - TX circular buffer: 1KB
 //! - Maximum frame size: 1KB (MAX_MESSAGE_SIZE from protocol)
 //!
-//! ## USB Descriptors
-//! - Device Class: CDC (0x02)
-//! - Vendor ID: TBD
-//! - Product ID: TBD
-//! - Product String: "ESP32 Bus Pirate"
-//! - Manufacturer: "ESP32 Bus Pirate Contributors"
-//!
-//! ## Features Required from HAL
-//! 1. USB peripheral initialization
-//! 2. USB CDC class implementation
-//! 3. Connection/disconnection event handling
-//! 4. Bulk IN/OUT endpoints
-//! 5. Control endpoint for CDC management
-//!
 //! # Architecture
 //!
 //! ```text
@@ -43,7 +22,7 @@
 //!                          │ MessageCodec::encode/decode
 //!                          ▼
 //! ┌──────────────────────────────────────────────────────────┐
-//! │                  USB CDC Transport                        │
+//! │        StreamTransport (esp32-bus-pirate-core)            │
 //! │                                                           │
 //! │  ┌─────────────────┐         ┌─────────────────┐        │
 //! │  │   RX Buffer     │         │   TX Buffer     │        │
@@ -57,139 +36,195 @@
 //!             │                           │
 //!             ▼                           ▼
 //! ┌──────────────────────────────────────────────────────────┐
-//! │                   USB HAL Layer                           │
-//! │         (esp-hal USB peripheral driver)                   │
+//! │        ConsolePort (esp32-bus-pirate-core)                │
+//! │        (terminal text to the shell, frames through)       │
+//! └────────────────────────────┬─────────────────────────────┘
+//!                              │
+//!                              ▼
+//! ┌──────────────────────────────────────────────────────────┐
+//! │                 UsbSerialPort (this module)               │
+//! │           (esp-hal USB-Serial-JTAG FIFOs)                 │
 //! └──────────────────────────────────────────────────────────┘
 //! ```
 //!
-//! # Frame Detection
+//! Frame detection, incomplete-frame timeouts, backpressure and TX
+//! throttling are described on
+//! [`StreamTransport`](esp32_bus_pirate_core::transport::StreamTransport).
 //!
-//! Incoming bytes are buffered and scanned for complete frames:
-//! 1. Wait for START_BYTE (0xAA)
-//! 2. Read VERSION and LENGTH
-//! 3. Read PAYLOAD + CRC + END_BYTE
-//! 4. Validate frame markers and CRC
-//! 5. Deliver complete frame to application
+//! # Text Shell
 //!
-//! # Flow Control
-//!
-//! - Backpressure: Stop accepting USB data when RX buffer is nearly full
-//! - TX throttling: Yield if TX buffer cannot accept a full frame
-//! - Timeout: Discard incomplete frames after timeout
+//! A terminal can use the same port: [`console_mut`](UsbCdcTransport::console_mut)
+//! gives the typed text to the [`Shell`](esp32_bus_pirate_core::Shell) and
+//! takes its output, and likewise for tools that put the port into the
+//! original Bus Pirate's [`Bitbang`](esp32_bus_pirate_core::Bitbang) mode.
+//! See [`ConsolePort`](esp32_bus_pirate_core::transport::ConsolePort) for
+//! how they are told apart from frames.
 //!
 //! # Connection Management
 //!
-//! - Detect USB connect/disconnect events from HAL
-//! - Clear buffers on disconnect
-//! - Signal connection state to application
+//! The USB-Serial-JTAG controller does not report when a terminal opens or
+//! closes the port. A host counts as connected once it sends data, and as
+//! gone once it stops draining the TX FIFO for [`HOST_GONE_MS`]; either
+//! change clears the buffers.
 //!
-//! # Example Usage (when implemented)
+//! # Example Usage
 //!
 //! ```rust,ignore
-//! use firmware::transport::{Transport, UsbCdcTransport};
-//! use esp32_bus_pirate_protocol::codec::MessageCodec;
+//! use firmware::transport::{now_ms, Transport, TransportError, UsbCdcTransport};
+//! use esp_hal::usb_serial_jtag::UsbSerialJtag;
 //!
-//! // Initialize USB transport
-//! let mut transport = UsbCdcTransport::new(usb_peripheral);
+//! let mut transport = UsbCdcTransport::new(UsbSerialJtag::new(peripherals.USB_DEVICE));
 //!
-//! // Main loop
 //! loop {
-//!     // Check for incoming messages
 //!     if let Ok(Some(frame)) = transport.receive() {
-//!         // Decode and handle message
-//!         if let Ok(msg) = MessageCodec::decode(frame) {
-//!             let response = handle_message(msg);
-//!             let response_frame = MessageCodec::encode(&response).unwrap();
-//!             transport.send(&response_frame).ok();
-//!         }
+//!         let reply = dispatcher.handle_frame(&MessageCodec::decode_frame(frame)?)?;
+//!         // Each send polls the port; a full TX ring means the host is slow
+//!         // to read, so retry, but only for so long
+//!         let deadline = now_ms() + 250;
+//!         while transport.send(&reply) == Err(TransportError::BufferFull) && now_ms() < deadline {}
 //!     }
 //! }
 //! ```
-//!
-//! # Testing Strategy
-//!
-//! 1. Unit tests for frame assembly and buffering logic (can be tested without hardware)
-//! 2. Mock USB peripheral for integration tests
-//! 3. Hardware tests with Python test client (tools/test_client.py)
-//! 4. Stress testing with rapid message bursts
-//! 5. Connection/disconnection handling tests
-//!
-//! # TODO (Phase B - after Task #1)
-//!
-//! - [ ] Implement RX circular buffer with frame assembly
-//! - [ ] Implement TX circular buffer with queueing
-//! - [ ] Integrate with esp-hal USB peripheral
-//! - [ ] Add USB descriptors
-//! - [ ] Implement connection event handling
-//! - [ ] Add flow control and backpressure
-//! - [ ] Write unit tests
-//! - [ ] Test with Python client on hardware
-
-use crate::transport::{Transport, TransportError};
-
-/// USB CDC transport implementation
-///
-/// ⚠️ Placeholder: Requires HAL USB support from Task #1
-pub struct UsbCdcTransport {
-    // TODO: Add fields after HAL USB is available
-    // usb_peripheral: UsbOtg,
-    // rx_buffer: RingBuffer<u8, 1024>,
-    // tx_buffer: RingBuffer<u8, 1024>,
-    // connected: bool,
+
+use super::{now_ms, Transport, TransportError};
+use esp32_bus_pirate_core::transport::{ClientId, ConsolePort, DecoderStats, SerialPort, StreamTransport};
+use esp_hal::{usb_serial_jtag::UsbSerialJtag, Blocking};
+
+/// RX ring size
+pub const RX_BUFFER_SIZE: usize = 1024;
+
+/// TX ring size
+pub const TX_BUFFER_SIZE: usize = 1024;
+
+/// Time the host may leave the TX FIFO full before it counts as gone
+pub const HOST_GONE_MS: u64 = 200;
+
+/// USB-Serial-JTAG controller as a non-blocking [`SerialPort`]
+pub struct UsbSerialPort<'d> {
+    usb: UsbSerialJtag<'d, Blocking>,
+    connected: bool,
+    /// Byte read while checking for a host, returned by the next read
+    pending: Option<u8>,
+    /// When the TX FIFO stopped accepting data
+    stalled_since: Option<u64>,
+}
+
+impl<'d> UsbSerialPort<'d> {
+    /// Wrap the USB-Serial-JTAG driver
+    pub fn new(usb: UsbSerialJtag<'d, Blocking>) -> Self {
+        Self {
+            usb,
+            connected: false,
+            pending: None,
+            stalled_since: None,
+        }
+    }
 }
 
-impl UsbCdcTransport {
+impl SerialPort for UsbSerialPort<'_> {
+    fn is_connected(&mut self) -> bool {
+        if !self.connected {
+            if let Ok(byte) = self.usb.read_byte() {
+                self.pending = Some(byte);
+                self.connected = true;
+                self.stalled_since = None;
+            }
+        }
+        self.connected
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, TransportError> {
+        let mut n = 0;
+        if let (Some(byte), Some(slot)) = (self.pending, buf.first_mut()) {
+            *slot = byte;
+            self.pending = None;
+            n = 1;
+        }
+        while n < buf.len() {
+            match self.usb.read_byte() {
+                Ok(byte) => {
+                    buf[n] = byte;
+                    n += 1;
+                }
+                Err(_) => break,
+            }
+        }
+        Ok(n)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<usize, TransportError> {
+        let mut n = 0;
+        for &byte in data {
+            if self.usb.write_byte_nb(byte).is_err() {
+                break;
+            }
+            n += 1;
+        }
+        // Send a partly filled packet now rather than when the FIFO fills
+        let _ = self.usb.flush_tx_nb();
+
+        if n > 0 || data.is_empty() {
+            self.stalled_since = None;
+        } else {
+            let since = *self.stalled_since.get_or_insert_with(now_ms);
+            if now_ms().saturating_sub(since) >= HOST_GONE_MS {
+                self.connected = false;
+                self.stalled_since = None;
+            }
+        }
+        Ok(n)
+    }
+}
+
+/// Console over the native USB port
+pub type UsbConsole<'d> = ConsolePort<UsbSerialPort<'d>, fn() -> u64>;
+
+/// Framed protocol transport over the native USB port
+pub struct UsbCdcTransport<'d> {
+    inner: StreamTransport<UsbConsole<'d>, fn() -> u64, RX_BUFFER_SIZE, TX_BUFFER_SIZE>,
+}
+
+impl<'d> UsbCdcTransport<'d> {
     /// Create a new USB CDC transport
-    ///
-    /// ⚠️ Not yet implemented - waiting on HAL
-    #[allow(dead_code)]
-    pub fn new(/* usb_peripheral: UsbOtg */) -> Self {
+    pub fn new(usb: UsbSerialJtag<'d, Blocking>) -> Self {
         Self {
-            // Placeholder
+            inner: StreamTransport::new(
+                ConsolePort::new(UsbSerialPort::new(usb), now_ms as fn() -> u64),
+                now_ms as fn() -> u64,
+            ),
         }
     }
+
+    /// Keep queued output moving while no frames are being exchanged
+    pub fn poll(&mut self) -> Result<(), TransportError> {
+        self.inner.poll()
+    }
+
+    /// Link statistics
+    pub fn stats(&self) -> &DecoderStats {
+        self.inner.stats()
+    }
+
+    /// The console, for the text shell's input and output
+    pub fn console_mut(&mut self) -> &mut UsbConsole<'d> {
+        self.inner.port_mut()
+    }
 }
 
-impl Transport for UsbCdcTransport {
-    fn send(&mut self, _frame: &[u8]) -> Result<(), TransportError> {
-        // TODO: Implement after HAL USB is available
-        Err(TransportError::Disconnected)
+impl Transport for UsbCdcTransport<'_> {
+    fn send(&mut self, frame: &[u8]) -> Result<(), TransportError> {
+        self.inner.send(frame)
     }
-    
+
     fn receive(&mut self) -> Result<Option<&[u8]>, TransportError> {
-        // TODO: Implement after HAL USB is available
-        Ok(None)
+        self.inner.receive()
     }
-    
+
     fn is_connected(&self) -> bool {
-        // TODO: Implement after HAL USB is available
-        false
+        self.inner.is_connected()
     }
-}
 
-// Future implementation notes:
-//
-// The RX path should:
-// 1. Read bytes from USB into circular buffer
-// 2. Scan for START_BYTE
-// 3. Parse frame header to get length
-// 4. Wait for complete frame
-// 5. Validate CRC and markers
-// 6. Return slice from buffer
-//
-// The TX path should:
-// 1. Check if frame fits in buffer
-// 2. Copy frame to circular buffer
-// 3. Trigger USB transmission
-// 4. Handle completion
-//
-// Buffer management:
-// - Use heapless::spsc::Queue for lock-free circular buffers
-// - Or manual ring buffer with head/tail pointers
-// - Keep separate read/write buffers to avoid contention
-//
-// USB integration:
-// - Set up CDC descriptors
-// - Configure bulk endpoints
-// - Handle setup packets
-// - Implement flow control
+    fn current_client(&self) -> Option<ClientId> {
+        self.inner.current_client()
+    }
+}
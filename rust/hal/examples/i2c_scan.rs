@@ -22,6 +22,7 @@ use esp32_bus_pirate_hal::{
     WaveshareS3Board,
     peripherals::i2c::I2cExt,
 };
+use esp32_bus_pirate_bus_modes::i2c::I2cScanOptions;
 
 #[entry]
 fn main() -> ! {
@@ -53,7 +54,7 @@ fn main() -> ! {
         println!("=== Scan #{} ===", scan_count);
         
         // Scan the I2C bus
-        let devices = board.i2c0.scan();
+        let devices = board.i2c0.scan(&I2cScanOptions::default()).unwrap_or_default();
         
         if devices.is_empty() {
             println!("No I2C devices found!");
@@ -30,6 +30,8 @@ use esp_hal::{
     i2c::master::{Config as I2cConfig, I2c},
     peripherals::Peripherals,
     spi::master::{Config as SpiConfig, Spi},
+    uart::{Config as UartConfig, Uart},
+    usb_serial_jtag::UsbSerialJtag,
     Blocking,
 };
 use esp_hal::time::Rate;
@@ -67,6 +69,16 @@ pub struct WaveshareS3Board {
     pub sdcard_spi: Spi<'static, esp_hal::peripherals::SPI3, FullDuplexMode>,
     pub sdcard_cs: Output<'static>,    // SD card chip select
     
+    /// Native USB port, for the host protocol
+    pub usb: UsbSerialJtag<'static, Blocking>,
+    
+    /// Bus Pirate I2C mode bus (I2C1, see [`pins::bus`])
+    pub bus_i2c: I2c<'static, Blocking>,
+    /// Bus Pirate UART mode port (UART1)
+    pub bus_uart: Uart<'static, Blocking>,
+    /// Bus Pirate SPI mode chip select, on the SD card's SPI3 bus
+    pub bus_spi_cs: Output<'static>,
+    
     // Timer group for general timing operations
     // pub timer_group: TimerGroup<'static, esp_hal::peripherals::TIMG0>,
 }
@@ -79,6 +91,7 @@ impl WaveshareS3Board {
     /// - Initializes SPI2 @ 40MHz for display
     /// - Initializes SPI3 @ 20MHz for SD card
     /// - Initializes I2C0 @ 100kHz for touch/IMU/RTC
+    /// - Initializes USB, I2C1 and UART1 for the Bus Pirate modes
     /// - Configures all GPIO pins
     /// - Creates a delay provider
     ///
@@ -141,6 +154,21 @@ impl WaveshareS3Board {
             .with_mosi(sd_mosi)
             .with_miso(sd_miso);
         
+        // ===== USB and Bus Pirate Mode Peripherals =====
+        let usb = UsbSerialJtag::new(peripherals.USB_DEVICE);
+        
+        let bus_i2c = I2c::new(peripherals.I2C1, I2cConfig::default().with_frequency(Rate::from_khz(100)))
+            .expect("Bus I2C initialization failed")
+            .with_sda(peripherals.GPIO8)
+            .with_scl(peripherals.GPIO9);
+        
+        let bus_uart = Uart::new(peripherals.UART1, UartConfig::default().with_baudrate(115_200))
+            .expect("Bus UART initialization failed")
+            .with_tx(peripherals.GPIO10)
+            .with_rx(peripherals.GPIO11);
+        
+        let bus_spi_cs = Output::new(peripherals.GPIO12, Level::High, OutputConfig::default());
+        
         Self {
             delay,
             display_spi,
@@ -153,6 +181,10 @@ impl WaveshareS3Board {
             touch_rst,
             sdcard_spi,
             sdcard_cs,
+            usb,
+            bus_i2c,
+            bus_uart,
+            bus_spi_cs,
         }
     }
     
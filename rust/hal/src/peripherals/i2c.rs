@@ -13,16 +13,29 @@
 //!     .with_frequency(HertzU32::kHz(100));
 //! ```
 
-use esp_hal::i2c::I2C;
-use esp_hal::peripherals::I2C0;
-use embedded_hal::i2c::{Error as I2cError, ErrorKind, ErrorType, I2c, Operation, SevenBitAddress, TenBitAddress};
+use esp_hal::i2c::master::{
+    AcknowledgeCheckFailedReason, BusTimeout, Config as EspI2cConfig, ConfigError, Error as EspI2cError,
+    I2c as EspI2c,
+};
+use esp_hal::delay::Delay;
+use esp_hal::gpio::{AnyPin, DriveMode, Flex, Level, OutputConfig, Pull};
+use esp_hal::time::Rate;
+use esp_hal::Blocking;
+use embedded_hal::i2c::{
+    Error as I2cError, ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation, SevenBitAddress, TenBitAddress,
+};
+use esp32_bus_pirate_bus_modes::i2c::{
+    recover_bus, scan_bus, ten_bit_transaction, I2cFault, I2cLine, I2cLines, I2cRecoveryReport, I2cScanOptions,
+    I2cScanProbe, TenBitError,
+};
+use esp32_bus_pirate_bus_modes::Error;
 
 /// I2C configuration
 #[derive(Debug, Clone, Copy)]
 pub struct I2cConfig {
     /// I2C bus frequency in Hz (typically 100kHz for standard mode, 400kHz for fast mode)
     pub frequency: fugit::HertzU32,
-    /// Timeout for I2C operations in milliseconds
+    /// How long SCL may be held low before a transfer is abandoned, in milliseconds
     pub timeout_ms: u32,
 }
 
@@ -55,39 +68,81 @@ impl I2cConfig {
         self.timeout_ms = timeout_ms;
         self
     }
+
+    /// The timeout in SCL periods, the unit the peripheral counts in
+    pub fn timeout_cycles(&self) -> u32 {
+        let cycles = u64::from(self.timeout_ms) * u64::from(self.frequency.to_Hz()) / 1000;
+        cycles.clamp(1, u64::from(u32::MAX)) as u32
+    }
 }
 
 /// Custom I2C error type
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum I2cErrorWrapper {
-    /// Bus error (arbitration lost, bus collision, etc.)
+    /// Bus error: a misplaced START or STOP, or a transfer the peripheral
+    /// could not finish
     Bus,
-    /// No acknowledgment received
-    NoAcknowledge,
-    /// Operation timed out
+    /// Another controller won arbitration
+    ArbitrationLost,
+    /// The address or a data byte was not acknowledged
+    NoAcknowledge(NoAcknowledgeSource),
+    /// SCL was held low for longer than [`I2cConfig::timeout_ms`]
     Timeout,
-    /// Other hardware error
+    /// A request the peripheral cannot carry out, such as an oversized
+    /// transfer or an out of range address
     Other,
 }
 
+impl From<EspI2cError> for I2cErrorWrapper {
+    fn from(err: EspI2cError) -> Self {
+        match err {
+            EspI2cError::AcknowledgeCheckFailed(reason) => I2cErrorWrapper::NoAcknowledge(match reason {
+                AcknowledgeCheckFailedReason::Address => NoAcknowledgeSource::Address,
+                AcknowledgeCheckFailedReason::Data => NoAcknowledgeSource::Data,
+                _ => NoAcknowledgeSource::Unknown,
+            }),
+            EspI2cError::Timeout => I2cErrorWrapper::Timeout,
+            EspI2cError::ArbitrationLost => I2cErrorWrapper::ArbitrationLost,
+            EspI2cError::ExecutionIncomplete => I2cErrorWrapper::Bus,
+            _ => I2cErrorWrapper::Other,
+        }
+    }
+}
+
 impl I2cError for I2cErrorWrapper {
     fn kind(&self) -> ErrorKind {
         match self {
             I2cErrorWrapper::Bus => ErrorKind::Bus,
-            I2cErrorWrapper::NoAcknowledge => ErrorKind::NoAcknowledge(embedded_hal::i2c::NoAcknowledgeSource::Unknown),
+            I2cErrorWrapper::ArbitrationLost => ErrorKind::ArbitrationLoss,
+            I2cErrorWrapper::NoAcknowledge(source) => ErrorKind::NoAcknowledge(*source),
             I2cErrorWrapper::Timeout => ErrorKind::Other,
             I2cErrorWrapper::Other => ErrorKind::Other,
         }
     }
 }
 
+impl I2cFault for I2cErrorWrapper {
+    fn is_timeout(&self) -> bool {
+        matches!(self, I2cErrorWrapper::Timeout)
+    }
+}
+
 /// I2C peripheral wrapper
 ///
 /// This wrapper provides a safe interface to the ESP32-S3 I2C peripheral
 /// and implements the `embedded-hal` I2C traits.
 pub struct I2cBus<'d> {
-    i2c: I2C<'d, I2C0>,
+    /// Only `None` while [`reconnect`](Self::reconnect) moves the driver
+    i2c: Option<EspI2c<'d, Blocking>>,
     config: I2cConfig,
+    pins: Option<I2cPins>,
+}
+
+/// GPIO numbers of a bus's SCL and SDA pads
+#[derive(Debug, Clone, Copy)]
+struct I2cPins {
+    scl: u8,
+    sda: u8,
 }
 
 impl<'d> I2cBus<'d> {
@@ -96,9 +151,15 @@ impl<'d> I2cBus<'d> {
     /// # Arguments
     ///
     /// * `i2c` - The ESP-HAL I2C peripheral
-    /// * `config` - Configuration for the I2C bus
-    pub fn new(i2c: I2C<'d, I2C0>, config: I2cConfig) -> Self {
-        Self { i2c, config }
+    /// * `config` - Configuration for the I2C bus, applied to the peripheral
+    pub fn new(i2c: EspI2c<'d, Blocking>, config: I2cConfig) -> Result<Self, ConfigError> {
+        let mut bus = Self {
+            i2c: Some(i2c),
+            config,
+            pins: None,
+        };
+        bus.set_config(config)?;
+        Ok(bus)
     }
 
     /// Get the current configuration
@@ -106,9 +167,81 @@ impl<'d> I2cBus<'d> {
         &self.config
     }
 
+    /// Reprogram the peripheral with `config`
+    ///
+    /// The timeout is enforced by the peripheral, which counts how long SCL
+    /// stays low. Its counter is short, so a timeout longer than it can
+    /// count is clamped to the longest it supports. Any other error, such as
+    /// a frequency out of range, is returned as it is.
+    pub fn set_config(&mut self, config: I2cConfig) -> Result<(), ConfigError> {
+        let esp_config = EspI2cConfig::default().with_frequency(Rate::from_hz(config.frequency.to_Hz()));
+        let i2c = self.driver();
+        match i2c.apply_config(&esp_config.with_timeout(BusTimeout::BusCycles(config.timeout_cycles()))) {
+            Err(ConfigError::TimeoutTooLong) => i2c.apply_config(&esp_config.with_timeout(BusTimeout::Maximum))?,
+            result => result?,
+        }
+        self.config = config;
+        Ok(())
+    }
+
+    /// Change the SCL frequency, keeping the timeout
+    pub fn set_frequency(&mut self, hz: u32) -> Result<(), ConfigError> {
+        self.set_config(self.config.with_frequency(fugit::HertzU32::Hz(hz)))
+    }
+
+    /// Tell the bus which GPIOs the peripheral is routed to, so
+    /// [`recover`](Self::recover) can drive them directly
+    ///
+    /// # Safety
+    ///
+    /// `scl` and `sda` must be the pins the driver was given with
+    /// `with_scl` and `with_sda`. [`recover`](Self::recover) conjures
+    /// drivers for them, which is only sound because the driver owns them
+    /// and nothing else does.
+    pub unsafe fn with_pins(mut self, scl: u8, sda: u8) -> Self {
+        self.pins = Some(I2cPins { scl, sda });
+        self
+    }
+
+    /// Free the bus if a device is holding SDA low, then re-initialise the
+    /// peripheral
+    ///
+    /// See [`recover_bus`] for what is done and reported. Fails with
+    /// [`I2cErrorWrapper::Other`] unless the pins were given with
+    /// [`with_pins`](Self::with_pins).
+    pub fn recover(&mut self) -> Result<I2cRecoveryReport, I2cErrorWrapper> {
+        let pins = self.pins.ok_or(I2cErrorWrapper::Other)?;
+        let report = {
+            // SAFETY: `with_pins` promised these are the pins moved into the
+            // driver, so no other code owns them, and the driver does not
+            // use them until `reconnect` hands them back below
+            let mut pads = LinePads::new(unsafe { AnyPin::steal(pins.scl) }, unsafe { AnyPin::steal(pins.sda) });
+            recover_bus(&mut pads)
+        };
+        self.reconnect(pins);
+        self.set_config(self.config).map_err(|_| I2cErrorWrapper::Other)?;
+        Ok(report)
+    }
+
+    /// Route the pads back to the peripheral after they were used as GPIOs
+    fn reconnect(&mut self, pins: I2cPins) {
+        let Some(i2c) = self.i2c.take() else {
+            return;
+        };
+        // SAFETY: as in `recover`: the pins are the driver's own, and the
+        // GPIO drivers made from them there have been dropped
+        let (scl, sda) = unsafe { (AnyPin::steal(pins.scl), AnyPin::steal(pins.sda)) };
+        self.i2c = Some(i2c.with_scl(scl).with_sda(sda));
+    }
+
+    /// The esp-hal driver
+    fn driver(&mut self) -> &mut EspI2c<'d, Blocking> {
+        self.i2c.as_mut().expect("I2C driver is only moved out inside reconnect")
+    }
+
     /// Get a mutable reference to the underlying I2C peripheral
-    pub fn inner_mut(&mut self) -> &mut I2C<'d, I2C0> {
-        &mut self.i2c
+    pub fn inner_mut(&mut self) -> &mut EspI2c<'d, Blocking> {
+        self.driver()
     }
 }
 
@@ -122,44 +255,94 @@ impl<'d> I2c<SevenBitAddress> for I2cBus<'d> {
         address: SevenBitAddress,
         operations: &mut [Operation<'_>],
     ) -> Result<(), Self::Error> {
-        for op in operations {
-            match op {
-                Operation::Read(buf) => {
-                    self.i2c
-                        .read(address, buf)
-                        .map_err(|_| I2cErrorWrapper::Other)?;
-                }
-                Operation::Write(buf) => {
-                    self.i2c
-                        .write(address, buf)
-                        .map_err(|_| I2cErrorWrapper::Other)?;
-                }
-            }
-        }
-        Ok(())
+        // Let the peripheral run the whole list, so segments are joined by
+        // repeated starts rather than a stop and a new start each
+        self.driver().transaction(address, operations).map_err(I2cErrorWrapper::from)
     }
 }
 
 impl<'d> I2c<TenBitAddress> for I2cBus<'d> {
     fn transaction(
         &mut self,
-        _address: TenBitAddress,
-        _operations: &mut [Operation<'_>],
+        address: TenBitAddress,
+        operations: &mut [Operation<'_>],
     ) -> Result<(), Self::Error> {
-        // ESP32-S3 I2C hardware doesn't natively support 10-bit addressing
-        // It would need to be implemented in software by using the 10-bit
-        // addressing scheme manually. For now, we return an error.
-        Err(I2cErrorWrapper::Other)
+        // The peripheral only knows 7-bit addresses, so the address bytes
+        // are sent in software, as the I2C mode does
+        ten_bit_transaction::<Self>(self, address, operations).map_err(|e| match e {
+            TenBitError::Invalid => I2cErrorWrapper::Other,
+            TenBitError::Bus(e) => e,
+        })
+    }
+}
+
+/// SCL and SDA taken over as open-drain GPIOs
+struct LinePads<'a> {
+    scl: Flex<'a>,
+    sda: Flex<'a>,
+    delay: Delay,
+}
+
+impl<'a> LinePads<'a> {
+    fn new(scl: AnyPin<'a>, sda: AnyPin<'a>) -> Self {
+        let mut pads = Self {
+            scl: Flex::new(scl),
+            sda: Flex::new(sda),
+            delay: Delay::new(),
+        };
+        for pad in [&mut pads.scl, &mut pads.sda] {
+            pad.set_level(Level::High);
+            pad.apply_output_config(&open_drain(Pull::Up));
+            pad.set_input_enable(true);
+            pad.set_output_enable(true);
+        }
+        pads
+    }
+
+    fn pad(&mut self, line: I2cLine) -> &mut Flex<'a> {
+        match line {
+            I2cLine::Scl => &mut self.scl,
+            I2cLine::Sda => &mut self.sda,
+        }
     }
 }
 
+impl I2cLines for LinePads<'_> {
+    fn set(&mut self, line: I2cLine, high: bool) {
+        self.pad(line).set_level(Level::from(high));
+    }
+
+    fn get(&mut self, line: I2cLine) -> bool {
+        self.pad(line).is_high()
+    }
+
+    fn set_internal_pull_ups(&mut self, enabled: bool) {
+        let pull = if enabled { Pull::Up } else { Pull::None };
+        self.scl.apply_output_config(&open_drain(pull));
+        self.sda.apply_output_config(&open_drain(pull));
+    }
+
+    fn delay_us(&mut self, us: u32) {
+        self.delay.delay_micros(us);
+    }
+}
+
+fn open_drain(pull: Pull) -> OutputConfig {
+    OutputConfig::default().with_drive_mode(DriveMode::OpenDrain).with_pull(pull)
+}
+
 /// Extension trait for convenient I2C operations
 pub trait I2cExt {
     /// Scan the I2C bus for devices
     ///
-    /// Returns a list of addresses that responded to a write operation.
-    /// This is useful for discovering I2C devices on the bus.
-    fn scan(&mut self) -> heapless::Vec<u8, 128>;
+    /// Returns the addresses `options` select that acknowledged the probe;
+    /// see [`scan_bus`]. A bus that knows its pins is
+    /// [`recover`](I2cBus::recover)ed first: one that stays stuck fails with
+    /// [`Error::BusFault`], and one that cannot be re-initialised with
+    /// [`Error::InvalidConfig`]. The peripheral cannot end a read without
+    /// clocking a byte, so [`I2cScanProbe::Quick`] fails with
+    /// [`Error::Unsupported`] before anything is sent.
+    fn scan(&mut self, options: &I2cScanOptions) -> Result<heapless::Vec<u8, 128>, Error>;
 
     /// Read a single byte from a device register
     fn read_register(&mut self, address: u8, register: u8) -> Result<u8, I2cErrorWrapper>;
@@ -175,38 +358,37 @@ pub trait I2cExt {
 }
 
 impl<'d> I2cExt for I2cBus<'d> {
-    fn scan(&mut self) -> heapless::Vec<u8, 128> {
-        let mut devices = heapless::Vec::new();
-        
-        // Scan addresses 0x08 to 0x77 (valid 7-bit I2C addresses)
-        for addr in 0x08..=0x77 {
-            // Try to write zero bytes to check if device responds
-            if self.i2c.write(addr, &[]).is_ok() {
-                let _ = devices.push(addr);
+    fn scan(&mut self, options: &I2cScanOptions) -> Result<heapless::Vec<u8, 128>, Error> {
+        if options.probe == I2cScanProbe::Quick {
+            return Err(Error::Unsupported);
+        }
+        if self.pins.is_some() {
+            let report = self.recover().map_err(|_| Error::InvalidConfig)?;
+            if !report.recovered {
+                return Err(Error::BusFault);
             }
         }
-        
-        devices
+        scan_bus(self, options)
     }
 
     fn read_register(&mut self, address: u8, register: u8) -> Result<u8, I2cErrorWrapper> {
         let mut buf = [0u8; 1];
-        self.i2c
+        self.driver()
             .write_read(address, &[register], &mut buf)
-            .map_err(|_| I2cErrorWrapper::Other)?;
+            .map_err(I2cErrorWrapper::from)?;
         Ok(buf[0])
     }
 
     fn write_register(&mut self, address: u8, register: u8, value: u8) -> Result<(), I2cErrorWrapper> {
-        self.i2c
+        self.driver()
             .write(address, &[register, value])
-            .map_err(|_| I2cErrorWrapper::Other)
+            .map_err(I2cErrorWrapper::from)
     }
 
     fn read_registers(&mut self, address: u8, register: u8, buffer: &mut [u8]) -> Result<(), I2cErrorWrapper> {
-        self.i2c
+        self.driver()
             .write_read(address, &[register], buffer)
-            .map_err(|_| I2cErrorWrapper::Other)
+            .map_err(I2cErrorWrapper::from)
     }
 
     fn write_registers(&mut self, address: u8, register: u8, data: &[u8]) -> Result<(), I2cErrorWrapper> {
@@ -221,9 +403,9 @@ impl<'d> I2cExt for I2cBus<'d> {
             }
         }
         
-        self.i2c
+        self.driver()
             .write(address, &buf)
-            .map_err(|_| I2cErrorWrapper::Other)
+            .map_err(I2cErrorWrapper::from)
     }
 }
 
@@ -246,4 +428,11 @@ mod tests {
         assert_eq!(config.frequency.to_Hz(), 400_000);
         assert_eq!(config.timeout_ms, 500);
     }
+
+    #[test]
+    fn test_i2c_timeout_cycles() {
+        let config = I2cConfig::default().with_timeout_ms(20);
+        assert_eq!(config.timeout_cycles(), 2_000);
+        assert_eq!(config.with_timeout_ms(0).timeout_cycles(), 1);
+    }
 }
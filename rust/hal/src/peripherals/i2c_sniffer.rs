@@ -0,0 +1,148 @@
+//! GPIO edge capture for the passive I2C sniffer
+//!
+//! Both bus lines are plain inputs with an any-edge interrupt. The handler
+//! samples SCL and SDA together, timestamps the sample and queues it for
+//! [`I2cSniffer`](esp32_bus_pirate_bus_modes::i2c_sniffer::I2cSniffer) to
+//! decode outside the interrupt.
+//!
+//! Interrupt latency bounds the bus speed that can be followed: standard
+//! mode (100 kHz) is reliable, fast mode loses edges under load. Lost edges
+//! are counted; the decoder resynchronises at the next START.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use esp32_bus_pirate_bus_modes::{i2c_sniffer::I2cSniffer, Sniffer};
+//! use esp32_bus_pirate_hal::peripherals::i2c_sniffer::GpioEdgeSource;
+//! use esp_hal::gpio::{Input, InputConfig, Io};
+//!
+//! let peripherals = esp_hal::init(esp_hal::Config::default());
+//! let mut io = Io::new(peripherals.IO_MUX);
+//! let scl = Input::new(peripherals.GPIO9, InputConfig::default());
+//! let sda = Input::new(peripherals.GPIO8, InputConfig::default());
+//!
+//! let mut sniffer = I2cSniffer::new(GpioEdgeSource::new(&mut io, scl, sda));
+//! sniffer.start_sniff().unwrap();
+//! while let Ok(Some(event)) = sniffer.read_event() {
+//!     // forward `event` to the host
+//! }
+//! ```
+
+use core::cell::RefCell;
+use critical_section::Mutex;
+use esp_hal::gpio::{Event, Input, Io};
+use esp_hal::handler;
+use esp_hal::time::Instant;
+use esp32_bus_pirate_bus_modes::i2c_sniffer::{Edge, EdgeSource};
+use esp32_bus_pirate_bus_modes::Error;
+use heapless::Deque;
+
+/// Edges buffered between the interrupt and the decoder; a byte is about
+/// 20 edges
+const EDGE_QUEUE: usize = 512;
+
+/// State shared with the GPIO interrupt handler
+struct Capture {
+    scl: Input<'static>,
+    sda: Input<'static>,
+    started: Instant,
+    edges: Deque<Edge, EDGE_QUEUE>,
+    dropped: u32,
+}
+
+static CAPTURE: Mutex<RefCell<Option<Capture>>> = Mutex::new(RefCell::new(None));
+
+/// Edge source sampling SCL and SDA from GPIO interrupts
+///
+/// Only one can exist at a time: the pins and the queue live in a static
+/// the interrupt handler can reach.
+pub struct GpioEdgeSource {
+    _private: (),
+}
+
+impl GpioEdgeSource {
+    /// Take over `scl` and `sda` and install the GPIO interrupt handler
+    ///
+    /// The handler replaces any other GPIO handler installed on `io`.
+    pub fn new(io: &mut Io<'_>, scl: Input<'static>, sda: Input<'static>) -> Self {
+        critical_section::with(|cs| {
+            CAPTURE.borrow_ref_mut(cs).replace(Capture {
+                scl,
+                sda,
+                started: Instant::now(),
+                edges: Deque::new(),
+                dropped: 0,
+            });
+        });
+        io.set_interrupt_handler(on_edge);
+        Self { _private: () }
+    }
+
+    /// Edges lost to a full queue since capture started
+    pub fn dropped(&self) -> u32 {
+        critical_section::with(|cs| CAPTURE.borrow_ref(cs).as_ref().map_or(0, |capture| capture.dropped))
+    }
+
+    /// Stop capturing and give the pins back
+    pub fn release(self) -> Option<(Input<'static>, Input<'static>)> {
+        critical_section::with(|cs| CAPTURE.borrow_ref_mut(cs).take()).map(|mut capture| {
+            capture.scl.unlisten();
+            capture.sda.unlisten();
+            (capture.scl, capture.sda)
+        })
+    }
+}
+
+impl EdgeSource for GpioEdgeSource {
+    fn start(&mut self) -> Result<(), Error> {
+        critical_section::with(|cs| {
+            let mut capture = CAPTURE.borrow_ref_mut(cs);
+            let capture = capture.as_mut().ok_or(Error::InvalidConfig)?;
+            capture.edges.clear();
+            capture.dropped = 0;
+            capture.started = Instant::now();
+            capture.scl.listen(Event::AnyEdge);
+            capture.sda.listen(Event::AnyEdge);
+            Ok(())
+        })
+    }
+
+    fn stop(&mut self) -> Result<(), Error> {
+        critical_section::with(|cs| {
+            let mut capture = CAPTURE.borrow_ref_mut(cs);
+            let capture = capture.as_mut().ok_or(Error::InvalidConfig)?;
+            capture.scl.unlisten();
+            capture.sda.unlisten();
+            Ok(())
+        })
+    }
+
+    fn next_edge(&mut self) -> Option<Edge> {
+        critical_section::with(|cs| CAPTURE.borrow_ref_mut(cs).as_mut()?.edges.pop_front())
+    }
+}
+
+#[handler]
+fn on_edge() {
+    critical_section::with(|cs| {
+        let mut capture = CAPTURE.borrow_ref_mut(cs);
+        let Some(capture) = capture.as_mut() else {
+            return;
+        };
+        if !capture.scl.is_interrupt_set() && !capture.sda.is_interrupt_set() {
+            return;
+        }
+        // Clear before sampling, so a change after the reads raises the
+        // interrupt again rather than going unseen
+        capture.scl.clear_interrupt();
+        capture.sda.clear_interrupt();
+        let edge = Edge {
+            timestamp_us: (Instant::now() - capture.started).as_micros() as u32,
+            scl: capture.scl.is_high(),
+            sda: capture.sda.is_high(),
+        };
+        if capture.edges.push_back(edge).is_err() {
+            capture.dropped = capture.dropped.saturating_add(1);
+        }
+    });
+}
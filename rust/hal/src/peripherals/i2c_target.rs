@@ -0,0 +1,171 @@
+//! I2C1 in target (slave) role, for I2C target emulation
+//!
+//! esp-hal only drives I2C as a controller, so this module programs the
+//! peripheral's registers directly. The ESP32-S3 has two I2C peripherals
+//! and both are taken: I2C0 is the controller for the on-board touch
+//! panel, IMU and RTC, and I2C1 is the I2C mode's controller on the bus
+//! header (GPIO9 SCL, GPIO8 SDA). The target therefore time-shares I2C1
+//! and its pins with the I2C mode: while a target listens, the dispatcher
+//! refuses controller commands with `Busy`, and once it stops the
+//! controller driver reprograms the peripheral. Since both roles use the
+//! peripheral's own SCL and SDA signals, the pin routing the controller
+//! driver set up serves the target as it is.
+//!
+//! Clock stretching does the flow control: the peripheral holds SCL low
+//! after every byte it receives until [`TargetBus::ack`] decides the
+//! acknowledge bit, and when the controller reads with an empty TX FIFO
+//! until [`TargetBus::send`] supplies the byte. The register map can then
+//! be served from the main loop, at the cost of a slower bus.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use esp32_bus_pirate_bus_modes::{i2c_target::{I2cRegisterMap, I2cTargetMode}, BusMode};
+//! use esp32_bus_pirate_hal::peripherals::{i2c::{I2cBus, I2cConfig}, i2c_target::I2cTargetBus};
+//! use esp32_bus_pirate_hal::WaveshareS3Board;
+//!
+//! let board = WaveshareS3Board::new();
+//! let mut controller = I2cBus::new(board.bus_i2c, I2cConfig::default()).unwrap();
+//! // SAFETY: `controller` runs no transfers while the target listens and
+//! // is reconfigured before it is used again
+//! let mut target = I2cTargetMode::new(unsafe { I2cTargetBus::new() });
+//! # let map: I2cRegisterMap = todo!();
+//! # let serving = || true;
+//! target.init(map).unwrap();
+//! while serving() {
+//!     if let Some(access) = target.poll() {
+//!         // report `access`
+//!     }
+//! }
+//! target.deinit().unwrap();
+//! controller.set_config(*controller.config()).unwrap();
+//! ```
+
+use esp_hal::peripherals::I2C1;
+use esp32_bus_pirate_bus_modes::i2c_target::{TargetBus, TargetEvent};
+use esp32_bus_pirate_bus_modes::Error;
+
+/// `SR.STRETCH_CAUSE` when the controller reads and the TX FIFO is empty
+const STRETCH_TX_EMPTY: u8 = 1;
+
+/// SCL cycles the peripheral waits before stretching, so SDA settles
+const STRETCH_PROTECT: u16 = 0x3FF;
+
+/// I2C1 answering as a target on the I2C mode's SCL and SDA pins
+pub struct I2cTargetBus {
+    /// A transaction is open: the START was reported, the STOP was not
+    active: bool,
+    /// The open transaction is a read
+    reading: bool,
+}
+
+impl I2cTargetBus {
+    /// Borrow I2C1 from the I2C mode's controller for target mode; the
+    /// target does not answer until [`listen`](TargetBus::listen)
+    ///
+    /// # Safety
+    ///
+    /// I2C1 must be driven by an esp-hal controller with its SCL and SDA
+    /// routed, which also keeps its clock enabled, and that controller
+    /// must not run transfers from [`listen`](TargetBus::listen) until
+    /// [`release`](TargetBus::release), and must be reconfigured before its
+    /// next transfer. The dispatcher does both when given this bus through
+    /// its `Platform`.
+    pub unsafe fn new() -> Self {
+        Self {
+            active: false,
+            reading: false,
+        }
+    }
+
+    /// Let go of SCL after a stretch
+    fn release_clock(&self) {
+        let regs = I2C1::regs();
+        regs.int_clr().write(|w| w.slave_stretch().clear_bit_by_one());
+        regs.scl_stretch_conf().modify(|_, w| w.slave_scl_stretch_clr().set_bit());
+    }
+}
+
+impl TargetBus for I2cTargetBus {
+    fn listen(&mut self, addr: u8) -> Result<(), Error> {
+        if addr > 0x7F {
+            return Err(Error::InvalidConfig);
+        }
+        let regs = I2C1::regs();
+        regs.ctr().write(|w| {
+            w.ms_mode().clear_bit();
+            w.sda_force_out().set_bit();
+            w.scl_force_out().set_bit();
+            w.slv_tx_auto_start_en().set_bit()
+        });
+        regs.slave_addr().write(|w| unsafe { w.slave_addr().bits(u16::from(addr)) });
+        regs.fifo_conf().modify(|_, w| {
+            w.nonfifo_en().clear_bit();
+            w.fifo_addr_cfg_en().clear_bit();
+            w.rx_fifo_rst().set_bit();
+            w.tx_fifo_rst().set_bit()
+        });
+        regs.fifo_conf().modify(|_, w| {
+            w.rx_fifo_rst().clear_bit();
+            w.tx_fifo_rst().clear_bit()
+        });
+        regs.scl_stretch_conf().write(|w| unsafe {
+            w.stretch_protect_num().bits(STRETCH_PROTECT);
+            w.slave_scl_stretch_en().set_bit();
+            w.slave_byte_ack_ctl_en().set_bit()
+        });
+        regs.int_clr().write(|w| unsafe { w.bits(u32::MAX) });
+        regs.ctr().modify(|_, w| w.conf_upgate().set_bit());
+        self.active = false;
+        Ok(())
+    }
+
+    fn release(&mut self) -> Result<(), Error> {
+        // The controller driver sets its mode again when it is reconfigured
+        let regs = I2C1::regs();
+        // Address 0 is the general call, which the target does not answer
+        regs.slave_addr().write(|w| unsafe { w.slave_addr().bits(0) });
+        regs.scl_stretch_conf().write(|w| w.slave_scl_stretch_clr().set_bit());
+        regs.ctr().modify(|_, w| w.conf_upgate().set_bit());
+        self.active = false;
+        Ok(())
+    }
+
+    fn next_event(&mut self) -> Option<TargetEvent> {
+        let regs = I2C1::regs();
+        let raw = regs.int_raw().read();
+        let status = regs.sr().read();
+
+        // A byte received with SCL stretched belongs to the open
+        // transaction, so it comes before a repeated start seen with it
+        if self.active && status.rxfifo_cnt().bits() > 0 {
+            return Some(TargetEvent::Written(regs.data().read().fifo_rdata().bits()));
+        }
+        if raw.trans_start().bit_is_set() {
+            regs.int_clr().write(|w| w.trans_start().clear_bit_by_one());
+            self.active = true;
+            self.reading = status.slave_rw().bit_is_set();
+            return Some(TargetEvent::Start { read: self.reading });
+        }
+        let stretched = raw.slave_stretch().bit_is_set();
+        if self.active && self.reading && stretched && status.stretch_cause().bits() == STRETCH_TX_EMPTY {
+            return Some(TargetEvent::ReadRequest);
+        }
+        if raw.trans_complete().bit_is_set() {
+            regs.int_clr().write(|w| w.trans_complete().clear_bit_by_one());
+            self.active = false;
+            return Some(TargetEvent::Stop);
+        }
+        None
+    }
+
+    fn ack(&mut self, ack: bool) {
+        I2C1::regs().scl_stretch_conf().modify(|_, w| w.slave_byte_ack_lvl().bit(!ack));
+        self.release_clock();
+    }
+
+    fn send(&mut self, byte: u8) {
+        I2C1::regs().data().write(|w| unsafe { w.fifo_rdata().bits(byte) });
+        self.release_clock();
+    }
+}
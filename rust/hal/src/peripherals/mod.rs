@@ -1,6 +1,8 @@
 //! Peripheral abstractions and utilities
 
 pub mod i2c;
+pub mod i2c_sniffer;
+pub mod i2c_target;
 pub mod spi;
 pub mod uart;
 pub mod gpio;
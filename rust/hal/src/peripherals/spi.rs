@@ -270,10 +270,12 @@ impl<'d> SpiBus for SpiBus3<'d> {
 /// SPI device with chip select management
 ///
 /// This wrapper provides a `SpiDevice` implementation that manages
-/// the chip select pin automatically for each transaction.
+/// the chip select pin automatically for each transaction, unless it is
+/// held with [`hold_chip_select`](Self::hold_chip_select).
 pub struct SpiDeviceWithCs<'d, SPI, CS> {
     bus: SPI,
     cs: CS,
+    held: bool,
     _phantom: PhantomData<&'d ()>,
 }
 
@@ -291,10 +293,23 @@ where
         Self {
             bus,
             cs,
+            held: false,
             _phantom: PhantomData,
         }
     }
 
+    /// Assert chip select and keep it asserted across transactions
+    /// (`true`), or release it and go back to one pulse per transaction
+    /// (`false`)
+    pub fn hold_chip_select(&mut self, held: bool) {
+        self.held = held;
+        if held {
+            let _ = self.cs.set_low();
+        } else {
+            let _ = self.cs.set_high();
+        }
+    }
+
     /// Release the SPI bus and CS pin
     pub fn release(self) -> (SPI, CS) {
         (self.bus, self.cs)
@@ -314,8 +329,10 @@ where
     CS: embedded_hal::digital::OutputPin,
 {
     fn transaction(&mut self, operations: &mut [embedded_hal::spi::Operation<'_, u8>]) -> Result<(), Self::Error> {
-        // Assert CS (active low)
-        let _ = self.cs.set_low();
+        // Assert CS (active low), unless it is already held
+        if !self.held {
+            let _ = self.cs.set_low();
+        }
 
         let result = operations.iter_mut().try_for_each(|op| match op {
             embedded_hal::spi::Operation::Read(buf) => self.bus.read(buf),
@@ -328,8 +345,10 @@ where
             }
         });
 
-        // Deassert CS
-        let _ = self.cs.set_high();
+        // Deassert CS, unless the caller holds it
+        if !self.held {
+            let _ = self.cs.set_high();
+        }
 
         result
     }
@@ -14,10 +14,13 @@
 //!     .with_stop_bits(StopBits::One);
 //! ```
 
-use esp_hal::uart::{Uart, UartTx, UartRx, DataBits as EspDataBits, Parity as EspParity, StopBits as EspStopBits, Config as EspUartConfig};
+use esp_hal::uart::{Uart, UartTx, UartRx, DataBits as EspDataBits, Parity as EspParity, StopBits as EspStopBits, Config as EspUartConfig, CtsConfig, HwFlowControl, RtsConfig};
 use esp_hal::peripherals::{UART0, UART1};
 use embedded_hal::serial::{Error as SerialError, ErrorKind, ErrorType};
-use embedded_io::{Read, Write, ErrorType as IoErrorType};
+use embedded_io::{Read, ReadReady, Write, WriteReady, ErrorType as IoErrorType};
+
+/// RX FIFO fill level (bytes) at which RTS is deasserted
+const RTS_THRESHOLD: u8 = 64;
 
 /// UART parity configuration
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -82,6 +85,33 @@ impl From<DataBits> for EspDataBits {
     }
 }
 
+/// UART flow control configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowControl {
+    /// No flow control
+    None,
+    /// Hardware RTS/CTS handshaking
+    ///
+    /// The RTS and CTS pins must be routed with esp-hal's `Uart::with_rts`
+    /// and `Uart::with_cts`.
+    RtsCts,
+}
+
+impl From<FlowControl> for HwFlowControl {
+    fn from(flow_control: FlowControl) -> Self {
+        match flow_control {
+            FlowControl::None => HwFlowControl {
+                cts: CtsConfig::Disabled,
+                rts: RtsConfig::Disabled,
+            },
+            FlowControl::RtsCts => HwFlowControl {
+                cts: CtsConfig::Enabled,
+                rts: RtsConfig::Enabled(RTS_THRESHOLD),
+            },
+        }
+    }
+}
+
 /// UART configuration
 #[derive(Debug, Clone, Copy)]
 pub struct UartConfig {
@@ -93,6 +123,8 @@ pub struct UartConfig {
     pub stop_bits: StopBits,
     /// Number of data bits
     pub data_bits: DataBits,
+    /// Hardware flow control
+    pub flow_control: FlowControl,
 }
 
 impl Default for UartConfig {
@@ -102,6 +134,7 @@ impl Default for UartConfig {
             parity: Parity::None,
             stop_bits: StopBits::One,
             data_bits: DataBits::Eight,
+            flow_control: FlowControl::None,
         }
     }
 }
@@ -114,6 +147,7 @@ impl UartConfig {
             parity: Parity::None,
             stop_bits: StopBits::One,
             data_bits: DataBits::Eight,
+            flow_control: FlowControl::None,
         }
     }
 
@@ -141,6 +175,12 @@ impl UartConfig {
         self
     }
 
+    /// Set the flow control
+    pub fn with_flow_control(mut self, flow_control: FlowControl) -> Self {
+        self.flow_control = flow_control;
+        self
+    }
+
     /// Convert to esp-hal UART config
     pub fn to_esp_config(&self) -> EspUartConfig {
         EspUartConfig {
@@ -148,6 +188,7 @@ impl UartConfig {
             data_bits: self.data_bits.into(),
             parity: self.parity.into(),
             stop_bits: self.stop_bits.into(),
+            hw_flow_ctrl: self.flow_control.into(),
             ..Default::default()
         }
     }
@@ -261,6 +302,18 @@ impl<'d> Write for UartBus0<'d> {
     }
 }
 
+impl<'d> ReadReady for UartBus0<'d> {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.uart.read_ready())
+    }
+}
+
+impl<'d> WriteReady for UartBus0<'d> {
+    fn write_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.uart.write_ready())
+    }
+}
+
 impl<'d> Read for UartBus0<'d> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
         let mut count = 0;
@@ -353,6 +406,18 @@ impl<'d> Write for UartBus1<'d> {
     }
 }
 
+impl<'d> ReadReady for UartBus1<'d> {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.uart.read_ready())
+    }
+}
+
+impl<'d> WriteReady for UartBus1<'d> {
+    fn write_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.uart.write_ready())
+    }
+}
+
 impl<'d> Read for UartBus1<'d> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
         let mut count = 0;
@@ -384,6 +449,7 @@ mod tests {
         assert_eq!(config.parity, Parity::None);
         assert_eq!(config.stop_bits, StopBits::One);
         assert_eq!(config.data_bits, DataBits::Eight);
+        assert_eq!(config.flow_control, FlowControl::None);
     }
 
     #[test]
@@ -392,11 +458,13 @@ mod tests {
             .with_baudrate(9600)
             .with_parity(Parity::Even)
             .with_stop_bits(StopBits::Two)
-            .with_data_bits(DataBits::Seven);
+            .with_data_bits(DataBits::Seven)
+            .with_flow_control(FlowControl::RtsCts);
         assert_eq!(config.baudrate, 9600);
         assert_eq!(config.parity, Parity::Even);
         assert_eq!(config.stop_bits, StopBits::Two);
         assert_eq!(config.data_bits, DataBits::Seven);
+        assert_eq!(config.flow_control, FlowControl::RtsCts);
     }
 
     #[test]
@@ -76,4 +76,20 @@ pub mod bus {
         // GPIO 19-20 are USB
         // GPIO 1-5, 39-42, 45 are used by display/touch
     ];
+    
+    /// I2C mode data line (I2C1)
+    pub const I2C_SDA: u8 = 8;
+    /// I2C mode clock line (I2C1)
+    pub const I2C_SCL: u8 = 9;
+    
+    /// UART mode transmit line (UART1)
+    pub const UART_TX: u8 = 10;
+    /// UART mode receive line (UART1)
+    pub const UART_RX: u8 = 11;
+    
+    /// SPI mode chip select
+    ///
+    /// SPI mode shares SPI3 with the SD card slot (SCLK 14, MOSI 17,
+    /// MISO 16) and selects its target with this pin.
+    pub const SPI_CS: u8 = 12;
 }
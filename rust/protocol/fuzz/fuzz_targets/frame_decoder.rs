@@ -0,0 +1,33 @@
+#![no_main]
+
+//! Fuzz the streaming decoder with arbitrary byte streams
+//!
+//! The first input byte selects the chunk size so that frame boundaries land
+//! at every possible offset. Run with:
+//!
+//! ```text
+//! cd rust/protocol
+//! cargo +nightly fuzz run frame_decoder --target x86_64-unknown-linux-gnu
+//! ```
+
+use esp32_bus_pirate_protocol::{FrameDecoder, MessageCodec};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&chunk, data)) = data.split_first() else {
+        return;
+    };
+    let chunk = (chunk as usize).max(1);
+
+    let mut decoder = FrameDecoder::new();
+    for piece in data.chunks(chunk) {
+        decoder.decode(piece, |item| {
+            // Anything the decoder accepts must re-encode cleanly
+            if let Ok(msg) = item {
+                MessageCodec::encode(&msg).expect("decoded message must re-encode");
+            }
+        });
+    }
+
+    assert!(decoder.buffered() <= esp32_bus_pirate_protocol::MAX_MESSAGE_SIZE);
+});
@@ -0,0 +1,284 @@
+//! Chunked bulk transfers for payloads larger than one frame
+//!
+//! A single frame carries at most [`MAX_MESSAGE_SIZE`](crate::MAX_MESSAGE_SIZE)
+//! bytes, which rules out dumping an SPI flash or reading a large file in one
+//! message. Bulk transfers split such payloads into numbered chunks:
+//!
+//! ```text
+//! Sender                              Receiver
+//!   │ BulkBegin { id, target, total_len } │
+//!   │────────────────────────────────────>│
+//!   │ BulkChunk { id, offset, data }      │
+//!   │────────────────────────────────────>│
+//!   │            BulkAck { id, offset }   │
+//!   │<────────────────────────────────────│
+//!   │              ...                    │
+//!   │ BulkEnd { id, crc32 }               │
+//!   │────────────────────────────────────>│
+//!   │            BulkAck { id, total_len }│
+//!   │<────────────────────────────────────│
+//! ```
+//!
+//! Every ack carries the next offset the receiver expects. Chunks that do not
+//! start at that offset are ignored and re-acked, so a sender that sees an
+//! ack behind its own position simply rewinds. After a broken link the sender
+//! issues `BulkResume { id }` and continues from the offset in the reply.
+//!
+//! The host uploads with `BulkBegin` and downloads by sending `BulkRequest`,
+//! after which the device acts as the sender.
+//!
+//! [`BulkSender`] and [`BulkReceiver`] implement both ends of the exchange
+//! without allocating; data is read and written through callbacks.
+
+use crate::message::{BulkTarget, ErrorCode, Message, BULK_CHUNK_SIZE};
+use crc::{Crc, Digest, CRC_32_ISO_HDLC};
+use heapless::Vec;
+
+static CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// CRC-32 (ISO-HDLC) of a complete payload, as carried by `BulkEnd`
+pub fn crc32(data: &[u8]) -> u32 {
+    CRC32.checksum(data)
+}
+
+/// CRC-32 of a payload fed in pieces; `finalize` returns what [`crc32`]
+/// would for the whole
+pub fn crc32_digest() -> Digest<'static, u32> {
+    CRC32.digest()
+}
+
+/// Bulk transfer errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BulkError {
+    /// Message refers to a different transfer
+    UnknownTransfer,
+    /// Chunk extends past the announced length, or ack is ahead of the data sent
+    Overflow,
+    /// `BulkEnd` arrived before all data was received
+    Incomplete,
+    /// Whole-payload CRC did not match
+    ChecksumMismatch,
+    /// Reading or writing the payload failed
+    Io,
+}
+
+impl From<BulkError> for ErrorCode {
+    fn from(err: BulkError) -> Self {
+        match err {
+            BulkError::UnknownTransfer => ErrorCode::UnknownTransfer,
+            BulkError::Overflow => ErrorCode::InvalidParameter,
+            BulkError::Incomplete => ErrorCode::ProtocolError,
+            BulkError::ChecksumMismatch => ErrorCode::ChecksumMismatch,
+            BulkError::Io => ErrorCode::BusError,
+        }
+    }
+}
+
+/// Sending side of a bulk transfer
+pub struct BulkSender {
+    id: u16,
+    target: BulkTarget,
+    total_len: u32,
+    next_offset: u32,
+    acked: u32,
+    digested: u32,
+    digest: Digest<'static, u32>,
+}
+
+impl BulkSender {
+    /// Prepare to send `total_len` bytes as transfer `id`
+    pub fn new(id: u16, target: BulkTarget, total_len: u32) -> Self {
+        Self {
+            id,
+            target,
+            total_len,
+            next_offset: 0,
+            acked: 0,
+            digested: 0,
+            digest: CRC32.digest(),
+        }
+    }
+
+    /// Transfer ID
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    /// The `BulkBegin` message announcing this transfer
+    pub fn begin(&self) -> Message {
+        Message::BulkBegin {
+            id: self.id,
+            target: self.target.clone(),
+            total_len: self.total_len,
+        }
+    }
+
+    /// Produce the next chunk, reading its data through `read`
+    ///
+    /// `read(offset, buf)` must fill `buf` with the payload bytes starting at
+    /// `offset`. Returns `Ok(None)` once every byte has been sent; the caller
+    /// then sends [`end`](Self::end).
+    pub fn next_chunk<F>(&mut self, mut read: F) -> Result<Option<Message>, BulkError>
+    where
+        F: FnMut(u32, &mut [u8]) -> Result<(), BulkError>,
+    {
+        let remaining = (self.total_len - self.next_offset) as usize;
+        if remaining == 0 {
+            return Ok(None);
+        }
+
+        let len = remaining.min(BULK_CHUNK_SIZE);
+        let mut data = Vec::new();
+        data.resize(len, 0).map_err(|_| BulkError::Overflow)?;
+        read(self.next_offset, &mut data)?;
+
+        // Only new data feeds the checksum; rewound chunks were counted already
+        if self.next_offset == self.digested {
+            self.digest.update(&data);
+            self.digested += len as u32;
+        }
+
+        let offset = self.next_offset;
+        self.next_offset += len as u32;
+        Ok(Some(Message::BulkChunk {
+            id: self.id,
+            offset,
+            data,
+        }))
+    }
+
+    /// Handle a `BulkAck`, rewinding to the receiver's offset if needed
+    pub fn on_ack(&mut self, id: u16, offset: u32) -> Result<(), BulkError> {
+        if id != self.id {
+            return Err(BulkError::UnknownTransfer);
+        }
+        if offset > self.digested {
+            return Err(BulkError::Overflow);
+        }
+
+        self.acked = offset;
+        if offset < self.next_offset {
+            self.next_offset = offset;
+        }
+        Ok(())
+    }
+
+    /// The `BulkEnd` message, available once all data has been sent
+    pub fn end(&self) -> Option<Message> {
+        (self.digested == self.total_len).then(|| Message::BulkEnd {
+            id: self.id,
+            crc32: self.digest.clone().finalize(),
+        })
+    }
+
+    /// The `BulkResume` message to send after an interruption
+    pub fn resume(&self) -> Message {
+        Message::BulkResume { id: self.id }
+    }
+
+    /// Bytes acknowledged by the receiver
+    pub fn acked(&self) -> u32 {
+        self.acked
+    }
+
+    /// Whether the receiver has acknowledged the whole payload
+    pub fn is_complete(&self) -> bool {
+        self.acked == self.total_len
+    }
+}
+
+/// Receiving side of a bulk transfer
+pub struct BulkReceiver {
+    id: u16,
+    total_len: u32,
+    offset: u32,
+    digest: Digest<'static, u32>,
+    finished: bool,
+}
+
+impl BulkReceiver {
+    /// Prepare to receive `total_len` bytes as transfer `id`
+    pub fn new(id: u16, total_len: u32) -> Self {
+        Self {
+            id,
+            total_len,
+            offset: 0,
+            digest: CRC32.digest(),
+            finished: false,
+        }
+    }
+
+    /// Transfer ID
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    /// Announced payload length
+    pub fn total_len(&self) -> u32 {
+        self.total_len
+    }
+
+    /// Next offset expected from the sender
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+
+    /// Whether `BulkEnd` has been received and verified
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Handle a `BulkChunk`, storing in-order data through `write`
+    ///
+    /// Returns the `BulkAck` to send back. Chunks that do not start at the
+    /// expected offset are dropped and answered with the expected offset.
+    pub fn on_chunk<F>(
+        &mut self,
+        id: u16,
+        offset: u32,
+        data: &[u8],
+        mut write: F,
+    ) -> Result<Message, BulkError>
+    where
+        F: FnMut(u32, &[u8]) -> Result<(), BulkError>,
+    {
+        if id != self.id || self.finished {
+            return Err(BulkError::UnknownTransfer);
+        }
+
+        if offset == self.offset {
+            if offset as u64 + data.len() as u64 > self.total_len as u64 {
+                return Err(BulkError::Overflow);
+            }
+            write(offset, data)?;
+            self.digest.update(data);
+            self.offset += data.len() as u32;
+        }
+
+        Ok(self.ack())
+    }
+
+    /// Handle a `BulkEnd`, verifying length and checksum
+    pub fn on_end(&mut self, id: u16, crc32: u32) -> Result<Message, BulkError> {
+        if id != self.id {
+            return Err(BulkError::UnknownTransfer);
+        }
+        if self.offset != self.total_len {
+            return Err(BulkError::Incomplete);
+        }
+        if self.digest.clone().finalize() != crc32 {
+            return Err(BulkError::ChecksumMismatch);
+        }
+
+        self.finished = true;
+        Ok(self.ack())
+    }
+
+    /// The `BulkAck` for the current position (also the reply to `BulkResume`)
+    pub fn ack(&self) -> Message {
+        Message::BulkAck {
+            id: self.id,
+            offset: self.offset,
+        }
+    }
+}
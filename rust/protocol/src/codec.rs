@@ -1,26 +1,88 @@
 //! Message encoding and decoding with CRC validation
 
-use crate::{message::Message, Error, END_BYTE, MAX_MESSAGE_SIZE, START_BYTE};
+use crate::{
+    message::Message,
+    version::{self, PROTOCOL_VERSION, PROTOCOL_VERSION_V1},
+    Error, END_BYTE, MAX_MESSAGE_SIZE, START_BYTE,
+};
 use crc::{Crc, CRC_16_IBM_SDLC};
 use heapless::Vec;
 use postcard::{from_bytes, to_slice};
 
 const CRC: Crc<u16> = Crc::<u16>::new(&CRC_16_IBM_SDLC);
 
+/// A decoded frame together with its header fields
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    /// Protocol version the frame was encoded with
+    pub version: u8,
+    /// Sequence/correlation ID ([`UNSEQUENCED`] for v1 frames)
+    pub seq: u16,
+    /// Decoded message
+    pub message: Message,
+}
+
+/// Sequence ID of frames that carry no correlation
+///
+/// v1 frames decode with this ID, and it is exempt from duplicate detection.
+pub const UNSEQUENCED: u16 = 0;
+
 /// Message codec for encoding and decoding protocol messages
 pub struct MessageCodec;
 
 impl MessageCodec {
     /// Encode a message into a framed byte stream
     ///
-    /// Frame format:
+    /// The frame uses the current [`PROTOCOL_VERSION`] and carries
+    /// [`UNSEQUENCED`] as its sequence ID. Use
+    /// [`encode_with_seq`](Self::encode_with_seq) for correlated requests.
+    ///
+    /// Frame format (v2):
     /// ```text
-    /// ┌─────────┬─────────┬─────────┬──────────┬─────────┬─────────┐
-    /// │ START   │ VERSION │ LENGTH  │ PAYLOAD  │ CRC16   │  END    │
-    /// │ (0xAA)  │ (1 byte)│ (2 bytes│ (n bytes)│ (2 bytes│ (0x55)  │
-    /// └─────────┴─────────┴─────────┴──────────┴─────────┴─────────┘
+    /// ┌─────────┬─────────┬─────────┬─────────┬──────────┬─────────┬─────────┐
+    /// │ START   │ VERSION │ LENGTH  │ SEQ     │ PAYLOAD  │ CRC16   │  END    │
+    /// │ (0xAA)  │ (1 byte)│ (2 bytes│ (2 bytes│ (n bytes)│ (2 bytes│ (0x55)  │
+    /// └─────────┴─────────┴─────────┴─────────┴──────────┴─────────┴─────────┘
     /// ```
+    ///
+    /// v1 frames are identical without the SEQ field.
     pub fn encode(msg: &Message) -> Result<Vec<u8, MAX_MESSAGE_SIZE>, Error> {
+        Self::encode_with_seq(msg, UNSEQUENCED)
+    }
+    
+    /// Encode a message with a sequence/correlation ID
+    pub fn encode_with_seq(msg: &Message, seq: u16) -> Result<Vec<u8, MAX_MESSAGE_SIZE>, Error> {
+        Self::encode_frame(PROTOCOL_VERSION, seq, msg)
+    }
+    
+    /// Encode a message as a legacy v1 frame (no sequence ID)
+    pub fn encode_v1(msg: &Message) -> Result<Vec<u8, MAX_MESSAGE_SIZE>, Error> {
+        Self::encode_frame(PROTOCOL_VERSION_V1, UNSEQUENCED, msg)
+    }
+    
+    /// Encode a reply to `request`, echoing its version and sequence ID
+    pub fn encode_reply(request: &Frame, msg: &Message) -> Result<Vec<u8, MAX_MESSAGE_SIZE>, Error> {
+        Self::encode_frame(request.version, request.seq, msg)
+    }
+    
+    /// Encode a message for a session that negotiated `version`
+    ///
+    /// Fails with [`Error::UnsupportedVersion`] if `version` is unknown or
+    /// too old to carry `msg`.
+    pub fn encode_versioned(
+        msg: &Message,
+        version: u8,
+        seq: u16,
+    ) -> Result<Vec<u8, MAX_MESSAGE_SIZE>, Error> {
+        Self::encode_frame(version, seq, msg)
+    }
+    
+    fn encode_frame(version: u8, seq: u16, msg: &Message) -> Result<Vec<u8, MAX_MESSAGE_SIZE>, Error> {
+        // A frame may only carry messages its version knows about
+        if !version::is_compatible(version) || msg.min_version() > version {
+            return Err(Error::UnsupportedVersion);
+        }
+        
         // Serialize message with postcard
         let mut payload_buf = [0u8; MAX_MESSAGE_SIZE];
         let payload_slice = to_slice(msg, &mut payload_buf)
@@ -31,17 +93,20 @@ impl MessageCodec {
         // Build frame
         let mut frame = Vec::new();
         frame.push(START_BYTE).map_err(|_| Error::BufferFull)?;
-        frame
-            .push(crate::version::PROTOCOL_VERSION)
-            .map_err(|_| Error::BufferFull)?;
+        frame.push(version).map_err(|_| Error::BufferFull)?;
         frame
             .extend_from_slice(&len.to_le_bytes())
             .map_err(|_| Error::BufferFull)?;
+        if version::has_seq(version) {
+            frame
+                .extend_from_slice(&seq.to_le_bytes())
+                .map_err(|_| Error::BufferFull)?;
+        }
         frame
             .extend_from_slice(payload_slice)
             .map_err(|_| Error::BufferFull)?;
         
-        // Calculate CRC over VERSION + LENGTH + PAYLOAD
+        // Calculate CRC over VERSION + LENGTH + SEQ + PAYLOAD
         let crc_data = &frame[1..];
         let crc_value = CRC.checksum(crc_data);
         
@@ -55,6 +120,11 @@ impl MessageCodec {
     
     /// Decode a framed byte stream into a message
     pub fn decode(frame: &[u8]) -> Result<Message, Error> {
+        Self::decode_frame(frame).map(|frame| frame.message)
+    }
+    
+    /// Decode a framed byte stream, keeping the header fields
+    pub fn decode_frame(frame: &[u8]) -> Result<Frame, Error> {
         // Minimum frame: START + VERSION + LEN(2) + CRC(2) + END = 7 bytes
         if frame.len() < 7 {
             return Err(Error::FrameTooShort);
@@ -67,20 +137,26 @@ impl MessageCodec {
         
         // Check version
         let version = frame[1];
-        if version != crate::version::PROTOCOL_VERSION {
+        if !version::is_compatible(version) {
             return Err(Error::UnsupportedVersion);
         }
         
         // Extract length
         let len = u16::from_le_bytes([frame[2], frame[3]]) as usize;
-        let payload_end = 4 + len;
+        let payload_start = version::header_len(version);
+        let payload_end = payload_start + len;
         
         if frame.len() < payload_end + 3 {
             return Err(Error::FrameTooShort);
         }
         
-        // Extract payload and CRC
-        let payload = &frame[4..payload_end];
+        // Extract sequence ID, payload and CRC
+        let seq = if version::has_seq(version) {
+            u16::from_le_bytes([frame[4], frame[5]])
+        } else {
+            UNSEQUENCED
+        };
+        let payload = &frame[payload_start..payload_end];
         let crc_received = u16::from_le_bytes([frame[payload_end], frame[payload_end + 1]]);
         
         // Verify CRC
@@ -92,6 +168,73 @@ impl MessageCodec {
         }
         
         // Deserialize payload
-        from_bytes(payload).map_err(|_| Error::DecodingFailed)
+        let message: Message = from_bytes(payload).map_err(|_| Error::DecodingFailed)?;
+        
+        // Messages introduced after the frame's version are not valid in it
+        if message.min_version() > version {
+            return Err(Error::UnsupportedVersion);
+        }
+        
+        Ok(Frame { version, seq, message })
+    }
+    
+    /// Check the CRC of a complete frame whose markers and length are valid
+    pub fn crc_matches(frame: &[u8]) -> bool {
+        let crc_end = frame.len() - 3;
+        let crc_received = u16::from_le_bytes([frame[crc_end], frame[crc_end + 1]]);
+        CRC.checksum(&frame[1..crc_end]) == crc_received
+    }
+}
+
+/// Number of recent sequence IDs remembered by [`SequenceWindow`]
+pub const SEQUENCE_WINDOW: usize = 32;
+
+/// Sliding window of recently seen sequence IDs
+///
+/// A receiver uses this to reject frames that repeat a sequence ID it has
+/// already processed, e.g. a command replayed by a confused host or
+/// duplicated by a transport retry. [`UNSEQUENCED`] frames are always
+/// accepted.
+#[derive(Debug, Clone)]
+pub struct SequenceWindow {
+    seen: [u16; SEQUENCE_WINDOW],
+    len: usize,
+    next: usize,
+}
+
+impl Default for SequenceWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SequenceWindow {
+    /// Create an empty window
+    pub const fn new() -> Self {
+        Self {
+            seen: [0; SEQUENCE_WINDOW],
+            len: 0,
+            next: 0,
+        }
+    }
+    
+    /// Record `seq`, failing if it was seen recently
+    pub fn check(&mut self, seq: u16) -> Result<(), Error> {
+        if seq == UNSEQUENCED {
+            return Ok(());
+        }
+        if self.seen[..self.len].contains(&seq) {
+            return Err(Error::DuplicateSequence);
+        }
+        self.seen[self.next] = seq;
+        self.next = (self.next + 1) % SEQUENCE_WINDOW;
+        self.len = (self.len + 1).min(SEQUENCE_WINDOW);
+        Ok(())
+    }
+    
+    /// Forget all remembered sequence IDs (e.g. when a new host connects)
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.next = 0;
     }
 }
@@ -0,0 +1,427 @@
+//! Incremental frame decoder for byte streams
+//!
+//! [`MessageCodec::decode`](crate::codec::MessageCodec::decode) expects a
+//! single, already-delimited frame. Transports, however, receive arbitrary
+//! chunks of bytes: a frame may be split across several reads, several frames
+//! may arrive in one read, and line noise may appear between them.
+//!
+//! [`FrameDecoder`] buffers incoming bytes, locates frames by their
+//! START_BYTE/LENGTH header and hands complete frames to the codec.
+//!
+//! The decoder also tracks recent sequence IDs in a [`SequenceWindow`] and
+//! rejects frames that repeat one with [`Error::DuplicateSequence`]. A
+//! `Hello` starts a new session and clears the window first, so a host that
+//! reconnects without the transport noticing can number its requests from 1
+//! again.
+//!
+//! Transports that pass frames on undecoded use
+//! [`poll_raw`](FrameDecoder::poll_raw) instead, and
+//! [`expire`](FrameDecoder::expire) drops a frame whose remaining bytes never
+//! arrive, so a lost byte cannot stall the link.
+//!
+//! # Resynchronization
+//!
+//! When a candidate frame turns out to be invalid (unsupported version,
+//! impossible length, bad END_BYTE or CRC mismatch), only its START_BYTE is
+//! discarded and scanning resumes from the very next byte. A corrupted frame
+//! therefore never swallows the valid frame that follows it.
+//!
+//! # Example
+//!
+//! ```
+//! use esp32_bus_pirate_protocol::{FrameDecoder, Message, MessageCodec};
+//!
+//! let frame = MessageCodec::encode(&Message::GetMode).unwrap();
+//!
+//! let mut decoder = FrameDecoder::new();
+//! let mut received = Vec::new();
+//!
+//! // Noise, then the frame split across two reads
+//! decoder.decode(&[0x00, 0x13, 0x37], |msg| received.push(msg));
+//! decoder.decode(&frame[..3], |msg| received.push(msg));
+//! decoder.decode(&frame[3..], |msg| received.push(msg));
+//!
+//! assert_eq!(received, vec![Ok(Message::GetMode)]);
+//! assert_eq!(decoder.stats().discarded_bytes, 3);
+//! ```
+
+use crate::{
+    codec::{Frame, MessageCodec, SequenceWindow},
+    message::Message,
+    version, Error, MAX_MESSAGE_SIZE, START_BYTE,
+};
+
+/// Bytes needed to read VERSION and LENGTH: START + VERSION + LENGTH(2)
+const MIN_HEADER_LEN: usize = 4;
+
+/// Size of the frame trailer: CRC(2) + END
+const TRAILER_LEN: usize = 3;
+
+/// Default time an incomplete frame may wait for its remaining bytes
+pub const DEFAULT_FRAME_TIMEOUT_MS: u64 = 500;
+
+/// Frame timeout for a serial link at `baudrate`
+///
+/// Twice the time a largest frame takes on the wire (10 bits per byte),
+/// and never less than [`DEFAULT_FRAME_TIMEOUT_MS`]. At 115200 baud and
+/// above the default applies; slow links get proportionally longer.
+pub fn frame_timeout_for_baud(baudrate: u32) -> u64 {
+    let wire_ms = (MAX_MESSAGE_SIZE as u64 * 10 * 1000).div_ceil(baudrate.max(1) as u64);
+    (2 * wire_ms).max(DEFAULT_FRAME_TIMEOUT_MS)
+}
+
+/// Per-error decoder statistics
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DecoderStats {
+    /// Frames decoded successfully
+    pub frames_ok: u32,
+    /// Bytes skipped while searching for a START_BYTE
+    pub discarded_bytes: u32,
+    /// Candidate frames with an unsupported version
+    pub unsupported_version: u32,
+    /// Candidate frames whose length cannot fit in a frame
+    pub invalid_length: u32,
+    /// Candidate frames with a bad END_BYTE
+    pub invalid_end: u32,
+    /// Candidate frames with a CRC mismatch
+    pub crc_errors: u32,
+    /// Well-formed frames whose payload could not be deserialized
+    pub decode_errors: u32,
+    /// Frames rejected for repeating a recent sequence ID
+    pub duplicates: u32,
+    /// Bytes dropped because the internal buffer was full
+    pub overflow_bytes: u32,
+    /// Incomplete frames dropped by [`FrameDecoder::expire`]
+    pub timeouts: u32,
+}
+
+impl DecoderStats {
+    /// Total number of frame errors of any kind
+    pub fn total_errors(&self) -> u32 {
+        self.unsupported_version
+            + self.invalid_length
+            + self.invalid_end
+            + self.crc_errors
+            + self.decode_errors
+            + self.duplicates
+            + self.timeouts
+    }
+}
+
+/// Incremental, resynchronizing frame decoder
+///
+/// The decoder owns a buffer of [`MAX_MESSAGE_SIZE`] bytes, which is the
+/// largest frame [`MessageCodec::encode`] can produce.
+pub struct FrameDecoder {
+    buf: [u8; MAX_MESSAGE_SIZE],
+    len: usize,
+    /// Length of the frame [`poll_raw`](FrameDecoder::poll_raw) returned,
+    /// consumed on the next call that changes the buffer
+    taken: usize,
+    timeout_ms: u64,
+    pending_since: Option<u64>,
+    stats: DecoderStats,
+    sequences: SequenceWindow,
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameDecoder {
+    /// Create an empty decoder
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; MAX_MESSAGE_SIZE],
+            len: 0,
+            taken: 0,
+            timeout_ms: DEFAULT_FRAME_TIMEOUT_MS,
+            pending_since: None,
+            stats: DecoderStats {
+                frames_ok: 0,
+                discarded_bytes: 0,
+                unsupported_version: 0,
+                invalid_length: 0,
+                invalid_end: 0,
+                crc_errors: 0,
+                decode_errors: 0,
+                duplicates: 0,
+                overflow_bytes: 0,
+                timeouts: 0,
+            },
+            sequences: SequenceWindow::new(),
+        }
+    }
+
+    /// Set how long an incomplete frame may wait for its remaining bytes
+    pub fn with_frame_timeout(mut self, timeout_ms: u64) -> Self {
+        self.timeout_ms = timeout_ms;
+        self
+    }
+
+    /// Append bytes to the internal buffer
+    ///
+    /// Returns the number of bytes accepted. Fewer than `data.len()` bytes
+    /// are accepted only when the buffer is full; call [`poll`](Self::poll)
+    /// to make room and push the remainder afterwards.
+    pub fn push(&mut self, data: &[u8]) -> usize {
+        self.release();
+        let n = data.len().min(self.buf.len() - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&data[..n]);
+        self.len += n;
+        n
+    }
+
+    /// Try to extract the next message from the buffered bytes
+    ///
+    /// Returns `None` when more input is needed, `Some(Ok(_))` for a decoded
+    /// message and `Some(Err(_))` for each rejected candidate frame.
+    pub fn poll(&mut self) -> Option<Result<Message, Error>> {
+        self.poll_frame().map(|result| result.map(|frame| frame.message))
+    }
+
+    /// Like [`poll`](Self::poll), but keeps the frame's version and
+    /// sequence ID so the caller can correlate or echo them
+    pub fn poll_frame(&mut self) -> Option<Result<Frame, Error>> {
+        self.release();
+        let frame_len = match self.next_frame()? {
+            Ok(frame_len) => frame_len,
+            Err(e) => return Some(Err(e)),
+        };
+
+        let result = MessageCodec::decode_frame(&self.buf[..frame_len]);
+        self.consume(frame_len);
+
+        let frame = match result {
+            Ok(frame) => frame,
+            Err(e) => {
+                self.stats.decode_errors += 1;
+                return Some(Err(e));
+            }
+        };
+
+        if matches!(frame.message, Message::Hello { .. }) {
+            self.sequences.clear();
+        }
+        if let Err(e) = self.sequences.check(frame.seq) {
+            self.stats.duplicates += 1;
+            return Some(Err(e));
+        }
+
+        self.stats.frames_ok += 1;
+        Some(Ok(frame))
+    }
+
+    /// Return the next complete, CRC-checked frame without decoding it
+    ///
+    /// For transports, which hand frames on as bytes. Invalid candidates
+    /// are skipped and only counted in the [stats](Self::stats), and
+    /// sequence IDs are not checked, since that needs the payload. The
+    /// frame stays buffered until the decoder is next changed.
+    pub fn poll_raw(&mut self) -> Option<&[u8]> {
+        self.release();
+        loop {
+            match self.next_frame()? {
+                Ok(frame_len) => {
+                    self.taken = frame_len;
+                    self.stats.frames_ok += 1;
+                    return Some(&self.buf[..frame_len]);
+                }
+                Err(_) => continue,
+            }
+        }
+    }
+
+    /// The frame most recently returned by [`poll_raw`](Self::poll_raw), or
+    /// nothing once the decoder has moved on
+    pub fn last_frame(&self) -> &[u8] {
+        &self.buf[..self.taken]
+    }
+
+    /// Drop an incomplete frame whose remaining bytes are overdue
+    ///
+    /// Call this with the current time whenever polling finds no frame; the
+    /// frame timeout runs from the first call that sees the frame
+    /// incomplete. Only the frame's START_BYTE is dropped, so scanning
+    /// resumes right after it. Returns whether a frame was dropped.
+    pub fn expire(&mut self, now_ms: u64) -> bool {
+        self.release();
+        self.skip_to_start();
+        if !self.is_incomplete() {
+            self.pending_since = None;
+            return false;
+        }
+        let since = *self.pending_since.get_or_insert(now_ms);
+        if now_ms.saturating_sub(since) < self.timeout_ms {
+            return false;
+        }
+        self.consume(1);
+        self.stats.timeouts += 1;
+        true
+    }
+
+    /// Feed an arbitrarily large chunk and report every decoded item
+    ///
+    /// This is the usual entry point for transports: it interleaves
+    /// [`push`](Self::push) and [`poll`](Self::poll) so that `data` does not
+    /// need to fit in the internal buffer.
+    pub fn decode<F>(&mut self, data: &[u8], mut on_item: F)
+    where
+        F: FnMut(Result<Message, Error>),
+    {
+        self.decode_frames(data, |item| on_item(item.map(|frame| frame.message)));
+    }
+
+    /// Like [`decode`](Self::decode), but reports whole [`Frame`]s
+    pub fn decode_frames<F>(&mut self, mut data: &[u8], mut on_item: F)
+    where
+        F: FnMut(Result<Frame, Error>),
+    {
+        loop {
+            let accepted = self.push(data);
+            data = &data[accepted..];
+
+            while let Some(item) = self.poll_frame() {
+                on_item(item);
+            }
+
+            if data.is_empty() {
+                break;
+            }
+
+            if accepted == 0 && self.len == self.buf.len() {
+                // A full buffer with nothing decodable cannot happen with a
+                // bounded LENGTH check, but never spin if it does.
+                self.stats.overflow_bytes += self.len as u32;
+                self.len = 0;
+            }
+        }
+    }
+
+    /// Discard any partially received frame
+    ///
+    /// Transports call this when an incomplete frame has been pending for
+    /// too long or when the link is reset.
+    pub fn reset(&mut self) {
+        self.release();
+        self.pending_since = None;
+        self.stats.discarded_bytes += self.len as u32;
+        self.len = 0;
+    }
+
+    /// Forget remembered sequence IDs
+    ///
+    /// Call this when a new host session starts so that its IDs are not
+    /// mistaken for duplicates of the previous session.
+    pub fn clear_sequences(&mut self) {
+        self.sequences.clear();
+    }
+
+    /// Number of bytes currently buffered
+    pub fn buffered(&self) -> usize {
+        self.len - self.taken
+    }
+
+    /// Decoder statistics
+    pub fn stats(&self) -> &DecoderStats {
+        &self.stats
+    }
+
+    /// Reset decoder statistics
+    pub fn clear_stats(&mut self) {
+        self.stats = DecoderStats::default();
+    }
+
+    /// Locate the next complete candidate frame at the start of the buffer
+    ///
+    /// On success the frame occupies `buf[..frame_len]`. Invalid candidates
+    /// are dropped one START_BYTE at a time and reported as errors.
+    fn next_frame(&mut self) -> Option<Result<usize, Error>> {
+        self.skip_to_start();
+
+        if self.len < MIN_HEADER_LEN {
+            return None;
+        }
+
+        let version = self.buf[1];
+        if !version::is_compatible(version) {
+            self.stats.unsupported_version += 1;
+            self.consume(1);
+            return Some(Err(Error::UnsupportedVersion));
+        }
+
+        let payload_len = u16::from_le_bytes([self.buf[2], self.buf[3]]) as usize;
+        let frame_len = version::header_len(version) + payload_len + TRAILER_LEN;
+        if frame_len > self.buf.len() {
+            self.stats.invalid_length += 1;
+            self.consume(1);
+            return Some(Err(Error::InvalidFrame));
+        }
+
+        if self.len < frame_len {
+            return None;
+        }
+
+        if self.buf[frame_len - 1] != crate::END_BYTE {
+            self.stats.invalid_end += 1;
+            self.consume(1);
+            return Some(Err(Error::InvalidFrame));
+        }
+
+        if !MessageCodec::crc_matches(&self.buf[..frame_len]) {
+            self.stats.crc_errors += 1;
+            self.consume(1);
+            return Some(Err(Error::CrcMismatch));
+        }
+
+        Some(Ok(frame_len))
+    }
+
+    /// Drop leading bytes up to the next START_BYTE
+    fn skip_to_start(&mut self) {
+        let skip = self.buf[..self.len]
+            .iter()
+            .position(|&b| b == START_BYTE)
+            .unwrap_or(self.len);
+        if skip > 0 {
+            self.stats.discarded_bytes += skip as u32;
+            self.consume(skip);
+        }
+    }
+
+    /// Whether the buffer starts with a frame still waiting for bytes
+    ///
+    /// Expects the buffer to start at a START_BYTE. A header that cannot
+    /// be valid is not waiting for anything; polling drops it.
+    fn is_incomplete(&self) -> bool {
+        if self.len == 0 {
+            return false;
+        }
+        if self.len < MIN_HEADER_LEN {
+            return true;
+        }
+        let version = self.buf[1];
+        let payload_len = u16::from_le_bytes([self.buf[2], self.buf[3]]) as usize;
+        let frame_len = version::header_len(version) + payload_len + TRAILER_LEN;
+        version::is_compatible(version) && frame_len <= self.buf.len() && self.len < frame_len
+    }
+
+    /// Consume the frame [`poll_raw`](Self::poll_raw) returned last
+    fn release(&mut self) {
+        let taken = core::mem::take(&mut self.taken);
+        if taken > 0 {
+            self.consume(taken);
+        }
+    }
+
+    /// Remove `n` bytes from the front of the buffer
+    ///
+    /// Whatever was pending is gone, so the frame timeout starts over.
+    fn consume(&mut self, n: usize) {
+        self.buf.copy_within(n..self.len, 0);
+        self.len -= n;
+        self.pending_since = None;
+    }
+}
@@ -0,0 +1,182 @@
+//! Event subscriptions and queueing
+//!
+//! Hosts opt in to unsolicited [`Event`]s per [`Topic`] with `Subscribe` and
+//! `Unsubscribe`. The firmware keeps one [`Subscriptions`] set per session
+//! and buffers outgoing events in an [`EventQueue`] until the transport can
+//! send them:
+//!
+//! ```
+//! use esp32_bus_pirate_protocol::{
+//!     event::{EventQueue, Subscriptions},
+//!     message::{Event, Topic},
+//! };
+//!
+//! let mut subs = Subscriptions::new();
+//! let mut queue: EventQueue<8> = EventQueue::new();
+//!
+//! subs.subscribe(Topic::UartRx);
+//! queue.publish(&subs, Event::UartRx { data: heapless::Vec::from_slice(b"hi").unwrap() });
+//! queue.publish(&subs, Event::Touch { x: 1, y: 2, pressed: true });
+//!
+//! assert!(matches!(queue.pop(), Some(Event::UartRx { .. })));
+//! assert_eq!(queue.pop(), None);
+//! ```
+
+use crate::message::{Event, Message, Topic};
+use heapless::Deque;
+
+/// Set of topics a host is subscribed to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Subscriptions(u8);
+
+impl Subscriptions {
+    /// No subscriptions
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Start delivering events on `topic`
+    pub fn subscribe(&mut self, topic: Topic) {
+        self.0 |= Self::bit(topic);
+    }
+
+    /// Stop delivering events on `topic`
+    pub fn unsubscribe(&mut self, topic: Topic) {
+        self.0 &= !Self::bit(topic);
+    }
+
+    /// Drop every subscription, e.g. when the host disconnects
+    pub fn clear(&mut self) {
+        self.0 = 0;
+    }
+
+    /// Check whether `topic` is subscribed
+    pub fn is_subscribed(&self, topic: Topic) -> bool {
+        self.0 & Self::bit(topic) != 0
+    }
+
+    /// Check whether no topic is subscribed
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Topics subscribed in either set
+    pub const fn union(self, other: Subscriptions) -> Subscriptions {
+        Subscriptions(self.0 | other.0)
+    }
+
+    /// Check whether `event` should be delivered
+    ///
+    /// Events without a topic go to any host with at least one subscription.
+    pub fn wants(&self, event: &Event) -> bool {
+        match event.topic() {
+            Some(topic) => self.is_subscribed(topic),
+            None => !self.is_empty(),
+        }
+    }
+
+    /// Apply a `Subscribe` or `Unsubscribe` message
+    ///
+    /// Returns `false` for any other message.
+    pub fn handle(&mut self, msg: &Message) -> bool {
+        match msg {
+            Message::Subscribe { topic } => self.subscribe(*topic),
+            Message::Unsubscribe { topic } => self.unsubscribe(*topic),
+            _ => return false,
+        }
+        true
+    }
+
+    const fn bit(topic: Topic) -> u8 {
+        1 << topic as u8
+    }
+}
+
+/// Bounded queue of events waiting to be sent
+///
+/// When the queue is full new events are dropped and counted. The count is
+/// reported to the host as an [`Event::Overflow`] in the position the lost
+/// events would have taken, so the host knows where data is missing.
+pub struct EventQueue<const N: usize> {
+    events: Deque<Event, N>,
+    dropped: u16,
+}
+
+impl<const N: usize> Default for EventQueue<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> EventQueue<N> {
+    /// Create an empty queue
+    pub const fn new() -> Self {
+        Self {
+            events: Deque::new(),
+            dropped: 0,
+        }
+    }
+
+    /// Queue `event` if the host wants it
+    ///
+    /// Returns `false` if the event was filtered out or dropped.
+    pub fn publish(&mut self, subs: &Subscriptions, event: Event) -> bool {
+        subs.wants(&event) && self.push(event)
+    }
+
+    /// Queue `event` unconditionally
+    ///
+    /// Returns `false` if the queue is full and the event was dropped.
+    pub fn push(&mut self, event: Event) -> bool {
+        if self.dropped > 0 {
+            // Room is needed for the overflow marker and the event itself
+            if N - self.events.len() < 2 {
+                self.dropped = self.dropped.saturating_add(1);
+                return false;
+            }
+            self.flush_dropped();
+        }
+
+        if self.events.push_back(event).is_err() {
+            self.dropped = 1;
+            return false;
+        }
+        true
+    }
+
+    /// Take the next event to send
+    pub fn pop(&mut self) -> Option<Event> {
+        if self.events.is_empty() && self.dropped > 0 {
+            self.flush_dropped();
+        }
+        self.events.pop_front()
+    }
+
+    /// Discard queued events and the drop count
+    pub fn clear(&mut self) {
+        self.events.clear();
+        self.dropped = 0;
+    }
+
+    /// Number of queued events
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Check whether nothing is waiting to be sent
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty() && self.dropped == 0
+    }
+
+    /// Events dropped since the last `Overflow` was queued
+    pub fn dropped(&self) -> u16 {
+        self.dropped
+    }
+
+    /// Queue an `Overflow` for the events dropped so far
+    fn flush_dropped(&mut self) {
+        let dropped = core::mem::take(&mut self.dropped);
+        // Callers guarantee room for the marker
+        let _ = self.events.push_back(Event::Overflow { dropped });
+    }
+}
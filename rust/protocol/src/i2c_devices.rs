@@ -0,0 +1,432 @@
+//! Parts commonly found at each 7-bit I2C address
+//!
+//! Ported from the legacy firmware's address list. A scan only learns which
+//! addresses answer, so [`candidates`] turns each into the parts that may be
+//! there. The table is shared by firmware and host: `I2cScanReport` carries
+//! addresses and identified chips, and the host looks the names up here.
+//!
+//! ```
+//! use esp32_bus_pirate_protocol::i2c_devices::candidates;
+//!
+//! assert!(candidates(0x76).iter().any(|device| device.part.contains("BME280")));
+//! assert!(candidates(0x7F).iter().all(|device| device.addr == 0x7F));
+//! ```
+
+/// A part known to answer at an I2C address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KnownDevice {
+    /// 7-bit address
+    pub addr: u8,
+    /// Part number, or several that share the address
+    pub part: &'static str,
+    /// What the part is
+    pub kind: &'static str,
+}
+
+const fn device(addr: u8, part: &'static str, kind: &'static str) -> KnownDevice {
+    KnownDevice { addr, part, kind }
+}
+
+/// Parts that may answer at `addr`, most common first; empty if none is known
+pub fn candidates(addr: u8) -> &'static [KnownDevice] {
+    let start = KNOWN_DEVICES.partition_point(|device| device.addr < addr);
+    let end = KNOWN_DEVICES.partition_point(|device| device.addr <= addr);
+    &KNOWN_DEVICES[start..end]
+}
+
+/// Every known part, sorted by address
+pub static KNOWN_DEVICES: &[KnownDevice] = &[
+    device(0x03, "Grove 6-Position DIP Switch", "Switch"),
+    device(0x03, "Grove 5-Way Switch", "Switch"),
+    device(0x04, "Xadow Multichannel Gas Sensor", "Gas Sensor"),
+    device(0x04, "Grove Multichannel Gas Sensor", "Gas Sensor"),
+    device(0x0B, "LC709203F", "Fuel Gauge"),
+    device(0x0C, "MLX90393", "3-Axis Magnetometer"),
+    device(0x0C, "QMP6988", "Barometric Pressure Sensor"),
+    device(0x0C, "AK8975", "Magnetometer"),
+    device(0x0D, "MLX90393", "3-Axis Magnetometer"),
+    device(0x0D, "AK8975", "Magnetometer"),
+    device(0x0E, "MAG3110", "3-Axis Magnetometer"),
+    device(0x0E, "IST-8310", "Magnetometer"),
+    device(0x0F, "MLX90393", "3-Axis Magnetometer"),
+    device(0x0F, "AK8975", "Magnetometer"),
+    device(0x0F, "Grove I2C Motor Driver", "Motor Driver"),
+    device(0x10, "VEML6075 / VEML7700", "UV / Ambient Light"),
+    device(0x10, "BMM150", "IMU-Pro Magnetometer"),
+    device(0x10, "VEML6075 / VEML7700 / PA1010D", "UV / Light / GPS"),
+    device(0x10, "LM25066", "Power Controller"),
+    device(0x10, "Grove I2C FM Receiver", "FM Receiver"),
+    device(0x11, "Si4713", "FM Transmitter"),
+    device(0x11, "SAA5243P/H", "TV Decoder"),
+    device(0x11, "SAA5243P/L", "TV Decoder"),
+    device(0x11, "SAA5243P/E", "TV Decoder"),
+    device(0x11, "SAA5243P/K", "TV Decoder"),
+    device(0x11, "SAA5246", "TV Decoder"),
+    device(0x11, "Grove I2C FM Receiver v1.1", "FM Receiver"),
+    device(0x11, "Grove 4-Channel SPDT Relay", "Relay"),
+    device(0x11, "Grove 4-Channel Solid State Relay", "Relay"),
+    device(0x11, "Grove 8-Channel Solid State Relay", "Relay"),
+    device(0x12, "PMSA0031", "Gas Sensor"),
+    device(0x12, "SEN-17374", "Air Quality Sensor"),
+    device(0x12, "PMSA003I", "Particulate Matter Sensor"),
+    device(0x12, "Grove I2C FM Receiver v1.1", "FM Receiver"),
+    device(0x12, "Grove 4-Channel SPDT Relay", "Relay"),
+    device(0x12, "Grove 4-Channel Solid State Relay", "Relay"),
+    device(0x12, "Grove 8-Channel Solid State Relay", "Relay"),
+    device(0x13, "VCNL4010", "Proximity / Light"),
+    device(0x13, "BMM150", "3-Axis Magnetometer (Compass)"),
+    device(0x13, "VCNL40x0", "Proximity Sensor"),
+    device(0x13, "SEN-17374", "Air Quality Sensor"),
+    device(0x14, "LM25066", "Power Controller"),
+    device(0x14, "Grove 3-Axis Accelerometer ±16g (BMA400)", "Accelerometer"),
+    device(0x15, "LM25066", "Power Controller"),
+    device(0x15, "Grove 3-Axis Accelerometer ±16g (BMA400)", "Accelerometer"),
+    device(0x16, "LM25066", "Power Controller"),
+    device(0x17, "LM25066", "Power Controller"),
+    device(0x18, "MCP9808", "Temp Sensor"),
+    device(0x18, "LIS331 / LIS3DH", "3-Axis Accelerometer"),
+    device(0x18, "COM-15093", "Accelerometer"),
+    device(0x18, "47L04/47C04/47L16/47C16", "EEPROM"),
+    device(0x18, "Grove 3-Axis Accelerometer ±400g", "Accelerometer"),
+    device(0x19, "LSM303 (Accel)", "3-Axis Accelerometer"),
+    device(0x19, "LIS331 / LIS3DH", "3-Axis Accelerometer"),
+    device(0x19, "LSM303", "Accelerometer (Accel=0x19)"),
+    device(0x1A, "AGS02MA", "TVOC Gas Sensor"),
+    device(0x1A, "47L04/47C04/47L16/47C16", "EEPROM"),
+    device(0x1B, "MCP9808", "Temp Sensor"),
+    device(0x1B, "Grove Q Touch Sensor", "Touch Sensor"),
+    device(0x1C, "MMA8451", "3-Axis Accelerometer"),
+    device(0x1C, "LIS3MDL", "Magnetometer"),
+    device(0x1C, "MMA845x", "3-Axis Accelerometer"),
+    device(0x1C, "FXOS8700", "Accelerometer/Magnetometer"),
+    device(0x1C, "SAA7706H", "DSP"),
+    device(0x1D, "ADXL345", "3-Axis Accelerometer"),
+    device(0x1D, "ADXL343 / ADXL345", "3-Axis Accelerometer"),
+    device(0x1D, "LSM9DS0", "Accel/Mag"),
+    device(0x1D, "FXOS8700", "Accel + Mag"),
+    device(0x1E, "LIS3MDL / HMC5883L", "3-Axis Magnetometer"),
+    device(0x1E, "HMC5883 / LIS2MDL / LIS3MDL", "Magnetometer"),
+    device(0x1E, "LSM303", "Magnetometer (Mag=0x1E)"),
+    device(0x1F, "FXOS8700", "Accel + Mag"),
+    device(0x20, "MCP23017 / PCF8574", "I/O Expander"),
+    device(0x20, "MCP23008 / MCP23017", "GPIO Expander"),
+    device(0x20, "XD8574A", "I/O Expander"),
+    device(0x20, "MA12070P", "Audio Amplifier"),
+    device(0x20, "Chirp! HW-061", "Distance Sensor"),
+    device(0x20, "PCA6408A", "I/O Expander"),
+    device(0x20, "TCA9554", "I/O Expander"),
+    device(0x21, "MCP23017", "I/O Expander"),
+    device(0x21, "SAA4700", "Video Processor"),
+    device(0x22, "MCP23017", "I/O Expander"),
+    device(0x22, "PCA1070", "I/O Expander"),
+    device(0x23, "BH1750", "Ambient Light"),
+    device(0x23, "MCP23017", "I/O Expander"),
+    device(0x23, "BH1750FVI", "Digital Light Sensor"),
+    device(0x24, "MCP23017", "I/O Expander"),
+    device(0x24, "M5Module4EncoderMotor", "Motor Driver (I2C)"),
+    device(0x24, "PN532", "NFC / RFID"),
+    device(0x24, "PCD3312C", "Teletext Decoder"),
+    device(0x24, "PCD3311C", "Teletext Decoder"),
+    device(0x24, "Grove NFC", "NFC"),
+    device(0x25, "MCP23017", "I/O Expander"),
+    device(0x26, "MCP23017", "I/O Expander"),
+    device(0x26, "STM32F030", "Scales"),
+    device(0x26, "MSA301", "3-Axis Accelerometer"),
+    device(0x27, "MCP23017", "I/O Expander"),
+    device(0x27, "HIH6130", "Humidity / Temp Sensor"),
+    device(0x28, "BNO055", "9-DOF IMU"),
+    device(0x28, "WS1850S", "RFID/NFC"),
+    device(0x28, "PM2008", "Air Quality Sensor"),
+    device(0x28, "MCP4532", "Digital Potentiometer"),
+    device(0x28, "FS3000", "Air Flow Sensor"),
+    device(0x29, "TCS34725 / VL53L0X", "Color / Distance Sensor"),
+    device(0x29, "VL53L0X", "ToF Distance Sensor"),
+    device(0x29, "TCS3472", "Color Sensor"),
+    device(0x29, "VL53L1X", "ToF Distance Sensor (ToF4M)"),
+    device(0x29, "VL53L0x / VL6180X / TCS34725", "ToF / Color Sensor"),
+    device(0x29, "Grove Digital Light Sensor", "Ambient Light"),
+    device(0x29, "Grove Time of Flight Distance Sensor (VL53L0X)", "ToF Distance Sensor"),
+    device(0x2A, "DS1841 / DS3502", "Digital Potentiometer"),
+    device(0x2A, "DS1841", "Digital Potentiometer"),
+    device(0x2A, "DS3502", "Digital Potentiometer"),
+    device(0x2B, "DS1841", "Digital Potentiometer"),
+    device(0x2B, "DS3502", "Digital Potentiometer"),
+    device(0x2C, "CAP1188", "Capacitive Touch"),
+    device(0x2C, "AD5252", "Digital Potentiometer"),
+    device(0x2C, "CAT5171", "Digital Potentiometer"),
+    device(0x2C, "AD5251", "Digital Potentiometer"),
+    device(0x2C, "AD5248", "Digital Potentiometer"),
+    device(0x2D, "ST25DV16K", "NFC EEPROM"),
+    device(0x2D, "AD5248", "Digital Potentiometer"),
+    device(0x2E, "PCT2075", "Temperature Sensor"),
+    device(0x2F, "AD5243", "Digital Potentiometer"),
+    device(0x30, "NeoKey / NeoSlider", "NeoPixel Input"),
+    device(0x30, "SAA2502", "Teletext Decoder"),
+    device(0x31, "SAA2502", "Teletext Decoder"),
+    device(0x32, "ZMOD4450", "Air Quality Sensor"),
+    device(0x32, "ZMOD4410", "Air Quality Sensor"),
+    device(0x33, "MLX90640", "Thermal Camera"),
+    device(0x34, "MAX30100", "Pulse Oximeter / HR Sensor"),
+    device(0x36, "MAX17043/MAX17048", "Fuel Gauge"),
+    device(0x36, "MAX17048", "Fuel Gauge"),
+    device(0x38, "AHT20 / DHT20", "Humidity / Temp Sensor"),
+    device(0x38, "PCF8574A", "I/O Expander"),
+    device(0x38, "SAA1064", "LED Driver"),
+    device(0x38, "PCF8574AP", "I/O Expander"),
+    device(0x38, "RRH46410", "Unknown Sensor"),
+    device(0x38, "FT6x06", "Touch Controller"),
+    device(0x38, "Grove I2C UV Sensor (VEML6070 LSB)", "UV Sensor"),
+    device(0x39, "APDS-9960 / AS7341", "Color / Proximity Sensor"),
+    device(0x39, "TSL2561 / AS7341 / APDS9960", "Light / Color / Proximity"),
+    device(0x39, "Grove I2C UV Sensor (VEML6070 MSB)", "UV Sensor"),
+    device(0x39, "Grove I2C Color Sensor", "Color Sensor"),
+    device(0x3A, "PCF8577C", "LCD Driver"),
+    device(0x3B, "PCF8569", "LCD Driver"),
+    device(0x3C, "SSD1306 OLED", "Display"),
+    device(0x3C, "SSD1306 / SH1106 / ST75256", "OLED / LCD Display"),
+    device(0x3C, "Grove OLED Display 1.12\"", "Display"),
+    device(0x3C, "Grove OLED Display 0.96\"", "Display"),
+    device(0x3C, "Grove OLED Display 1.12'' V2", "Display"),
+    device(0x3C, "Grove LCD RGB Backlight", "Display"),
+    device(0x3D, "SSD1306 OLED", "Display"),
+    device(0x3E, "BU9796", "LCD Driver"),
+    device(0x3E, "Grove LCD RGB Backlight", "Display"),
+    device(0x3E, "Grove 16 x 2 LCD (Black on Yellow)", "Display"),
+    device(0x3E, "Grove 16 x 2 LCD (Black on Red)", "Display"),
+    device(0x3E, "Grove 16 x 2 LCD (White on Blue)", "Display"),
+    device(0x3F, "PCF8574AP", "I/O Expander"),
+    device(0x40, "INA219 / INA260", "Power Monitor"),
+    device(0x40, "Si7021 / HTU21D", "Humidity / Temp Sensor"),
+    device(0x40, "Si7021 / HTU21D / HDC1008", "Humidity/Temp"),
+    device(0x40, "TEA6330", "Audio Processor"),
+    device(0x40, "TMP006", "IR Temp Sensor"),
+    device(0x40, "TDA8421", "Audio Processor"),
+    device(0x40, "HTU31D", "Humidity/Temp"),
+    device(0x40, "HDC1080", "Humidity/Temp"),
+    device(0x40, "TEA6320", "Audio Processor"),
+    device(0x40, "TDA9860", "Audio Processor"),
+    device(0x40, "TEA6300", "Audio Processor"),
+    device(0x40, "NE5751", "Audio Processor"),
+    device(0x40, "Grove Temperature & Humidity Sensor (High Accuracy)", "Humidity / Temp Sensor"),
+    device(0x41, "TDA8424", "Audio Processor"),
+    device(0x41, "STMPE610", "Touch Controller"),
+    device(0x41, "STMPE811", "Touch Controller"),
+    device(0x41, "TDA8425", "Audio Processor"),
+    device(0x41, "TDA8426", "Audio Processor"),
+    device(0x41, "PCA9536", "I/O Expander"),
+    device(0x42, "STM32F030", "AC Measurement"),
+    device(0x42, "TDA8417", "Audio Processor"),
+    device(0x42, "TDA8415", "Audio Processor"),
+    device(0x44, "SHT31", "Humidity / Temp Sensor"),
+    device(0x44, "SHT30", "Temperature/Humidity Sensor"),
+    device(0x44, "SHT40", "Humidity/Temperature (ENV-IV)"),
+    device(0x44, "SHT31 / SHT40 / SHT45", "Humidity/Temp"),
+    device(0x44, "TDA4671", "Video Processor"),
+    device(0x44, "TDA4780", "Video Processor"),
+    device(0x44, "TDA4670", "Video Processor"),
+    device(0x44, "TDA4688", "Video Processor"),
+    device(0x44, "ISL29125", "Color Sensor"),
+    device(0x44, "TDA4672", "Video Processor"),
+    device(0x44, "TDA4687", "Video Processor"),
+    device(0x44, "HS30xx", "Humidity Sensor"),
+    device(0x44, "TDA8442", "Audio Processor"),
+    device(0x44, "TDA4680", "Video Processor"),
+    device(0x45, "SHT31", "Humidity / Temp Sensor"),
+    device(0x45, "TDA7433", "Audio Processor"),
+    device(0x45, "TDA8376", "Video Processor"),
+    device(0x45, "Grove Sunlight Sensor", "Light Sensor"),
+    device(0x46, "TDA8370", "Video Processor"),
+    device(0x46, "TDA9150", "Video Processor"),
+    device(0x48, "TMP102 / TMP117", "Temp Sensor"),
+    device(0x48, "ADS1115 / ADS1015", "ADC"),
+    device(0x48, "PN532", "NFC Reader"),
+    device(0x48, "ADS1115", "Ammeter ADC"),
+    device(0x48, "ADS1100", "ADC"),
+    device(0x48, "ADS1110", "ADC v1.1"),
+    device(0x48, "ADS1115 / TMP102 / TMP117", "ADC / Temp"),
+    device(0x48, "LM75b", "Temp Sensor"),
+    device(0x48, "STDS75", "Temp Sensor"),
+    device(0x49, "ADS1115", "ADC"),
+    device(0x49, "AS7262 / TSL2561", "Color / Light Sensor"),
+    device(0x49, "AS7262", "Spectral Sensor"),
+    device(0x49, "CS43L22", "Audio Codec"),
+    device(0x4A, "ADS1115", "ADC"),
+    device(0x4A, "Grove Mini Track Ball", "Input Device"),
+    device(0x4B, "ADS1115", "ADC"),
+    device(0x4C, "EMC2101", "Temp/Fan Controller"),
+    device(0x4C, "Grove 3-Axis Accelerometer ±1.5g", "Accelerometer"),
+    device(0x50, "AT24Cxx / EDID", "EEPROM"),
+    device(0x50, "EDID EEPROM", "Display Identification"),
+    device(0x50, "24LCxx / MB85RC", "EEPROM / FRAM"),
+    device(0x50, "FS1015", "Flow Sensor"),
+    device(0x50, "AT24C02N", "EEPROM"),
+    device(0x51, "PCF8563", "RTC"),
+    device(0x51, "AT24Cxx", "EEPROM"),
+    device(0x51, "EEPROM", "Ammeter Memory"),
+    device(0x51, "VCNL4200", "Proximity Sensor"),
+    device(0x51, "Grove High Precision RTC", "RTC"),
+    device(0x52, "AT24Cxx", "EEPROM"),
+    device(0x52, "Nunchuck", "Nintendo Controller"),
+    device(0x52, "APDS-9250", "Gesture Sensor"),
+    device(0x52, "SI1133", "UV Sensor"),
+    device(0x52, "Grove I2C Color Sensor v2.0", "Color Sensor"),
+    device(0x53, "AT24Cxx", "EEPROM"),
+    device(0x53, "ADXL345", "Accelerometer"),
+    device(0x53, "ADXL343 / ADXL345", "3-Axis Accelerometer"),
+    device(0x53, "ST25DV16K", "NFC EEPROM"),
+    device(0x53, "Grove 3-Axis Accelerometer ±16g", "Accelerometer"),
+    device(0x53, "Grove NFC Tag", "NFC"),
+    device(0x54, "AT24Cxx", "EEPROM"),
+    device(0x54, "HS40xx", "Humidity Sensor"),
+    device(0x55, "AT24Cxx", "EEPROM"),
+    device(0x55, "STM32F030", "AIN4-20mA"),
+    device(0x55, "D7S", "Earthquake Sensor"),
+    device(0x55, "Grove I2C ADC", "ADC"),
+    device(0x56, "AT24Cxx", "EEPROM"),
+    device(0x56, "47L04/47C04/47L16/47C16", "EEPROM"),
+    device(0x57, "AT24Cxx", "EEPROM"),
+    device(0x57, "SFP EEPROM", "EEPROM in SFP module"),
+    device(0x57, "RCWL9260", "Range Sensor"),
+    device(0x57, "MAX30100", "Heart Rate Sensor"),
+    device(0x57, "MAX3010x", "Pulse & Oximetry Sensor"),
+    device(0x57, "ST25DV16K", "NFC EEPROM"),
+    device(0x57, "Grove NFC Tag", "NFC"),
+    device(0x58, "SGP30", "Air Quality Sensor"),
+    device(0x58, "AT24Cxx", "EEPROM"),
+    device(0x58, "GP8413", "DAC2 (alt address)"),
+    device(0x58, "TPA2016", "Audio Amplifier"),
+    device(0x59, "SGP40", "Air Quality Sensor"),
+    device(0x59, "AT24Cxx", "EEPROM"),
+    device(0x59, "STM32F030", "ExtEncoder"),
+    device(0x59, "GP8413", "DAC2 (default address)"),
+    device(0x5A, "MLX90614", "IR Temp Sensor"),
+    device(0x5A, "CCS811", "Air Quality Sensor"),
+    device(0x5A, "DRV2605L", "Haptic Driver"),
+    device(0x5A, "AT24Cxx", "EEPROM"),
+    device(0x5A, "MPR121", "Capacitive Touch"),
+    device(0x5A, "STHS34PF80", "Thermal PIR (TMOS PIR)"),
+    device(0x5A, "MPR121 / CCS811 / DRV2605 / MLX9061x", "Touch / VOC / Haptic / Temp"),
+    device(0x5A, "Grove Haptic Motor", "Haptic Driver"),
+    device(0x5A, "Grove I2C Touch Sensor", "Touch Sensor"),
+    device(0x5B, "CCS811", "Air Quality Sensor"),
+    device(0x5B, "AT24Cxx", "EEPROM"),
+    device(0x5B, "Grove I2C Touch Sensor", "Touch Sensor"),
+    device(0x5B, "Grove Digital Infrared Temperature Sensor", "IR Temp Sensor"),
+    device(0x5B, "Grove 12 Key Capacitive Touch V2 (MPR121)", "Touch Sensor"),
+    device(0x5C, "LPS22HB", "Pressure Sensor"),
+    device(0x5C, "AT24Cxx", "EEPROM"),
+    device(0x5C, "AM2315 / AM2320 / LPS2x", "Humidity / Pressure"),
+    device(0x5C, "Grove I2C Touch Sensor", "Touch Sensor"),
+    device(0x5C, "Grove 12 Key Capacitive Touch V2 (MPR121)", "Touch Sensor"),
+    device(0x5D, "LPS25HB", "Pressure Sensor"),
+    device(0x5D, "AT24Cxx", "EEPROM"),
+    device(0x5D, "SFA30", "Air Quality Sensor"),
+    device(0x5D, "Grove I2C Touch Sensor", "Touch Sensor"),
+    device(0x5D, "Grove 12 Key Capacitive Touch V2 (MPR121)", "Touch Sensor"),
+    device(0x5E, "AT24Cxx", "EEPROM"),
+    device(0x5F, "HTS221", "Humidity/Temp"),
+    device(0x60, "MPL3115A2", "Pressure / Altitude Sensor"),
+    device(0x60, "VCNL4040", "Proximity / Light"),
+    device(0x60, "MCP4725", "DAC"),
+    device(0x60, "Si5351", "Clock Generator"),
+    device(0x60, "MCP4728 / MCP9600 / Si5351", "DAC / Temp / Clock"),
+    device(0x60, "SAB3037", "Audio Processor"),
+    device(0x60, "ATECC508A", "Crypto Auth IC"),
+    device(0x60, "MPL115A2", "Pressure Sensor"),
+    device(0x60, "ATECC608A", "Crypto Auth IC"),
+    device(0x60, "TSA5511", "Tuner"),
+    device(0x60, "Si1145", "UV / Ambient Light"),
+    device(0x60, "Grove I2C Thermocouple Amplifier (MCP9600)", "Temp Sensor"),
+    device(0x61, "SCD30", "CO2 Sensor"),
+    device(0x62, "SCD41", "CO2 Sensor"),
+    device(0x62, "SCD40-D-R2", "CO2 Sensor"),
+    device(0x62, "Grove LCD RGB Backlight (3rd Addr)", "Display"),
+    device(0x63, "UMA1014T", "Audio Processor"),
+    device(0x64, "MCP4725A2", "DAC"),
+    device(0x65, "GP8413", "DAC2 (alt address)"),
+    device(0x65, "MCP4725A2", "DAC"),
+    device(0x66, "MAX31855KASA+T", "K-Type Thermocouple"),
+    device(0x66, "STM32F030", "KMeter-ISO"),
+    device(0x66, "MCP4725A3", "DAC"),
+    device(0x66, "LTC4151", "Power Monitor"),
+    device(0x67, "LTC4151", "Power Monitor"),
+    device(0x67, "Grove I2C Thermocouple Amplifier (MCP9600)", "Temp Sensor"),
+    device(0x68, "MPU6050 / MPU9250", "6-Axis / 9-Axis IMU"),
+    device(0x68, "DS1307 / DS3231", "RTC"),
+    device(0x68, "AMG8833", "IR Thermal Camera"),
+    device(0x68, "MPU6886", "6-Axis IMU"),
+    device(0x68, "BMI270", "IMU-Pro Accelerometer/Gyroscope"),
+    device(0x68, "DS1307 / DS3231 / MPU6050", "RTC / IMU"),
+    device(0x68, "ITG3200", "Gyroscope"),
+    device(0x68, "ICM-20948", "IMU"),
+    device(0x68, "MCP3422", "ADC"),
+    device(0x68, "WITTY PI 3", "RTC / Power Mgmt"),
+    device(0x68, "DS1371", "RTC"),
+    device(0x68, "BQ32000", "RTC"),
+    device(0x68, "Grove RTC", "RTC"),
+    device(0x68, "Grove 3-Axis Digital Gyro", "Gyroscope"),
+    device(0x68, "Grove IR Temperature Sensor Array (AMG8833)", "IR Array"),
+    device(0x68, "Grove IMU 9DOF v2.0", "IMU"),
+    device(0x68, "Grove IMU 10DOF v2.0", "IMU"),
+    device(0x69, "MPU9250", "9-Axis IMU"),
+    device(0x69, "DS3232", "RTC"),
+    device(0x69, "VL53L1X", "Time-of-Flight Sensor"),
+    device(0x69, "MPU9250 / ICM20649", "IMU"),
+    device(0x69, "RRH62000", "Unknown Sensor"),
+    device(0x69, "SPS30", "Air Quality Sensor"),
+    device(0x69, "MAX31341", "RTC"),
+    device(0x69, "Grove IR Temperature Sensor Array (AMG8833)", "IR Array"),
+    device(0x69, "Grove IMU 9DOF v2.0", "IMU"),
+    device(0x69, "Grove IMU 10DOF v2.0", "IMU"),
+    device(0x69, "Grove IMU 9DOF (ICM20600 & AK09918)", "IMU"),
+    device(0x6A, "LSM6DS3", "6-Axis IMU"),
+    device(0x6A, "PCF8523", "RTC"),
+    device(0x6A, "LSM6DS33 / LSM6DSOX", "6-Axis IMU"),
+    device(0x6A, "L3GD20H", "Gyroscope"),
+    device(0x6B, "LSM6DS3", "6-Axis IMU"),
+    device(0x6B, "L3GD20H", "Gyroscope"),
+    device(0x6C, "LTC4151", "Power Monitor"),
+    device(0x6D, "LTC4151", "Power Monitor"),
+    device(0x6E, "LTC4151", "Power Monitor"),
+    device(0x6F, "MCP79410", "RTC"),
+    device(0x6F, "MCP7940N", "RTC"),
+    device(0x70, "HT16K33 / PCA9685 AllCall", "LED Driver / PWM"),
+    device(0x70, "TCA9548A", "I2C Multiplexer"),
+    device(0x70, "PaHub v2.1", "I2C Multiplexer (PCA9548A)"),
+    device(0x70, "QMP6988", "Barometric Pressure (ENV-III)"),
+    device(0x70, "HT16K33 / TCA9548 / SHTC3", "LED Matrix / I2C Mux / Temp"),
+    device(0x71, "TCA9548A", "I2C Multiplexer"),
+    device(0x71, "PaHub v2.1", "I2C Multiplexer (PCA9548A)"),
+    device(0x72, "TCA9544A", "I2C Multiplexer"),
+    device(0x73, "PAJ7620U2", "Gesture Sensor"),
+    device(0x73, "Grove Gesture Sensor (PAJ7620U2)", "Gesture Sensor"),
+    device(0x74, "IS31FL3731", "LED Matrix Driver"),
+    device(0x74, "PCA9539", "I/O Expander"),
+    device(0x75, "IP5306 (Power Management)", "Power IC / Battery Management"),
+    device(0x75, "PCA9539", "I/O Expander"),
+    device(0x76, "BME280 / BMP280 / BMP388", "Humidity / Pressure Sensor"),
+    device(0x76, "BMP280", "Pressure/Temperature Sensor"),
+    device(0x76, "BME280 / BMP280 / DPS310", "Barometric / Temp / Humidity"),
+    device(0x76, "MS5611", "Pressure Sensor"),
+    device(0x76, "SPL06-007", "Pressure Sensor"),
+    device(0x76, "MS5607", "Pressure Sensor"),
+    device(0x76, "Grove Barometer (High-Accuracy)", "Pressure Sensor"),
+    device(0x76, "Grove Temp/Humidity/Gas/Pressure Sensor (BME680)", "Env Sensor"),
+    device(0x77, "BMP180 / BME680", "Pressure / Gas Sensor"),
+    device(0x77, "PaHub v2.1", "I2C Multiplexer (PCA9548A)"),
+    device(0x77, "BME688", "Humidity/Temp/Gas/Pressure (ENV-Pro)"),
+    device(0x77, "BME680 / BMP180 / BMP388", "Barometric / Gas / Temp"),
+    device(0x77, "BMA180", "Accelerometer"),
+    device(0x77, "BMP085", "Pressure Sensor"),
+    device(0x77, "IS31FL3731", "LED Matrix Driver"),
+    device(0x77, "Grove IMU 9DOF / 10DOF", "IMU"),
+    device(0x77, "Grove Temp/Humidity/Gas/Pressure Sensor (BME680)", "Env Sensor"),
+    device(0x77, "Grove Barometer Sensor", "Pressure Sensor"),
+    device(0x77, "Grove Barometer Sensor (BMP180)", "Pressure Sensor"),
+    device(0x7A, "HTU21D", "Humidity / Temp Sensor"),
+    device(0x7B, "SHT20", "Humidity / Temp Sensor"),
+    device(0x7C, "SHT30", "Humidity / Temp Sensor"),
+    device(0x7D, "SHT31", "Humidity / Temp Sensor"),
+    device(0x7E, "SHT35", "Humidity / Temp Sensor"),
+    device(0x7F, "SHT40", "Humidity / Temp Sensor"),
+];
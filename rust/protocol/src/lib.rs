@@ -6,12 +6,25 @@
 //! with the Bus Pirate firmware over serial, USB, or network.
 
 pub mod message;
+pub mod bulk;
 pub mod codec;
+pub mod decoder;
+pub mod event;
+pub mod i2c_devices;
 pub mod version;
 
-pub use codec::MessageCodec;
-pub use message::{Message, Mode, Response, ErrorCode};
-pub use version::PROTOCOL_VERSION;
+pub use bulk::{BulkError, BulkReceiver, BulkSender};
+pub use codec::{Frame, MessageCodec, SequenceWindow, UNSEQUENCED};
+pub use decoder::{frame_timeout_for_baud, DecoderStats, FrameDecoder};
+pub use event::{EventQueue, Subscriptions};
+pub use message::{
+    BulkTarget, DeviceInfo, EepromPart, ErrorCode, Event, Features, I2cAddress, I2cChip, I2cEeprom, I2cLineState, I2cNack,
+    I2cOp, I2cRecoveryReport, I2cRegister, I2cRegisterMap, I2cScanEntry, I2cScanOptions, I2cScanProbe, I2cScanReport,
+    I2cSpeed, I2cSweepEntry, I2cSweepReport, I2cTargetAccess, I2cTransactionReport, Message, Mode, NackSource,
+    RegisterAccess, Response, ScriptError, ScriptReport, SniffRecord, SnifferEvent, Topic, I2C_MAX_SEGMENTS,
+    I2C_TARGET_LOG_SIZE, I2C_TARGET_REGISTERS, I2C_WRITE_SIZE,
+};
+pub use version::{PROTOCOL_VERSION, PROTOCOL_VERSION_V1, PROTOCOL_VERSION_V2};
 
 /// Protocol framing constants
 pub const START_BYTE: u8 = 0xAA;
@@ -37,6 +50,8 @@ pub enum Error {
     DecodingFailed,
     /// Buffer full
     BufferFull,
+    /// Sequence ID was already seen recently
+    DuplicateSequence,
 }
 
 
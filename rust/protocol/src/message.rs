@@ -1,5 +1,7 @@
 //! Protocol message definitions
 
+use crate::i2c_devices::KnownDevice;
+use crate::version::{PROTOCOL_VERSION_V1, PROTOCOL_VERSION_V2};
 use heapless::{String, Vec};
 use serde::{Deserialize, Serialize};
 
@@ -55,6 +57,111 @@ pub enum Message {
     Response(Response),
     /// Error response
     Error(ErrorCode),
+    
+    // ===== Session (v2+) =====
+    // New variants are appended so that v1 variant indices stay stable.
+    /// Open a session and offer the protocol versions the host supports
+    Hello { versions: Vec<u8, 8>, max_frame: u16 },
+    
+    // ===== Bulk Transfer (v2+) =====
+    /// Request a device-to-host transfer of `len` bytes from `target`
+    BulkRequest { id: u16, target: BulkTarget, offset: u32, len: u32 },
+    /// Announce a transfer of `total_len` bytes to or from `target`
+    BulkBegin { id: u16, target: BulkTarget, total_len: u32 },
+    /// One chunk of transfer data starting at `offset`
+    BulkChunk { id: u16, offset: u32, data: Vec<u8, BULK_CHUNK_SIZE> },
+    /// Finish a transfer; `crc32` covers the whole payload
+    BulkEnd { id: u16, crc32: u32 },
+    /// Receiver has everything before `offset` (also the reply to `BulkResume`)
+    BulkAck { id: u16, offset: u32 },
+    /// Ask the receiver where to resume an interrupted transfer
+    BulkResume { id: u16 },
+    
+    // ===== Events (v2+) =====
+    /// Start receiving unsolicited events on `topic`
+    Subscribe { topic: Topic },
+    /// Stop receiving events on `topic`
+    Unsubscribe { topic: Topic },
+    /// Unsolicited notification from the device
+    Event(Event),
+    
+    // ===== Scripts (v2+) =====
+    /// Run the script stored at `path` (written with `FileWrite` or a bulk transfer)
+    RunScript { path: String<128> },
+    
+    // ===== I2C Transactions (v2+) =====
+    /// Run `ops` on the device at `addr` as one transaction, with a repeated
+    /// start between segments and a single stop at the end
+    I2cTransaction { addr: I2cAddress, ops: Vec<I2cOp, I2C_MAX_SEGMENTS> },
+    /// Scan the I2C bus for devices with 10-bit addresses
+    I2cScanTenBit,
+    
+    // ===== I2C Recovery (v2+) =====
+    /// Free an I2C bus a device is holding low and report on its lines
+    I2cRecover,
+    
+    // ===== I2C EEPROM (v2+) =====
+    // Contents are read and written with bulk transfers to `BulkTarget::I2cEeprom`
+    /// List the addresses of `eeprom`'s blocks that acknowledge
+    I2cEepromProbe { eeprom: I2cEeprom },
+    /// Fill `eeprom` with 0xFF
+    I2cEepromErase { eeprom: I2cEeprom },
+    /// Check `len` bytes of `eeprom` from `address` against their CRC-32;
+    /// fails with `ChecksumMismatch` if they differ
+    I2cEepromVerify { eeprom: I2cEeprom, address: u32, len: u32, crc32: u32 },
+    
+    // ===== I2C Target (v2+) =====
+    /// Emulate the device described by `map`, answering at `map.addr`;
+    /// accesses are published on `Topic::I2cTarget`. The target may share
+    /// the controller's peripheral, so until `I2cTargetStop` I2C controller
+    /// commands fail with `Busy`
+    I2cTargetStart { map: I2cRegisterMap },
+    /// Stop emulating and release the bus
+    I2cTargetStop,
+    /// Current register values of the emulated device
+    I2cTargetRegisters,
+    
+    // ===== I2C Scan Report (v2+) =====
+    /// Scan the I2C bus as `options` say and, with `identify`, read the ID
+    /// register of parts known to have one to tell apart those that share
+    /// an address
+    I2cScanAnnotated { options: I2cScanOptions, identify: bool },
+    /// Scan the I2C bus at each `I2cSpeed`, then return to the configured
+    /// frequency
+    I2cScanSweep { options: I2cScanOptions },
+}
+
+impl Message {
+    /// Oldest protocol version that can carry this message
+    pub fn min_version(&self) -> u8 {
+        match self {
+            Message::Hello { .. }
+            | Message::BulkRequest { .. }
+            | Message::BulkBegin { .. }
+            | Message::BulkChunk { .. }
+            | Message::BulkEnd { .. }
+            | Message::BulkAck { .. }
+            | Message::BulkResume { .. }
+            | Message::Subscribe { .. }
+            | Message::Unsubscribe { .. }
+            | Message::Event(_)
+            | Message::RunScript { .. }
+            | Message::I2cTransaction { .. }
+            | Message::I2cScanTenBit
+            | Message::I2cRecover
+            | Message::I2cEepromProbe { .. }
+            | Message::I2cEepromErase { .. }
+            | Message::I2cEepromVerify { .. }
+            | Message::I2cTargetStart { .. }
+            | Message::I2cTargetStop
+            | Message::I2cTargetRegisters
+            | Message::I2cScanAnnotated { .. }
+            | Message::I2cScanSweep { .. } => PROTOCOL_VERSION_V2,
+            Message::Response(response) => response.min_version(),
+            Message::Error(code) => code.min_version(),
+            _ => PROTOCOL_VERSION_V1,
+        }
+    }
 }
 
 /// Bus Pirate operating modes
@@ -117,6 +224,548 @@ pub enum Response {
     ConfigValue(String<64>),
     /// File list
     FileList(Vec<String<64>, 32>),
+    /// Device capabilities, in reply to `Hello`
+    DeviceInfo(DeviceInfo),
+    /// Outcome of `RunScript`
+    Script(ScriptReport),
+    /// Outcome of `I2cTransaction`
+    I2cTransaction(I2cTransactionReport),
+    /// 10-bit addresses found by `I2cScanTenBit`
+    I2cTenBitDevices(Vec<u16, 128>),
+    /// Outcome of `I2cRecover`
+    I2cRecovery(I2cRecoveryReport),
+    /// Registers of the emulated I2C device, in reply to `I2cTargetRegisters`
+    I2cRegisterMap(I2cRegisterMap),
+    /// Devices found by `I2cScanAnnotated`
+    I2cScanReport(I2cScanReport),
+    /// Devices found by `I2cScanSweep`, with the speeds each answered at
+    I2cSweepReport(I2cSweepReport),
+}
+
+impl Response {
+    /// Oldest protocol version that can carry this response
+    pub fn min_version(&self) -> u8 {
+        match self {
+            Response::DeviceInfo(_)
+            | Response::Script(_)
+            | Response::I2cTransaction(_)
+            | Response::I2cTenBitDevices(_)
+            | Response::I2cRecovery(_)
+            | Response::I2cRegisterMap(_)
+            | Response::I2cScanReport(_)
+            | Response::I2cSweepReport(_) => PROTOCOL_VERSION_V2,
+            _ => PROTOCOL_VERSION_V1,
+        }
+    }
+}
+
+/// What a script did, in reply to `RunScript`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScriptReport {
+    /// Bytes the script emitted
+    pub output: Vec<u8, 512>,
+    /// Line the script stopped at and why, if it did not run to the end
+    pub error: Option<ScriptError>,
+}
+
+/// Where and why a script stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScriptError {
+    /// Line of the script, counting from 1
+    pub line: u16,
+    /// `InvalidCommand` for a line that does not compile, `AssertionFailed`
+    /// for a failed `assert`, otherwise what the bus reported
+    pub code: ErrorCode,
+}
+
+/// Most segments in one `I2cTransaction`
+pub const I2C_MAX_SEGMENTS: usize = 8;
+
+/// Most bytes in one `I2cOp::Write`
+///
+/// Adjacent writes go out without a repeated start in between, so a longer
+/// write can be split across several segments.
+pub const I2C_WRITE_SIZE: usize = 64;
+
+/// Address of an I2C device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum I2cAddress {
+    /// 7-bit address (0x00-0x7F)
+    SevenBit(u8),
+    /// 10-bit address (0x000-0x3FF)
+    TenBit(u16),
+}
+
+impl From<u8> for I2cAddress {
+    fn from(addr: u8) -> Self {
+        I2cAddress::SevenBit(addr)
+    }
+}
+
+/// One segment of an `I2cTransaction`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum I2cOp {
+    /// Write these bytes
+    Write(Vec<u8, I2C_WRITE_SIZE>),
+    /// Read this many bytes (at least one)
+    Read(u16),
+}
+
+/// What an I2C transaction read, in reply to `I2cTransaction`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cTransactionReport {
+    /// Bytes of every `Read` segment, concatenated in order; empty after a NACK
+    pub data: Vec<u8, 512>,
+    /// The NACK that ended the transaction, if any
+    pub nack: Option<I2cNack>,
+}
+
+/// A NACK that ended an I2C transaction
+///
+/// The controller reports whether the address or a data byte was refused,
+/// but not in which segment, so the segment is inferred: an address NACK is
+/// on the first segment and a data NACK on the only `Write`. A data NACK in a
+/// transaction with several writes, or to a 10-bit address, has no segment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct I2cNack {
+    /// Index into the transaction's `ops`, or `None` when it is not known
+    pub segment: Option<u8>,
+    /// Whether the address or a data byte was refused
+    pub source: NackSource,
+}
+
+/// Which part of a segment was not acknowledged
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NackSource {
+    /// The address byte
+    Address,
+    /// A data byte
+    Data,
+    /// The bus could not tell
+    Unknown,
+}
+
+/// What the lines of an I2C bus looked like and whether it was freed, in
+/// reply to `I2cRecover`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct I2cRecoveryReport {
+    /// SCL before recovery
+    pub scl: I2cLineState,
+    /// SDA before recovery
+    pub sda: I2cLineState,
+    /// SCL pulses it took for the device to let go of SDA (at most 9)
+    pub clocks: u8,
+    /// Both lines are high now
+    pub recovered: bool,
+}
+
+/// One I2C line, sampled with the bus released
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct I2cLineState {
+    /// The line rose without the internal pull-ups, so an external one is
+    /// fitted; always `false` for a line held low
+    pub pull_up: bool,
+    /// The line stayed low even with the internal pull-ups on
+    pub held_low: bool,
+}
+
+/// A 24Cxx-series I2C EEPROM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct I2cEeprom {
+    /// 7-bit address set by the A2..A0 pins (0x50-0x57); parts with block
+    /// select bits ignore the low bits they use
+    pub addr: u8,
+    /// Part number, which sets the geometry
+    pub part: EepromPart,
+}
+
+/// A 24Cxx part, named by its capacity in kilobits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EepromPart {
+    C01,
+    C02,
+    C04,
+    C08,
+    C16,
+    C32,
+    C64,
+    C128,
+    C256,
+    C512,
+    C1024,
+}
+
+impl EepromPart {
+    /// Every part, smallest first
+    pub const ALL: [EepromPart; 11] = [
+        EepromPart::C01,
+        EepromPart::C02,
+        EepromPart::C04,
+        EepromPart::C08,
+        EepromPart::C16,
+        EepromPart::C32,
+        EepromPart::C64,
+        EepromPart::C128,
+        EepromPart::C256,
+        EepromPart::C512,
+        EepromPart::C1024,
+    ];
+    
+    /// Size in bytes
+    pub const fn capacity(self) -> u32 {
+        128 << self as u32
+    }
+    
+    /// Bytes one write can program; longer writes wrap within the page
+    pub const fn page_size(self) -> u32 {
+        match self {
+            EepromPart::C01 | EepromPart::C02 => 8,
+            EepromPart::C04 | EepromPart::C08 | EepromPart::C16 => 16,
+            EepromPart::C32 | EepromPart::C64 => 32,
+            EepromPart::C128 | EepromPart::C256 => 64,
+            EepromPart::C512 => 128,
+            EepromPart::C1024 => 256,
+        }
+    }
+    
+    /// Memory address bytes sent before data (1 up to the 24C16, then 2)
+    pub const fn address_bytes(self) -> u8 {
+        if self.capacity() <= 2048 {
+            1
+        } else {
+            2
+        }
+    }
+    
+    /// Device addresses the part answers at; the memory address bits that
+    /// do not fit in the address bytes go in the low bits of the device
+    /// address
+    pub const fn blocks(self) -> u8 {
+        let block = 1 << (8 * self.address_bytes() as u32);
+        if self.capacity() > block {
+            (self.capacity() / block) as u8
+        } else {
+            1
+        }
+    }
+}
+
+/// Most registers in an `I2cRegisterMap`
+pub const I2C_TARGET_REGISTERS: usize = 32;
+
+/// Most data bytes an `I2cTargetAccess` carries; longer accesses are
+/// counted in full but truncated
+pub const I2C_TARGET_LOG_SIZE: usize = 32;
+
+/// A device for the I2C target mode to emulate
+///
+/// A write's first byte selects a register; the bytes after it, and the
+/// bytes of a following read, are the register's value, most significant
+/// byte first.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct I2cRegisterMap {
+    /// 7-bit address the target answers at
+    pub addr: u8,
+    /// Move on to the next register address once all bytes of a register
+    /// are transferred, rather than starting the same register again
+    pub auto_increment: bool,
+    /// Registers, each at a distinct address
+    pub registers: Vec<I2cRegister, I2C_TARGET_REGISTERS>,
+}
+
+/// One register of an emulated I2C device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct I2cRegister {
+    /// Register address, as the controller writes it
+    pub address: u8,
+    /// Size in bytes, 1 to 4
+    pub width: u8,
+    /// Whether the controller may write it
+    pub access: RegisterAccess,
+    /// Current value; must fit in `width` bytes
+    pub value: u32,
+}
+
+/// Whether a register of an emulated device takes writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RegisterAccess {
+    /// Writes are not acknowledged
+    ReadOnly,
+    /// Writes change the value
+    ReadWrite,
+}
+
+/// One controller access to the emulated I2C device, from START to STOP or
+/// repeated start
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct I2cTargetAccess {
+    /// The controller read rather than wrote
+    pub read: bool,
+    /// Register the data started at: the register byte of a write, or the
+    /// register selected before a read
+    pub reg: u8,
+    /// Data bytes, without a write's register byte; the first
+    /// `I2C_TARGET_LOG_SIZE` only
+    pub data: Vec<u8, I2C_TARGET_LOG_SIZE>,
+    /// Data bytes transferred, including any not in `data`
+    pub len: u16,
+    /// The target refused a byte of the write: an unknown register or a
+    /// read-only one
+    pub nacked: bool,
+}
+
+/// Which addresses an I2C scan probes, and how
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct I2cScanOptions {
+    /// What is put on the bus at each address: by default
+    /// [`I2cScanProbe::Write`], the address for writing and a STOP with no
+    /// data. [`I2cScanProbe::Read`] reads and NACKs one byte, and
+    /// [`I2cScanProbe::Quick`] addresses for reading without clocking a byte
+    pub probe: I2cScanProbe,
+    /// Also probe the reserved addresses 0x00-0x07 (general call, CBUS,
+    /// HS-mode codes) and 0x78-0x7F (10-bit prefixes, device ID)
+    pub reserved: bool,
+}
+
+impl I2cScanOptions {
+    /// First and last address probed
+    pub const fn range(&self) -> (u8, u8) {
+        if self.reserved {
+            (0x00, 0x7F)
+        } else {
+            (0x08, 0x77)
+        }
+    }
+}
+
+/// How an I2C scan asks whether a device is at an address
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum I2cScanProbe {
+    /// Address for writing, then STOP (SMBus Quick Write); no data is sent,
+    /// but a part that latches on a write start may act on it
+    #[default]
+    Write,
+    /// Read a byte, NACK it and STOP (SMBus Receive Byte); finds devices that
+    /// only acknowledge reads, but may pop a FIFO or clear a status flag
+    Read,
+    /// Address for reading, then STOP (SMBus Quick Read); nothing is written
+    /// or read. Needs a controller that can end a read without clocking a
+    /// byte; boards without one refuse it with `ErrorCode::Unsupported`
+    Quick,
+}
+
+/// I2C bus speeds a sweep scans at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum I2cSpeed {
+    /// 100 kHz standard mode
+    Standard,
+    /// 400 kHz fast mode
+    Fast,
+    /// 1 MHz fast mode plus
+    FastPlus,
+}
+
+impl I2cSpeed {
+    /// Every speed, slowest first
+    pub const ALL: [I2cSpeed; 3] = [I2cSpeed::Standard, I2cSpeed::Fast, I2cSpeed::FastPlus];
+
+    /// SCL frequency in Hz
+    pub const fn hz(self) -> u32 {
+        match self {
+            I2cSpeed::Standard => 100_000,
+            I2cSpeed::Fast => 400_000,
+            I2cSpeed::FastPlus => 1_000_000,
+        }
+    }
+}
+
+/// Devices that answered an I2C speed sweep, in reply to `I2cScanSweep`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct I2cSweepReport {
+    /// One entry per address that acknowledged at any speed, lowest first
+    pub devices: Vec<I2cSweepEntry, 128>,
+}
+
+/// One address that answered an I2C speed sweep
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct I2cSweepEntry {
+    /// 7-bit address
+    pub addr: u8,
+    /// Speeds it acknowledged at, slowest first
+    pub speeds: Vec<I2cSpeed, 3>,
+}
+
+/// Devices that answered an I2C scan, in reply to `I2cScanAnnotated`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct I2cScanReport {
+    /// One entry per address that acknowledged, lowest first
+    pub devices: Vec<I2cScanEntry, 128>,
+}
+
+/// One address that answered an I2C scan
+///
+/// Part names are not sent: [`candidates`](Self::candidates) looks them up
+/// in the table both ends share.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct I2cScanEntry {
+    /// 7-bit address
+    pub addr: u8,
+    /// Part whose ID register matched, if the scan identified one
+    pub chip: Option<I2cChip>,
+}
+
+impl I2cScanEntry {
+    /// Parts known to answer at this address
+    pub fn candidates(&self) -> &'static [KnownDevice] {
+        crate::i2c_devices::candidates(self.addr)
+    }
+}
+
+/// A part an I2C scan can identify by its ID register
+///
+/// New parts are appended so that variant indices stay stable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum I2cChip {
+    Bme280,
+    Bmp280,
+    Bme680,
+    Bmp180,
+    Bmp388,
+    Bmp390,
+    Mpu6050,
+    Mpu6500,
+    Mpu9250,
+    Mpu6886,
+    Bmi270,
+    Icm20948,
+    Lsm6ds3,
+    Lsm6dsl,
+    Lsm6dsox,
+    L3gd20h,
+    Lis3dh,
+    Lis3mdl,
+    Lis2mdl,
+    Adxl345,
+    Bno055,
+    Mma8451,
+    Mag3110,
+    Vl53l0x,
+    Tcs34725,
+    Apds9960,
+    Lps22hb,
+    Lps25hb,
+    Hts221,
+    Mcp9808,
+    Ccs811,
+    Mpl3115a2,
+    Ina260,
+    Hdc1080,
+}
+
+impl I2cChip {
+    /// Part number as printed on the package
+    pub const fn name(self) -> &'static str {
+        match self {
+            I2cChip::Bme280 => "BME280",
+            I2cChip::Bmp280 => "BMP280",
+            I2cChip::Bme680 => "BME680",
+            I2cChip::Bmp180 => "BMP180",
+            I2cChip::Bmp388 => "BMP388",
+            I2cChip::Bmp390 => "BMP390",
+            I2cChip::Mpu6050 => "MPU6050",
+            I2cChip::Mpu6500 => "MPU6500",
+            I2cChip::Mpu9250 => "MPU9250",
+            I2cChip::Mpu6886 => "MPU6886",
+            I2cChip::Bmi270 => "BMI270",
+            I2cChip::Icm20948 => "ICM-20948",
+            I2cChip::Lsm6ds3 => "LSM6DS3",
+            I2cChip::Lsm6dsl => "LSM6DSL",
+            I2cChip::Lsm6dsox => "LSM6DSOX",
+            I2cChip::L3gd20h => "L3GD20H",
+            I2cChip::Lis3dh => "LIS3DH",
+            I2cChip::Lis3mdl => "LIS3MDL",
+            I2cChip::Lis2mdl => "LIS2MDL",
+            I2cChip::Adxl345 => "ADXL345",
+            I2cChip::Bno055 => "BNO055",
+            I2cChip::Mma8451 => "MMA8451",
+            I2cChip::Mag3110 => "MAG3110",
+            I2cChip::Vl53l0x => "VL53L0X",
+            I2cChip::Tcs34725 => "TCS34725",
+            I2cChip::Apds9960 => "APDS-9960",
+            I2cChip::Lps22hb => "LPS22HB",
+            I2cChip::Lps25hb => "LPS25HB",
+            I2cChip::Hts221 => "HTS221",
+            I2cChip::Mcp9808 => "MCP9808",
+            I2cChip::Ccs811 => "CCS811",
+            I2cChip::Mpl3115a2 => "MPL3115A2",
+            I2cChip::Ina260 => "INA260",
+            I2cChip::Hdc1080 => "HDC1080",
+        }
+    }
+}
+
+/// Device capabilities reported during the `Hello` handshake
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeviceInfo {
+    /// Protocol version selected for this session
+    pub version: u8,
+    /// All protocol versions the firmware can speak
+    pub versions: Vec<u8, 8>,
+    /// Largest frame either side may send in this session
+    pub max_frame: u16,
+    /// Firmware version string (e.g. "0.1.0")
+    pub firmware_version: String<16>,
+    /// Board name
+    pub board: String<32>,
+    /// Bus modes implemented by the firmware
+    pub modes: Vec<Mode, 32>,
+    /// Receive buffer size in bytes
+    pub rx_buffer: u16,
+    /// Transmit buffer size in bytes
+    pub tx_buffer: u16,
+    /// Optional feature flags
+    pub features: Features,
+}
+
+/// Optional firmware feature flags
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Features(pub u32);
+
+impl Features {
+    /// No optional features
+    pub const NONE: Features = Features(0);
+    /// Frames carry sequence IDs (protocol v2)
+    pub const SEQUENCE_IDS: Features = Features(1 << 0);
+    /// File operations are backed by a filesystem
+    pub const FILESYSTEM: Features = Features(1 << 1);
+    /// Wi-Fi transports are available
+    pub const WIFI: Features = Features(1 << 2);
+    /// On-board display is available
+    pub const DISPLAY: Features = Features(1 << 3);
+    /// Chunked bulk transfers are supported
+    pub const BULK_TRANSFER: Features = Features(1 << 4);
+    /// Unsolicited events can be subscribed to
+    pub const EVENTS: Features = Features(1 << 5);
+    /// Stored scripts can be run with `RunScript`
+    pub const SCRIPTS: Features = Features(1 << 6);
+    
+    /// Check whether all flags in `other` are set
+    pub const fn contains(self, other: Features) -> bool {
+        self.0 & other.0 == other.0
+    }
+    
+    /// Combine two sets of flags
+    pub const fn union(self, other: Features) -> Features {
+        Features(self.0 | other.0)
+    }
+}
+
+impl core::ops::BitOr for Features {
+    type Output = Features;
+    
+    fn bitor(self, rhs: Features) -> Features {
+        self.union(rhs)
+    }
 }
 
 /// Error codes
@@ -138,4 +787,175 @@ pub enum ErrorCode {
     NotConfigured,
     /// Invalid parameter
     InvalidParameter,
+    /// Transfer checksum did not match (v2+)
+    ChecksumMismatch,
+    /// Transfer ID is unknown or already finished (v2+)
+    UnknownTransfer,
+    /// Another client holds the bus (v2+)
+    Busy,
+    /// A script's `assert` did not hold (v2+)
+    AssertionFailed,
+    /// No device acknowledged the I2C address (v2+, `BusError` before)
+    AddressNack,
+    /// The I2C device refused a data byte (v2+, `BusError` before)
+    DataNack,
+    /// Another controller won arbitration for the bus (v2+, `BusError` before)
+    ArbitrationLost,
+    /// The board cannot do what was asked, e.g. an I2C probe its controller
+    /// cannot send (v2+)
+    Unsupported,
+}
+
+impl ErrorCode {
+    /// Oldest protocol version that can carry this error code
+    pub fn min_version(&self) -> u8 {
+        match self {
+            ErrorCode::ChecksumMismatch
+            | ErrorCode::UnknownTransfer
+            | ErrorCode::Busy
+            | ErrorCode::AssertionFailed
+            | ErrorCode::AddressNack
+            | ErrorCode::DataNack
+            | ErrorCode::ArbitrationLost
+            | ErrorCode::Unsupported => PROTOCOL_VERSION_V2,
+            _ => PROTOCOL_VERSION_V1,
+        }
+    }
+    
+    /// The closest code a host speaking `version` understands
+    pub fn downgrade(self, version: u8) -> ErrorCode {
+        if self.min_version() <= version {
+            return self;
+        }
+        match self {
+            ErrorCode::Busy => ErrorCode::PermissionDenied,
+            ErrorCode::AddressNack | ErrorCode::DataNack | ErrorCode::ArbitrationLost => ErrorCode::BusError,
+            ErrorCode::Unsupported => ErrorCode::InvalidParameter,
+            _ => ErrorCode::ProtocolError,
+        }
+    }
+}
+
+/// Maximum data bytes carried by one `BulkChunk`
+///
+/// Leaves room for the chunk header inside a [`MAX_MESSAGE_SIZE`](crate::MAX_MESSAGE_SIZE) frame.
+pub const BULK_CHUNK_SIZE: usize = 768;
+
+/// Source or destination of a bulk transfer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BulkTarget {
+    /// File on the device filesystem
+    File(String<128>),
+    /// SPI flash in the current SPI mode, starting at `address`
+    ///
+    /// Uploads must start on a 4 KB sector boundary and erase every sector
+    /// they reach, including the rest of the last one.
+    SpiFlash { address: u32 },
+    /// I2C EEPROM in the current I2C mode, starting at `address`
+    I2cEeprom { eeprom: I2cEeprom, address: u32 },
+}
+
+/// Event topics a host can subscribe to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Topic {
+    /// Bytes received in UART mode
+    UartRx,
+    /// Traffic captured by a bus sniffer
+    Sniffer,
+    /// GPIO edge interrupts
+    Gpio,
+    /// Touchscreen input
+    Touch,
+    /// Firmware log lines
+    Log,
+    /// Controller accesses to the emulated I2C device
+    I2cTarget,
+}
+
+impl Topic {
+    /// Every topic, in declaration order
+    pub const ALL: [Topic; 6] = [
+        Topic::UartRx,
+        Topic::Sniffer,
+        Topic::Gpio,
+        Topic::Touch,
+        Topic::Log,
+        Topic::I2cTarget,
+    ];
+}
+
+/// Unsolicited notifications pushed from device to host
+///
+/// Events are sent with sequence ID `UNSEQUENCED`, since they do not answer
+/// any command.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Event {
+    /// Bytes received on the UART
+    UartRx { data: Vec<u8, 256> },
+    /// One record captured by a bus sniffer
+    Sniffer(SnifferEvent),
+    /// Level change on a watched GPIO pin
+    GpioEdge { pin: u8, rising: bool, timestamp_us: u32 },
+    /// Touchscreen press, move or release
+    Touch { x: u16, y: u16, pressed: bool },
+    /// Firmware log line
+    Log { level: LogLevel, text: String<128> },
+    /// Events were dropped because the host could not keep up
+    Overflow { dropped: u16 },
+    /// A controller accessed the emulated I2C device
+    I2cTarget(I2cTargetAccess),
+}
+
+impl Event {
+    /// Topic this event is published on, or `None` for events every
+    /// subscriber receives
+    pub fn topic(&self) -> Option<Topic> {
+        match self {
+            Event::UartRx { .. } => Some(Topic::UartRx),
+            Event::Sniffer(_) => Some(Topic::Sniffer),
+            Event::GpioEdge { .. } => Some(Topic::Gpio),
+            Event::Touch { .. } => Some(Topic::Touch),
+            Event::Log { .. } => Some(Topic::Log),
+            Event::I2cTarget(_) => Some(Topic::I2cTarget),
+            Event::Overflow { .. } => None,
+        }
+    }
+}
+
+/// A sniffer capture record with its origin and time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnifferEvent {
+    /// Bus mode that captured the record
+    pub mode: Mode,
+    /// Capture time in microseconds since sniffing started
+    pub timestamp_us: u32,
+    /// What was seen on the bus
+    pub record: SniffRecord,
+}
+
+/// Bus-level conditions reported by sniffers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SniffRecord {
+    /// Transaction start (I2C START, SPI chip select asserted)
+    Start,
+    /// Repeated start without a stop (I2C)
+    RepeatedStart,
+    /// Transaction end (I2C STOP, SPI chip select released)
+    Stop,
+    /// Address phase (I2C)
+    Address { addr: u16, read: bool, ack: bool },
+    /// Data byte, with the acknowledge bit where the bus has one
+    Data { value: u8, ack: bool },
+    /// Full-duplex byte pair (SPI)
+    Exchange { mosi: u8, miso: u8 },
+}
+
+/// Log severity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
 }
@@ -1,11 +1,59 @@
 //! Protocol version management
 
 /// Current protocol version
-pub const PROTOCOL_VERSION: u8 = 0x01;
+pub const PROTOCOL_VERSION: u8 = PROTOCOL_VERSION_V2;
+
+/// Original protocol version without sequence IDs
+pub const PROTOCOL_VERSION_V1: u8 = 0x01;
+
+/// Adds a sequence/correlation ID to every frame and the `Hello` handshake
+pub const PROTOCOL_VERSION_V2: u8 = 0x02;
+
+/// Oldest protocol version that can still be decoded
+pub const MIN_PROTOCOL_VERSION: u8 = PROTOCOL_VERSION_V1;
+
+/// Protocol versions this crate can encode and decode, oldest first
+pub const SUPPORTED_VERSIONS: &[u8] = &[PROTOCOL_VERSION_V1, PROTOCOL_VERSION];
 
 /// Check if a version is compatible with the current version
 pub fn is_compatible(version: u8) -> bool {
-    // For now, only exact version match is supported
-    // Future versions can implement backward compatibility
-    version == PROTOCOL_VERSION
+    // Every version from v1 up to the current one can be decoded
+    (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version)
+}
+
+/// Pick the highest protocol version supported by both sides
+///
+/// Returns `None` when the peer offers no version we understand.
+pub fn negotiate(peer_versions: &[u8]) -> Option<u8> {
+    peer_versions
+        .iter()
+        .copied()
+        .filter(|v| SUPPORTED_VERSIONS.contains(v))
+        .max()
+}
+
+/// Pick the frame size limit for a session
+///
+/// A peer advertising zero has no limit of its own.
+pub fn negotiate_max_frame(peer_max_frame: u16) -> u16 {
+    let ours = crate::MAX_MESSAGE_SIZE as u16;
+    if peer_max_frame == 0 {
+        ours
+    } else {
+        peer_max_frame.min(ours)
+    }
+}
+
+/// Whether frames of this version carry a sequence ID
+pub(crate) fn has_seq(version: u8) -> bool {
+    version >= PROTOCOL_VERSION_V2
+}
+
+/// Length of the frame header (START + VERSION + LENGTH [+ SEQ])
+pub fn header_len(version: u8) -> usize {
+    if has_seq(version) {
+        6
+    } else {
+        4
+    }
 }
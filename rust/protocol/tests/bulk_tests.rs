@@ -0,0 +1,326 @@
+//! Chunked bulk transfer tests
+//!
+//! These tests drive a BulkSender and BulkReceiver through the real codec and
+//! FrameDecoder, as a host and device would over a serial link.
+
+use esp32_bus_pirate_protocol::{
+    bulk::{crc32, BulkError, BulkReceiver, BulkSender},
+    codec::MessageCodec,
+    message::*,
+    FrameDecoder, MAX_MESSAGE_SIZE,
+};
+use heapless::String;
+
+/// Deterministic test payload
+fn payload_byte(offset: u32) -> u8 {
+    (offset.wrapping_mul(31) ^ (offset >> 11)) as u8
+}
+
+fn read_payload(offset: u32, buf: &mut [u8]) -> Result<(), BulkError> {
+    for (i, byte) in buf.iter_mut().enumerate() {
+        *byte = payload_byte(offset + i as u32);
+    }
+    Ok(())
+}
+
+/// Send `msg` through the codec and a stream decoder, as over a link
+fn over_link(decoder: &mut FrameDecoder, msg: &Message, seq: u16) -> Message {
+    let frame = MessageCodec::encode_with_seq(msg, seq).unwrap();
+    let mut out = None;
+    decoder.decode(&frame, |item| out = Some(item.unwrap()));
+    out.expect("frame did not decode")
+}
+
+struct Link {
+    to_device: FrameDecoder,
+    to_host: FrameDecoder,
+    seq: u16,
+}
+
+impl Link {
+    fn new() -> Self {
+        Self {
+            to_device: FrameDecoder::new(),
+            to_host: FrameDecoder::new(),
+            seq: 0,
+        }
+    }
+
+    fn next_seq(&mut self) -> u16 {
+        self.seq = self.seq.wrapping_add(1).max(1);
+        self.seq
+    }
+
+    fn send(&mut self, msg: &Message) -> Message {
+        let seq = self.next_seq();
+        over_link(&mut self.to_device, msg, seq)
+    }
+
+    fn reply(&mut self, msg: &Message) -> Message {
+        let seq = self.next_seq();
+        over_link(&mut self.to_host, msg, seq)
+    }
+}
+
+/// Deliver one message to the receiver and return its reply
+fn receive(receiver: &mut BulkReceiver, msg: Message, store: &mut Vec<u8>) -> Result<Message, BulkError> {
+    match msg {
+        Message::BulkChunk { id, offset, data } => receiver.on_chunk(id, offset, &data, |off, bytes| {
+            assert_eq!(off as usize, store.len());
+            store.extend_from_slice(bytes);
+            Ok(())
+        }),
+        Message::BulkEnd { id, crc32 } => receiver.on_end(id, crc32),
+        Message::BulkResume { id } if id == receiver.id() => Ok(receiver.ack()),
+        other => panic!("unexpected message {:?}", other),
+    }
+}
+
+fn ack_offset(msg: &Message) -> (u16, u32) {
+    match msg {
+        Message::BulkAck { id, offset } => (*id, *offset),
+        other => panic!("expected BulkAck, got {:?}", other),
+    }
+}
+
+// ===== Encoding =====
+
+#[test]
+fn test_full_chunk_fits_in_frame() {
+    let msg = Message::BulkChunk {
+        id: u16::MAX,
+        offset: u32::MAX,
+        data: heapless::Vec::from_slice(&[0xFF; BULK_CHUNK_SIZE]).unwrap(),
+    };
+    let frame = MessageCodec::encode_with_seq(&msg, u16::MAX).unwrap();
+    assert!(frame.len() <= MAX_MESSAGE_SIZE);
+    assert_eq!(MessageCodec::decode(&frame).unwrap(), msg);
+}
+
+#[test]
+fn test_encode_decode_bulk_messages() {
+    let messages = [
+        Message::BulkRequest {
+            id: 1,
+            target: BulkTarget::SpiFlash { address: 0 },
+            offset: 0,
+            len: 16 * 1024 * 1024,
+        },
+        Message::BulkBegin {
+            id: 2,
+            target: BulkTarget::File(String::try_from("/dump.bin").unwrap()),
+            total_len: 4096,
+        },
+        Message::BulkEnd { id: 2, crc32: 0xDEAD_BEEF },
+        Message::BulkAck { id: 2, offset: 768 },
+        Message::BulkResume { id: 2 },
+        Message::Error(ErrorCode::ChecksumMismatch),
+    ];
+
+    for msg in messages {
+        let frame = MessageCodec::encode_with_seq(&msg, 1).unwrap();
+        assert_eq!(MessageCodec::decode(&frame).unwrap(), msg);
+    }
+}
+
+#[test]
+fn test_bulk_messages_require_v2() {
+    let msg = Message::BulkResume { id: 1 };
+    assert!(MessageCodec::encode_v1(&msg).is_err());
+}
+
+// ===== Round Trips =====
+
+#[test]
+fn test_multi_megabyte_round_trip() {
+    const TOTAL: u32 = 4 * 1024 * 1024;
+
+    let mut link = Link::new();
+    let mut sender = BulkSender::new(7, BulkTarget::SpiFlash { address: 0 }, TOTAL);
+    let mut store = Vec::with_capacity(TOTAL as usize);
+
+    let begin = link.send(&sender.begin());
+    let mut receiver = match begin {
+        Message::BulkBegin { id, total_len, .. } => BulkReceiver::new(id, total_len),
+        other => panic!("unexpected {:?}", other),
+    };
+
+    while let Some(chunk) = sender.next_chunk(read_payload).unwrap() {
+        let chunk = link.send(&chunk);
+        let ack = receive(&mut receiver, chunk, &mut store).unwrap();
+        let (id, offset) = ack_offset(&link.reply(&ack));
+        sender.on_ack(id, offset).unwrap();
+    }
+
+    let end = link.send(&sender.end().unwrap());
+    let ack = receive(&mut receiver, end, &mut store).unwrap();
+    let (id, offset) = ack_offset(&link.reply(&ack));
+    sender.on_ack(id, offset).unwrap();
+
+    assert!(sender.is_complete());
+    assert!(receiver.is_finished());
+    assert_eq!(store.len(), TOTAL as usize);
+    assert!(store
+        .iter()
+        .enumerate()
+        .all(|(i, &b)| b == payload_byte(i as u32)));
+    assert_eq!(link.to_device.stats().total_errors(), 0);
+}
+
+#[test]
+fn test_resume_after_lost_chunks() {
+    const TOTAL: u32 = 2 * 1024 * 1024 + 123;
+
+    let mut link = Link::new();
+    let mut sender = BulkSender::new(3, BulkTarget::SpiFlash { address: 0x1000 }, TOTAL);
+    let mut receiver = BulkReceiver::new(3, TOTAL);
+    let mut store = Vec::new();
+    let mut sent = 0u32;
+
+    while let Some(chunk) = sender.next_chunk(read_payload).unwrap() {
+        sent += 1;
+
+        // Every 97th chunk is lost on the wire, and its ack never comes
+        if sent.is_multiple_of(97) {
+            // The sender notices the missing ack and asks where to resume
+            let resume = link.send(&sender.resume());
+            let ack = receive(&mut receiver, resume, &mut store).unwrap();
+            let (id, offset) = ack_offset(&link.reply(&ack));
+            sender.on_ack(id, offset).unwrap();
+            continue;
+        }
+
+        let chunk = link.send(&chunk);
+        let ack = receive(&mut receiver, chunk, &mut store).unwrap();
+        let (id, offset) = ack_offset(&link.reply(&ack));
+        sender.on_ack(id, offset).unwrap();
+    }
+
+    let end = link.send(&sender.end().unwrap());
+    receive(&mut receiver, end, &mut store).unwrap();
+
+    assert!(receiver.is_finished());
+    assert_eq!(store.len(), TOTAL as usize);
+    assert_eq!(crc32(&store), {
+        let expected: Vec<u8> = (0..TOTAL).map(payload_byte).collect();
+        crc32(&expected)
+    });
+}
+
+#[test]
+fn test_pipelined_chunks_rewind_on_gap() {
+    // Sender streams ahead without waiting; one chunk is dropped
+    let total = (BULK_CHUNK_SIZE * 4) as u32;
+    let mut sender = BulkSender::new(1, BulkTarget::SpiFlash { address: 0 }, total);
+    let mut receiver = BulkReceiver::new(1, total);
+    let mut store = Vec::new();
+
+    let c0 = sender.next_chunk(read_payload).unwrap().unwrap();
+    let _lost = sender.next_chunk(read_payload).unwrap().unwrap();
+    let c2 = sender.next_chunk(read_payload).unwrap().unwrap();
+
+    receive(&mut receiver, c0, &mut store).unwrap();
+    let ack = receive(&mut receiver, c2, &mut store).unwrap();
+
+    // Receiver ignored the out-of-order chunk and still expects chunk 1
+    let (id, offset) = ack_offset(&ack);
+    assert_eq!(offset, BULK_CHUNK_SIZE as u32);
+    sender.on_ack(id, offset).unwrap();
+
+    while let Some(chunk) = sender.next_chunk(read_payload).unwrap() {
+        let ack = receive(&mut receiver, chunk, &mut store).unwrap();
+        let (id, offset) = ack_offset(&ack);
+        sender.on_ack(id, offset).unwrap();
+    }
+    receive(&mut receiver, sender.end().unwrap(), &mut store).unwrap();
+
+    assert!(receiver.is_finished());
+    assert_eq!(store.len(), total as usize);
+}
+
+#[test]
+fn test_small_transfer_single_chunk() {
+    let mut sender = BulkSender::new(9, BulkTarget::SpiFlash { address: 0 }, 10);
+    let mut receiver = BulkReceiver::new(9, 10);
+    let mut store = Vec::new();
+
+    let chunk = sender.next_chunk(read_payload).unwrap().unwrap();
+    receive(&mut receiver, chunk, &mut store).unwrap();
+    assert!(sender.next_chunk(read_payload).unwrap().is_none());
+
+    receive(&mut receiver, sender.end().unwrap(), &mut store).unwrap();
+    assert_eq!(store.len(), 10);
+}
+
+#[test]
+fn test_empty_transfer() {
+    let sender = BulkSender::new(1, BulkTarget::SpiFlash { address: 0 }, 0);
+    let mut receiver = BulkReceiver::new(1, 0);
+    let mut store = Vec::new();
+
+    receive(&mut receiver, sender.end().unwrap(), &mut store).unwrap();
+    assert!(receiver.is_finished());
+}
+
+// ===== Errors =====
+
+#[test]
+fn test_checksum_mismatch_detected() {
+    let mut receiver = BulkReceiver::new(1, 4);
+    let mut store = Vec::new();
+
+    receive(
+        &mut receiver,
+        Message::BulkChunk {
+            id: 1,
+            offset: 0,
+            data: heapless::Vec::from_slice(&[1, 2, 3, 4]).unwrap(),
+        },
+        &mut store,
+    )
+    .unwrap();
+
+    let result = receiver.on_end(1, crc32(&[1, 2, 3, 5]));
+    assert_eq!(result, Err(BulkError::ChecksumMismatch));
+    assert_eq!(ErrorCode::from(BulkError::ChecksumMismatch), ErrorCode::ChecksumMismatch);
+}
+
+#[test]
+fn test_end_before_all_data() {
+    let mut receiver = BulkReceiver::new(1, 100);
+    assert_eq!(receiver.on_end(1, 0), Err(BulkError::Incomplete));
+}
+
+#[test]
+fn test_chunk_past_end_rejected() {
+    let mut receiver = BulkReceiver::new(1, 2);
+    let result = receiver.on_chunk(1, 0, &[1, 2, 3], |_, _| Ok(()));
+    assert_eq!(result, Err(BulkError::Overflow));
+}
+
+#[test]
+fn test_wrong_transfer_id_rejected() {
+    let mut receiver = BulkReceiver::new(1, 2);
+    assert_eq!(
+        receiver.on_chunk(2, 0, &[1], |_, _| Ok(())),
+        Err(BulkError::UnknownTransfer)
+    );
+
+    let mut sender = BulkSender::new(1, BulkTarget::SpiFlash { address: 0 }, 2);
+    assert_eq!(sender.on_ack(2, 0), Err(BulkError::UnknownTransfer));
+}
+
+#[test]
+fn test_ack_ahead_of_sent_data_rejected() {
+    let mut sender = BulkSender::new(1, BulkTarget::SpiFlash { address: 0 }, 4096);
+    sender.next_chunk(read_payload).unwrap();
+    assert_eq!(sender.on_ack(1, 4096), Err(BulkError::Overflow));
+}
+
+#[test]
+fn test_sink_error_propagates() {
+    let mut receiver = BulkReceiver::new(1, 2);
+    let result = receiver.on_chunk(1, 0, &[1, 2], |_, _| Err(BulkError::Io));
+    assert_eq!(result, Err(BulkError::Io));
+    assert_eq!(receiver.offset(), 0);
+}
@@ -2,7 +2,7 @@
 
 use esp32_bus_pirate_protocol::{
     codec::MessageCodec, message::*, Error, ErrorCode, Mode, Response, END_BYTE,
-    MAX_MESSAGE_SIZE, PROTOCOL_VERSION, START_BYTE,
+    MAX_MESSAGE_SIZE, PROTOCOL_VERSION, PROTOCOL_VERSION_V1, PROTOCOL_VERSION_V2, START_BYTE,
 };
 use heapless::{String, Vec};
 
@@ -550,6 +550,216 @@ fn test_encode_decode_all_error_codes() {
     }
 }
 
+#[test]
+fn test_busy_error_code_needs_v2() {
+    let msg = Message::Error(ErrorCode::Busy);
+    let frame = MessageCodec::encode_versioned(&msg, PROTOCOL_VERSION_V2, 1).unwrap();
+    assert_eq!(MessageCodec::decode(&frame).unwrap(), msg);
+    assert!(MessageCodec::encode_v1(&msg).is_err());
+
+    // v1 hosts are told the closest thing they know
+    assert_eq!(ErrorCode::Busy.downgrade(PROTOCOL_VERSION_V1), ErrorCode::PermissionDenied);
+    assert_eq!(ErrorCode::Busy.downgrade(PROTOCOL_VERSION_V2), ErrorCode::Busy);
+    assert_eq!(ErrorCode::UnknownTransfer.downgrade(PROTOCOL_VERSION_V1), ErrorCode::ProtocolError);
+    assert_eq!(ErrorCode::BusError.downgrade(PROTOCOL_VERSION_V1), ErrorCode::BusError);
+    assert_eq!(ErrorCode::Unsupported.downgrade(PROTOCOL_VERSION_V1), ErrorCode::InvalidParameter);
+}
+
+#[test]
+fn test_i2c_error_codes_need_v2() {
+    for code in [ErrorCode::AddressNack, ErrorCode::DataNack, ErrorCode::ArbitrationLost] {
+        let msg = Message::Error(code);
+        let frame = MessageCodec::encode_versioned(&msg, PROTOCOL_VERSION_V2, 1).unwrap();
+        assert_eq!(MessageCodec::decode(&frame).unwrap(), msg);
+        assert!(MessageCodec::encode_v1(&msg).is_err());
+        assert_eq!(code.downgrade(PROTOCOL_VERSION_V1), ErrorCode::BusError);
+        assert_eq!(code.downgrade(PROTOCOL_VERSION_V2), code);
+    }
+}
+
+#[test]
+fn test_run_script_needs_v2() {
+    let run = Message::RunScript {
+        path: String::try_from("/scripts/bme280.bp").unwrap(),
+    };
+    let report = Message::Response(Response::Script(ScriptReport {
+        output: Vec::from_slice(&[0x60]).unwrap(),
+        error: Some(ScriptError {
+            line: 12,
+            code: ErrorCode::AssertionFailed,
+        }),
+    }));
+
+    for msg in [run, report] {
+        let frame = MessageCodec::encode_versioned(&msg, PROTOCOL_VERSION_V2, 1).unwrap();
+        assert_eq!(MessageCodec::decode(&frame).unwrap(), msg);
+        assert!(MessageCodec::encode_v1(&msg).is_err());
+    }
+    assert_eq!(ErrorCode::AssertionFailed.downgrade(PROTOCOL_VERSION_V1), ErrorCode::ProtocolError);
+}
+
+#[test]
+fn test_i2c_transaction_needs_v2() {
+    let txn = Message::I2cTransaction {
+        addr: I2cAddress::TenBit(0x2A5),
+        ops: Vec::from_slice(&[
+            I2cOp::Write(Vec::from_slice(&[0x01, 0x00]).unwrap()),
+            I2cOp::Read(4),
+        ])
+        .unwrap(),
+    };
+    let read = Message::Response(Response::I2cTransaction(I2cTransactionReport {
+        data: Vec::from_slice(&[1, 2, 3, 4]).unwrap(),
+        nack: None,
+    }));
+    let nack = Message::Response(Response::I2cTransaction(I2cTransactionReport {
+        data: Vec::new(),
+        nack: Some(I2cNack {
+            segment: Some(1),
+            source: NackSource::Data,
+        }),
+    }));
+
+    let scan = Message::I2cScanTenBit;
+    let found = Message::Response(Response::I2cTenBitDevices(Vec::from_slice(&[0x150, 0x3FF]).unwrap()));
+
+    for msg in [txn, read, nack, scan, found] {
+        let frame = MessageCodec::encode_versioned(&msg, PROTOCOL_VERSION_V2, 1).unwrap();
+        assert_eq!(MessageCodec::decode(&frame).unwrap(), msg);
+        assert!(MessageCodec::encode_v1(&msg).is_err());
+    }
+}
+
+#[test]
+fn test_i2c_recover_needs_v2() {
+    let report = Message::Response(Response::I2cRecovery(I2cRecoveryReport {
+        scl: I2cLineState { pull_up: true, held_low: false },
+        sda: I2cLineState { pull_up: false, held_low: true },
+        clocks: 3,
+        recovered: true,
+    }));
+
+    for msg in [Message::I2cRecover, report] {
+        let frame = MessageCodec::encode_versioned(&msg, PROTOCOL_VERSION_V2, 1).unwrap();
+        assert_eq!(MessageCodec::decode(&frame).unwrap(), msg);
+        assert!(MessageCodec::encode_v1(&msg).is_err());
+    }
+}
+
+#[test]
+fn test_i2c_eeprom_needs_v2() {
+    let eeprom = I2cEeprom { addr: 0x50, part: EepromPart::C256 };
+    let dump = Message::BulkRequest {
+        id: 1,
+        target: BulkTarget::I2cEeprom { eeprom, address: 0x100 },
+        offset: 0,
+        len: 64,
+    };
+    let verify = Message::I2cEepromVerify { eeprom, address: 0, len: 32768, crc32: 0xDEAD_BEEF };
+
+    for msg in [Message::I2cEepromProbe { eeprom }, Message::I2cEepromErase { eeprom }, verify, dump] {
+        let frame = MessageCodec::encode_versioned(&msg, PROTOCOL_VERSION_V2, 1).unwrap();
+        assert_eq!(MessageCodec::decode(&frame).unwrap(), msg);
+        assert!(MessageCodec::encode_v1(&msg).is_err());
+    }
+}
+
+#[test]
+fn test_eeprom_geometry() {
+    let geometry: Vec<(u32, u32, u8, u8), 11> = EepromPart::ALL
+        .iter()
+        .map(|part| (part.capacity(), part.page_size(), part.address_bytes(), part.blocks()))
+        .collect();
+
+    assert_eq!(
+        geometry,
+        [
+            (128, 8, 1, 1),
+            (256, 8, 1, 1),
+            (512, 16, 1, 2),
+            (1024, 16, 1, 4),
+            (2048, 16, 1, 8),
+            (4096, 32, 2, 1),
+            (8192, 32, 2, 1),
+            (16384, 64, 2, 1),
+            (32768, 64, 2, 1),
+            (65536, 128, 2, 1),
+            (131072, 256, 2, 2),
+        ]
+    );
+}
+
+#[test]
+fn test_i2c_target_needs_v2() {
+    let map = I2cRegisterMap {
+        addr: 0x48,
+        auto_increment: true,
+        registers: (0..I2C_TARGET_REGISTERS as u8)
+            .map(|address| I2cRegister {
+                address,
+                width: 4,
+                access: RegisterAccess::ReadWrite,
+                value: u32::MAX,
+            })
+            .collect(),
+    };
+    let access = I2cTargetAccess {
+        read: true,
+        reg: 0x1F,
+        data: Vec::from_slice(&[0xFF; I2C_TARGET_LOG_SIZE]).unwrap(),
+        len: u16::MAX,
+        nacked: false,
+    };
+
+    for msg in [
+        Message::I2cTargetStart { map: map.clone() },
+        Message::I2cTargetStop,
+        Message::I2cTargetRegisters,
+        Message::Response(Response::I2cRegisterMap(map)),
+        Message::Event(Event::I2cTarget(access)),
+    ] {
+        let frame = MessageCodec::encode_versioned(&msg, PROTOCOL_VERSION_V2, u16::MAX).unwrap();
+        assert_eq!(MessageCodec::decode(&frame).unwrap(), msg);
+        assert!(MessageCodec::encode_v1(&msg).is_err());
+    }
+}
+
+#[test]
+fn test_full_i2c_scan_reports_fit_a_frame() {
+    let report = I2cScanReport {
+        devices: (0..128)
+            .map(|addr| I2cScanEntry { addr, chip: Some(I2cChip::Hdc1080) })
+            .collect(),
+    };
+    let sweep = I2cSweepReport {
+        devices: (0..128)
+            .map(|addr| I2cSweepEntry { addr, speeds: I2cSpeed::ALL.into_iter().collect() })
+            .collect(),
+    };
+    let options = I2cScanOptions { probe: I2cScanProbe::Quick, reserved: true };
+    for msg in [
+        Message::I2cScanAnnotated { options, identify: true },
+        Message::I2cScanSweep { options },
+        Message::Response(Response::I2cScanReport(report)),
+        Message::Response(Response::I2cSweepReport(sweep)),
+    ] {
+        let frame = MessageCodec::encode_versioned(&msg, PROTOCOL_VERSION_V2, MAX_MESSAGE_SIZE as u16).unwrap();
+        assert_eq!(MessageCodec::decode(&frame).unwrap(), msg);
+        assert!(MessageCodec::encode_v1(&msg).is_err());
+    }
+}
+
+#[test]
+fn test_largest_i2c_transaction_fits_a_frame() {
+    let segment = I2cOp::Write(Vec::from_slice(&[0xA5; I2C_WRITE_SIZE]).unwrap());
+    let msg = Message::I2cTransaction {
+        addr: I2cAddress::TenBit(0x3FF),
+        ops: (0..I2C_MAX_SEGMENTS).map(|_| segment.clone()).collect(),
+    };
+    let frame = MessageCodec::encode_versioned(&msg, PROTOCOL_VERSION_V2, u16::MAX).unwrap();
+    assert_eq!(MessageCodec::decode(&frame).unwrap(), msg);
+}
+
 // ===== Stress Tests =====
 
 #[test]
@@ -611,8 +821,9 @@ fn test_large_response_data() {
 fn test_getmode_encoding_matches_python() {
     use std::string::String as StdString;
     
+    // The Python client speaks unsequenced v1 frames
     let msg = Message::GetMode;
-    let encoded = MessageCodec::encode(&msg).unwrap();
+    let encoded = MessageCodec::encode_v1(&msg).unwrap();
     
     // Print for verification with Python
     let hex_string = encoded.iter()
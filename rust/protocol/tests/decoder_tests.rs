@@ -0,0 +1,357 @@
+//! FrameDecoder streaming and resynchronization tests
+
+use esp32_bus_pirate_protocol::{
+    codec::MessageCodec, frame_timeout_for_baud, message::*, Error, FrameDecoder, Mode, END_BYTE, START_BYTE,
+};
+use heapless::Vec;
+
+fn decode_all(decoder: &mut FrameDecoder, data: &[u8]) -> std::vec::Vec<Result<Message, Error>> {
+    let mut out = std::vec::Vec::new();
+    decoder.decode(data, |item| out.push(item));
+    out
+}
+
+fn i2c_write_frame() -> (Message, Vec<u8, 1024>) {
+    let mut data = Vec::new();
+    data.extend_from_slice(&[0x01, 0x02, 0x03, 0x04, 0x05]).unwrap();
+    let msg = Message::I2cWrite { addr: 0x50, data };
+    let frame = MessageCodec::encode(&msg).unwrap();
+    (msg, frame)
+}
+
+/// Small deterministic PRNG so the garbage tests are reproducible
+struct Lcg(u32);
+
+impl Lcg {
+    fn next(&mut self) -> u8 {
+        self.0 = self.0.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        (self.0 >> 16) as u8
+    }
+}
+
+// ===== Chunking =====
+
+#[test]
+fn test_single_frame_single_chunk() {
+    let frame = MessageCodec::encode(&Message::GetMode).unwrap();
+    let mut decoder = FrameDecoder::new();
+
+    assert_eq!(decode_all(&mut decoder, &frame), vec![Ok(Message::GetMode)]);
+    assert_eq!(decoder.buffered(), 0);
+    assert_eq!(decoder.stats().frames_ok, 1);
+}
+
+#[test]
+fn test_frame_split_byte_by_byte() {
+    let (msg, frame) = i2c_write_frame();
+    let mut decoder = FrameDecoder::new();
+    let mut out = std::vec::Vec::new();
+
+    for byte in frame.iter() {
+        decoder.decode(core::slice::from_ref(byte), |item| out.push(item));
+    }
+
+    assert_eq!(out, vec![Ok(msg)]);
+}
+
+#[test]
+fn test_multiple_frames_in_one_chunk() {
+    let messages = [
+        Message::SetMode { mode: Mode::I2c },
+        Message::I2cScan,
+        Message::GetMode,
+    ];
+    let mut stream = std::vec::Vec::new();
+    for msg in &messages {
+        stream.extend_from_slice(&MessageCodec::encode(msg).unwrap());
+    }
+
+    let mut decoder = FrameDecoder::new();
+    let out = decode_all(&mut decoder, &stream);
+
+    assert_eq!(out, messages.iter().cloned().map(Ok).collect::<std::vec::Vec<_>>());
+}
+
+#[test]
+fn test_chunk_larger_than_buffer() {
+    let (msg, frame) = i2c_write_frame();
+    let mut stream = std::vec::Vec::new();
+    for _ in 0..200 {
+        stream.extend_from_slice(&frame);
+    }
+    assert!(stream.len() > 1024);
+
+    let mut decoder = FrameDecoder::new();
+    let out = decode_all(&mut decoder, &stream);
+
+    assert_eq!(out.len(), 200);
+    assert!(out.iter().all(|item| *item == Ok(msg.clone())));
+}
+
+#[test]
+fn test_push_reports_accepted_bytes() {
+    let mut decoder = FrameDecoder::new();
+    let noise = [0u8; 2048];
+
+    assert_eq!(decoder.push(&noise), 1024);
+    assert_eq!(decoder.push(&noise), 0);
+    assert_eq!(decoder.poll(), None);
+    assert_eq!(decoder.buffered(), 0);
+    assert_eq!(decoder.stats().discarded_bytes, 1024);
+}
+
+// ===== Noise and Resynchronization =====
+
+#[test]
+fn test_skips_leading_noise() {
+    let frame = MessageCodec::encode(&Message::I2cScan).unwrap();
+    let mut stream = vec![0x00, 0xFF, END_BYTE, 0x12];
+    stream.extend_from_slice(&frame);
+
+    let mut decoder = FrameDecoder::new();
+    assert_eq!(decode_all(&mut decoder, &stream), vec![Ok(Message::I2cScan)]);
+    assert_eq!(decoder.stats().discarded_bytes, 4);
+}
+
+#[test]
+fn test_resync_after_crc_mismatch() {
+    let (msg, good) = i2c_write_frame();
+    let mut bad = good.clone();
+    bad[6] ^= 0xFF;
+
+    let mut stream = std::vec::Vec::new();
+    stream.extend_from_slice(&bad);
+    stream.extend_from_slice(&good);
+
+    let mut decoder = FrameDecoder::new();
+    let out = decode_all(&mut decoder, &stream);
+
+    assert_eq!(out, vec![Err(Error::CrcMismatch), Ok(msg)]);
+    assert_eq!(decoder.stats().crc_errors, 1);
+    assert_eq!(decoder.stats().frames_ok, 1);
+}
+
+#[test]
+fn test_resync_after_bad_end_byte() {
+    let mut bad = MessageCodec::encode(&Message::GetMode).unwrap();
+    let last = bad.len() - 1;
+    bad[last] = 0x00;
+    let good = MessageCodec::encode(&Message::I2cScan).unwrap();
+
+    let mut stream = std::vec::Vec::new();
+    stream.extend_from_slice(&bad);
+    stream.extend_from_slice(&good);
+
+    let mut decoder = FrameDecoder::new();
+    let out = decode_all(&mut decoder, &stream);
+
+    assert_eq!(out, vec![Err(Error::InvalidFrame), Ok(Message::I2cScan)]);
+    assert_eq!(decoder.stats().invalid_end, 1);
+}
+
+#[test]
+fn test_resync_after_truncated_frame() {
+    // A frame cut short by a link glitch must not consume the next frame
+    let (_, first) = i2c_write_frame();
+    let second = MessageCodec::encode(&Message::GetMode).unwrap();
+
+    let mut stream = std::vec::Vec::new();
+    stream.extend_from_slice(&first[..first.len() - 4]);
+    stream.extend_from_slice(&second);
+
+    let mut decoder = FrameDecoder::new();
+    let out = decode_all(&mut decoder, &stream);
+
+    assert_eq!(out.last(), Some(&Ok(Message::GetMode)));
+    assert_eq!(decoder.stats().frames_ok, 1);
+}
+
+#[test]
+fn test_rejects_impossible_length_immediately() {
+    let good = MessageCodec::encode(&Message::GetMode).unwrap();
+    let mut stream = vec![START_BYTE, good[1], 0xFF, 0xFF];
+    stream.extend_from_slice(&good);
+
+    let mut decoder = FrameDecoder::new();
+    let out = decode_all(&mut decoder, &stream);
+
+    assert_eq!(out, vec![Err(Error::InvalidFrame), Ok(Message::GetMode)]);
+    assert_eq!(decoder.stats().invalid_length, 1);
+}
+
+#[test]
+fn test_rejects_unsupported_version() {
+    let mut bad = MessageCodec::encode(&Message::GetMode).unwrap();
+    bad[1] = 0x99;
+    let good = MessageCodec::encode(&Message::GetMode).unwrap();
+
+    let mut stream = std::vec::Vec::new();
+    stream.extend_from_slice(&bad);
+    stream.extend_from_slice(&good);
+
+    let mut decoder = FrameDecoder::new();
+    let out = decode_all(&mut decoder, &stream);
+
+    assert_eq!(out, vec![Err(Error::UnsupportedVersion), Ok(Message::GetMode)]);
+    assert_eq!(decoder.stats().unsupported_version, 1);
+}
+
+#[test]
+fn test_start_byte_inside_payload() {
+    // Payload bytes equal to START_BYTE must not confuse the decoder
+    let mut data = Vec::new();
+    data.extend_from_slice(&[START_BYTE, START_BYTE, END_BYTE, START_BYTE]).unwrap();
+    let msg = Message::SpiTransfer { data };
+    let frame = MessageCodec::encode(&msg).unwrap();
+
+    let mut stream = vec![START_BYTE];
+    stream.extend_from_slice(&frame);
+
+    let mut decoder = FrameDecoder::new();
+    let out = decode_all(&mut decoder, &stream);
+
+    assert_eq!(out.last(), Some(&Ok(msg)));
+}
+
+// ===== State Management =====
+
+#[test]
+fn test_reset_discards_partial_frame() {
+    let frame = MessageCodec::encode(&Message::GetMode).unwrap();
+    let mut decoder = FrameDecoder::new();
+
+    assert!(decode_all(&mut decoder, &frame[..5]).is_empty());
+    assert_eq!(decoder.buffered(), 5);
+
+    decoder.reset();
+    assert_eq!(decoder.buffered(), 0);
+
+    assert_eq!(decode_all(&mut decoder, &frame), vec![Ok(Message::GetMode)]);
+}
+
+#[test]
+fn test_stats_total_and_clear() {
+    let mut bad = MessageCodec::encode(&Message::GetMode).unwrap();
+    bad[4] ^= 0x01;
+    let mut decoder = FrameDecoder::new();
+
+    decode_all(&mut decoder, &bad);
+    assert_eq!(decoder.stats().total_errors(), 1);
+
+    decoder.clear_stats();
+    assert_eq!(decoder.stats().total_errors(), 0);
+    assert_eq!(decoder.stats().frames_ok, 0);
+}
+
+// ===== Raw Frames and Timeouts =====
+
+#[test]
+fn test_poll_raw_returns_frames_undecoded() {
+    let get_mode = MessageCodec::encode(&Message::GetMode).unwrap();
+    let v1 = MessageCodec::encode_v1(&Message::I2cScan).unwrap();
+    let mut corrupted = MessageCodec::encode(&Message::GetMode).unwrap();
+    let crc_pos = corrupted.len() - 3;
+    corrupted[crc_pos] ^= 0xFF;
+    let mut decoder = FrameDecoder::new();
+
+    decoder.push(&[0x00, 0x13]);
+    decoder.push(&corrupted);
+    decoder.push(&get_mode[..3]);
+    assert_eq!(decoder.poll_raw(), None);
+    decoder.push(&get_mode[3..]);
+    decoder.push(&v1);
+
+    assert_eq!(decoder.poll_raw(), Some(&get_mode[..]));
+    assert_eq!(decoder.last_frame(), &get_mode[..]);
+    assert_eq!(decoder.poll_raw(), Some(&v1[..]));
+    assert_eq!(decoder.poll_raw(), None);
+    assert_eq!(decoder.buffered(), 0);
+    assert_eq!(decoder.stats().frames_ok, 2);
+    assert_eq!(decoder.stats().crc_errors, 1);
+}
+
+#[test]
+fn test_poll_raw_buffers_largest_frame() {
+    let chunk = MessageCodec::encode(&Message::BulkChunk {
+        id: 1,
+        offset: 0,
+        data: Vec::from_slice(&[0xAA; BULK_CHUNK_SIZE]).unwrap(),
+    })
+    .unwrap();
+    let mut decoder = FrameDecoder::new();
+    assert_eq!(decoder.push(&chunk), chunk.len());
+    assert_eq!(decoder.poll_raw(), Some(&chunk[..]));
+}
+
+#[test]
+fn test_expire_drops_incomplete_frame() {
+    let frame = MessageCodec::encode(&Message::GetMode).unwrap();
+    let mut decoder = FrameDecoder::new().with_frame_timeout(100);
+
+    // The rest of this frame is lost
+    decoder.push(&frame[..5]);
+    assert_eq!(decoder.poll_raw(), None);
+    assert!(!decoder.expire(1_000));
+    assert!(!decoder.expire(1_099));
+    assert_eq!(decoder.stats().timeouts, 0);
+
+    assert!(decoder.expire(1_100));
+    assert_eq!(decoder.stats().timeouts, 1);
+
+    // The next frame is not swallowed by the stale one
+    decoder.push(&frame);
+    assert_eq!(decoder.poll_raw(), Some(&frame[..]));
+    assert!(!decoder.expire(5_000));
+}
+
+#[test]
+fn test_frame_timeout_scales_with_baud() {
+    assert_eq!(frame_timeout_for_baud(115_200), 500);
+    assert_eq!(frame_timeout_for_baud(921_600), 500);
+    // A 1 KB frame takes about 1.07 s at 9600 baud
+    assert_eq!(frame_timeout_for_baud(9_600), 2_134);
+}
+
+// ===== Garbage Robustness =====
+
+#[test]
+fn test_random_garbage_never_panics() {
+    let mut rng = Lcg(0xDEAD_BEEF);
+    let mut decoder = FrameDecoder::new();
+
+    for _ in 0..200 {
+        let len = rng.next() as usize;
+        let chunk: std::vec::Vec<u8> = (0..len).map(|_| rng.next()).collect();
+        decoder.decode(&chunk, |_| {});
+    }
+}
+
+#[test]
+fn test_valid_frames_survive_interleaved_garbage() {
+    let mut rng = Lcg(42);
+    let (msg, frame) = i2c_write_frame();
+    let mut decoder = FrameDecoder::new();
+    let mut decoded = 0;
+
+    for _ in 0..100 {
+        // Garbage that never contains a START_BYTE cannot start a bogus frame
+        let garbage: std::vec::Vec<u8> = (0..rng.next() % 32)
+            .map(|_| rng.next())
+            .filter(|&b| b != START_BYTE)
+            .collect();
+        decoder.decode(&garbage, |_| {});
+
+        // Deliver the frame in two random-sized pieces
+        let split = rng.next() as usize % frame.len();
+        decoder.decode(&frame[..split], |item| {
+            assert_eq!(item, Ok(msg.clone()));
+            decoded += 1;
+        });
+        decoder.decode(&frame[split..], |item| {
+            assert_eq!(item, Ok(msg.clone()));
+            decoded += 1;
+        });
+    }
+
+    assert_eq!(decoded, 100);
+}
@@ -0,0 +1,217 @@
+//! Event subscription and delivery tests
+
+use esp32_bus_pirate_protocol::{
+    codec::MessageCodec,
+    event::{EventQueue, Subscriptions},
+    message::*,
+    FrameDecoder, UNSEQUENCED,
+};
+use heapless::{String, Vec};
+
+fn uart_rx(bytes: &[u8]) -> Event {
+    Event::UartRx { data: Vec::from_slice(bytes).unwrap() }
+}
+
+fn sniffed(record: SniffRecord) -> Event {
+    Event::Sniffer(SnifferEvent {
+        mode: Mode::I2c,
+        timestamp_us: 1234,
+        record,
+    })
+}
+
+// ===== Encoding =====
+
+#[test]
+fn test_encode_decode_subscription_messages() {
+    for topic in Topic::ALL {
+        for msg in [Message::Subscribe { topic }, Message::Unsubscribe { topic }] {
+            let frame = MessageCodec::encode_with_seq(&msg, 5).unwrap();
+            assert_eq!(MessageCodec::decode(&frame).unwrap(), msg);
+        }
+    }
+}
+
+#[test]
+fn test_encode_decode_events() {
+    let events = [
+        uart_rx(b"hello"),
+        sniffed(SniffRecord::Start),
+        sniffed(SniffRecord::Address { addr: 0x50, read: false, ack: true }),
+        sniffed(SniffRecord::Data { value: 0xA5, ack: false }),
+        sniffed(SniffRecord::Exchange { mosi: 0x9F, miso: 0xEF }),
+        Event::GpioEdge { pin: 4, rising: true, timestamp_us: 99 },
+        Event::Touch { x: 120, y: 64, pressed: false },
+        Event::Log {
+            level: LogLevel::Warn,
+            text: String::try_from("brownout").unwrap(),
+        },
+        Event::Overflow { dropped: 3 },
+    ];
+
+    for event in events {
+        let msg = Message::Event(event);
+        let frame = MessageCodec::encode(&msg).unwrap();
+        assert_eq!(MessageCodec::decode(&frame).unwrap(), msg);
+    }
+}
+
+#[test]
+fn test_full_uart_event_fits_in_frame() {
+    let msg = Message::Event(uart_rx(&[0xFF; 256]));
+    assert!(MessageCodec::encode(&msg).is_ok());
+}
+
+#[test]
+fn test_events_require_v2() {
+    assert!(MessageCodec::encode_v1(&Message::Event(uart_rx(b"x"))).is_err());
+    assert!(MessageCodec::encode_v1(&Message::Subscribe { topic: Topic::Log }).is_err());
+}
+
+#[test]
+fn test_events_are_unsequenced() {
+    let frame = MessageCodec::encode(&Message::Event(uart_rx(b"abc"))).unwrap();
+    let mut decoder = FrameDecoder::new();
+
+    // Repeated events must not be mistaken for duplicate commands
+    for _ in 0..3 {
+        let mut out = None;
+        decoder.decode_frames(&frame, |item| out = Some(item.unwrap()));
+        assert_eq!(out.unwrap().seq, UNSEQUENCED);
+    }
+    assert_eq!(decoder.stats().duplicates, 0);
+}
+
+#[test]
+fn test_event_topics() {
+    assert_eq!(uart_rx(b"").topic(), Some(Topic::UartRx));
+    assert_eq!(sniffed(SniffRecord::Stop).topic(), Some(Topic::Sniffer));
+    assert_eq!(Event::Overflow { dropped: 1 }.topic(), None);
+}
+
+// ===== Subscriptions =====
+
+#[test]
+fn test_subscribe_and_unsubscribe() {
+    let mut subs = Subscriptions::new();
+    assert!(subs.is_empty());
+
+    assert!(subs.handle(&Message::Subscribe { topic: Topic::Sniffer }));
+    assert!(subs.handle(&Message::Subscribe { topic: Topic::UartRx }));
+    assert!(subs.is_subscribed(Topic::Sniffer));
+    assert!(subs.is_subscribed(Topic::UartRx));
+    assert!(!subs.is_subscribed(Topic::Gpio));
+
+    assert!(subs.handle(&Message::Unsubscribe { topic: Topic::Sniffer }));
+    assert!(!subs.is_subscribed(Topic::Sniffer));
+    assert!(subs.is_subscribed(Topic::UartRx));
+
+    subs.clear();
+    assert!(subs.is_empty());
+}
+
+#[test]
+fn test_handle_ignores_other_messages() {
+    let mut subs = Subscriptions::new();
+    assert!(!subs.handle(&Message::GetMode));
+    assert!(subs.is_empty());
+}
+
+#[test]
+fn test_every_topic_is_independent() {
+    let mut subs = Subscriptions::new();
+    for topic in Topic::ALL {
+        subs.subscribe(topic);
+    }
+    for topic in Topic::ALL {
+        subs.unsubscribe(topic);
+        assert!(!subs.is_subscribed(topic));
+    }
+    assert!(subs.is_empty());
+}
+
+#[test]
+fn test_overflow_goes_to_any_subscriber() {
+    let mut subs = Subscriptions::new();
+    let overflow = Event::Overflow { dropped: 1 };
+    assert!(!subs.wants(&overflow));
+
+    subs.subscribe(Topic::Log);
+    assert!(subs.wants(&overflow));
+}
+
+// ===== Queueing =====
+
+#[test]
+fn test_publish_filters_unsubscribed_topics() {
+    let mut subs = Subscriptions::new();
+    subs.subscribe(Topic::Sniffer);
+    let mut queue: EventQueue<4> = EventQueue::new();
+
+    assert!(!queue.publish(&subs, uart_rx(b"ignored")));
+    assert!(queue.publish(&subs, sniffed(SniffRecord::Start)));
+
+    assert_eq!(queue.pop(), Some(sniffed(SniffRecord::Start)));
+    assert_eq!(queue.pop(), None);
+}
+
+#[test]
+fn test_queue_preserves_order() {
+    let mut queue: EventQueue<8> = EventQueue::new();
+    for i in 0..5u8 {
+        assert!(queue.push(uart_rx(&[i])));
+    }
+    assert_eq!(queue.len(), 5);
+
+    for i in 0..5u8 {
+        assert_eq!(queue.pop(), Some(uart_rx(&[i])));
+    }
+    assert!(queue.is_empty());
+}
+
+#[test]
+fn test_overflow_reported_where_events_were_lost() {
+    let mut queue: EventQueue<3> = EventQueue::new();
+    for i in 0..3u8 {
+        assert!(queue.push(uart_rx(&[i])));
+    }
+
+    // Queue is full: these two are lost
+    assert!(!queue.push(uart_rx(&[3])));
+    assert!(!queue.push(uart_rx(&[4])));
+    assert_eq!(queue.dropped(), 2);
+
+    // Draining two slots makes room for the marker and a new event
+    assert_eq!(queue.pop(), Some(uart_rx(&[0])));
+    assert_eq!(queue.pop(), Some(uart_rx(&[1])));
+    assert!(queue.push(uart_rx(&[5])));
+
+    let rest: std::vec::Vec<Event> = core::iter::from_fn(|| queue.pop()).collect();
+    assert_eq!(
+        rest,
+        vec![uart_rx(&[2]), Event::Overflow { dropped: 2 }, uart_rx(&[5])]
+    );
+}
+
+#[test]
+fn test_overflow_reported_when_queue_drains() {
+    let mut queue: EventQueue<1> = EventQueue::new();
+    assert!(queue.push(uart_rx(b"a")));
+    assert!(!queue.push(uart_rx(b"b")));
+
+    assert_eq!(queue.pop(), Some(uart_rx(b"a")));
+    assert_eq!(queue.pop(), Some(Event::Overflow { dropped: 1 }));
+    assert_eq!(queue.pop(), None);
+    assert!(queue.is_empty());
+}
+
+#[test]
+fn test_clear_discards_everything() {
+    let mut queue: EventQueue<1> = EventQueue::new();
+    queue.push(uart_rx(b"a"));
+    queue.push(uart_rx(b"b"));
+
+    queue.clear();
+    assert!(queue.is_empty());
+    assert_eq!(queue.pop(), None);
+}
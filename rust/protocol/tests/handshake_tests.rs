@@ -0,0 +1,147 @@
+//! Hello/DeviceInfo handshake and version negotiation tests
+
+use esp32_bus_pirate_protocol::{
+    codec::MessageCodec, message::*, version, Error, MAX_MESSAGE_SIZE, PROTOCOL_VERSION,
+    PROTOCOL_VERSION_V1, PROTOCOL_VERSION_V2,
+};
+use heapless::{String, Vec};
+
+fn versions(list: &[u8]) -> Vec<u8, 8> {
+    Vec::from_slice(list).unwrap()
+}
+
+fn sample_device_info() -> DeviceInfo {
+    DeviceInfo {
+        version: PROTOCOL_VERSION_V2,
+        versions: versions(version::SUPPORTED_VERSIONS),
+        max_frame: 1024,
+        firmware_version: String::try_from("0.1.0").unwrap(),
+        board: String::try_from("Waveshare ESP32-S3-Touch-LCD-2.8").unwrap(),
+        modes: Vec::from_slice(&[Mode::HiZ, Mode::I2c, Mode::Spi, Mode::Uart]).unwrap(),
+        rx_buffer: 1024,
+        tx_buffer: 1024,
+        features: Features::SEQUENCE_IDS | Features::DISPLAY,
+    }
+}
+
+// ===== Encoding =====
+
+#[test]
+fn test_encode_decode_hello() {
+    let msg = Message::Hello {
+        versions: versions(&[1, 2]),
+        max_frame: 4096,
+    };
+    let encoded = MessageCodec::encode_with_seq(&msg, 1).unwrap();
+    assert_eq!(MessageCodec::decode(&encoded).unwrap(), msg);
+}
+
+#[test]
+fn test_encode_decode_device_info() {
+    let msg = Message::Response(Response::DeviceInfo(sample_device_info()));
+    let encoded = MessageCodec::encode_with_seq(&msg, 1).unwrap();
+    assert!(encoded.len() < MAX_MESSAGE_SIZE);
+    assert_eq!(MessageCodec::decode(&encoded).unwrap(), msg);
+}
+
+#[test]
+fn test_v1_variant_indices_unchanged() {
+    // GetMode must still encode as variant 1 for v1 clients
+    let frame = MessageCodec::encode_v1(&Message::GetMode).unwrap();
+    assert_eq!(&frame[4..5], &[0x01]);
+}
+
+// ===== Negotiation =====
+
+#[test]
+fn test_negotiate_picks_highest_common_version() {
+    assert_eq!(version::negotiate(&[1, 2]), Some(PROTOCOL_VERSION));
+    assert_eq!(version::negotiate(&[2, 1]), Some(PROTOCOL_VERSION));
+    assert_eq!(version::negotiate(&[1]), Some(PROTOCOL_VERSION_V1));
+    assert_eq!(version::negotiate(&[1, 2, 7]), Some(PROTOCOL_VERSION));
+}
+
+#[test]
+fn test_negotiate_without_common_version() {
+    assert_eq!(version::negotiate(&[]), None);
+    assert_eq!(version::negotiate(&[0, 9]), None);
+}
+
+#[test]
+fn test_negotiate_max_frame() {
+    assert_eq!(version::negotiate_max_frame(256), 256);
+    assert_eq!(version::negotiate_max_frame(u16::MAX), MAX_MESSAGE_SIZE as u16);
+    assert_eq!(version::negotiate_max_frame(0), MAX_MESSAGE_SIZE as u16);
+}
+
+// ===== Backward-Compatibility Rules =====
+
+#[test]
+fn test_hello_cannot_be_sent_as_v1() {
+    let msg = Message::Hello {
+        versions: versions(&[1, 2]),
+        max_frame: 0,
+    };
+    assert_eq!(MessageCodec::encode_v1(&msg), Err(Error::UnsupportedVersion));
+}
+
+#[test]
+fn test_device_info_cannot_be_sent_as_v1() {
+    let msg = Message::Response(Response::DeviceInfo(sample_device_info()));
+    assert_eq!(
+        MessageCodec::encode_versioned(&msg, PROTOCOL_VERSION_V1, 0),
+        Err(Error::UnsupportedVersion)
+    );
+}
+
+#[test]
+fn test_v1_frame_carrying_v2_message_is_rejected() {
+    // Hand-build a v1 frame around a Hello payload
+    let v2 = MessageCodec::encode_with_seq(
+        &Message::Hello {
+            versions: versions(&[2]),
+            max_frame: 0,
+        },
+        0,
+    )
+    .unwrap();
+    let payload = &v2[6..v2.len() - 3];
+
+    let crc = crc::Crc::<u16>::new(&crc::CRC_16_IBM_SDLC);
+    let mut frame = std::vec![0xAA, PROTOCOL_VERSION_V1];
+    frame.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+    frame.extend_from_slice(payload);
+    let checksum = crc.checksum(&frame[1..]);
+    frame.extend_from_slice(&checksum.to_le_bytes());
+    frame.push(0x55);
+
+    assert_eq!(MessageCodec::decode(&frame), Err(Error::UnsupportedVersion));
+}
+
+#[test]
+fn test_v1_messages_encode_in_every_version() {
+    for &v in version::SUPPORTED_VERSIONS {
+        let frame = MessageCodec::encode_versioned(&Message::I2cScan, v, 0).unwrap();
+        assert_eq!(frame[1], v);
+        assert_eq!(MessageCodec::decode(&frame).unwrap(), Message::I2cScan);
+    }
+}
+
+#[test]
+fn test_unknown_version_cannot_be_encoded() {
+    assert_eq!(
+        MessageCodec::encode_versioned(&Message::GetMode, 0x7F, 0),
+        Err(Error::UnsupportedVersion)
+    );
+}
+
+// ===== Features =====
+
+#[test]
+fn test_feature_flags() {
+    let features = Features::SEQUENCE_IDS | Features::WIFI;
+    assert!(features.contains(Features::SEQUENCE_IDS));
+    assert!(features.contains(Features::WIFI));
+    assert!(!features.contains(Features::DISPLAY));
+    assert!(features.contains(Features::NONE));
+}
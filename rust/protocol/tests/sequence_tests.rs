@@ -0,0 +1,229 @@
+//! Sequence/correlation ID tests (protocol v2)
+
+use esp32_bus_pirate_protocol::{
+    codec::MessageCodec, message::*, version, Error, Frame, FrameDecoder, Mode, SequenceWindow,
+    PROTOCOL_VERSION, PROTOCOL_VERSION_V1, UNSEQUENCED,
+};
+
+// ===== Frame Layout =====
+
+#[test]
+fn test_v2_frame_carries_seq_after_length() {
+    let frame = MessageCodec::encode_with_seq(&Message::GetMode, 0x1234).unwrap();
+
+    assert_eq!(frame[1], PROTOCOL_VERSION);
+    assert_eq!(u16::from_le_bytes([frame[2], frame[3]]), 1);
+    assert_eq!(u16::from_le_bytes([frame[4], frame[5]]), 0x1234);
+    // START + VERSION + LEN(2) + SEQ(2) + PAYLOAD(1) + CRC(2) + END
+    assert_eq!(frame.len(), 10);
+}
+
+#[test]
+fn test_decode_frame_returns_seq() {
+    let msg = Message::I2cRead { addr: 0x50, len: 4 };
+    let frame = MessageCodec::encode_with_seq(&msg, 77).unwrap();
+
+    let decoded = MessageCodec::decode_frame(&frame).unwrap();
+    assert_eq!(
+        decoded,
+        Frame {
+            version: PROTOCOL_VERSION,
+            seq: 77,
+            message: msg,
+        }
+    );
+}
+
+#[test]
+fn test_encode_defaults_to_unsequenced() {
+    let frame = MessageCodec::encode(&Message::I2cScan).unwrap();
+    assert_eq!(MessageCodec::decode_frame(&frame).unwrap().seq, UNSEQUENCED);
+}
+
+#[test]
+fn test_crc_covers_seq() {
+    let mut frame = MessageCodec::encode_with_seq(&Message::GetMode, 5).unwrap();
+    frame[4] ^= 0x01;
+
+    assert_eq!(MessageCodec::decode_frame(&frame), Err(Error::CrcMismatch));
+}
+
+// ===== Backward Compatibility =====
+
+#[test]
+fn test_v1_frames_still_decode() {
+    let msg = Message::SetMode { mode: Mode::Spi };
+    let frame = MessageCodec::encode_v1(&msg).unwrap();
+
+    assert_eq!(frame[1], PROTOCOL_VERSION_V1);
+    let decoded = MessageCodec::decode_frame(&frame).unwrap();
+    assert_eq!(decoded.version, PROTOCOL_VERSION_V1);
+    assert_eq!(decoded.seq, UNSEQUENCED);
+    assert_eq!(decoded.message, msg);
+}
+
+#[test]
+fn test_is_compatible_accepts_v1_and_v2() {
+    assert!(version::is_compatible(PROTOCOL_VERSION_V1));
+    assert!(version::is_compatible(PROTOCOL_VERSION));
+    assert!(!version::is_compatible(0x00));
+    assert!(!version::is_compatible(PROTOCOL_VERSION + 1));
+}
+
+// ===== Replies =====
+
+#[test]
+fn test_reply_echoes_seq_and_version() {
+    let request = MessageCodec::encode_with_seq(&Message::I2cScan, 900).unwrap();
+    let request = MessageCodec::decode_frame(&request).unwrap();
+
+    let response = Message::Response(Response::Success);
+    let reply = MessageCodec::encode_reply(&request, &response).unwrap();
+    let reply = MessageCodec::decode_frame(&reply).unwrap();
+
+    assert_eq!(reply.seq, 900);
+    assert_eq!(reply.version, PROTOCOL_VERSION);
+    assert_eq!(reply.message, response);
+}
+
+#[test]
+fn test_reply_to_v1_request_is_v1() {
+    let request = MessageCodec::encode_v1(&Message::GetMode).unwrap();
+    let request = MessageCodec::decode_frame(&request).unwrap();
+
+    let reply = MessageCodec::encode_reply(&request, &Message::Response(Response::Success)).unwrap();
+    assert_eq!(reply[1], PROTOCOL_VERSION_V1);
+}
+
+#[test]
+fn test_pipelined_responses_correlate() {
+    // Host pipelines three commands; device answers out of order
+    let requests = [
+        (1u16, Message::I2cRead { addr: 0x50, len: 1 }),
+        (2, Message::SpiTransfer { data: heapless::Vec::new() }),
+        (3, Message::UartRead { len: 8 }),
+    ];
+
+    let mut stream = Vec::new();
+    for (seq, msg) in requests.iter().rev() {
+        let response = match msg {
+            Message::I2cRead { .. } => Message::Error(ErrorCode::BusError),
+            _ => Message::Response(Response::Success),
+        };
+        stream.extend_from_slice(&MessageCodec::encode_with_seq(&response, *seq).unwrap());
+    }
+
+    let mut decoder = FrameDecoder::new();
+    let mut replies = Vec::new();
+    decoder.decode_frames(&stream, |item| replies.push(item.unwrap()));
+
+    let i2c_reply = replies.iter().find(|f| f.seq == 1).unwrap();
+    assert_eq!(i2c_reply.message, Message::Error(ErrorCode::BusError));
+    assert_eq!(replies.len(), 3);
+}
+
+// ===== Duplicate Rejection =====
+
+#[test]
+fn test_sequence_window_rejects_duplicates() {
+    let mut window = SequenceWindow::new();
+
+    assert_eq!(window.check(10), Ok(()));
+    assert_eq!(window.check(11), Ok(()));
+    assert_eq!(window.check(10), Err(Error::DuplicateSequence));
+}
+
+#[test]
+fn test_sequence_window_ignores_unsequenced() {
+    let mut window = SequenceWindow::new();
+
+    assert_eq!(window.check(UNSEQUENCED), Ok(()));
+    assert_eq!(window.check(UNSEQUENCED), Ok(()));
+}
+
+#[test]
+fn test_sequence_window_forgets_old_ids() {
+    let mut window = SequenceWindow::new();
+
+    for seq in 1..=(esp32_bus_pirate_protocol::codec::SEQUENCE_WINDOW as u16) {
+        window.check(seq).unwrap();
+    }
+    // Window is full; the next ID evicts seq 1
+    window.check(1000).unwrap();
+    assert_eq!(window.check(1), Ok(()));
+    assert_eq!(window.check(1000), Err(Error::DuplicateSequence));
+}
+
+#[test]
+fn test_decoder_rejects_duplicate_frames() {
+    let frame = MessageCodec::encode_with_seq(&Message::I2cScan, 42).unwrap();
+    let next = MessageCodec::encode_with_seq(&Message::GetMode, 43).unwrap();
+
+    let mut stream = Vec::new();
+    stream.extend_from_slice(&frame);
+    stream.extend_from_slice(&frame);
+    stream.extend_from_slice(&next);
+
+    let mut decoder = FrameDecoder::new();
+    let mut out = Vec::new();
+    decoder.decode(&stream, |item| out.push(item));
+
+    assert_eq!(
+        out,
+        vec![
+            Ok(Message::I2cScan),
+            Err(Error::DuplicateSequence),
+            Ok(Message::GetMode)
+        ]
+    );
+    assert_eq!(decoder.stats().duplicates, 1);
+}
+
+#[test]
+fn test_decoder_clear_sequences_allows_reuse() {
+    let frame = MessageCodec::encode_with_seq(&Message::I2cScan, 42).unwrap();
+    let mut decoder = FrameDecoder::new();
+    let mut out = Vec::new();
+
+    decoder.decode(&frame, |item| out.push(item));
+    decoder.clear_sequences();
+    decoder.decode(&frame, |item| out.push(item));
+
+    assert_eq!(out, vec![Ok(Message::I2cScan), Ok(Message::I2cScan)]);
+}
+
+#[test]
+fn test_decoder_hello_starts_new_sequence_window() {
+    // A second host on the same link numbers its requests from 1 again
+    let hello = Message::Hello {
+        versions: heapless::Vec::from_slice(&[PROTOCOL_VERSION]).unwrap(),
+        max_frame: 512,
+    };
+    let mut stream = Vec::new();
+    for _ in 0..2 {
+        stream.extend_from_slice(&MessageCodec::encode_with_seq(&hello, 1).unwrap());
+        stream.extend_from_slice(&MessageCodec::encode_with_seq(&Message::GetMode, 2).unwrap());
+    }
+
+    let mut decoder = FrameDecoder::new();
+    let mut out = Vec::new();
+    decoder.decode(&stream, |item| out.push(item));
+
+    assert_eq!(out.iter().filter(|item| item.is_ok()).count(), 4);
+    assert_eq!(decoder.stats().duplicates, 0);
+}
+
+#[test]
+fn test_decoder_handles_mixed_versions() {
+    let mut stream = Vec::new();
+    stream.extend_from_slice(&MessageCodec::encode_v1(&Message::GetMode).unwrap());
+    stream.extend_from_slice(&MessageCodec::encode_with_seq(&Message::I2cScan, 1).unwrap());
+    stream.extend_from_slice(&MessageCodec::encode_v1(&Message::GetMode).unwrap());
+
+    let mut decoder = FrameDecoder::new();
+    let mut out = Vec::new();
+    decoder.decode_frames(&stream, |item| out.push(item.unwrap()));
+
+    let versions: Vec<u8> = out.iter().map(|f| f.version).collect();
+    assert_eq!(versions, vec![PROTOCOL_VERSION_V1, PROTOCOL_VERSION, PROTOCOL_VERSION_V1]);
+}
@@ -25,13 +25,42 @@ echo ""
 
 cd ..
 
-echo -e "${YELLOW}Checking bus-modes tests...${NC}"
+echo -e "${YELLOW}Running client tests...${NC}"
+cd client
+cargo test --verbose
+echo -e "${GREEN}✓ Client tests passed${NC}"
+echo ""
+
+cd ..
+
+echo -e "${YELLOW}Running bpctl tests...${NC}"
+cd cli
+cargo test --verbose
+echo -e "${GREEN}✓ bpctl tests passed${NC}"
+echo ""
+
+cd ..
+
+echo -e "${YELLOW}Running core tests...${NC}"
+cd core
+cargo test --verbose
+echo -e "${GREEN}✓ Core tests passed${NC}"
+echo ""
+
+cd ..
+
+echo -e "${YELLOW}Running simulator tests...${NC}"
+cd simulator
+cargo test --verbose
+echo -e "${GREEN}✓ Simulator tests passed${NC}"
+echo ""
+
+cd ..
+
+echo -e "${YELLOW}Running bus-modes tests...${NC}"
 cd bus-modes
-if cargo test --lib --verbose 2>/dev/null; then
-    echo -e "${GREEN}✓ Bus-modes tests passed${NC}"
-else
-    echo -e "${YELLOW}⚠ No tests in bus-modes yet${NC}"
-fi
+cargo test --verbose
+echo -e "${GREEN}✓ Bus-modes tests passed${NC}"
 echo ""
 
 cd ..
@@ -0,0 +1,172 @@
+//! File storage for the simulated device
+
+use esp32_bus_pirate_core::Storage;
+use esp32_bus_pirate_protocol::ErrorCode;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+/// Files behind `FileList`, `FileRead`, `FileWrite` and bulk transfers
+///
+/// Device paths are absolute (`/scripts/init.bp`); `..` is rejected.
+#[derive(Debug)]
+pub enum Files {
+    /// Kept in memory and lost on exit
+    Memory(BTreeMap<String, Vec<u8>>),
+    /// Stored under a host directory
+    Dir(PathBuf),
+}
+
+impl Files {
+    /// Empty in-memory filesystem
+    pub fn memory() -> Self {
+        Files::Memory(BTreeMap::new())
+    }
+
+    /// Filesystem rooted at host directory `root`
+    pub fn dir(root: impl Into<PathBuf>) -> Self {
+        Files::Dir(root.into())
+    }
+}
+
+/// Canonical form of a device path: `/` followed by its non-empty components
+fn normalize(path: &str) -> Result<String, ErrorCode> {
+    let mut out = String::new();
+    for component in path.split('/').filter(|c| !c.is_empty() && *c != ".") {
+        if component == ".." {
+            return Err(ErrorCode::PermissionDenied);
+        }
+        out.push('/');
+        out.push_str(component);
+    }
+    if out.is_empty() {
+        out.push('/');
+    }
+    Ok(out)
+}
+
+/// Host location of device path `path` under `root`
+fn host_path(root: &Path, path: &str) -> Result<PathBuf, ErrorCode> {
+    Ok(root.join(normalize(path)?.trim_start_matches('/')))
+}
+
+fn io_error(err: io::Error) -> ErrorCode {
+    match err.kind() {
+        io::ErrorKind::NotFound => ErrorCode::FileNotFound,
+        io::ErrorKind::PermissionDenied => ErrorCode::PermissionDenied,
+        _ => ErrorCode::InvalidParameter,
+    }
+}
+
+impl Storage for Files {
+    fn list(&mut self, path: &str) -> Result<heapless::Vec<heapless::String<64>, 32>, ErrorCode> {
+        let names: BTreeSet<String> = match self {
+            Files::Memory(files) => {
+                let dir = normalize(path)?;
+                let prefix = if dir == "/" { dir.clone() } else { format!("{dir}/") };
+                let names: BTreeSet<String> = files
+                    .keys()
+                    .filter_map(|name| name.strip_prefix(&prefix))
+                    .map(|rest| rest.split('/').next().unwrap_or(rest).to_owned())
+                    .collect();
+                // Directories only exist through the files in them
+                if names.is_empty() && dir != "/" {
+                    return Err(ErrorCode::FileNotFound);
+                }
+                names
+            }
+            Files::Dir(root) => fs::read_dir(host_path(root, path)?)
+                .map_err(io_error)?
+                .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                .collect(),
+        };
+
+        // Entries that do not fit the reply are left out
+        let mut list = heapless::Vec::new();
+        for name in names {
+            if let Ok(name) = heapless::String::try_from(name.as_str()) {
+                if list.push(name).is_err() {
+                    break;
+                }
+            }
+        }
+        Ok(list)
+    }
+
+    fn size(&mut self, path: &str) -> Result<u32, ErrorCode> {
+        let len = match self {
+            Files::Memory(files) => files.get(&normalize(path)?).ok_or(ErrorCode::FileNotFound)?.len() as u64,
+            Files::Dir(root) => {
+                let meta = fs::metadata(host_path(root, path)?).map_err(io_error)?;
+                if !meta.is_file() {
+                    return Err(ErrorCode::FileNotFound);
+                }
+                meta.len()
+            }
+        };
+        u32::try_from(len).map_err(|_| ErrorCode::InvalidParameter)
+    }
+
+    fn read(&mut self, path: &str, offset: u32, buf: &mut [u8]) -> Result<usize, ErrorCode> {
+        match self {
+            Files::Memory(files) => {
+                let data = files.get(&normalize(path)?).ok_or(ErrorCode::FileNotFound)?;
+                let start = (offset as usize).min(data.len());
+                let n = buf.len().min(data.len() - start);
+                buf[..n].copy_from_slice(&data[start..start + n]);
+                Ok(n)
+            }
+            Files::Dir(root) => {
+                let mut file = File::open(host_path(root, path)?).map_err(io_error)?;
+                file.seek(SeekFrom::Start(offset.into())).map_err(io_error)?;
+                let mut n = 0;
+                while n < buf.len() {
+                    match file.read(&mut buf[n..]).map_err(io_error)? {
+                        0 => break,
+                        read => n += read,
+                    }
+                }
+                Ok(n)
+            }
+        }
+    }
+
+    fn write(&mut self, path: &str, offset: u32, data: &[u8]) -> Result<(), ErrorCode> {
+        let offset = offset as usize;
+        match self {
+            Files::Memory(files) => {
+                let name = normalize(path)?;
+                if name == "/" {
+                    return Err(ErrorCode::InvalidParameter);
+                }
+                let file = files.entry(name).or_default();
+                if offset == 0 {
+                    file.clear();
+                }
+                if offset > file.len() {
+                    return Err(ErrorCode::InvalidParameter);
+                }
+                file.truncate(offset);
+                file.extend_from_slice(data);
+                Ok(())
+            }
+            Files::Dir(root) => {
+                let host_path = host_path(root, path)?;
+                if let Some(parent) = host_path.parent() {
+                    fs::create_dir_all(parent).map_err(io_error)?;
+                }
+                let mut file = OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(offset == 0)
+                    .open(host_path)
+                    .map_err(io_error)?;
+                file.seek(SeekFrom::Start(offset as u64)).map_err(io_error)?;
+                file.write_all(data).map_err(io_error)
+            }
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! Virtual I2C bus with register-based target devices
+
+use embedded_hal::i2c::{ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation};
+use esp32_bus_pirate_bus_modes::{
+    i2c::{recover_bus, I2cLine, I2cLines, I2cRecoveryReport},
+    Error,
+};
+use std::collections::BTreeMap;
+
+/// A simulated device on the virtual I2C bus
+pub trait I2cTarget: Send {
+    /// The controller sent this device's address; return `false` to NACK it,
+    /// as a busy device does
+    fn select(&mut self) -> bool {
+        true
+    }
+
+    /// Bytes written by the controller; return `false` to NACK them
+    fn write(&mut self, data: &[u8]) -> bool;
+
+    /// Bytes read by the controller
+    fn read(&mut self, buf: &mut [u8]);
+}
+
+/// Device with an 8-bit register pointer, like most sensors and small EEPROMs
+///
+/// The first byte of a write sets the pointer and the remaining bytes are
+/// stored from there on. Reads return registers from the pointer on. The
+/// pointer auto-increments and wraps at the end of the register file.
+pub struct RegisterDevice {
+    registers: Vec<u8>,
+    read_only: Vec<bool>,
+    pointer: usize,
+}
+
+impl RegisterDevice {
+    /// Device with `size` registers (1 to 256), all reading `fill`
+    pub fn new(size: usize, fill: u8) -> Self {
+        let size = size.clamp(1, 256);
+        Self {
+            registers: vec![fill; size],
+            read_only: vec![false; size],
+            pointer: 0,
+        }
+    }
+
+    /// 24C02-style 256-byte EEPROM, erased to 0xFF
+    pub fn eeprom_24c02() -> Self {
+        Self::new(256, 0xFF)
+    }
+
+    /// BME280 humidity sensor: chip ID 0x60 at register 0xD0
+    pub fn bme280() -> Self {
+        Self::new(256, 0x00).with_constant(0xD0, 0x60)
+    }
+
+    /// Set register `reg` to `value`
+    pub fn with_register(mut self, reg: u8, value: u8) -> Self {
+        if let Some(r) = self.registers.get_mut(reg as usize) {
+            *r = value;
+        }
+        self
+    }
+
+    /// Set register `reg` to `value` and ignore writes to it
+    pub fn with_constant(mut self, reg: u8, value: u8) -> Self {
+        self = self.with_register(reg, value);
+        if let Some(ro) = self.read_only.get_mut(reg as usize) {
+            *ro = true;
+        }
+        self
+    }
+
+    /// Current register contents
+    pub fn registers(&self) -> &[u8] {
+        &self.registers
+    }
+
+    fn advance(&mut self) {
+        self.pointer = (self.pointer + 1) % self.registers.len();
+    }
+}
+
+impl I2cTarget for RegisterDevice {
+    fn write(&mut self, data: &[u8]) -> bool {
+        let Some((&reg, values)) = data.split_first() else {
+            // Address-only probe
+            return true;
+        };
+        self.pointer = reg as usize % self.registers.len();
+        for &value in values {
+            if !self.read_only[self.pointer] {
+                self.registers[self.pointer] = value;
+            }
+            self.advance();
+        }
+        true
+    }
+
+    fn read(&mut self, buf: &mut [u8]) {
+        for byte in buf {
+            *byte = self.registers[self.pointer];
+            self.advance();
+        }
+    }
+}
+
+/// I2C bus implementing `embedded_hal::i2c::I2c` for the bus modes
+///
+/// Addresses without a device NACK, as an empty bus would. The bus can be
+/// left stuck, with SDA held low, to exercise [`recover`](Self::recover).
+#[derive(Default)]
+pub struct VirtualI2c {
+    devices: BTreeMap<u8, Box<dyn I2cTarget>>,
+    /// Fastest SCL frequency each limited device still answers at
+    speed_limits: BTreeMap<u8, u32>,
+    /// SCL frequency in Hz; 0 until one is set
+    frequency: u32,
+    stuck_clocks: u8,
+    no_pull_ups: bool,
+}
+
+impl VirtualI2c {
+    /// Empty bus
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attach `device` at 7-bit address `addr`
+    pub fn with_device(mut self, addr: u8, device: impl I2cTarget + 'static) -> Self {
+        self.attach(addr, device);
+        self
+    }
+
+    /// Attach `device` at 7-bit address `addr`, replacing any device there
+    pub fn attach(&mut self, addr: u8, device: impl I2cTarget + 'static) {
+        self.devices.insert(addr, Box::new(device));
+    }
+
+    /// Addresses with a device attached
+    pub fn addresses(&self) -> impl Iterator<Item = u8> + '_ {
+        self.devices.keys().copied()
+    }
+
+    /// Have the device at `addr` stop answering above `max_hz`, as one on a
+    /// long cable or with weak pull-ups does
+    pub fn with_speed_limit(mut self, addr: u8, max_hz: u32) -> Self {
+        self.speed_limits.insert(addr, max_hz);
+        self
+    }
+
+    /// SCL frequency last set, in Hz
+    pub fn frequency(&self) -> u32 {
+        self.frequency
+    }
+
+    /// Change the SCL frequency, for
+    /// [`Dispatcher::with_i2c_frequency`](esp32_bus_pirate_core::Dispatcher::with_i2c_frequency)
+    pub fn set_frequency(&mut self, hz: u32) -> Result<(), Error> {
+        self.frequency = hz;
+        Ok(())
+    }
+
+    /// Have a device hold SDA low until it sees `clocks` more SCL pulses, as
+    /// after a controller reset in the middle of a read
+    pub fn with_stuck_sda(mut self, clocks: u8) -> Self {
+        self.stuck_clocks = clocks;
+        self
+    }
+
+    /// Leave out the external pull-ups; transfers still work, but recovery
+    /// reports them missing
+    pub fn without_pull_ups(mut self) -> Self {
+        self.no_pull_ups = true;
+        self
+    }
+
+    /// Free the bus by driving its lines, for
+    /// [`Dispatcher::with_i2c_recovery`](esp32_bus_pirate_core::Dispatcher::with_i2c_recovery)
+    pub fn recover(&mut self) -> Result<I2cRecoveryReport, Error> {
+        let mut lines = VirtualLines {
+            bus: self,
+            scl: true,
+            sda: true,
+            internal_pull_ups: true,
+        };
+        Ok(recover_bus(&mut lines))
+    }
+}
+
+/// The lines of a [`VirtualI2c`] driven as GPIOs; no time passes on them
+struct VirtualLines<'a> {
+    bus: &'a mut VirtualI2c,
+    scl: bool,
+    sda: bool,
+    internal_pull_ups: bool,
+}
+
+impl I2cLines for VirtualLines<'_> {
+    fn set(&mut self, line: I2cLine, high: bool) {
+        match line {
+            I2cLine::Scl => {
+                // A stuck device shifts out a bit on every rising edge
+                if high && !self.scl {
+                    self.bus.stuck_clocks = self.bus.stuck_clocks.saturating_sub(1);
+                }
+                self.scl = high;
+            }
+            I2cLine::Sda => self.sda = high,
+        }
+    }
+
+    fn get(&mut self, line: I2cLine) -> bool {
+        let pulled_up = !self.bus.no_pull_ups || self.internal_pull_ups;
+        match line {
+            I2cLine::Scl => self.scl && pulled_up,
+            I2cLine::Sda => self.sda && pulled_up && self.bus.stuck_clocks == 0,
+        }
+    }
+
+    fn set_internal_pull_ups(&mut self, enabled: bool) {
+        self.internal_pull_ups = enabled;
+    }
+
+    fn delay_us(&mut self, _us: u32) {}
+}
+
+impl ErrorType for VirtualI2c {
+    type Error = ErrorKind;
+}
+
+impl I2c for VirtualI2c {
+    fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Self::Error> {
+        if self.stuck_clocks > 0 {
+            // No START can be made with SDA low
+            return Err(ErrorKind::Bus);
+        }
+        if self.speed_limits.get(&address).is_some_and(|&max_hz| self.frequency > max_hz) {
+            // Too fast for the device to see its address
+            return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+        }
+        let device = self
+            .devices
+            .get_mut(&address)
+            .and_then(|device| device.select().then_some(device))
+            .ok_or(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address))?;
+
+        // Adjacent writes go out without a repeated start, so the device
+        // sees them as one
+        let mut written: Option<Vec<u8>> = None;
+        for operation in operations {
+            match operation {
+                Operation::Write(data) => written.get_or_insert_with(Vec::new).extend_from_slice(data),
+                Operation::Read(buf) => {
+                    if let Some(data) = written.take() {
+                        deliver(device.as_mut(), &data)?;
+                    }
+                    device.read(buf);
+                }
+            }
+        }
+        if let Some(data) = written {
+            deliver(device.as_mut(), &data)?;
+        }
+        Ok(())
+    }
+}
+
+fn deliver(device: &mut dyn I2cTarget, data: &[u8]) -> Result<(), ErrorKind> {
+    if device.write(data) {
+        Ok(())
+    } else {
+        Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data))
+    }
+}
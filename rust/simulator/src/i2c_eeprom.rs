@@ -0,0 +1,105 @@
+//! Simulated 24Cxx I2C EEPROM
+
+use crate::i2c::I2cTarget;
+use esp32_bus_pirate_protocol::EepromPart;
+
+/// Address NACKs after a write, standing in for the write cycle
+const DEFAULT_WRITE_CYCLE: u32 = 3;
+
+/// 24Cxx EEPROM for the [`VirtualI2c`](crate::VirtualI2c) bus
+///
+/// Writes start with the memory address bytes; data past the end of the
+/// page wraps to its start, as on the real parts. After a write the part
+/// does not acknowledge its address for a few transfers, so controllers
+/// have to poll. Reads continue from the address pointer and wrap at the
+/// end of memory.
+///
+/// Only parts that answer at a single device address are modelled, which
+/// excludes the 24C04 to 24C16 and the 24C1024.
+pub struct Eeprom24 {
+    part: EepromPart,
+    memory: Vec<u8>,
+    pointer: usize,
+    write_cycle: u32,
+    busy: u32,
+}
+
+impl Eeprom24 {
+    /// Erased `part`
+    ///
+    /// # Panics
+    ///
+    /// If the part uses block select bits.
+    pub fn new(part: EepromPart) -> Self {
+        assert_eq!(part.blocks(), 1, "{part:?} answers at several addresses");
+        Self {
+            part,
+            memory: vec![0xFF; part.capacity() as usize],
+            pointer: 0,
+            write_cycle: DEFAULT_WRITE_CYCLE,
+            busy: 0,
+        }
+    }
+
+    /// Erased 24C256 (32 KB, 64-byte pages)
+    pub fn c256() -> Self {
+        Self::new(EepromPart::C256)
+    }
+
+    /// Load `image` at address 0; longer images are truncated
+    pub fn with_image(mut self, image: &[u8]) -> Self {
+        let len = image.len().min(self.memory.len());
+        self.memory[..len].copy_from_slice(&image[..len]);
+        self
+    }
+
+    /// Refuse the address this many times after each write
+    pub fn with_write_cycle(mut self, nacks: u32) -> Self {
+        self.write_cycle = nacks;
+        self
+    }
+
+    /// Memory contents
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+}
+
+impl I2cTarget for Eeprom24 {
+    fn select(&mut self) -> bool {
+        if self.busy > 0 {
+            self.busy -= 1;
+            return false;
+        }
+        true
+    }
+
+    fn write(&mut self, data: &[u8]) -> bool {
+        let width = usize::from(self.part.address_bytes());
+        if data.len() < width {
+            // Address-only probe, or an address cut short
+            return true;
+        }
+        let (address, values) = data.split_at(width);
+        self.pointer = address.iter().fold(0, |acc, &b| acc << 8 | usize::from(b)) % self.memory.len();
+        if values.is_empty() {
+            // Random read: the pointer is set, nothing is programmed
+            return true;
+        }
+
+        let page = self.part.page_size() as usize;
+        let start = self.pointer - self.pointer % page;
+        for (i, &value) in values.iter().enumerate() {
+            self.memory[start + (self.pointer % page + i) % page] = value;
+        }
+        self.busy = self.write_cycle;
+        true
+    }
+
+    fn read(&mut self, buf: &mut [u8]) {
+        for byte in buf {
+            *byte = self.memory[self.pointer];
+            self.pointer = (self.pointer + 1) % self.memory.len();
+        }
+    }
+}
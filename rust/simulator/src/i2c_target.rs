@@ -0,0 +1,120 @@
+//! Simulated I2C target peripheral, with a scripted controller on the
+//! other side
+
+use esp32_bus_pirate_bus_modes::{
+    i2c_target::{TargetBus, TargetEvent},
+    Error,
+};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+#[derive(Debug, Default)]
+struct Bus {
+    listening: Option<u8>,
+    pending: VecDeque<TargetEvent>,
+    received: Vec<u8>,
+    acks: Vec<bool>,
+}
+
+/// I2C target peripheral for [`VirtualPins`](crate::VirtualPins)
+///
+/// Clones share one bus: the dispatcher answers through one clone while a
+/// test, playing the external controller, queues transactions on another.
+/// Queued transactions are answered the next time the dispatcher polls for
+/// events.
+#[derive(Debug, Clone, Default)]
+pub struct VirtualTarget {
+    bus: Arc<Mutex<Bus>>,
+}
+
+impl VirtualTarget {
+    /// Target that does not answer at any address yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Address the target answers at, while it runs
+    pub fn address(&self) -> Option<u8> {
+        self.lock().listening
+    }
+
+    /// Queue a write of `data` to `addr`, ended by a STOP
+    ///
+    /// Returns `false`, and queues nothing, if the target does not answer
+    /// at `addr`.
+    pub fn write(&self, addr: u8, data: &[u8]) -> bool {
+        self.transaction(addr, data, None)
+    }
+
+    /// Queue a read of `len` bytes from `addr`, ended by a STOP
+    pub fn read(&self, addr: u8, len: usize) -> bool {
+        self.transaction(addr, &[], Some(len))
+    }
+
+    /// Queue a write of `data`, a repeated start and a read of `len` bytes
+    pub fn write_read(&self, addr: u8, data: &[u8], len: usize) -> bool {
+        self.transaction(addr, data, Some(len))
+    }
+
+    /// Bytes the target has sent for reads, oldest first; clears them
+    pub fn take_received(&self) -> Vec<u8> {
+        std::mem::take(&mut self.lock().received)
+    }
+
+    /// Whether the target acknowledged each written byte, oldest first;
+    /// clears them
+    pub fn take_acks(&self) -> Vec<bool> {
+        std::mem::take(&mut self.lock().acks)
+    }
+
+    fn transaction(&self, addr: u8, data: &[u8], read: Option<usize>) -> bool {
+        let mut bus = self.lock();
+        if bus.listening != Some(addr) {
+            return false;
+        }
+        if !data.is_empty() || read.is_none() {
+            bus.pending.push_back(TargetEvent::Start { read: false });
+            bus.pending.extend(data.iter().map(|&b| TargetEvent::Written(b)));
+        }
+        if let Some(len) = read {
+            bus.pending.push_back(TargetEvent::Start { read: true });
+            bus.pending.extend((0..len).map(|_| TargetEvent::ReadRequest));
+        }
+        bus.pending.push_back(TargetEvent::Stop);
+        true
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Bus> {
+        // A test that panicked mid-transaction leaves nothing inconsistent
+        self.bus.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl TargetBus for VirtualTarget {
+    fn listen(&mut self, addr: u8) -> Result<(), Error> {
+        self.lock().listening = Some(addr);
+        Ok(())
+    }
+
+    fn release(&mut self) -> Result<(), Error> {
+        let mut bus = self.lock();
+        bus.listening = None;
+        // Transactions in flight end with the target gone
+        bus.pending.clear();
+        Ok(())
+    }
+
+    fn next_event(&mut self) -> Option<TargetEvent> {
+        self.lock().pending.pop_front()
+    }
+
+    fn ack(&mut self, ack: bool) {
+        self.lock().acks.push(ack);
+    }
+
+    fn send(&mut self, byte: u8) {
+        self.lock().received.push(byte);
+    }
+}
@@ -0,0 +1,182 @@
+//! Desktop simulator for the ESP32 Bus Pirate
+//!
+//! Runs the firmware's [`Dispatcher`] against virtual buses and serves the
+//! framed protocol over TCP, a Unix socket or a pseudo-terminal. Host tools
+//! (`bpctl`, the client library, `tools/test_client.py`) can then be tested
+//! end to end without an ESP32.
+//!
+//! The default device has:
+//!
+//! | Bus  | Device |
+//! |------|--------|
+//! | I2C  | 24C02 EEPROM at 0x50, BME280 at 0x76 (chip ID 0x60 in register 0xD0) |
+//! | I2C target | Answers a controller scripted through [`Simulator::i2c_target`] |
+//! | SPI  | W25Q32 flash, 4 MB, JEDEC ID `EF 40 16` |
+//! | UART | Loopback: everything written can be read back |
+//! | GPIO | Pins 0–48 read back the level last driven |
+//!
+//! ```no_run
+//! use esp32_bus_pirate_simulator::Simulator;
+//! use std::net::TcpListener;
+//!
+//! let listener = TcpListener::bind("127.0.0.1:5555")?;
+//! Simulator::new().serve_tcp(&listener)?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+pub mod files;
+pub mod i2c;
+pub mod i2c_eeprom;
+pub mod i2c_target;
+pub mod pins;
+#[cfg(unix)]
+pub mod pty;
+pub mod spi_flash;
+pub mod uart;
+
+pub use files::Files;
+pub use i2c::{I2cTarget, RegisterDevice, VirtualI2c};
+pub use i2c_eeprom::Eeprom24;
+pub use i2c_target::VirtualTarget;
+pub use pins::VirtualPins;
+#[cfg(unix)]
+pub use pty::Pty;
+pub use spi_flash::SpiFlash;
+pub use uart::Loopback;
+
+use esp32_bus_pirate_core::Dispatcher;
+use esp32_bus_pirate_protocol::FrameDecoder;
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::TcpListener,
+    thread,
+    time::Duration,
+};
+
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+
+/// Board name reported in `DeviceInfo`
+pub const BOARD_NAME: &str = "Simulator";
+
+/// Read timeout on socket connections; events are polled in between
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The simulated device's dispatcher
+pub type SimDispatcher = Dispatcher<VirtualI2c, SpiFlash, Loopback, Files, VirtualPins>;
+
+/// A simulated Bus Pirate
+pub struct Simulator {
+    dispatcher: SimDispatcher,
+    target: VirtualTarget,
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Simulator {
+    /// Simulator with the default devices and in-memory files
+    pub fn new() -> Self {
+        Self::with_parts(default_i2c(), SpiFlash::w25q32(), Files::memory())
+    }
+
+    /// Simulator with custom I2C devices, flash and file storage
+    pub fn with_parts(i2c: VirtualI2c, flash: SpiFlash, files: Files) -> Self {
+        let target = VirtualTarget::new();
+        Self {
+            dispatcher: Dispatcher::new(i2c, flash, Loopback::new(), files)
+                .with_platform(VirtualPins::new().with_i2c_target(target.clone()))
+                .with_i2c_recovery(VirtualI2c::recover)
+                .with_i2c_frequency(VirtualI2c::set_frequency)
+                .with_board(BOARD_NAME),
+            target,
+        }
+    }
+
+    /// The dispatcher, e.g. to publish events or inspect the mode
+    pub fn dispatcher(&mut self) -> &mut SimDispatcher {
+        &mut self.dispatcher
+    }
+
+    /// The controller side of the I2C target peripheral, to exercise
+    /// `I2cTargetStart` with
+    pub fn i2c_target(&self) -> VirtualTarget {
+        self.target.clone()
+    }
+
+    /// Serve one host over `io` until it disconnects
+    ///
+    /// Reads should time out (or return `WouldBlock`) now and then, so
+    /// events can be sent while the host is idle.
+    pub fn serve<T: Read + Write>(&mut self, io: &mut T) -> io::Result<()> {
+        let result = self.session(io);
+        self.dispatcher.end_session();
+        result
+    }
+
+    /// Accept TCP connections forever, serving one host at a time
+    pub fn serve_tcp(&mut self, listener: &TcpListener) -> io::Result<()> {
+        loop {
+            let (mut stream, _) = listener.accept()?;
+            stream.set_read_timeout(Some(POLL_INTERVAL))?;
+            stream.set_nodelay(true)?;
+            // A broken connection only ends its own session
+            let _ = self.serve(&mut stream);
+        }
+    }
+
+    /// Accept Unix socket connections forever, serving one host at a time
+    #[cfg(unix)]
+    pub fn serve_unix(&mut self, listener: &UnixListener) -> io::Result<()> {
+        loop {
+            let (mut stream, _) = listener.accept()?;
+            stream.set_read_timeout(Some(POLL_INTERVAL))?;
+            let _ = self.serve(&mut stream);
+        }
+    }
+
+    fn session<T: Read + Write>(&mut self, io: &mut T) -> io::Result<()> {
+        let mut decoder = FrameDecoder::new();
+        let mut buf = [0u8; 1024];
+        let mut frames = Vec::new();
+
+        loop {
+            match io.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(n) => {
+                    // Malformed frames are dropped; the host times out and retries
+                    decoder.decode_frames(&buf[..n], |item| frames.extend(item.ok()));
+                    for frame in frames.drain(..) {
+                        let reply = self.dispatcher.handle_frame(&frame).map_err(invalid_data)?;
+                        io.write_all(&reply)?;
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+
+            while let Some(event) = self.dispatcher.poll_event() {
+                let frame = self.dispatcher.encode_event(event).map_err(invalid_data)?;
+                io.write_all(&frame)?;
+            }
+            io.flush()?;
+        }
+    }
+}
+
+/// The default I2C bus: a 24C02 EEPROM at 0x50 and a BME280 at 0x76
+pub fn default_i2c() -> VirtualI2c {
+    VirtualI2c::new()
+        .with_device(0x50, RegisterDevice::eeprom_24c02())
+        .with_device(0x76, RegisterDevice::bme280())
+}
+
+fn invalid_data(err: esp32_bus_pirate_protocol::Error) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, format!("{err:?}"))
+}
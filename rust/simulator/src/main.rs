@@ -0,0 +1,106 @@
+//! `bpsim` - ESP32 Bus Pirate simulator
+//!
+//! ```text
+//! bpsim                                   # TCP on 127.0.0.1:5555
+//! bpsim --pty --link /tmp/bus-pirate      # serial tools open /tmp/bus-pirate
+//! bpsim --unix /tmp/bus-pirate.sock --files ./sim-files
+//! ```
+
+use clap::Parser;
+use esp32_bus_pirate_simulator::{default_i2c, Files, Simulator, SpiFlash};
+use std::{error::Error, fs, net::TcpListener, path::PathBuf, process::ExitCode};
+
+#[cfg(unix)]
+use esp32_bus_pirate_simulator::Pty;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+
+/// Simulated ESP32 Bus Pirate for testing host tools without hardware
+#[derive(Debug, Parser)]
+#[command(name = "bpsim", version)]
+struct Args {
+    /// Listen for TCP connections on this address
+    #[arg(long, value_name = "ADDR", conflicts_with_all = ["unix", "pty"])]
+    tcp: Option<String>,
+
+    /// Listen on a Unix domain socket
+    #[arg(long, value_name = "PATH", conflicts_with = "pty")]
+    unix: Option<PathBuf>,
+
+    /// Serve a pseudo-terminal that serial-port tools can open
+    #[arg(long)]
+    pty: bool,
+
+    /// Symlink this path to the pseudo-terminal
+    #[arg(long, value_name = "PATH", requires = "pty")]
+    link: Option<PathBuf>,
+
+    /// Keep device files in this directory instead of in memory
+    #[arg(long, value_name = "DIR")]
+    files: Option<PathBuf>,
+
+    /// Load the SPI flash contents from this image
+    #[arg(long, value_name = "FILE")]
+    flash_image: Option<PathBuf>,
+}
+
+/// Address used when no endpoint is given
+const DEFAULT_TCP: &str = "127.0.0.1:5555";
+
+fn main() -> ExitCode {
+    match run(Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("bpsim: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let files = match &args.files {
+        Some(dir) => {
+            fs::create_dir_all(dir)?;
+            Files::dir(dir)
+        }
+        None => Files::memory(),
+    };
+    let mut flash = SpiFlash::w25q32();
+    if let Some(image) = &args.flash_image {
+        flash = flash.with_image(&fs::read(image)?);
+    }
+    let mut sim = Simulator::with_parts(default_i2c(), flash, files);
+
+    #[cfg(unix)]
+    if args.pty {
+        return serve_pty(&mut sim, args.link.as_deref());
+    }
+
+    #[cfg(unix)]
+    if let Some(path) = &args.unix {
+        // A socket left over from an earlier run would make bind fail
+        let _ = fs::remove_file(path);
+        let listener = UnixListener::bind(path)?;
+        eprintln!("bpsim: listening on unix:{}", path.display());
+        return Ok(sim.serve_unix(&listener)?);
+    }
+
+    let addr = args.tcp.as_deref().unwrap_or(DEFAULT_TCP);
+    let listener = TcpListener::bind(addr)?;
+    eprintln!("bpsim: listening on {}", listener.local_addr()?);
+    Ok(sim.serve_tcp(&listener)?)
+}
+
+#[cfg(unix)]
+fn serve_pty(sim: &mut Simulator, link: Option<&std::path::Path>) -> Result<(), Box<dyn Error>> {
+    let mut pty = Pty::open()?;
+    if let Some(link) = link {
+        let _ = fs::remove_file(link);
+        std::os::unix::fs::symlink(pty.path(), link)?;
+    }
+    // The path goes to stdout so scripts can capture it
+    println!("{}", pty.path().display());
+    loop {
+        sim.serve(&mut pty)?;
+    }
+}
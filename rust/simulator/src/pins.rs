@@ -0,0 +1,66 @@
+//! GPIO pins and delays for scripts, and the I2C target peripheral
+
+use crate::VirtualTarget;
+use esp32_bus_pirate_bus_modes::i2c_target::TargetBus;
+use esp32_bus_pirate_core::Platform;
+use esp32_bus_pirate_protocol::ErrorCode;
+use std::{thread, time::Duration};
+
+/// GPIOs on the ESP32-S3, numbered 0 to 48
+pub const PIN_COUNT: usize = 49;
+
+/// Pins with nothing attached, each reading back the level last driven
+///
+/// Delays sleep the serving thread, like a delay on the device blocks the
+/// main loop. There is no I2C target unless one is given.
+#[derive(Debug, Clone)]
+pub struct VirtualPins {
+    levels: [bool; PIN_COUNT],
+    target: Option<VirtualTarget>,
+}
+
+impl Default for VirtualPins {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VirtualPins {
+    /// Every pin low
+    pub fn new() -> Self {
+        Self {
+            levels: [false; PIN_COUNT],
+            target: None,
+        }
+    }
+
+    /// Give the dispatcher `target` for I2C target mode
+    pub fn with_i2c_target(mut self, target: VirtualTarget) -> Self {
+        self.target = Some(target);
+        self
+    }
+
+    fn level(&mut self, pin: u8) -> Result<&mut bool, ErrorCode> {
+        self.levels.get_mut(usize::from(pin)).ok_or(ErrorCode::InvalidParameter)
+    }
+}
+
+impl Platform for VirtualPins {
+    fn set_pin(&mut self, pin: u8, high: bool) -> Result<(), ErrorCode> {
+        *self.level(pin)? = high;
+        Ok(())
+    }
+
+    fn pin(&mut self, pin: u8) -> Result<bool, ErrorCode> {
+        self.level(pin).map(|level| *level)
+    }
+
+    fn delay_us(&mut self, us: u32) -> Result<(), ErrorCode> {
+        thread::sleep(Duration::from_micros(u64::from(us)));
+        Ok(())
+    }
+
+    fn i2c_target(&mut self) -> Option<&mut dyn TargetBus> {
+        self.target.as_mut().map(|target| target as &mut dyn TargetBus)
+    }
+}
@@ -0,0 +1,106 @@
+//! Pseudo-terminal endpoint, so serial-port tools can open the simulator
+
+use std::{
+    ffi::CStr,
+    fs::File,
+    io::{self, Read, Write},
+    os::fd::{AsRawFd, FromRawFd},
+    path::{Path, PathBuf},
+    ptr,
+};
+
+/// How long a read waits for data before reporting `WouldBlock`
+const POLL_TIMEOUT_MS: libc::c_int = 10;
+
+/// The device side of a pseudo-terminal pair
+///
+/// Hosts open [`path`](Self::path) like any serial port. The terminal is in
+/// raw mode, and the simulator keeps its own handle on it so the PTY
+/// survives hosts connecting and disconnecting. Like a USB CDC port, it does
+/// not report hosts leaving; a new host's `Hello` starts its session.
+pub struct Pty {
+    master: File,
+    _slave: File,
+    path: PathBuf,
+}
+
+impl Pty {
+    /// Allocate a new pseudo-terminal
+    pub fn open() -> io::Result<Pty> {
+        let (mut master, mut slave) = (0, 0);
+        // SAFETY: out-pointers are valid; name, termios and winsize are optional
+        let ret = unsafe { libc::openpty(&mut master, &mut slave, ptr::null_mut(), ptr::null(), ptr::null()) };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: openpty returned two fresh descriptors that we now own
+        let (master, slave) = unsafe { (File::from_raw_fd(master), File::from_raw_fd(slave)) };
+
+        make_raw(&slave)?;
+        let path = tty_name(&slave)?;
+        Ok(Pty {
+            master,
+            _slave: slave,
+            path,
+        })
+    }
+
+    /// Path hosts open, e.g. `/dev/pts/3`
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+fn make_raw(tty: &File) -> io::Result<()> {
+    // SAFETY: termios is plain data, filled in by tcgetattr before use
+    unsafe {
+        let mut termios = std::mem::zeroed::<libc::termios>();
+        if libc::tcgetattr(tty.as_raw_fd(), &mut termios) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        libc::cfmakeraw(&mut termios);
+        if libc::tcsetattr(tty.as_raw_fd(), libc::TCSANOW, &termios) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+fn tty_name(tty: &File) -> io::Result<PathBuf> {
+    let mut buf = [0 as libc::c_char; 256];
+    // SAFETY: the buffer length is passed along, and ttyname_r NUL-terminates on success
+    let ret = unsafe { libc::ttyname_r(tty.as_raw_fd(), buf.as_mut_ptr(), buf.len()) };
+    if ret != 0 {
+        return Err(io::Error::from_raw_os_error(ret));
+    }
+    // SAFETY: see above
+    let name = unsafe { CStr::from_ptr(buf.as_ptr()) };
+    Ok(PathBuf::from(name.to_string_lossy().into_owned()))
+}
+
+impl Read for Pty {
+    /// Waits briefly for input, so the caller can poll for events in between
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut fd = libc::pollfd {
+            fd: self.master.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: one valid pollfd
+        match unsafe { libc::poll(&mut fd, 1, POLL_TIMEOUT_MS) } {
+            -1 => Err(io::Error::last_os_error()),
+            0 => Err(io::ErrorKind::WouldBlock.into()),
+            _ => self.master.read(buf),
+        }
+    }
+}
+
+impl Write for Pty {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.master.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.master.flush()
+    }
+}
@@ -0,0 +1,222 @@
+//! Simulated 25-series SPI NOR flash
+
+use embedded_hal::spi::{ErrorKind, ErrorType, Operation, SpiDevice};
+
+const READ_ID: u8 = 0x9F;
+const READ: u8 = 0x03;
+const FAST_READ: u8 = 0x0B;
+const READ_STATUS: u8 = 0x05;
+const WRITE_ENABLE: u8 = 0x06;
+const WRITE_DISABLE: u8 = 0x04;
+const PAGE_PROGRAM: u8 = 0x02;
+const SECTOR_ERASE: u8 = 0x20;
+const BLOCK_ERASE: u8 = 0xD8;
+const CHIP_ERASE: u8 = 0xC7;
+const CHIP_ERASE_ALT: u8 = 0x60;
+
+/// Status register: write enable latch
+const STATUS_WEL: u8 = 0x02;
+
+const PAGE_SIZE: usize = 256;
+const SECTOR_SIZE: usize = 4096;
+const BLOCK_SIZE: usize = 65536;
+
+/// SPI flash implementing `embedded_hal::spi::SpiDevice`
+///
+/// Supports JEDEC ID, (fast) read, status, write enable/disable, page
+/// program and sector/block/chip erase. Writes and erases complete
+/// instantly, so the busy bit never sets. Like real NOR flash, programming
+/// can only clear bits; erase sets them back to 1.
+pub struct SpiFlash {
+    id: [u8; 3],
+    memory: Vec<u8>,
+    write_enabled: bool,
+}
+
+impl SpiFlash {
+    /// Erased flash with JEDEC `id`; the capacity byte `id[2]` sets the size
+    pub fn new(id: [u8; 3]) -> Self {
+        let size = 1usize << id[2].clamp(10, 24);
+        Self {
+            id,
+            memory: vec![0xFF; size],
+            write_enabled: false,
+        }
+    }
+
+    /// Winbond W25Q32 (4 MB)
+    pub fn w25q32() -> Self {
+        Self::new([0xEF, 0x40, 0x16])
+    }
+
+    /// Load `image` at address 0; longer images are truncated
+    pub fn with_image(mut self, image: &[u8]) -> Self {
+        let len = image.len().min(self.memory.len());
+        self.memory[..len].copy_from_slice(&image[..len]);
+        self
+    }
+
+    /// Flash contents
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// MISO byte for position `index` of the transaction, given MOSI so far
+    fn output(&self, mosi: &[u8], index: usize) -> u8 {
+        let Some(&command) = mosi.first() else {
+            return 0xFF;
+        };
+        match command {
+            READ_ID => self.id.get(index.wrapping_sub(1)).copied().unwrap_or(0),
+            READ_STATUS if index >= 1 => {
+                if self.write_enabled {
+                    STATUS_WEL
+                } else {
+                    0
+                }
+            }
+            READ if index >= 4 => self.memory[(address(mosi) + index - 4) % self.memory.len()],
+            FAST_READ if index >= 5 => self.memory[(address(mosi) + index - 5) % self.memory.len()],
+            _ => 0xFF,
+        }
+    }
+
+    /// Apply a write or erase command when chip select is released
+    fn finish(&mut self, mosi: &[u8]) {
+        let Some(&command) = mosi.first() else {
+            return;
+        };
+        match command {
+            WRITE_ENABLE => self.write_enabled = true,
+            WRITE_DISABLE => self.write_enabled = false,
+            PAGE_PROGRAM if self.write_enabled && mosi.len() > 4 => {
+                let start = address(mosi) % self.memory.len();
+                let page = start - start % PAGE_SIZE;
+                // Data past the end of the page wraps to its start
+                for (i, &byte) in mosi[4..].iter().enumerate() {
+                    let addr = page + (start - page + i) % PAGE_SIZE;
+                    self.memory[addr] &= byte;
+                }
+                self.write_enabled = false;
+            }
+            SECTOR_ERASE if self.write_enabled && mosi.len() >= 4 => self.erase(address(mosi), SECTOR_SIZE),
+            BLOCK_ERASE if self.write_enabled && mosi.len() >= 4 => self.erase(address(mosi), BLOCK_SIZE),
+            CHIP_ERASE | CHIP_ERASE_ALT if self.write_enabled => self.erase(0, self.memory.len()),
+            _ => {}
+        }
+    }
+
+    fn erase(&mut self, addr: usize, size: usize) {
+        let start = (addr - addr % size) % self.memory.len();
+        let end = (start + size).min(self.memory.len());
+        self.memory[start..end].fill(0xFF);
+        self.write_enabled = false;
+    }
+}
+
+/// 24-bit address following the command byte
+fn address(mosi: &[u8]) -> usize {
+    mosi.iter()
+        .skip(1)
+        .take(3)
+        .fold(0, |addr, &byte| addr << 8 | byte as usize)
+}
+
+impl ErrorType for SpiFlash {
+    type Error = ErrorKind;
+}
+
+impl SpiDevice for SpiFlash {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        // Chip select is held for the whole transaction
+        let mut mosi = Vec::new();
+        let mut clock = |flash: &SpiFlash, byte: u8| {
+            let index = mosi.len();
+            mosi.push(byte);
+            flash.output(&mosi, index)
+        };
+
+        for operation in operations {
+            match operation {
+                Operation::Read(buf) => {
+                    for byte in buf.iter_mut() {
+                        *byte = clock(self, 0x00);
+                    }
+                }
+                Operation::Write(data) => {
+                    for &byte in data.iter() {
+                        clock(self, byte);
+                    }
+                }
+                Operation::Transfer(read, write) => {
+                    for i in 0..read.len().max(write.len()) {
+                        let out = clock(self, write.get(i).copied().unwrap_or(0x00));
+                        if let Some(byte) = read.get_mut(i) {
+                            *byte = out;
+                        }
+                    }
+                }
+                Operation::TransferInPlace(buf) => {
+                    for byte in buf.iter_mut() {
+                        *byte = clock(self, *byte);
+                    }
+                }
+                Operation::DelayNs(_) => {}
+            }
+        }
+
+        self.finish(&mosi);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jedec_id_and_size() {
+        let mut flash = SpiFlash::w25q32();
+        let mut buf = [READ_ID, 0, 0, 0];
+        flash.transfer_in_place(&mut buf).unwrap();
+        assert_eq!(buf[1..], [0xEF, 0x40, 0x16]);
+        assert_eq!(flash.memory().len(), 4 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_program_needs_write_enable_and_only_clears_bits() {
+        let mut flash = SpiFlash::w25q32();
+        flash.write(&[PAGE_PROGRAM, 0, 0, 0, 0x00]).unwrap();
+        assert_eq!(flash.memory()[0], 0xFF);
+
+        flash.write(&[WRITE_ENABLE]).unwrap();
+        flash.write(&[PAGE_PROGRAM, 0, 0, 0, 0xF0]).unwrap();
+        flash.write(&[WRITE_ENABLE]).unwrap();
+        flash.write(&[PAGE_PROGRAM, 0, 0, 0, 0x3C]).unwrap();
+        assert_eq!(flash.memory()[0], 0x30);
+    }
+
+    #[test]
+    fn test_page_program_wraps_within_page() {
+        let mut flash = SpiFlash::w25q32();
+        flash.write(&[WRITE_ENABLE]).unwrap();
+        flash.write(&[PAGE_PROGRAM, 0x00, 0x01, 0xFF, 0x11, 0x22]).unwrap();
+        assert_eq!(flash.memory()[0x1FF], 0x11);
+        assert_eq!(flash.memory()[0x100], 0x22);
+        assert_eq!(flash.memory()[0x200], 0xFF);
+    }
+
+    #[test]
+    fn test_sector_erase_and_read() {
+        let mut flash = SpiFlash::w25q32().with_image(&[0u8; 2 * SECTOR_SIZE]);
+        flash.write(&[WRITE_ENABLE]).unwrap();
+        flash.write(&[SECTOR_ERASE, 0x00, 0x10, 0x20]).unwrap();
+
+        let mut data = [0u8; 2];
+        flash
+            .transaction(&mut [Operation::Write(&[READ, 0x00, 0x0F, 0xFF]), Operation::Read(&mut data)])
+            .unwrap();
+        assert_eq!(data, [0x00, 0xFF]);
+        assert!(flash.memory()[SECTOR_SIZE..2 * SECTOR_SIZE].iter().all(|&b| b == 0xFF));
+    }
+}
@@ -0,0 +1,168 @@
+//! 24Cxx EEPROM tests: the bus-modes EEPROM driver against a simulated
+//! 24C256 on the virtual I2C bus
+
+use embedded_hal::i2c::{I2c, NoAcknowledgeSource, Operation};
+use esp32_bus_pirate_bus_modes::i2c::I2cMode;
+use esp32_bus_pirate_bus_modes::i2c_eeprom::{Eeprom, EepromPart, I2cEeprom};
+use esp32_bus_pirate_bus_modes::Error;
+use esp32_bus_pirate_protocol::bulk::crc32;
+use esp32_bus_pirate_simulator::{Eeprom24, RegisterDevice, VirtualI2c};
+
+const C256: I2cEeprom = I2cEeprom { addr: 0x50, part: EepromPart::C256 };
+
+fn bus(eeprom: Eeprom24) -> I2cMode<VirtualI2c> {
+    I2cMode::new(VirtualI2c::new().with_device(0x50, eeprom))
+}
+
+/// Deterministic test payload
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 + i / 256) as u8).collect()
+}
+
+#[test]
+fn test_probe() {
+    let mut i2c = bus(Eeprom24::c256());
+    assert_eq!(Eeprom::new(&mut i2c, C256).probe().unwrap(), [0x50]);
+
+    let missing = I2cEeprom { addr: 0x51, ..C256 };
+    assert!(Eeprom::new(&mut i2c, missing).probe().unwrap().is_empty());
+}
+
+#[test]
+fn test_read() {
+    let image = pattern(32768);
+    let mut i2c = bus(Eeprom24::c256().with_image(&image));
+    let mut eeprom = Eeprom::new(&mut i2c, C256);
+
+    let mut buf = [0u8; 100];
+    eeprom.read(0x1234, &mut buf).unwrap();
+    assert_eq!(buf[..], image[0x1234..0x1234 + 100]);
+
+    let mut all = vec![0u8; 32768];
+    eeprom.read(0, &mut all).unwrap();
+    assert_eq!(all, image);
+}
+
+#[test]
+fn test_write_splits_pages() {
+    let data = pattern(200);
+    let mut i2c = bus(Eeprom24::c256());
+    let mut eeprom = Eeprom::new(&mut i2c, C256);
+
+    // 0x30 is 16 bytes before a page boundary: 16, 64, 64, 56 bytes
+    eeprom.write(0x30, &data).unwrap();
+
+    let mut buf = vec![0u8; 0x30 + 200 + 16];
+    eeprom.read(0, &mut buf).unwrap();
+    assert!(buf[..0x30].iter().all(|&b| b == 0xFF));
+    assert_eq!(buf[0x30..0x30 + 200], data[..]);
+    assert!(buf[0x30 + 200..].iter().all(|&b| b == 0xFF));
+}
+
+#[test]
+fn test_unsplit_write_wraps_within_page() {
+    // What the splitting avoids: the part wraps a write at the page end
+    let mut i2c = VirtualI2c::new().with_device(0x50, Eeprom24::c256().with_write_cycle(0));
+    i2c.write(0x50, &[0x00, 0x3E, 1, 2, 3, 4]).unwrap();
+
+    let mut buf = [0u8; 64];
+    i2c.write_read(0x50, &[0x00, 0x00], &mut buf).unwrap();
+    assert_eq!(buf[..2], [3, 4]);
+    assert_eq!(buf[0x3E..], [1, 2]);
+}
+
+#[test]
+fn test_write_waits_for_write_cycle() {
+    let mut i2c = bus(Eeprom24::c256().with_write_cycle(50));
+    let mut eeprom = Eeprom::new(&mut i2c, C256);
+
+    // Without polling, the second page would be refused
+    eeprom.write(0, &pattern(128)).unwrap();
+    assert_eq!(eeprom.verify(0, &pattern(128)).unwrap(), None);
+}
+
+#[test]
+fn test_hung_write_cycle_times_out() {
+    let mut i2c = bus(Eeprom24::c256().with_write_cycle(u32::MAX));
+    let err = Eeprom::new(&mut i2c, C256).write(0, &[0xA5]).unwrap_err();
+    assert!(matches!(err, Error::Timeout));
+}
+
+#[test]
+fn test_missing_part_is_address_nack() {
+    let mut i2c = bus(Eeprom24::c256());
+    let missing = I2cEeprom { addr: 0x57, ..C256 };
+    let err = Eeprom::new(&mut i2c, missing).read(0, &mut [0u8; 4]).unwrap_err();
+    assert!(matches!(err, Error::Nack { source: NoAcknowledgeSource::Address, .. }));
+}
+
+#[test]
+fn test_block_select() {
+    // A 24C16 is eight 256-byte blocks at 0x50-0x57, each like a 24C02
+    let mut virtual_bus = VirtualI2c::new();
+    for addr in 0x50..=0x57 {
+        virtual_bus.attach(addr, RegisterDevice::eeprom_24c02());
+    }
+    let mut i2c = I2cMode::new(virtual_bus);
+    let c16 = I2cEeprom { addr: 0x50, part: EepromPart::C16 };
+    let mut eeprom = Eeprom::new(&mut i2c, c16);
+
+    assert_eq!(eeprom.probe().unwrap(), [0x50, 0x51, 0x52, 0x53, 0x54, 0x55, 0x56, 0x57]);
+    let data = pattern(32);
+    eeprom.write(0x2F0, &data).unwrap();
+    let mut buf = [0u8; 32];
+    eeprom.read(0x2F0, &mut buf).unwrap();
+    assert_eq!(buf[..], data[..]);
+
+    // The write crossed from block 2 into block 3
+    let mut block = [0u8; 16];
+    i2c.transaction(0x52, &mut [Operation::Write(&[0xF0]), Operation::Read(&mut block)]).unwrap();
+    assert_eq!(block[..], data[..16]);
+    i2c.transaction(0x53, &mut [Operation::Write(&[0x00]), Operation::Read(&mut block)]).unwrap();
+    assert_eq!(block[..], data[16..]);
+}
+
+#[test]
+fn test_erase() {
+    let mut i2c = bus(Eeprom24::c256().with_image(&pattern(32768)).with_write_cycle(1));
+    let mut eeprom = Eeprom::new(&mut i2c, C256);
+    eeprom.erase().unwrap();
+
+    assert_eq!(eeprom.verify(0, &[0xFF; 32768]).unwrap(), None);
+}
+
+#[test]
+fn test_verify_reports_first_difference() {
+    let image = pattern(4096);
+    let mut i2c = bus(Eeprom24::c256().with_image(&image));
+    let mut eeprom = Eeprom::new(&mut i2c, C256);
+
+    assert_eq!(eeprom.verify(0, &image).unwrap(), None);
+
+    let mut changed = image.clone();
+    changed[1000] ^= 0x01;
+    changed[3000] ^= 0x01;
+    assert_eq!(eeprom.verify(0, &changed).unwrap(), Some(1000));
+    assert_eq!(eeprom.verify(100, &changed[100..]).unwrap(), Some(1000));
+}
+
+#[test]
+fn test_checksum() {
+    let image = pattern(32768);
+    let mut i2c = bus(Eeprom24::c256().with_image(&image));
+    let mut eeprom = Eeprom::new(&mut i2c, C256);
+
+    assert_eq!(eeprom.checksum(0, 32768).unwrap(), crc32(&image));
+    assert_eq!(eeprom.checksum(0x100, 1000).unwrap(), crc32(&image[0x100..0x100 + 1000]));
+    assert_eq!(eeprom.checksum(0, 0).unwrap(), crc32(&[]));
+}
+
+#[test]
+fn test_out_of_range_is_refused() {
+    let mut i2c = bus(Eeprom24::c256());
+    let mut eeprom = Eeprom::new(&mut i2c, C256);
+
+    assert!(matches!(eeprom.read(32760, &mut [0u8; 16]), Err(Error::InvalidConfig)));
+    assert!(matches!(eeprom.write(32768, &[0]), Err(Error::InvalidConfig)));
+    assert!(matches!(eeprom.checksum(1, 32768), Err(Error::InvalidConfig)));
+}
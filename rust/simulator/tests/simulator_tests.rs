@@ -0,0 +1,502 @@
+//! End-to-end tests: the host client against the simulator over real sockets
+
+use esp32_bus_pirate_client::{Client, Error};
+use esp32_bus_pirate_protocol::{
+    BulkTarget, EepromPart, ErrorCode, Event, Features, FrameDecoder, I2cChip, I2cEeprom, I2cScanOptions, I2cScanProbe, I2cScanReport, I2cSpeed, I2cNack, I2cOp, I2cRegister,
+    I2cRegisterMap, I2cTargetAccess, RegisterAccess, Message, MessageCodec, Mode,
+    NackSource, Response, Topic, PROTOCOL_VERSION_V1, PROTOCOL_VERSION_V2,
+};
+use esp32_bus_pirate_simulator::{default_i2c, Eeprom24, Files, RegisterDevice, Simulator, SpiFlash, VirtualI2c};
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+    time::Duration,
+};
+
+/// Start a simulator on a free port and return its address
+fn spawn_simulator() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || Simulator::new().serve_tcp(&listener));
+    addr
+}
+
+fn connect(addr: &str) -> Client<TcpStream> {
+    let stream = TcpStream::connect(addr).unwrap();
+    stream.set_read_timeout(Some(Duration::from_millis(20))).unwrap();
+    let mut client = Client::new(stream).with_timeout(Duration::from_secs(5));
+    client.handshake().unwrap();
+    client
+}
+
+fn client() -> Client<TcpStream> {
+    connect(&spawn_simulator())
+}
+
+/// Deterministic test payload
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 + i / 256) as u8).collect()
+}
+
+#[test]
+fn test_handshake_reports_simulator() {
+    let client = client();
+    let info = client.device_info().expect("simulator answers Hello");
+    assert_eq!(info.version, PROTOCOL_VERSION_V2);
+    assert_eq!(info.board.as_str(), "Simulator");
+    assert!(info.modes.contains(&Mode::I2c));
+    assert!(info
+        .features
+        .contains(Features::BULK_TRANSFER | Features::EVENTS | Features::FILESYSTEM | Features::SCRIPTS));
+}
+
+#[test]
+fn test_mode_switching() {
+    let mut client = client();
+    assert_eq!(client.get_mode().unwrap(), Mode::HiZ);
+    client.set_mode(Mode::Spi).unwrap();
+    assert_eq!(client.get_mode().unwrap(), Mode::Spi);
+
+    // Modes the simulator lacks are refused without leaving the current one
+    let err = client.set_mode(Mode::Jtag).unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::InvalidParameter));
+    assert_eq!(client.get_mode().unwrap(), Mode::Spi);
+}
+
+#[test]
+fn test_commands_need_their_mode() {
+    let mut client = client();
+    let err = client.i2c_scan().unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::NotConfigured));
+}
+
+#[test]
+fn test_i2c_devices() {
+    let mut client = client();
+    client.set_mode(Mode::I2c).unwrap();
+    assert_eq!(client.i2c_scan().unwrap(), vec![0x50, 0x76]);
+
+    // BME280 chip ID
+    assert_eq!(client.i2c_read_register(0x76, 0xD0).unwrap(), 0x60);
+
+    // EEPROM: write a page, read it back sequentially
+    client.i2c_write(0x50, &[0x10, 1, 2, 3, 4]).unwrap();
+    client.i2c_write(0x50, &[0x10]).unwrap();
+    assert_eq!(client.i2c_read(0x50, 4).unwrap(), vec![1, 2, 3, 4]);
+
+    client.i2c_write_register(0x50, 0x20, 0xAB).unwrap();
+    assert_eq!(client.i2c_read_register(0x50, 0x20).unwrap(), 0xAB);
+
+    let err = client.i2c_read(0x42, 1).unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::AddressNack));
+}
+
+#[test]
+fn test_i2c_scan_report() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    // A BMP280 shares the BME280's address and ID register
+    let i2c = default_i2c().with_device(0x77, RegisterDevice::new(256, 0x00).with_constant(0xD0, 0x58));
+    thread::spawn(move || Simulator::with_parts(i2c, SpiFlash::w25q32(), Files::memory()).serve_tcp(&listener));
+    let mut client = connect(&addr);
+    client.set_mode(Mode::I2c).unwrap();
+
+    let report = client.i2c_scan_report(I2cScanOptions::default(), true).unwrap();
+    let found: Vec<_> = report.devices.iter().map(|entry| (entry.addr, entry.chip)).collect();
+    assert_eq!(found, [(0x50, None), (0x76, Some(I2cChip::Bme280)), (0x77, Some(I2cChip::Bmp280))]);
+    assert!(report.devices[0].candidates().iter().any(|device| device.kind == "EEPROM"));
+
+    // Without identification only the addresses come back
+    let report = client.i2c_scan_report(I2cScanOptions::default(), false).unwrap();
+    assert!(report.devices.iter().all(|entry| entry.chip.is_none()));
+    assert_eq!(report.devices.len(), 3);
+}
+
+#[test]
+fn test_i2c_scan_strategies() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    // A device on the general call address, and one too slow for fast mode plus
+    let i2c = default_i2c()
+        .with_device(0x00, RegisterDevice::new(1, 0x00))
+        .with_speed_limit(0x76, 400_000);
+    thread::spawn(move || Simulator::with_parts(i2c, SpiFlash::w25q32(), Files::memory()).serve_tcp(&listener));
+    let mut client = connect(&addr);
+    client.set_mode(Mode::I2c).unwrap();
+
+    let addrs = |report: I2cScanReport| report.devices.iter().map(|entry| entry.addr).collect::<Vec<_>>();
+    for probe in [I2cScanProbe::Write, I2cScanProbe::Read, I2cScanProbe::Quick] {
+        let report = client.i2c_scan_report(I2cScanOptions { probe, reserved: false }, false).unwrap();
+        assert_eq!(addrs(report), [0x50, 0x76], "{probe:?}");
+    }
+    let report = client.i2c_scan_report(I2cScanOptions { reserved: true, ..Default::default() }, false).unwrap();
+    assert_eq!(addrs(report), [0x00, 0x50, 0x76]);
+
+    let sweep = client.i2c_scan_sweep(I2cScanOptions::default()).unwrap();
+    let speeds: Vec<_> = sweep.devices.iter().map(|entry| (entry.addr, entry.speeds.to_vec())).collect();
+    assert_eq!(speeds, [(0x50, I2cSpeed::ALL.to_vec()), (0x76, vec![I2cSpeed::Standard, I2cSpeed::Fast])]);
+
+    // The bus is back at its configured speed afterwards
+    assert_eq!(client.i2c_scan().unwrap(), vec![0x50, 0x76]);
+}
+
+#[test]
+fn test_i2c_transactions() {
+    let mut client = client();
+    client.set_mode(Mode::I2c).unwrap();
+    let write = |bytes: &[u8]| I2cOp::Write(bytes.try_into().unwrap());
+
+    client.i2c_transaction(0x50, &[write(&[0x40]), write(&[1, 2, 3])]).unwrap();
+    let report = client
+        .i2c_transaction(0x50, &[write(&[0x40]), I2cOp::Read(2), I2cOp::Read(1)])
+        .unwrap();
+    assert_eq!((report.data.as_slice(), report.nack), (&[1, 2, 3][..], None));
+
+    let report = client.i2c_transaction(0x42, &[write(&[0xD0]), I2cOp::Read(1)]).unwrap();
+    assert!(report.data.is_empty());
+    assert_eq!(report.nack, Some(I2cNack { segment: Some(0), source: NackSource::Address }));
+}
+
+#[test]
+fn test_i2c_recovery() {
+    let mut client = client();
+    client.set_mode(Mode::I2c).unwrap();
+    let report = client.i2c_recover().unwrap();
+    assert!(report.recovered && report.scl.pull_up && report.sda.pull_up);
+    assert_eq!(report.clocks, 0);
+}
+
+#[test]
+fn test_i2c_stuck_bus_is_recovered() {
+    let spawn = |i2c: VirtualI2c| {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || Simulator::with_parts(i2c, SpiFlash::w25q32(), Files::memory()).serve_tcp(&listener));
+        let mut client = connect(&addr);
+        client.set_mode(Mode::I2c).unwrap();
+        client
+    };
+
+    let mut client = spawn(default_i2c().with_stuck_sda(3));
+    let err = client.i2c_read(0x50, 1).unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::BusError));
+    let report = client.i2c_recover().unwrap();
+    assert!(report.sda.held_low && !report.scl.held_low);
+    assert_eq!((report.clocks, report.recovered), (3, true));
+    assert_eq!(client.i2c_read(0x50, 1).unwrap(), vec![0xFF]);
+
+    // A scan frees the bus by itself
+    let mut client = spawn(default_i2c().with_stuck_sda(5));
+    assert_eq!(client.i2c_scan().unwrap(), vec![0x50, 0x76]);
+
+    // Missing pull-ups are reported, but the internal ones keep the bus going
+    let mut client = spawn(default_i2c().without_pull_ups());
+    let report = client.i2c_recover().unwrap();
+    assert!(!report.scl.pull_up && !report.sda.pull_up && !report.scl.held_low);
+    assert!(report.recovered);
+}
+
+#[test]
+fn test_i2c_eeprom() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let i2c = VirtualI2c::new().with_device(0x50, Eeprom24::c256());
+    thread::spawn(move || Simulator::with_parts(i2c, SpiFlash::w25q32(), Files::memory()).serve_tcp(&listener));
+    let mut client = connect(&addr);
+    client.set_mode(Mode::I2c).unwrap();
+
+    let eeprom = I2cEeprom { addr: 0x50, part: EepromPart::C256 };
+    assert_eq!(client.i2c_eeprom_probe(eeprom).unwrap(), vec![0x50]);
+    let missing = I2cEeprom { addr: 0x51, ..eeprom };
+    assert!(client.i2c_eeprom_probe(missing).unwrap().is_empty());
+
+    // Unaligned start, spanning several pages
+    let data = pattern(3000);
+    let target = BulkTarget::I2cEeprom { eeprom, address: 0x123 };
+    client.bulk_write(target.clone(), &data).unwrap();
+    assert_eq!(client.bulk_read(target, 0, data.len() as u32).unwrap(), data);
+    client.i2c_eeprom_verify(eeprom, 0x123, &data).unwrap();
+
+    let mut changed = data.clone();
+    changed[2000] ^= 0x01;
+    let err = client.i2c_eeprom_verify(eeprom, 0x123, &changed).unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::ChecksumMismatch));
+
+    // Reads are clamped to the part
+    let tail = client.bulk_read(BulkTarget::I2cEeprom { eeprom, address: 0x7F00 }, 0, u32::MAX).unwrap();
+    assert_eq!(tail.len(), 0x100);
+
+    client.i2c_eeprom_erase(eeprom).unwrap();
+    client.i2c_eeprom_verify(eeprom, 0, &[0xFF; 32768]).unwrap();
+}
+
+#[test]
+fn test_i2c_target_emulation() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let mut simulator = Simulator::new();
+    let controller = simulator.i2c_target();
+    thread::spawn(move || simulator.serve_tcp(&listener));
+    let mut client = connect(&addr);
+
+    let map = I2cRegisterMap {
+        addr: 0x48,
+        auto_increment: true,
+        registers: [
+            I2cRegister { address: 0x0F, width: 1, access: RegisterAccess::ReadOnly, value: 0x33 },
+            I2cRegister { address: 0x20, width: 2, access: RegisterAccess::ReadWrite, value: 0 },
+        ]
+        .into_iter()
+        .collect(),
+    };
+    let err = client.i2c_target_start(map.clone()).unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::NotConfigured));
+    client.set_mode(Mode::I2c).unwrap();
+    client.subscribe(Topic::I2cTarget).unwrap();
+    client.i2c_target_start(map).unwrap();
+    assert_eq!(controller.address(), Some(0x48));
+
+    // The device's driver identifies the part, then configures it
+    assert!(controller.write_read(0x48, &[0x0F], 1));
+    assert!(controller.write(0x48, &[0x20, 0xBE, 0xEF]));
+    assert!(!controller.write(0x49, &[0x20]));
+
+    let mut accesses = Vec::new();
+    while accesses.len() < 3 {
+        match client.poll_event(Duration::from_secs(5)).unwrap() {
+            Some(Event::I2cTarget(access)) => accesses.push(access),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+    let access = |read, reg, data: &[u8]| I2cTargetAccess {
+        read,
+        reg,
+        data: data.iter().copied().collect(),
+        len: data.len() as u16,
+        nacked: false,
+    };
+    assert_eq!(
+        accesses,
+        [access(false, 0x0F, &[]), access(true, 0x0F, &[0x33]), access(false, 0x20, &[0xBE, 0xEF])]
+    );
+    assert_eq!(controller.take_received(), [0x33]);
+
+    let registers = client.i2c_target_registers().unwrap();
+    assert_eq!(registers.registers[1].value, 0xBEEF);
+
+    // The target may have the controller's peripheral until it stops
+    let err = client.i2c_scan().unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::Busy));
+    client.i2c_target_stop().unwrap();
+    assert_eq!(controller.address(), None);
+    client.i2c_scan().unwrap();
+    client.i2c_target_start(registers).unwrap();
+
+    // Leaving I2C mode stops the target
+    client.set_mode(Mode::HiZ).unwrap();
+    assert_eq!(controller.address(), None);
+    let err = client.i2c_target_registers().unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::NotConfigured));
+}
+
+#[test]
+fn test_spi_flash_id() {
+    let mut client = client();
+    client.set_mode(Mode::Spi).unwrap();
+    let reply = client.spi_transfer(&[0x9F, 0, 0, 0]).unwrap();
+    assert_eq!(reply[1..], [0xEF, 0x40, 0x16]);
+}
+
+#[test]
+fn test_flash_bulk_round_trip() {
+    let mut client = client();
+    client.set_mode(Mode::Spi).unwrap();
+
+    // The sector before the upload holds data of its own
+    let before = pattern(0x1000);
+    client.bulk_write(BulkTarget::SpiFlash { address: 0 }, &before).unwrap();
+
+    // Spanning several pages and sectors, ending mid-sector
+    let data = pattern(10_000);
+    let target = BulkTarget::SpiFlash { address: 0x1000 };
+    client.bulk_write(target.clone(), &data).unwrap();
+    assert_eq!(client.bulk_read(target, 0, data.len() as u32).unwrap(), data);
+    assert_eq!(client.bulk_read(BulkTarget::SpiFlash { address: 0 }, 0, 0x1000).unwrap(), before);
+
+    // An unaligned start would erase bytes before it
+    let err = client.bulk_write(BulkTarget::SpiFlash { address: 0x1F00 }, &data).unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::InvalidParameter));
+    assert_eq!(client.bulk_read(BulkTarget::SpiFlash { address: 0 }, 0, 0x1000).unwrap(), before);
+}
+
+#[test]
+fn test_flash_read_is_clamped_to_capacity() {
+    let mut client = client();
+    client.set_mode(Mode::Spi).unwrap();
+    let tail = client
+        .bulk_read(BulkTarget::SpiFlash { address: 0x3F_FF00 }, 0, u32::MAX)
+        .unwrap();
+    assert_eq!(tail.len(), 0x100);
+}
+
+#[test]
+fn test_uart_loopback() {
+    let mut client = client();
+    client.set_mode(Mode::Uart).unwrap();
+    client.uart_config(9600).unwrap();
+    client.uart_write(b"hello").unwrap();
+    assert_eq!(client.uart_read(64).unwrap(), b"hello");
+    assert!(client.uart_read(64).unwrap().is_empty());
+}
+
+#[test]
+fn test_uart_rx_events() {
+    let mut client = client();
+    client.set_mode(Mode::Uart).unwrap();
+    client.subscribe(Topic::UartRx).unwrap();
+    client.uart_write(b"ping").unwrap();
+
+    match client.poll_event(Duration::from_secs(5)).unwrap() {
+        Some(Event::UartRx { data }) => assert_eq!(data.as_slice(), b"ping"),
+        other => panic!("unexpected {other:?}"),
+    }
+
+    client.unsubscribe(Topic::UartRx).unwrap();
+    client.uart_write(b"pong").unwrap();
+    assert_eq!(client.poll_event(Duration::from_millis(100)).unwrap(), None);
+    assert_eq!(client.uart_read(64).unwrap(), b"pong");
+}
+
+#[test]
+fn test_files() {
+    let mut client = client();
+    client.file_write("/scripts/init.bp", b"[0xA0 0x00]").unwrap();
+    assert_eq!(client.file_list("/").unwrap(), vec!["scripts"]);
+    assert_eq!(client.file_list("/scripts").unwrap(), vec!["init.bp"]);
+    assert_eq!(client.file_read("/scripts/init.bp").unwrap(), b"[0xA0 0x00]");
+
+    let err = client.file_read("/missing").unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::FileNotFound));
+}
+
+#[test]
+fn test_file_bulk_round_trip() {
+    let mut client = client();
+    let data = pattern(5_000);
+    let target = BulkTarget::File("/dump.bin".try_into().unwrap());
+    client.bulk_write(target.clone(), &data).unwrap();
+    assert_eq!(client.bulk_read(target.clone(), 0, u32::MAX).unwrap(), data);
+    assert_eq!(client.bulk_read(target, 4_000, 100).unwrap(), data[4_000..4_100]);
+
+    // Too large for a single FileRead reply
+    let err = client.file_read("/dump.bin").unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::InvalidParameter));
+}
+
+#[test]
+fn test_scripts() {
+    let mut client = client();
+    let script = b"\
+mode i2c
+i2c writeread 0x76 0xD0 -> id
+i2c write 0x50 0x30 \"bp\"
+i2c writeread 0x50 0x30 -> a b
+pin write 5 1
+pin read 5 -> level
+delay 1 ms
+emit id a b level
+assert id == 0x58
+";
+    client.file_write("/scripts/probe.bp", script).unwrap();
+    let report = client.run_script("/scripts/probe.bp").unwrap();
+    assert_eq!(report.output, [0x60, b'b', b'p', 1]);
+    let error = report.error.expect("the chip ID is not 0x58");
+    assert_eq!((error.line, error.code), (9, ErrorCode::AssertionFailed));
+    assert_eq!(client.get_mode().unwrap(), Mode::I2c);
+
+    let err = client.run_script("/scripts/missing.bp").unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::FileNotFound));
+}
+
+#[test]
+fn test_config_values() {
+    let mut client = client();
+    client.set_config("wifi.ssid", "lab").unwrap();
+    assert_eq!(client.get_config("wifi.ssid").unwrap(), "lab");
+    assert!(matches!(client.get_config("nope"), Err(Error::Device(ErrorCode::InvalidParameter))));
+}
+
+#[test]
+fn test_state_survives_reconnect() {
+    let addr = spawn_simulator();
+    {
+        let mut client = connect(&addr);
+        client.set_mode(Mode::I2c).unwrap();
+        client.subscribe(Topic::Log).unwrap();
+    }
+    // The bus mode is device state; subscriptions end with the session
+    let mut client = connect(&addr);
+    assert_eq!(client.get_mode().unwrap(), Mode::I2c);
+}
+
+#[test]
+fn test_v1_host_without_handshake() {
+    // What `tools/test_client.py` sends: bare v1 frames, no Hello
+    let addr = spawn_simulator();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    stream.write_all(&MessageCodec::encode_v1(&Message::GetMode).unwrap()).unwrap();
+
+    let mut decoder = FrameDecoder::new();
+    let mut buf = [0u8; 64];
+    let frame = loop {
+        let n = stream.read(&mut buf).unwrap();
+        assert!(n > 0, "simulator closed the connection");
+        decoder.push(&buf[..n]);
+        if let Some(frame) = decoder.poll_frame() {
+            break frame.unwrap();
+        }
+    };
+    assert_eq!(frame.version, PROTOCOL_VERSION_V1);
+    assert_eq!(frame.message, Message::Response(Response::CurrentMode(Mode::HiZ)));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_pty_serves_hosts_in_turn() {
+    use esp32_bus_pirate_simulator::Pty;
+    use std::fs::OpenOptions;
+
+    let mut pty = Pty::open().unwrap();
+    let path = pty.path().to_owned();
+    thread::spawn(move || {
+        let mut sim = Simulator::new();
+        loop {
+            let _ = sim.serve(&mut pty);
+        }
+    });
+
+    // Each host numbers its requests from 1; a new host must not look like a replay
+    let hello = Message::Hello {
+        versions: heapless::Vec::from_slice(&[PROTOCOL_VERSION_V2]).unwrap(),
+        max_frame: 512,
+    };
+    for _ in 0..2 {
+        let mut port = OpenOptions::new().read(true).write(true).open(&path).unwrap();
+        port.write_all(&MessageCodec::encode_with_seq(&hello, 1).unwrap()).unwrap();
+
+        let mut decoder = FrameDecoder::new();
+        let mut buf = [0u8; 256];
+        let frame = loop {
+            let n = port.read(&mut buf).unwrap();
+            decoder.push(&buf[..n]);
+            if let Some(frame) = decoder.poll_frame() {
+                break frame.unwrap();
+            }
+        };
+        assert_eq!(frame.seq, 1);
+        assert!(matches!(frame.message, Message::Response(Response::DeviceInfo(_))));
+    }
+}
+